@@ -0,0 +1,23 @@
+-- Passwordless sign-in links sent by email
+CREATE TABLE IF NOT EXISTS magic_link_tokens (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    requested_ip VARCHAR(64),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_magic_link_tokens_user ON magic_link_tokens(user_id, created_at DESC);
+
+-- Every request is logged (including unknown identifiers) for rate limiting
+CREATE TABLE IF NOT EXISTS magic_link_requests (
+    id SERIAL PRIMARY KEY,
+    identifier VARCHAR(255) NOT NULL,
+    ip_address VARCHAR(64),
+    requested_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_magic_link_requests_ip ON magic_link_requests(ip_address, requested_at DESC);
+CREATE INDEX IF NOT EXISTS idx_magic_link_requests_identifier ON magic_link_requests(identifier, requested_at DESC);
@@ -0,0 +1,44 @@
+-- Versioned consent documents, one row per version and locale.
+-- A version is in force once published_at is set; the highest published version is current.
+CREATE TABLE IF NOT EXISTS consent_documents (
+    id SERIAL PRIMARY KEY,
+    version INTEGER NOT NULL,
+    locale VARCHAR(10) NOT NULL,
+    title TEXT NOT NULL,
+    body TEXT NOT NULL,
+    created_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    published_at TIMESTAMPTZ,
+    UNIQUE (version, locale)
+);
+
+CREATE INDEX IF NOT EXISTS idx_consent_documents_published ON consent_documents(version) WHERE published_at IS NOT NULL;
+
+-- Acceptance of a consent version by (or on behalf of) a user
+CREATE TABLE IF NOT EXISTS user_consents (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    locale VARCHAR(10) NOT NULL,
+    accepted_by INTEGER REFERENCES users(id) ON DELETE SET NULL, -- parent or admin when accepted for someone else
+    on_behalf_of_minor BOOLEAN NOT NULL DEFAULT FALSE,
+    ip_address TEXT,
+    accepted_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (user_id, version)
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_consents_user ON user_consents(user_id);
+
+-- Version 1 is the text previously shipped with the app
+INSERT INTO consent_documents (version, locale, title, body, published_at)
+VALUES
+    (1, 'en', 'Consent to Personal Data Processing',
+     E'By registering and using the app and website, you consent to the processing of your personal data under the following conditions:\nData is used solely to provide music learning content and operate the service.\nYou may edit or delete your profile at any time.\nData transmission is protected using TLS encryption.\nData is not shared with third parties, except for email delivery via SendGrid.',
+     NOW()),
+    (1, 'de', 'Einwilligung zur Verarbeitung personenbezogener Daten',
+     E'Durch die Registrierung und Nutzung der App und Website stimmen Sie der Verarbeitung Ihrer personenbezogenen Daten unter folgenden Bedingungen zu:\nDaten werden ausschliesslich fuer Musiklern-Inhalte und den Betrieb des Dienstes verwendet.\nSie koennen Ihr Profil jederzeit bearbeiten oder loeschen.\nDie Datenuebertragung ist durch TLS geschuetzt.\nDaten werden nicht an Dritte weitergegeben, ausser fuer E-Mail-Versand ueber SendGrid.',
+     NOW()),
+    (1, 'ru', 'Согласие на обработку персональных данных',
+     E'Регистрируясь и используя приложение и сайт, вы соглашаетесь на обработку персональных данных на следующих условиях:\nДанные используются только для предоставления музыкального учебного контента и работы сервиса.\nВы можете в любой момент изменить или удалить профиль.\nПередача данных защищена TLS.\nДанные не передаются третьим лицам, кроме сервиса отправки писем SendGrid.',
+     NOW())
+ON CONFLICT (version, locale) DO NOTHING;
@@ -0,0 +1,5 @@
+-- Preferred language for notifications and emails
+ALTER TABLE users ADD COLUMN IF NOT EXISTS locale VARCHAR(10) NOT NULL DEFAULT 'en';
+
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_locale_check;
+ALTER TABLE users ADD CONSTRAINT users_locale_check CHECK (locale IN ('en', 'de', 'ru'));
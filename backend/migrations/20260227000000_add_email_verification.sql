@@ -0,0 +1,33 @@
+-- Email addresses must be confirmed before password reset links, sign-in links
+-- or notifications are sent to them. Existing addresses start unverified.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS email_verified_at TIMESTAMPTZ;
+
+-- Any change of address (profile, admin or role endpoints) drops the verification
+CREATE OR REPLACE FUNCTION reset_email_verification()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.email IS DISTINCT FROM OLD.email THEN
+        NEW.email_verified_at = NULL;
+    END IF;
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+DROP TRIGGER IF EXISTS reset_users_email_verification ON users;
+CREATE TRIGGER reset_users_email_verification
+    BEFORE UPDATE OF email ON users
+    FOR EACH ROW
+    EXECUTE FUNCTION reset_email_verification();
+
+-- Confirmation links; a token only verifies the address it was sent to
+CREATE TABLE IF NOT EXISTS email_verification_tokens (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    email VARCHAR(255) NOT NULL,
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_email_verification_tokens_user ON email_verification_tokens(user_id, created_at DESC);
@@ -0,0 +1,12 @@
+-- Delivery channels per user and notification type (NotificationBody.type).
+-- The 'default' row applies to types without their own row; without any row
+-- notifications are stored in-app and pushed, but not emailed.
+CREATE TABLE IF NOT EXISTS notification_channel_preferences (
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    notification_type VARCHAR(50) NOT NULL,
+    in_app BOOLEAN NOT NULL DEFAULT TRUE,
+    push BOOLEAN NOT NULL DEFAULT TRUE,
+    email BOOLEAN NOT NULL DEFAULT FALSE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, notification_type)
+);
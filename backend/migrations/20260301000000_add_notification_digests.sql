@@ -0,0 +1,24 @@
+-- IANA timezone used for digests and other local-time schedules
+ALTER TABLE users ADD COLUMN IF NOT EXISTS timezone VARCHAR(64) NOT NULL DEFAULT 'Europe/Berlin';
+
+-- Opt-in daily or weekly summaries instead of individual push/email deliveries
+CREATE TABLE IF NOT EXISTS notification_digest_settings (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    frequency VARCHAR(10) NOT NULL DEFAULT 'off'
+        CHECK (frequency IN ('off', 'daily', 'weekly')),
+    send_hour SMALLINT NOT NULL DEFAULT 18 CHECK (send_hour BETWEEN 0 AND 23),
+    send_weekday SMALLINT NOT NULL DEFAULT 7 CHECK (send_weekday BETWEEN 1 AND 7), -- ISO, 1 = Monday
+    channel VARCHAR(10) NOT NULL DEFAULT 'email' CHECK (channel IN ('email', 'push')),
+    notification_types TEXT[] NOT NULL DEFAULT '{}', -- empty = every digestible type
+    last_sent_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_notification_digest_settings_active
+    ON notification_digest_settings(frequency) WHERE frequency <> 'off';
+
+-- Set once a notification has been summarised in a digest
+ALTER TABLE notifications ADD COLUMN IF NOT EXISTS digested_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_notifications_digest_pending
+    ON notifications(user_id, created_at) WHERE digested_at IS NULL;
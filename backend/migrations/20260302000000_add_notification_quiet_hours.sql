@@ -0,0 +1,11 @@
+-- Quiet hours in the user's local time (users.timezone); push is suppressed in
+-- this window while in-app notifications are still stored.
+-- A window whose start is after its end spans midnight (e.g. 22:00-07:00).
+CREATE TABLE IF NOT EXISTS notification_quiet_hours (
+    user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    start_time TIME NOT NULL DEFAULT '22:00',
+    end_time TIME NOT NULL DEFAULT '07:00',
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (start_time <> end_time)
+);
@@ -0,0 +1,34 @@
+-- Durable queue for push notifications, drained by the outbox worker with retries
+CREATE TABLE IF NOT EXISTS push_outbox (
+    id BIGSERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    notification_id INTEGER REFERENCES notifications(id) ON DELETE SET NULL,
+    body JSONB NOT NULL,
+    status VARCHAR(16) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'sending', 'delivered', 'failed', 'skipped')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    -- For 'sending' rows this is the end of the worker's lease
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_push_outbox_due
+    ON push_outbox(next_attempt_at) WHERE status IN ('pending', 'sending');
+CREATE INDEX IF NOT EXISTS idx_push_outbox_created_at ON push_outbox(created_at);
+
+-- Outcome per device token of each queued push
+CREATE TABLE IF NOT EXISTS push_deliveries (
+    id BIGSERIAL PRIMARY KEY,
+    outbox_id BIGINT NOT NULL REFERENCES push_outbox(id) ON DELETE CASCADE,
+    token TEXT NOT NULL,
+    status VARCHAR(16) NOT NULL
+        CHECK (status IN ('delivered', 'failed', 'rejected', 'unregistered')),
+    attempts INTEGER NOT NULL DEFAULT 1,
+    last_error TEXT,
+    delivered_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (outbox_id, token)
+);
+
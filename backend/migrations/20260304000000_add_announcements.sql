@@ -0,0 +1,65 @@
+-- Announcements broadcast by admins (school-wide) and teachers (to their own students and parents)
+CREATE TABLE IF NOT EXISTS announcements (
+    id SERIAL PRIMARY KEY,
+    created_by INTEGER REFERENCES users(id) ON DELETE SET NULL,
+    -- Teacher whose students, groups and parents bound the audience; NULL for admins
+    teacher_scope_id INTEGER REFERENCES teachers(user_id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    message TEXT NOT NULL,
+    image_url TEXT,
+    link_label TEXT,
+    link_route TEXT,
+    priority TEXT NOT NULL DEFAULT 'normal' CHECK (priority IN ('low', 'normal', 'high', 'urgent')),
+    -- Targeting as submitted; resolved to users when the announcement is sent
+    audience JSONB NOT NULL,
+    scheduled_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    status VARCHAR(16) NOT NULL DEFAULT 'scheduled'
+        CHECK (status IN ('scheduled', 'sending', 'sent', 'cancelled')),
+    -- When a scheduler started sending; a 'sending' row older than its lease is retried
+    claimed_at TIMESTAMPTZ,
+    sent_at TIMESTAMPTZ,
+    recipient_count INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_announcements_due
+    ON announcements(scheduled_at) WHERE status = 'scheduled';
+CREATE INDEX IF NOT EXISTS idx_announcements_created_by ON announcements(created_by);
+
+CREATE TRIGGER update_announcements_updated_at
+    BEFORE UPDATE ON announcements
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- Who an announcement reached; the notification links to read state and push delivery
+CREATE TABLE IF NOT EXISTS announcement_recipients (
+    announcement_id INTEGER NOT NULL REFERENCES announcements(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    notification_id INTEGER REFERENCES notifications(id) ON DELETE SET NULL,
+    -- FALSE when the recipient's preferences kept it out of the app
+    in_app BOOLEAN NOT NULL,
+    PRIMARY KEY (announcement_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_announcement_recipients_user ON announcement_recipients(user_id);
+CREATE INDEX IF NOT EXISTS idx_announcement_recipients_notification
+    ON announcement_recipients(notification_id);
+
+-- Users a teacher may address: their students, the students of their groups and the parents of both
+CREATE OR REPLACE FUNCTION teacher_audience(teacher_id INTEGER)
+RETURNS TABLE (user_id INTEGER)
+LANGUAGE sql STABLE AS $$
+    WITH own_students AS (
+        SELECT tsr.student_user_id FROM teacher_student_relations tsr
+        WHERE tsr.teacher_user_id = teacher_id
+        UNION
+        SELECT gsr.student_user_id FROM group_student_relations gsr
+        JOIN student_groups g ON g.id = gsr.group_id
+        WHERE g.teacher_user_id = teacher_id
+    )
+    SELECT student_user_id FROM own_students
+    UNION
+    SELECT psr.parent_user_id FROM parent_student_relations psr
+    WHERE psr.student_user_id IN (SELECT student_user_id FROM own_students)
+$$;
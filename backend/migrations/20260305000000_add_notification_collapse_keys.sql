@@ -0,0 +1,14 @@
+-- Notifications sharing a collapse key (e.g. one chat thread) update a single unread row;
+-- created_at then holds the time of the latest occurrence
+ALTER TABLE notifications
+    ADD COLUMN IF NOT EXISTS collapse_key TEXT,
+    ADD COLUMN IF NOT EXISTS collapsed_count INTEGER NOT NULL DEFAULT 1;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_notifications_user_collapse_key
+    ON notifications(user_id, collapse_key)
+    WHERE read_at IS NULL AND collapse_key IS NOT NULL;
+
+-- Lets a new push replace queued ones with the same key
+CREATE INDEX IF NOT EXISTS idx_push_outbox_collapse_key
+    ON push_outbox(user_id, (body->>'collapse_key'))
+    WHERE status = 'pending' AND body ? 'collapse_key';
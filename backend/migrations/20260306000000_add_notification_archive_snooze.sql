@@ -0,0 +1,9 @@
+-- Archived notifications leave the inbox but stay listable with ?archived=true;
+-- snoozed ones are hidden (and left out of the badge and digests) until snoozed_until
+ALTER TABLE notifications
+    ADD COLUMN IF NOT EXISTS archived_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS snoozed_until TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_notifications_user_inbox
+    ON notifications(user_id, created_at DESC)
+    WHERE archived_at IS NULL;
@@ -0,0 +1,43 @@
+-- Retention windows applied by the backend's retention worker. The most specific
+-- policy wins: type and priority, then type, then priority, then the default row.
+CREATE TABLE IF NOT EXISTS notification_retention_policies (
+    id SERIAL PRIMARY KEY,
+    -- NULL matches every type / priority
+    notification_type TEXT,
+    priority TEXT CHECK (priority IN ('low', 'normal', 'high', 'urgent')),
+    -- Days after being read
+    read_retention_days INTEGER NOT NULL CHECK (read_retention_days > 0),
+    -- Days after creation for notifications never read; NULL keeps them
+    unread_retention_days INTEGER CHECK (unread_retention_days > 0),
+    -- Days after archiving, after which archived notifications are deleted; NULL keeps
+    -- them. Archived notifications are exempt from the read and unread windows.
+    archived_retention_days INTEGER CHECK (archived_retention_days > 0),
+    -- What happens once the window has passed
+    expire_action VARCHAR(16) NOT NULL DEFAULT 'delete' CHECK (expire_action IN ('delete', 'archive')),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_notification_retention_policies_scope
+    ON notification_retention_policies (COALESCE(notification_type, ''), COALESCE(priority, ''));
+
+-- Same default as the former pg_cron job; important notifications are archived instead.
+-- What users archive themselves is kept for 90 days, important notifications for good.
+INSERT INTO notification_retention_policies
+    (notification_type, priority, read_retention_days, archived_retention_days, expire_action)
+VALUES (NULL, NULL, 2, 90, 'delete'),
+       (NULL, 'high', 7, NULL, 'archive'),
+       (NULL, 'urgent', 7, NULL, 'archive')
+ON CONFLICT DO NOTHING;
+
+CREATE INDEX IF NOT EXISTS idx_notifications_created_at ON notifications(created_at);
+
+-- Retention and the push outbox cleanup now run in the backend, with or without pg_cron
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM information_schema.tables WHERE table_schema = 'cron' AND table_name = 'job') THEN
+        PERFORM cron.unschedule(jobid)
+        FROM cron.job
+        WHERE jobname IN ('cleanup_read_notifications_older_than_2_days',
+                          'cleanup_finished_push_outbox_older_than_30_days');
+    END IF;
+END $$;
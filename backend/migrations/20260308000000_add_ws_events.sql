@@ -0,0 +1,15 @@
+-- Chat and comment events broadcast over WebSockets, kept for a while so a
+-- reconnecting client can replay what it missed. The id is the event's
+-- sequence number on the wire.
+CREATE TABLE IF NOT EXISTS ws_events (
+    id BIGSERIAL PRIMARY KEY,
+    thread_id INTEGER REFERENCES chat_threads(id) ON DELETE CASCADE,
+    post_id INTEGER REFERENCES feed_posts(id) ON DELETE CASCADE,
+    message JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK ((thread_id IS NULL) <> (post_id IS NULL))
+);
+
+CREATE INDEX IF NOT EXISTS idx_ws_events_thread ON ws_events(thread_id, id) WHERE thread_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_ws_events_post ON ws_events(post_id, id) WHERE post_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_ws_events_created_at ON ws_events(created_at);
@@ -0,0 +1,17 @@
+-- Backend replicas serving WebSockets, kept alive by a heartbeat, and the
+-- users connected to each. A user is online while any live replica has a
+-- session for them. Both tables only describe running processes, so they
+-- don't need to survive a crash.
+CREATE UNLOGGED TABLE IF NOT EXISTS ws_instances (
+    id TEXT PRIMARY KEY,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNLOGGED TABLE IF NOT EXISTS ws_presence (
+    instance_id TEXT NOT NULL REFERENCES ws_instances(id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL,
+    PRIMARY KEY (instance_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_ws_presence_user ON ws_presence(user_id);
@@ -0,0 +1,4 @@
+-- Logged events are now stored as version 2 protocol messages. The ones in
+-- the old envelope are dropped; clients resuming from before this point are
+-- told their replay is incomplete and reload over REST.
+DELETE FROM ws_events;
@@ -0,0 +1,10 @@
+-- Data exports started by each user, to allow one at a time and a few a day
+CREATE TABLE IF NOT EXISTS data_exports (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_data_exports_user ON data_exports(user_id, started_at DESC);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_data_exports_running ON data_exports(user_id) WHERE finished_at IS NULL;
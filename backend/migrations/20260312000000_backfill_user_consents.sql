@@ -0,0 +1,6 @@
+-- Registration already required accepting version 1 in the app, so existing
+-- accounts start with it recorded instead of all being locked out until they
+-- accept it again. Only versions published later have to be re-accepted.
+INSERT INTO user_consents (user_id, version, locale, accepted_at)
+SELECT id, 1, 'en', created_at FROM users
+ON CONFLICT (user_id, version) DO NOTHING;
@@ -0,0 +1,11 @@
+-- Frames for one user's sessions, such as notifications, relayed to the
+-- other replicas by id because NOTIFY payloads are limited in size. Rows
+-- are only read right after they are written and are pruned soon after.
+CREATE UNLOGGED TABLE IF NOT EXISTS ws_user_events (
+    id BIGSERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    message JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_ws_user_events_created_at ON ws_user_events(created_at);
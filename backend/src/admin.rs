@@ -6,6 +6,8 @@ use log::{debug, error};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
+use crate::audit::{self, AuditEvent};
+use crate::email_verification;
 use crate::password_reset;
 use crate::users::verify_token;
 use crate::AppState;
@@ -16,6 +18,7 @@ pub struct UserResponse {
     pub username: String,
     pub full_name: String,
     pub email: Option<String>,
+    pub email_verified: bool,
     pub phone: Option<String>,
     #[sqlx(skip)]
     pub roles: Vec<String>,
@@ -129,7 +132,7 @@ async fn get_users(
     let users_result = if let Some(search) = &search {
         let pattern = format!("%{}%", search);
         sqlx::query_as::<_, UserResponse>(
-            "SELECT id, username, full_name, email, phone FROM users \
+            "SELECT id, username, full_name, email, email_verified_at IS NOT NULL AS email_verified, phone FROM users \
              WHERE username ILIKE $1 OR full_name ILIKE $1 \
              ORDER BY username LIMIT $2 OFFSET $3",
         )
@@ -140,7 +143,7 @@ async fn get_users(
         .await
     } else {
         sqlx::query_as::<_, UserResponse>(
-            "SELECT id, username, full_name, email, phone FROM users \
+            "SELECT id, username, full_name, email, email_verified_at IS NOT NULL AS email_verified, phone FROM users \
              ORDER BY username LIMIT $1 OFFSET $2",
         )
         .bind(page_size)
@@ -226,7 +229,7 @@ async fn get_user_by_id(
 
     let user_id = user_id.into_inner();
     let user_result = sqlx::query_as::<_, UserResponse>(
-        "SELECT id, username, full_name, email, phone FROM users WHERE id = $1",
+        "SELECT id, username, full_name, email, email_verified_at IS NOT NULL AS email_verified, phone FROM users WHERE id = $1",
     )
     .bind(user_id)
     .fetch_optional(&app_state.db)
@@ -355,6 +358,21 @@ async fn create_user(
         }
     }
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("user.create", "user", Some(user_id))
+            .after(audit::user_snapshot(&app_state.db, user_id).await),
+    )
+    .await;
+
+    email_verification::request_verification_after_change(
+        &app_state.db,
+        user_id,
+        app_state.email_service.clone(),
+    )
+    .await;
+
     HttpResponse::Ok().json(serde_json::json!({
         "id": user_id,
         "username": user_data.username,
@@ -374,6 +392,7 @@ async fn update_user(
     }
 
     let user_id = user_id.into_inner();
+    let before = audit::user_snapshot(&app_state.db, user_id).await;
 
     // Update username if provided
     if let Some(username) = &user_data.username {
@@ -429,6 +448,13 @@ async fn update_user(
                 "error": "Failed to update email"
             }));
         }
+
+        email_verification::request_verification_after_change(
+            &app_state.db,
+            user_id,
+            app_state.email_service.clone(),
+        )
+        .await;
     }
 
     // Update phone if provided
@@ -485,6 +511,15 @@ async fn update_user(
         }
     }
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("user.update", "user", Some(user_id))
+            .before(before)
+            .after(audit::user_snapshot(&app_state.db, user_id).await),
+    )
+    .await;
+
     HttpResponse::Ok().json(serde_json::json!({
         "message": "User updated successfully"
     }))
@@ -501,6 +536,7 @@ async fn delete_user(
     }
 
     let user_id = user_id.into_inner();
+    let before = audit::user_snapshot(&app_state.db, user_id).await;
 
     let result = sqlx::query("DELETE FROM users WHERE id = $1")
         .bind(user_id)
@@ -508,9 +544,18 @@ async fn delete_user(
         .await;
 
     match result {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-            "message": "User deleted successfully"
-        })),
+        Ok(_) => {
+            audit::record(
+                &app_state,
+                &req,
+                AuditEvent::new("user.delete", "user", Some(user_id)).before(before),
+            )
+            .await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "User deleted successfully"
+            }))
+        }
         Err(e) => {
             error!("Database error: {}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
@@ -567,6 +612,13 @@ async fn generate_reset_link(
             let reset_url_base = format!("{}/reset-password", api_base_url.trim_end_matches('/'));
             let reset_link = format!("{}/{}", reset_url_base, token);
 
+            audit::record(
+                &app_state,
+                &req,
+                AuditEvent::new("user.reset_link_generated", "user", Some(user_id)),
+            )
+            .await;
+
             HttpResponse::Ok().json(GenerateResetLinkResponse {
                 reset_link,
                 expires_at: "1 hour".to_string(),
@@ -653,9 +705,22 @@ async fn resolve_password_reset_request(
     };
 
     match password_reset::resolve_request(&app_state.db, *request_id, admin_id).await {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-            "message": "Password reset request resolved"
-        })),
+        Ok(_) => {
+            audit::record(
+                &app_state,
+                &req,
+                AuditEvent::new(
+                    "password_reset_request.resolve",
+                    "password_reset_request",
+                    Some(*request_id),
+                ),
+            )
+            .await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "Password reset request resolved"
+            }))
+        }
         Err(e) => {
             error!("Failed to resolve password reset request: {}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
@@ -691,6 +756,7 @@ async fn make_student(
     }
 
     let user_id = user_id.into_inner();
+    let before = audit::user_snapshot(&app_state.db, user_id).await;
 
     // Parse birthday
     let birthday = match NaiveDate::parse_from_str(&student_data.birthday, "%Y-%m-%d") {
@@ -805,6 +871,15 @@ async fn make_student(
         }));
     }
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("user.make_student", "user", Some(user_id))
+            .before(before)
+            .after(audit::user_snapshot(&app_state.db, user_id).await),
+    )
+    .await;
+
     HttpResponse::Ok().json(serde_json::json!({
         "message": "User converted to student successfully"
     }))
@@ -823,6 +898,7 @@ async fn make_parent(
     }
 
     let user_id = user_id.into_inner();
+    let before = audit::user_snapshot(&app_state.db, user_id).await;
 
     // Validate at least one student
     if parent_data.student_ids.is_empty() {
@@ -971,6 +1047,15 @@ async fn make_parent(
         }));
     }
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("user.make_parent", "user", Some(user_id))
+            .before(before)
+            .after(audit::user_snapshot(&app_state.db, user_id).await),
+    )
+    .await;
+
     HttpResponse::Ok().json(serde_json::json!({
         "message": "User converted to parent successfully"
     }))
@@ -989,6 +1074,7 @@ async fn make_teacher(
     }
 
     let user_id = user_id.into_inner();
+    let before = audit::user_snapshot(&app_state.db, user_id).await;
 
     // Start transaction
     let mut tx = match app_state.db.begin().await {
@@ -1126,6 +1212,15 @@ async fn make_teacher(
         }));
     }
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("user.make_teacher", "user", Some(user_id))
+            .before(before)
+            .after(audit::user_snapshot(&app_state.db, user_id).await),
+    )
+    .await;
+
     HttpResponse::Ok().json(serde_json::json!({
         "message": "User converted to teacher successfully"
     }))
@@ -0,0 +1,726 @@
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{FromRow, PgPool};
+use std::time::Duration;
+
+use crate::audit::{self, AuditEvent};
+use crate::notification_builders::build_announcement_notification;
+use crate::users::{verify_token, Claims};
+use crate::AppState;
+
+/// How often the scheduler looks for announcements that are due
+const CHECK_INTERVAL_SECS: u64 = 60;
+/// How long a claimed announcement is reserved for the scheduler sending it
+const LEASE_MINUTES: i32 = 10;
+const PRIORITIES: [&str; 4] = ["low", "normal", "high", "urgent"];
+const ROLES: [&str; 4] = ["admin", "teacher", "parent", "student"];
+
+/// Who receives an announcement. Every list adds recipients; for teachers the
+/// result is limited to their own students, their groups' students and the
+/// parents of both.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Audience {
+    /// Everyone with one of these roles
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// Students of these groups
+    #[serde(default)]
+    pub group_ids: Vec<i32>,
+    /// Also the parents of the students in `group_ids`
+    #[serde(default)]
+    pub include_parents: bool,
+    #[serde(default)]
+    pub user_ids: Vec<i32>,
+    /// Parents of the students of these teachers
+    #[serde(default)]
+    pub parents_of_teacher_ids: Vec<i32>,
+}
+
+impl Audience {
+    fn is_empty(&self) -> bool {
+        self.roles.is_empty()
+            && self.group_ids.is_empty()
+            && self.user_ids.is_empty()
+            && self.parents_of_teacher_ids.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize, FromRow)]
+struct Announcement {
+    id: i32,
+    created_by: Option<i32>,
+    teacher_scope_id: Option<i32>,
+    title: String,
+    message: String,
+    image_url: Option<String>,
+    link_label: Option<String>,
+    link_route: Option<String>,
+    priority: String,
+    audience: Json<Audience>,
+    scheduled_at: DateTime<Utc>,
+    status: String,
+    sent_at: Option<DateTime<Utc>>,
+    recipient_count: i32,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+struct AnnouncementSummary {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    announcement: Announcement,
+    in_app_count: i64,
+    read_count: i64,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+struct RecipientReport {
+    user_id: i32,
+    username: String,
+    full_name: String,
+    in_app: bool,
+    notification_id: Option<i32>,
+    read_at: Option<DateTime<Utc>>,
+    /// Stored in the app but deleted by the recipient since
+    deleted: bool,
+    /// Status of the queued push (`pending`, `delivered`, `failed`, `skipped`), if any
+    push_status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateAnnouncementRequest {
+    title: String,
+    message: String,
+    image_url: Option<String>,
+    link_label: Option<String>,
+    link_route: Option<String>,
+    priority: Option<String>,
+    audience: Audience,
+    /// Send later; omitted or in the past sends right away
+    scheduled_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListAnnouncementsQuery {
+    status: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+const ANNOUNCEMENT_COLUMNS: &str = "a.id, a.created_by, a.teacher_scope_id, a.title, a.message,
+    a.image_url, a.link_label, a.link_route, a.priority, a.audience, a.scheduled_at, a.status,
+    a.sent_at, a.recipient_count, a.created_at";
+
+fn error_response(status: actix_web::http::StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(serde_json::json!({ "error": message }))
+}
+
+fn database_error(context: &str, e: sqlx::Error) -> HttpResponse {
+    error!("Database error {}: {:?}", context, e);
+    HttpResponse::InternalServerError().json(serde_json::json!({
+        "error": "Internal server error"
+    }))
+}
+
+/// The announcement author and the teacher scope (`None` for admins).
+/// Impersonated sessions cannot broadcast.
+async fn verify_sender(
+    req: &HttpRequest,
+    app_state: &AppState,
+) -> Result<(Claims, i32, Option<i32>), HttpResponse> {
+    let claims = verify_token(req, app_state)?;
+    let is_admin = claims.roles.contains(&"admin".to_string());
+    let is_teacher = claims.roles.contains(&"teacher".to_string());
+    if claims.impersonation.is_some() || !(is_admin || is_teacher) {
+        return Err(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Admin or teacher access required"
+        })));
+    }
+
+    let user_id = sqlx::query_scalar::<_, i32>("SELECT id FROM users WHERE username = $1")
+        .bind(&claims.sub)
+        .fetch_optional(&app_state.db)
+        .await
+        .map_err(|e| database_error("resolving announcement sender", e))?
+        .ok_or_else(|| {
+            HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "User not found"
+            }))
+        })?;
+
+    let scope = if is_admin { None } else { Some(user_id) };
+    Ok((claims, user_id, scope))
+}
+
+/// Reject targets outside a teacher's own students, groups and parents
+async fn check_teacher_audience(
+    db: &PgPool,
+    teacher_id: i32,
+    audience: &Audience,
+) -> Result<(), HttpResponse> {
+    use actix_web::http::StatusCode;
+
+    if audience
+        .roles
+        .iter()
+        .any(|role| role != "student" && role != "parent")
+    {
+        return Err(error_response(
+            StatusCode::FORBIDDEN,
+            "Teachers can only address students and parents",
+        ));
+    }
+    if audience
+        .parents_of_teacher_ids
+        .iter()
+        .any(|id| *id != teacher_id)
+    {
+        return Err(error_response(
+            StatusCode::FORBIDDEN,
+            "Teachers can only address the parents of their own students",
+        ));
+    }
+
+    let own_groups = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM student_groups WHERE id = ANY($1) AND teacher_user_id = $2",
+    )
+    .bind(&audience.group_ids)
+    .bind(teacher_id)
+    .fetch_one(db)
+    .await
+    .map_err(|e| database_error("checking announcement groups", e))?;
+    let mut group_ids = audience.group_ids.clone();
+    group_ids.sort_unstable();
+    group_ids.dedup();
+    if own_groups as usize != group_ids.len() {
+        return Err(error_response(
+            StatusCode::FORBIDDEN,
+            "Teachers can only address their own groups",
+        ));
+    }
+
+    let outside = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM UNNEST($1::int[]) AS t(user_id)
+         WHERE t.user_id NOT IN (SELECT user_id FROM teacher_audience($2))",
+    )
+    .bind(&audience.user_ids)
+    .bind(teacher_id)
+    .fetch_one(db)
+    .await
+    .map_err(|e| database_error("checking announcement recipients", e))?;
+    if outside > 0 {
+        return Err(error_response(
+            StatusCode::FORBIDDEN,
+            "Teachers can only address their own students and their parents",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Active users targeted by `audience`, without the sender
+async fn resolve_audience(
+    db: &PgPool,
+    audience: &Audience,
+    teacher_scope_id: Option<i32>,
+    sender_id: Option<i32>,
+) -> Result<Vec<i32>, sqlx::Error> {
+    sqlx::query_scalar::<_, i32>(
+        "WITH targets AS (
+             SELECT ur.user_id FROM user_roles ur JOIN roles r ON r.id = ur.role_id
+             WHERE r.name = ANY($1)
+             UNION
+             SELECT gsr.student_user_id FROM group_student_relations gsr
+             JOIN student_groups g ON g.id = gsr.group_id
+             WHERE gsr.group_id = ANY($2) AND g.status = 'active'
+             UNION
+             SELECT psr.parent_user_id FROM parent_student_relations psr
+             JOIN group_student_relations gsr ON gsr.student_user_id = psr.student_user_id
+             JOIN student_groups g ON g.id = gsr.group_id
+             WHERE $3 AND gsr.group_id = ANY($2) AND g.status = 'active'
+             UNION
+             SELECT id FROM users WHERE id = ANY($4)
+             UNION
+             SELECT psr.parent_user_id FROM parent_student_relations psr
+             JOIN teacher_student_relations tsr ON tsr.student_user_id = psr.student_user_id
+             WHERE tsr.teacher_user_id = ANY($5)
+         )
+         SELECT t.user_id FROM targets t
+         WHERE ($6::int IS NULL OR t.user_id IN (SELECT user_id FROM teacher_audience($6)))
+           AND t.user_id IS DISTINCT FROM $7
+           AND (EXISTS(SELECT 1 FROM user_roles ur JOIN roles r ON ur.role_id = r.id
+                       WHERE ur.user_id = t.user_id AND r.name = 'admin')
+                OR EXISTS(SELECT 1 FROM students s WHERE s.user_id = t.user_id AND s.status = 'active')
+                OR EXISTS(SELECT 1 FROM parents p WHERE p.user_id = t.user_id AND p.status = 'active')
+                OR EXISTS(SELECT 1 FROM teachers te WHERE te.user_id = t.user_id AND te.status = 'active'))
+         ORDER BY t.user_id",
+    )
+    .bind(&audience.roles)
+    .bind(&audience.group_ids)
+    .bind(audience.include_parents)
+    .bind(&audience.user_ids)
+    .bind(&audience.parents_of_teacher_ids)
+    .bind(teacher_scope_id)
+    .bind(sender_id)
+    .fetch_all(db)
+    .await
+}
+
+/// Resolve the audience, notify everyone and record who received what
+async fn deliver(app_state: &AppState, announcement: &Announcement) {
+    let db = &app_state.db;
+    let recipients = match resolve_audience(
+        db,
+        &announcement.audience,
+        announcement.teacher_scope_id,
+        announcement.created_by,
+    )
+    .await
+    {
+        Ok(recipients) => recipients,
+        Err(e) => {
+            error!(
+                "Database error resolving audience of announcement {}: {:?}",
+                announcement.id, e
+            );
+            // Try again on the next scheduler round
+            let _ = sqlx::query("UPDATE announcements SET status = 'scheduled' WHERE id = $1")
+                .bind(announcement.id)
+                .execute(db)
+                .await;
+            return;
+        }
+    };
+    // Recipients recorded by an earlier attempt that didn't finish were notified already
+    let recipients = match sqlx::query_scalar::<_, i32>(
+        "SELECT r.user_id FROM UNNEST($2::int[]) AS r(user_id)
+         WHERE NOT EXISTS (
+             SELECT 1 FROM announcement_recipients ar
+             WHERE ar.announcement_id = $1 AND ar.user_id = r.user_id
+         )",
+    )
+    .bind(announcement.id)
+    .bind(&recipients)
+    .fetch_all(db)
+    .await
+    {
+        Ok(remaining) => remaining,
+        Err(e) => {
+            error!(
+                "Database error loading recipients of announcement {}: {:?}",
+                announcement.id, e
+            );
+            let _ = sqlx::query("UPDATE announcements SET status = 'scheduled' WHERE id = $1")
+                .bind(announcement.id)
+                .execute(db)
+                .await;
+            return;
+        }
+    };
+    debug!(
+        "Sending announcement {} to {} recipients",
+        announcement.id,
+        recipients.len()
+    );
+
+    let mut body = build_announcement_notification(
+        &announcement.title,
+        &announcement.message,
+        announcement.image_url.as_deref(),
+        announcement.link_label.as_deref(),
+        announcement.link_route.as_deref(),
+    );
+    body.metadata = Some(serde_json::json!({ "announcement_id": announcement.id }));
+
+    let stored = app_state
+        .notifications
+        .notify_many(&recipients, |_| body.clone(), &announcement.priority)
+        .await;
+
+    let stored_users: Vec<i32> = stored.iter().map(|n| n.user_id).collect();
+    let stored_ids: Vec<i32> = stored.iter().map(|n| n.id).collect();
+    if let Err(e) = sqlx::query(
+        "INSERT INTO announcement_recipients (announcement_id, user_id, notification_id, in_app)
+         SELECT $1, r.user_id, n.notification_id, n.notification_id IS NOT NULL
+         FROM UNNEST($2::int[]) AS r(user_id)
+         LEFT JOIN UNNEST($3::int[], $4::int[]) AS n(user_id, notification_id)
+           ON n.user_id = r.user_id
+         ON CONFLICT (announcement_id, user_id) DO NOTHING",
+    )
+    .bind(announcement.id)
+    .bind(&recipients)
+    .bind(&stored_users)
+    .bind(&stored_ids)
+    .execute(db)
+    .await
+    {
+        error!(
+            "Database error recording recipients of announcement {}: {:?}",
+            announcement.id, e
+        );
+    }
+
+    if let Err(e) = sqlx::query(
+        "UPDATE announcements SET status = 'sent', sent_at = NOW(),
+             recipient_count = (SELECT COUNT(*) FROM announcement_recipients WHERE announcement_id = $1)
+         WHERE id = $1",
+    )
+    .bind(announcement.id)
+    .execute(db)
+    .await
+    {
+        error!(
+            "Database error finishing announcement {}: {:?}",
+            announcement.id, e
+        );
+    }
+
+    info!(
+        "Announcement {} sent to {} recipients ({} in app)",
+        announcement.id,
+        recipients.len(),
+        stored.len()
+    );
+}
+
+/// Reserve due announcements so concurrent schedulers do not send them twice.
+/// A crashed scheduler's reservations expire with the lease.
+async fn claim_due(db: &PgPool) -> Result<Vec<Announcement>, sqlx::Error> {
+    sqlx::query_as::<_, Announcement>(&format!(
+        "UPDATE announcements a SET status = 'sending', claimed_at = NOW()
+         WHERE a.id IN (
+             SELECT id FROM announcements
+             WHERE (status = 'scheduled' AND scheduled_at <= NOW())
+                OR (status = 'sending' AND claimed_at <= NOW() - make_interval(mins => $1))
+             ORDER BY scheduled_at
+             FOR UPDATE SKIP LOCKED
+         )
+         RETURNING {}",
+        ANNOUNCEMENT_COLUMNS
+    ))
+    .bind(LEASE_MINUTES)
+    .fetch_all(db)
+    .await
+}
+
+pub async fn send_due_announcements(app_state: &AppState) {
+    let due = match claim_due(&app_state.db).await {
+        Ok(due) => due,
+        Err(e) => {
+            error!("Database error claiming due announcements: {:?}", e);
+            return;
+        }
+    };
+
+    for announcement in &due {
+        deliver(app_state, announcement).await;
+    }
+}
+
+/// Background loop sending scheduled announcements once they are due
+pub async fn run_scheduler(app_state: web::Data<AppState>) {
+    let mut interval = actix_web::rt::time::interval(Duration::from_secs(CHECK_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        send_due_announcements(&app_state).await;
+    }
+}
+
+async fn announcement_snapshot(db: &PgPool, id: i32) -> Option<serde_json::Value> {
+    sqlx::query_scalar::<_, serde_json::Value>(
+        "SELECT to_jsonb(a) FROM announcements a WHERE a.id = $1",
+    )
+    .bind(id)
+    .fetch_optional(db)
+    .await
+    .unwrap_or_else(|e| {
+        error!("Failed to snapshot announcement {} for audit: {}", id, e);
+        None
+    })
+}
+
+/// Create an announcement; it is sent right away unless scheduled for later
+#[post("/api/announcements")]
+async fn create_announcement(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    payload: web::Json<CreateAnnouncementRequest>,
+) -> impl Responder {
+    use actix_web::http::StatusCode;
+
+    let (_, sender_id, teacher_scope_id) = match verify_sender(&req, &app_state).await {
+        Ok(sender) => sender,
+        Err(response) => return response,
+    };
+    let payload = payload.into_inner();
+
+    let title = payload.title.trim();
+    let message = payload.message.trim();
+    if title.is_empty() || message.is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "Title and message are required");
+    }
+    let priority = payload.priority.unwrap_or_else(|| "normal".to_string());
+    if !PRIORITIES.contains(&priority.as_str()) {
+        return error_response(StatusCode::BAD_REQUEST, "Invalid priority");
+    }
+    if payload.link_label.is_some() != payload.link_route.is_some() {
+        return error_response(
+            StatusCode::BAD_REQUEST,
+            "link_label and link_route must be given together",
+        );
+    }
+    let audience = payload.audience;
+    if audience.is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "Audience is empty");
+    }
+    if let Some(role) = audience
+        .roles
+        .iter()
+        .find(|role| !ROLES.contains(&role.as_str()))
+    {
+        return error_response(StatusCode::BAD_REQUEST, &format!("Unknown role: {}", role));
+    }
+    if let Some(teacher_id) = teacher_scope_id {
+        if let Err(response) = check_teacher_audience(&app_state.db, teacher_id, &audience).await
+        {
+            return response;
+        }
+    }
+
+    let now = Utc::now();
+    let scheduled_at = payload.scheduled_at.filter(|at| *at > now);
+    let status = if scheduled_at.is_some() {
+        "scheduled"
+    } else {
+        "sending"
+    };
+
+    let announcement = match sqlx::query_as::<_, Announcement>(&format!(
+        "INSERT INTO announcements AS a
+             (created_by, teacher_scope_id, title, message, image_url, link_label, link_route,
+              priority, audience, scheduled_at, status, claimed_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+         RETURNING {}",
+        ANNOUNCEMENT_COLUMNS
+    ))
+    .bind(sender_id)
+    .bind(teacher_scope_id)
+    .bind(title)
+    .bind(message)
+    .bind(&payload.image_url)
+    .bind(&payload.link_label)
+    .bind(&payload.link_route)
+    .bind(&priority)
+    .bind(Json(&audience))
+    .bind(scheduled_at.unwrap_or(now))
+    .bind(status)
+    // Sent right away by this request
+    .bind(scheduled_at.is_none().then_some(now))
+    .fetch_one(&app_state.db)
+    .await
+    {
+        Ok(announcement) => announcement,
+        Err(e) => return database_error("creating announcement", e),
+    };
+
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("announcement.create", "announcement", Some(announcement.id))
+            .after(announcement_snapshot(&app_state.db, announcement.id).await),
+    )
+    .await;
+
+    if scheduled_at.is_some() {
+        return HttpResponse::Created().json(announcement);
+    }
+
+    deliver(&app_state, &announcement).await;
+    match load_announcement(&app_state.db, announcement.id).await {
+        Ok(Some(announcement)) => HttpResponse::Created().json(announcement),
+        Ok(None) => error_response(StatusCode::NOT_FOUND, "Announcement not found"),
+        Err(e) => database_error("loading announcement", e),
+    }
+}
+
+async fn load_announcement(db: &PgPool, id: i32) -> Result<Option<Announcement>, sqlx::Error> {
+    sqlx::query_as::<_, Announcement>(&format!(
+        "SELECT {} FROM announcements a WHERE a.id = $1",
+        ANNOUNCEMENT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(db)
+    .await
+}
+
+/// Announcement visible to the sender: admins see all, teachers their own
+async fn load_for_sender(
+    req: &HttpRequest,
+    app_state: &AppState,
+    id: i32,
+) -> Result<Announcement, HttpResponse> {
+    use actix_web::http::StatusCode;
+
+    let (_, sender_id, teacher_scope_id) = verify_sender(req, app_state).await?;
+    let announcement = load_announcement(&app_state.db, id)
+        .await
+        .map_err(|e| database_error("loading announcement", e))?
+        .ok_or_else(|| error_response(StatusCode::NOT_FOUND, "Announcement not found"))?;
+
+    if teacher_scope_id.is_some() && announcement.created_by != Some(sender_id) {
+        return Err(error_response(StatusCode::NOT_FOUND, "Announcement not found"));
+    }
+    Ok(announcement)
+}
+
+/// Announcements with read statistics; teachers see their own
+#[get("/api/announcements")]
+async fn list_announcements(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<ListAnnouncementsQuery>,
+) -> impl Responder {
+    let (_, sender_id, teacher_scope_id) = match verify_sender(&req, &app_state).await {
+        Ok(sender) => sender,
+        Err(response) => return response,
+    };
+
+    let limit = query.limit.unwrap_or(50).clamp(1, 200);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let announcements = sqlx::query_as::<_, AnnouncementSummary>(&format!(
+        "SELECT {},
+                (SELECT COUNT(*) FROM announcement_recipients ar
+                 WHERE ar.announcement_id = a.id AND ar.in_app) AS in_app_count,
+                (SELECT COUNT(*) FROM announcement_recipients ar
+                 JOIN notifications n ON n.id = ar.notification_id
+                 WHERE ar.announcement_id = a.id AND n.read_at IS NOT NULL) AS read_count
+         FROM announcements a
+         WHERE ($1::int IS NULL OR a.created_by = $1)
+           AND ($2::text IS NULL OR a.status = $2)
+         ORDER BY a.scheduled_at DESC
+         LIMIT $3 OFFSET $4",
+        ANNOUNCEMENT_COLUMNS
+    ))
+    .bind(teacher_scope_id.map(|_| sender_id))
+    .bind(&query.status)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&app_state.db)
+    .await;
+
+    match announcements {
+        Ok(announcements) => HttpResponse::Ok().json(announcements),
+        Err(e) => database_error("listing announcements", e),
+    }
+}
+
+/// Delivery and read state of an announcement per recipient
+#[get("/api/announcements/{id}/report")]
+async fn announcement_report(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+) -> impl Responder {
+    let announcement = match load_for_sender(&req, &app_state, path.into_inner()).await {
+        Ok(announcement) => announcement,
+        Err(response) => return response,
+    };
+
+    let recipients = sqlx::query_as::<_, RecipientReport>(
+        "SELECT u.id AS user_id, u.username, u.full_name, ar.in_app, ar.notification_id,
+                n.read_at,
+                (ar.in_app AND ar.notification_id IS NULL) AS deleted,
+                (SELECT po.status FROM push_outbox po
+                 WHERE po.notification_id = ar.notification_id
+                 ORDER BY po.id DESC LIMIT 1) AS push_status
+         FROM announcement_recipients ar
+         JOIN users u ON u.id = ar.user_id
+         LEFT JOIN notifications n ON n.id = ar.notification_id
+         WHERE ar.announcement_id = $1
+         ORDER BY u.full_name, u.username",
+    )
+    .bind(announcement.id)
+    .fetch_all(&app_state.db)
+    .await;
+
+    let recipients = match recipients {
+        Ok(recipients) => recipients,
+        Err(e) => return database_error("loading announcement report", e),
+    };
+
+    let in_app = recipients.iter().filter(|r| r.in_app).count();
+    let read = recipients.iter().filter(|r| r.read_at.is_some()).count();
+    let push_delivered = recipients
+        .iter()
+        .filter(|r| r.push_status.as_deref() == Some("delivered"))
+        .count();
+    let push_failed = recipients
+        .iter()
+        .filter(|r| r.push_status.as_deref() == Some("failed"))
+        .count();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "announcement": announcement,
+        "summary": {
+            "recipients": recipients.len(),
+            "in_app": in_app,
+            "read": read,
+            "push_delivered": push_delivered,
+            "push_failed": push_failed,
+        },
+        "recipients": recipients,
+    }))
+}
+
+/// Cancel an announcement that has not been sent yet
+#[delete("/api/announcements/{id}")]
+async fn cancel_announcement(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+) -> impl Responder {
+    use actix_web::http::StatusCode;
+
+    let announcement = match load_for_sender(&req, &app_state, path.into_inner()).await {
+        Ok(announcement) => announcement,
+        Err(response) => return response,
+    };
+
+    let before = announcement_snapshot(&app_state.db, announcement.id).await;
+    let result = sqlx::query(
+        "UPDATE announcements SET status = 'cancelled' WHERE id = $1 AND status = 'scheduled'",
+    )
+    .bind(announcement.id)
+    .execute(&app_state.db)
+    .await;
+
+    match result {
+        Ok(result) if result.rows_affected() == 0 => error_response(
+            StatusCode::CONFLICT,
+            "Only scheduled announcements can be cancelled",
+        ),
+        Ok(_) => {
+            audit::record(
+                &app_state,
+                &req,
+                AuditEvent::new("announcement.cancel", "announcement", Some(announcement.id))
+                    .before(before)
+                    .after(announcement_snapshot(&app_state.db, announcement.id).await),
+            )
+            .await;
+            HttpResponse::Ok().json(serde_json::json!({ "cancelled": true }))
+        }
+        Err(e) => database_error("cancelling announcement", e),
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_announcement)
+        .service(list_announcements)
+        .service(announcement_report)
+        .service(cancel_announcement);
+}
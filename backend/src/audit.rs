@@ -0,0 +1,235 @@
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+
+use crate::users::verify_token;
+use crate::AppState;
+
+/// A single auditable action, recorded with `record`.
+#[derive(Debug)]
+pub struct AuditEvent {
+    pub action: &'static str,      // e.g. "user.delete", "parent.archive"
+    pub entity_type: &'static str, // e.g. "user", "feed_post"
+    pub entity_id: Option<i32>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+impl AuditEvent {
+    pub fn new(action: &'static str, entity_type: &'static str, entity_id: Option<i32>) -> Self {
+        AuditEvent {
+            action,
+            entity_type,
+            entity_id,
+            before: None,
+            after: None,
+        }
+    }
+
+    pub fn before(mut self, snapshot: Option<serde_json::Value>) -> Self {
+        self.before = snapshot;
+        self
+    }
+
+    pub fn after(mut self, snapshot: Option<serde_json::Value>) -> Self {
+        self.after = snapshot;
+        self
+    }
+}
+
+/// Record an action performed by the user authenticated on `req`.
+/// Failures are logged and never abort the action being audited.
+pub async fn record(app_state: &AppState, req: &HttpRequest, event: AuditEvent) {
+    let claims = verify_token(req, app_state).ok();
+    let actor_username = claims.as_ref().map(|c| c.sub.clone());
+    let impersonation_session_id = claims
+        .as_ref()
+        .and_then(|c| c.impersonation.as_ref())
+        .map(|i| i.session_id);
+    let ip = req.connection_info().realip_remote_addr().map(str::to_string);
+
+    let result = sqlx::query(
+        "INSERT INTO audit_log
+            (actor_user_id, actor_username, impersonation_session_id, action, entity_type,
+             entity_id, before_data, after_data, ip_address)
+         VALUES ((SELECT id FROM users WHERE username = $1), $1, $2, $3, $4, $5, $6, $7, $8)",
+    )
+    .bind(&actor_username)
+    .bind(impersonation_session_id)
+    .bind(event.action)
+    .bind(event.entity_type)
+    .bind(event.entity_id)
+    .bind(&event.before)
+    .bind(&event.after)
+    .bind(ip)
+    .execute(&app_state.db)
+    .await;
+
+    if let Err(e) = result {
+        error!("Failed to write audit log entry {}: {}", event.action, e);
+    }
+}
+
+/// Snapshot of a user's account and role state, without credentials.
+pub async fn user_snapshot(db: &PgPool, user_id: i32) -> Option<serde_json::Value> {
+    sqlx::query_scalar::<_, serde_json::Value>(
+        "SELECT jsonb_build_object(
+            'id', u.id,
+            'username', u.username,
+            'full_name', u.full_name,
+            'email', u.email,
+            'phone', u.phone,
+            'roles', COALESCE((SELECT jsonb_agg(r.name ORDER BY r.name)
+                               FROM user_roles ur JOIN roles r ON r.id = ur.role_id
+                               WHERE ur.user_id = u.id), '[]'::jsonb),
+            'student_status', (SELECT status::text FROM students WHERE user_id = u.id),
+            'parent_status', (SELECT status::text FROM parents WHERE user_id = u.id),
+            'teacher_status', (SELECT status::text FROM teachers WHERE user_id = u.id)
+         )
+         FROM users u WHERE u.id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(db)
+    .await
+    .unwrap_or_else(|e| {
+        error!("Failed to snapshot user {} for audit: {}", user_id, e);
+        None
+    })
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct AuditLogEntry {
+    pub id: i64,
+    pub actor_user_id: Option<i32>,
+    pub actor_username: Option<String>,
+    pub impersonation_session_id: Option<i32>,
+    pub action: String,
+    pub entity_type: String,
+    pub entity_id: Option<i32>,
+    pub before_data: Option<serde_json::Value>,
+    pub after_data: Option<serde_json::Value>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditLogQuery {
+    pub actor_user_id: Option<i32>,
+    pub action: Option<String>, // exact action, or a prefix ending in '.' such as "user."
+    pub entity_type: Option<String>,
+    pub entity_id: Option<i32>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub page: Option<i64>,
+    pub page_size: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditLogPageResponse {
+    pub entries: Vec<AuditLogEntry>,
+    pub total: i64,
+    pub page: i64,
+    pub page_size: i64,
+}
+
+const FILTER_CLAUSE: &str = "($1::INT IS NULL OR actor_user_id = $1)
+    AND ($2::TEXT IS NULL OR action = $2 OR (RIGHT($2, 1) = '.' AND action LIKE $2 || '%'))
+    AND ($3::TEXT IS NULL OR entity_type = $3)
+    AND ($4::INT IS NULL OR entity_id = $4)
+    AND ($5::TIMESTAMPTZ IS NULL OR created_at >= $5)
+    AND ($6::TIMESTAMPTZ IS NULL OR created_at < $6)";
+
+#[get("/api/admin/audit-log")]
+async fn list_audit_log(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<AuditLogQuery>,
+) -> impl Responder {
+    let claims = match verify_token(&req, &app_state) {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+
+    if claims.impersonation.is_some() || !claims.roles.contains(&"admin".to_string()) {
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Admin access required"
+        }));
+    }
+
+    let page = query.page.unwrap_or(1).max(1);
+    let page_size = query.page_size.unwrap_or(50).clamp(1, 200);
+    let offset = (page - 1) * page_size;
+    let action = query
+        .action
+        .as_ref()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+    let entity_type = query
+        .entity_type
+        .as_ref()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+
+    let total = sqlx::query_scalar::<_, i64>(&format!(
+        "SELECT COUNT(*) FROM audit_log WHERE {}",
+        FILTER_CLAUSE
+    ))
+    .bind(query.actor_user_id)
+    .bind(&action)
+    .bind(&entity_type)
+    .bind(query.entity_id)
+    .bind(query.from)
+    .bind(query.to)
+    .fetch_one(&app_state.db)
+    .await;
+
+    let total = match total {
+        Ok(total) => total,
+        Err(e) => {
+            error!("Failed to count audit log entries: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to fetch audit log"
+            }));
+        }
+    };
+
+    let entries = sqlx::query_as::<_, AuditLogEntry>(&format!(
+        "SELECT id, actor_user_id, actor_username, impersonation_session_id, action, entity_type,
+                entity_id, before_data, after_data, ip_address, created_at
+         FROM audit_log WHERE {}
+         ORDER BY created_at DESC, id DESC
+         LIMIT $7 OFFSET $8",
+        FILTER_CLAUSE
+    ))
+    .bind(query.actor_user_id)
+    .bind(&action)
+    .bind(&entity_type)
+    .bind(query.entity_id)
+    .bind(query.from)
+    .bind(query.to)
+    .bind(page_size)
+    .bind(offset)
+    .fetch_all(&app_state.db)
+    .await;
+
+    match entries {
+        Ok(entries) => HttpResponse::Ok().json(AuditLogPageResponse {
+            entries,
+            total,
+            page,
+            page_size,
+        }),
+        Err(e) => {
+            error!("Failed to fetch audit log: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to fetch audit log"
+            }))
+        }
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_audit_log);
+}
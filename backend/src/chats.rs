@@ -1,17 +1,17 @@
 use actix_web::{web, HttpRequest, HttpResponse, Result};
 use chrono::{DateTime, Utc};
 use log::{debug, error, warn};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use sqlx::{FromRow, PgPool};
 use std::collections::{HashMap, HashSet};
 
-use crate::notifications::{
-    is_user_notification_eligible, ContentBlock, NotificationBody, NotificationContent,
-};
-use crate::push;
+use crate::audit::{self, AuditEvent};
+use crate::i18n::{tr, tr_args, Locale};
+use crate::notifications::{ContentBlock, NotificationBody, NotificationContent};
 use crate::users::verify_token;
-use crate::websockets;
+use crate::websocket_protocol::{ChatMessageEvent, NotificationChange, ServerMessage};
 use crate::AppState;
 
 // ============================================================================
@@ -55,14 +55,21 @@ async fn fetch_user_display_name(db: &PgPool, user_id: i32) -> String {
 }
 
 fn build_chat_notification(
+    locale: Locale,
     sender_name: &str,
     message: &str,
     thread_id: i32,
     sender_id: i32,
 ) -> NotificationBody {
+    let message = if message.is_empty() {
+        tr(locale, "chat.new_message")
+    } else {
+        message
+    };
+
     NotificationBody {
         body_type: "chat_message".to_string(),
-        title: format!("New message from {}", sender_name),
+        title: tr_args(locale, "chat.title", &[("sender", sender_name)]),
         route: Some(format!("/chat/{}", thread_id)),
         content: NotificationContent {
             blocks: vec![ContentBlock::Text {
@@ -75,9 +82,11 @@ fn build_chat_notification(
             "thread_id": thread_id,
             "sender_id": sender_id,
         })),
+        collapse_key: Some(format!("chat:{}", thread_id)),
     }
 }
 
+/// Plain-text preview of a Quill message; empty when the message has no text
 fn chat_message_preview(body: &serde_json::Value) -> String {
     let mut text = String::new();
     if let Some(ops) = body.get("ops").and_then(|value| value.as_array()) {
@@ -89,10 +98,6 @@ fn chat_message_preview(body: &serde_json::Value) -> String {
     }
 
     let trimmed = text.trim().to_string();
-    if trimmed.is_empty() {
-        return "New message".to_string();
-    }
-
     let limit = 180;
     if trimmed.len() <= limit {
         return trimmed;
@@ -297,7 +302,7 @@ pub struct ReceiptResponse {
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct ChatAttachmentResponse {
     pub media_id: i32,
     pub attachment_type: String,
@@ -697,7 +702,7 @@ pub async fn get_related_teachers(
 }
 
 /// Check if a user can view a specific thread
-async fn can_view_thread(pool: &PgPool, user_id: i32, thread_id: i32) -> Result<bool, sqlx::Error> {
+pub(crate) async fn can_view_thread(pool: &PgPool, user_id: i32, thread_id: i32) -> Result<bool, sqlx::Error> {
     // Get thread info
     let thread = sqlx::query_as::<_, ChatThread>(
         "SELECT id, participant_a_id, participant_b_id, is_admin_chat, created_at, updated_at
@@ -1184,69 +1189,35 @@ async fn send_message(
     // Broadcast message via WebSocket
     let sender_name = fetch_user_display_name(&app_state.db, user_id).await;
     let preview = chat_message_preview(&payload.body);
-    let message_data = serde_json::json!({
-        "message_id": message_id,
-        "thread_id": thread_id,
-        "sender_id": user_id,
-        "sender_name": sender_name,
-        "body": payload.body.clone(),
-        "attachments": attachments,
-        "created_at": created_at.to_rfc3339(),
-        "updated_at": updated_at.to_rfc3339(),
-    });
-
-    let ws_message = websockets::WsMessage {
-        msg_type: "chat_message".to_string(),
-        user_id: Some(user_id),
-        thread_id: Some(thread_id),
-        post_id: None,
-        data: message_data,
-    };
+    let ws_message = ServerMessage::ChatMessage(Box::new(ChatMessageEvent {
+        message_id,
+        thread_id,
+        sender_id: user_id,
+        sender_name: sender_name.clone(),
+        body: payload.body.clone(),
+        attachments: attachments.clone(),
+        created_at,
+        updated_at,
+    }));
 
     app_state
         .ws_server
         .broadcast_to_thread(thread_id, ws_message)
         .await;
 
-    let notification_body = build_chat_notification(&sender_name, &preview, thread_id, user_id);
-    for recipient_id in &recipients {
-        if !is_user_notification_eligible(&app_state.db, *recipient_id).await {
-            continue;
-        }
-
-        let notification_id = sqlx::query_scalar::<_, i32>(
-            "INSERT INTO notifications (user_id, type, title, body, priority)
-             VALUES ($1, $2, $3, $4, $5)
-             RETURNING id",
+    debug!(
+        "[CHAT] Notifying {} users for thread {}",
+        recipients.len(),
+        thread_id
+    );
+    app_state
+        .notifications
+        .notify_many(
+            &recipients,
+            |locale| build_chat_notification(locale, &sender_name, &preview, thread_id, user_id),
+            "normal",
         )
-        .bind(recipient_id)
-        .bind(&notification_body.body_type)
-        .bind(&notification_body.title)
-        .bind(serde_json::to_value(&notification_body).unwrap_or_default())
-        .bind("normal")
-        .fetch_optional(&app_state.db)
-        .await
-        .unwrap_or(None);
-
-        if let Some(notification_id) = notification_id {
-            debug!(
-                "[CHAT] Sending push to user {} for thread {}",
-                recipient_id, thread_id
-            );
-            let db = app_state.db.clone();
-            let recipient_id = *recipient_id;
-            let notification_body = notification_body.clone();
-            actix_web::rt::spawn(async move {
-                push::send_notification_to_user(
-                    &db,
-                    recipient_id,
-                    &notification_body,
-                    Some(notification_id),
-                )
-                .await;
-            });
-        }
-    }
+        .await;
 
     Ok(HttpResponse::Created().json(json!({
         "message_id": message_id,
@@ -1343,69 +1314,35 @@ async fn send_admin_message(
     // Broadcast message via WebSocket
     let sender_name = fetch_user_display_name(&app_state.db, user_id).await;
     let preview = chat_message_preview(&payload.body);
-    let message_data = serde_json::json!({
-        "message_id": message_id,
-        "thread_id": thread_id,
-        "sender_id": user_id,
-        "sender_name": sender_name,
-        "body": payload.body.clone(),
-        "attachments": attachments,
-        "created_at": created_at.to_rfc3339(),
-        "updated_at": updated_at.to_rfc3339(),
-    });
-
-    let ws_message = websockets::WsMessage {
-        msg_type: "chat_message".to_string(),
-        user_id: Some(user_id),
-        thread_id: Some(thread_id),
-        post_id: None,
-        data: message_data,
-    };
+    let ws_message = ServerMessage::ChatMessage(Box::new(ChatMessageEvent {
+        message_id,
+        thread_id,
+        sender_id: user_id,
+        sender_name: sender_name.clone(),
+        body: payload.body.clone(),
+        attachments: attachments.clone(),
+        created_at,
+        updated_at,
+    }));
 
     app_state
         .ws_server
         .broadcast_to_thread(thread_id, ws_message)
         .await;
 
-    let notification_body = build_chat_notification(&sender_name, &preview, thread_id, user_id);
-    for admin_id in &admin_recipients {
-        if !is_user_notification_eligible(&app_state.db, *admin_id).await {
-            continue;
-        }
-
-        let notification_id = sqlx::query_scalar::<_, i32>(
-            "INSERT INTO notifications (user_id, type, title, body, priority)
-             VALUES ($1, $2, $3, $4, $5)
-             RETURNING id",
+    debug!(
+        "[CHAT] Notifying {} admins for thread {}",
+        admin_recipients.len(),
+        thread_id
+    );
+    app_state
+        .notifications
+        .notify_many(
+            &admin_recipients,
+            |locale| build_chat_notification(locale, &sender_name, &preview, thread_id, user_id),
+            "normal",
         )
-        .bind(admin_id)
-        .bind(&notification_body.body_type)
-        .bind(&notification_body.title)
-        .bind(serde_json::to_value(&notification_body).unwrap_or_default())
-        .bind("normal")
-        .fetch_optional(&app_state.db)
-        .await
-        .unwrap_or(None);
-
-        if let Some(notification_id) = notification_id {
-            debug!(
-                "[CHAT] Sending push to admin {} for thread {}",
-                admin_id, thread_id
-            );
-            let db = app_state.db.clone();
-            let admin_id = *admin_id;
-            let notification_body = notification_body.clone();
-            actix_web::rt::spawn(async move {
-                push::send_notification_to_user(
-                    &db,
-                    admin_id,
-                    &notification_body,
-                    Some(notification_id),
-                )
-                .await;
-            });
-        }
-    }
+        .await;
 
     Ok(HttpResponse::Created().json(json!({
         "message_id": message_id,
@@ -1476,17 +1413,39 @@ async fn update_receipt(
         })));
     }
 
+    // The thread's collapsed notification is done once everything in it is read
+    if payload.state == "read" {
+        let cleared = sqlx::query_scalar::<_, i32>(
+            "UPDATE notifications SET read_at = NOW()
+             WHERE user_id = $1 AND collapse_key = $2 AND read_at IS NULL
+               AND NOT EXISTS(
+                   SELECT 1 FROM message_receipts mr
+                   JOIN chat_messages m ON m.id = mr.message_id
+                   WHERE m.thread_id = $3 AND mr.recipient_id = $1 AND mr.state <> 'read'
+               )
+             RETURNING id",
+        )
+        .bind(user_id)
+        .bind(format!("chat:{}", thread_id))
+        .bind(thread_id)
+        .fetch_all(&app_state.db)
+        .await
+        .unwrap_or_else(|e| {
+            error!("Failed to clear chat notification: {}", e);
+            Vec::new()
+        });
+        app_state
+            .notifications
+            .publish_change(user_id, NotificationChange::Read, &cleared)
+            .await;
+    }
+
     // Broadcast receipt update via WebSocket
-    let ws_message = websockets::WsMessage {
-        msg_type: "receipt".to_string(),
-        user_id: Some(user_id),
-        thread_id: Some(thread_id),
-        post_id: None,
-        data: serde_json::json!({
-            "message_id": message_id,
-            "recipient_id": user_id,
-            "state": payload.state,
-        }),
+    let ws_message = ServerMessage::Receipt {
+        thread_id,
+        message_id,
+        recipient_id: user_id,
+        state: payload.state.clone(),
     };
 
     app_state
@@ -1572,22 +1531,16 @@ async fn update_message(
         attachments: attachments.clone(),
     };
 
-    let ws_message = websockets::WsMessage {
-        msg_type: "chat_message_updated".to_string(),
-        user_id: Some(user_id),
-        thread_id: Some(updated_message.thread_id),
-        post_id: None,
-        data: serde_json::json!({
-            "message_id": response.id,
-            "thread_id": updated_message.thread_id,
-            "sender_id": response.sender_id,
-            "sender_name": response.sender_name,
-            "body": response.body,
-            "attachments": response.attachments,
-            "created_at": response.created_at.to_rfc3339(),
-            "updated_at": response.updated_at.to_rfc3339(),
-        }),
-    };
+    let ws_message = ServerMessage::ChatMessageUpdated(Box::new(ChatMessageEvent {
+        message_id: response.id,
+        thread_id: updated_message.thread_id,
+        sender_id: response.sender_id,
+        sender_name: response.sender_name.clone(),
+        body: response.body.clone(),
+        attachments: response.attachments.clone(),
+        created_at: response.created_at,
+        updated_at: response.updated_at,
+    }));
 
     app_state
         .ws_server
@@ -1678,14 +1631,18 @@ async fn delete_message(
         actix_web::error::ErrorInternalServerError("Failed to delete message")
     })?;
 
-    let ws_message = websockets::WsMessage {
-        msg_type: "chat_message_deleted".to_string(),
-        user_id: Some(user_id),
-        thread_id: Some(message.thread_id),
-        post_id: None,
-        data: serde_json::json!({
-            "message_id": message_id,
-        }),
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("chat_message.delete", "chat_message", Some(message_id))
+            .before(serde_json::to_value(&message).ok()),
+    )
+    .await;
+
+    let ws_message = ServerMessage::ChatMessageDeleted {
+        thread_id: message.thread_id,
+        message_id,
+        deleted_by: user_id,
     };
 
     app_state
@@ -0,0 +1,805 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{decode, DecodingKey, Validation};
+use log::error;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection, PgExecutor, PgPool};
+
+use crate::audit::{self, AuditEvent};
+use crate::impersonation::request_token;
+use crate::users::{verify_token, Claims};
+use crate::AppState;
+
+const DEFAULT_LOCALE: &str = "en";
+/// Students younger than this need a parent (or an admin) to accept for them
+const MIN_SELF_CONSENT_AGE: i32 = 16;
+
+/// Paths that stay reachable while consent is outstanding
+const EXEMPT_PREFIXES: &[&str] = &[
+    "/api/auth/",
+    "/api/consent/",
+    "/api/profile/export",
+    "/api/profile/deletion-request",
+];
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ConsentDocument {
+    pub version: i32,
+    pub locale: String,
+    pub title: String,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ConsentRecord {
+    pub version: i32,
+    pub locale: String,
+    pub accepted_by: Option<i32>,
+    pub accepted_by_username: Option<String>,
+    pub on_behalf_of_minor: bool,
+    pub ip_address: Option<String>,
+    pub accepted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ChildConsentStatus {
+    pub user_id: i32,
+    pub full_name: String,
+    pub accepted_version: Option<i32>,
+    #[sqlx(skip)]
+    pub needs_acceptance: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConsentStatusResponse {
+    pub current_version: Option<i32>,
+    pub accepted_version: Option<i32>,
+    pub needs_acceptance: bool,
+    pub guardian_required: bool, // the user is a minor and cannot accept themselves
+    pub children: Vec<ChildConsentStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LocaleQuery {
+    pub locale: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AcceptConsentRequest {
+    pub version: i32,
+    pub locale: String,
+    pub student_user_id: Option<i32>, // set when a parent accepts for their child
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConsentText {
+    pub locale: String,
+    pub title: String,
+    pub body: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateConsentVersionRequest {
+    pub texts: Vec<ConsentText>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecordConsentRequest {
+    pub locale: String,
+}
+
+/// Highest published consent version, if any document was published yet
+pub async fn current_version<'e>(db: impl PgExecutor<'e>) -> Result<Option<i32>, sqlx::Error> {
+    sqlx::query_scalar::<_, Option<i32>>(
+        "SELECT MAX(version) FROM consent_documents
+         WHERE published_at IS NOT NULL AND published_at <= NOW()",
+    )
+    .fetch_one(db)
+    .await
+}
+
+async fn is_minor<'e>(db: impl PgExecutor<'e>, user_id: i32) -> Result<bool, sqlx::Error> {
+    Ok(sqlx::query_scalar::<_, bool>(
+        "SELECT birthday > CURRENT_DATE - make_interval(years => $2)
+         FROM students WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .bind(MIN_SELF_CONSENT_AGE)
+    .fetch_optional(db)
+    .await?
+    .unwrap_or(false))
+}
+
+async fn record_acceptance<'e>(
+    db: impl PgExecutor<'e>,
+    user_id: i32,
+    version: i32,
+    locale: &str,
+    accepted_by: Option<i32>,
+    on_behalf_of_minor: bool,
+    ip_address: Option<String>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO user_consents (user_id, version, locale, accepted_by, on_behalf_of_minor, ip_address)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         ON CONFLICT (user_id, version) DO NOTHING",
+    )
+    .bind(user_id)
+    .bind(version)
+    .bind(locale)
+    .bind(accepted_by)
+    .bind(on_behalf_of_minor)
+    .bind(ip_address)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Outcome of the consent given on the registration form
+pub(crate) enum RegistrationConsent {
+    Recorded,
+    /// No version is in force yet, or the new user is a minor whose parent accepts
+    NotRequired,
+    /// The form didn't accept this version, the one in force
+    Missing(i32),
+    UnknownLocale,
+}
+
+/// Record the consent the registration form showed, inside the registration
+/// transaction once the user's role rows exist
+pub(crate) async fn accept_on_registration(
+    conn: &mut PgConnection,
+    user_id: i32,
+    version: Option<i32>,
+    locale: Option<&str>,
+    ip_address: Option<String>,
+) -> Result<RegistrationConsent, sqlx::Error> {
+    let Some(current) = current_version(&mut *conn).await? else {
+        return Ok(RegistrationConsent::NotRequired);
+    };
+    if is_minor(&mut *conn, user_id).await? {
+        return Ok(RegistrationConsent::NotRequired);
+    }
+    if version != Some(current) {
+        return Ok(RegistrationConsent::Missing(current));
+    }
+
+    let locale = locale.unwrap_or(DEFAULT_LOCALE);
+    let locale_exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM consent_documents WHERE version = $1 AND locale = $2)",
+    )
+    .bind(current)
+    .bind(locale)
+    .fetch_one(&mut *conn)
+    .await?;
+    if !locale_exists {
+        return Ok(RegistrationConsent::UnknownLocale);
+    }
+
+    record_acceptance(&mut *conn, user_id, current, locale, None, false, ip_address).await?;
+    Ok(RegistrationConsent::Recorded)
+}
+
+async fn user_id_for(db: &PgPool, username: &str) -> Result<Option<i32>, sqlx::Error> {
+    sqlx::query_scalar::<_, i32>("SELECT id FROM users WHERE username = $1")
+        .bind(username)
+        .fetch_optional(db)
+        .await
+}
+
+fn verify_admin_claims(req: &HttpRequest, app_state: &AppState) -> Result<Claims, HttpResponse> {
+    let claims = verify_token(req, app_state)?;
+
+    if claims.impersonation.is_some() || !claims.roles.contains(&"admin".to_string()) {
+        return Err(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Admin access required"
+        })));
+    }
+
+    Ok(claims)
+}
+
+/// Current consent text in the requested locale, falling back to English
+#[get("/api/consent/current")]
+async fn get_current_document(
+    app_state: web::Data<AppState>,
+    query: web::Query<LocaleQuery>,
+) -> impl Responder {
+    let locale = query.locale.as_deref().unwrap_or(DEFAULT_LOCALE);
+
+    let document = sqlx::query_as::<_, ConsentDocument>(
+        "SELECT version, locale, title, body, created_at, published_at
+         FROM consent_documents
+         WHERE version = (SELECT MAX(version) FROM consent_documents
+                          WHERE published_at IS NOT NULL AND published_at <= NOW())
+         ORDER BY (locale = $1) DESC, (locale = $2) DESC, locale
+         LIMIT 1",
+    )
+    .bind(locale)
+    .bind(DEFAULT_LOCALE)
+    .fetch_optional(&app_state.db)
+    .await;
+
+    match document {
+        Ok(Some(document)) => HttpResponse::Ok().json(document),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No consent document published"
+        })),
+        Err(e) => {
+            error!("Database error: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }))
+        }
+    }
+}
+
+/// Whether the current user (and, for parents, their children) accepted the current version
+#[get("/api/consent/status")]
+async fn get_consent_status(app_state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    let claims = match verify_token(&req, &app_state) {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+
+    let user_id = match user_id_for(&app_state.db, &claims.sub).await {
+        Ok(Some(id)) => id,
+        Ok(None) => {
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "User not found"
+            }));
+        }
+        Err(e) => {
+            error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    };
+
+    let current = match current_version(&app_state.db).await {
+        Ok(version) => version,
+        Err(e) => {
+            error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    };
+
+    let accepted_version = sqlx::query_scalar::<_, Option<i32>>(
+        "SELECT MAX(version) FROM user_consents WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_one(&app_state.db)
+    .await
+    .unwrap_or_else(|e| {
+        error!("Failed to load consent status: {}", e);
+        None
+    });
+
+    let guardian_required = is_minor(&app_state.db, user_id).await.unwrap_or_else(|e| {
+        error!("Failed to check student age: {}", e);
+        false
+    });
+
+    let mut children = sqlx::query_as::<_, ChildConsentStatus>(
+        "SELECT u.id AS user_id, u.full_name,
+                (SELECT MAX(version) FROM user_consents uc WHERE uc.user_id = u.id) AS accepted_version
+         FROM parent_student_relations psr
+         JOIN users u ON u.id = psr.student_user_id
+         JOIN students s ON s.user_id = u.id
+         WHERE psr.parent_user_id = $1
+           AND s.birthday > CURRENT_DATE - make_interval(years => $2)
+         ORDER BY u.full_name",
+    )
+    .bind(user_id)
+    .bind(MIN_SELF_CONSENT_AGE)
+    .fetch_all(&app_state.db)
+    .await
+    .unwrap_or_else(|e| {
+        error!("Failed to load children consent status: {}", e);
+        Vec::new()
+    });
+
+    for child in &mut children {
+        child.needs_acceptance = current.is_some() && child.accepted_version < current;
+    }
+
+    HttpResponse::Ok().json(ConsentStatusResponse {
+        current_version: current,
+        accepted_version,
+        needs_acceptance: current.is_some() && accepted_version < current,
+        guardian_required,
+        children,
+    })
+}
+
+/// Accept the current consent version, for yourself or for a minor child
+#[post("/api/consent/accept")]
+async fn accept_consent(
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<AcceptConsentRequest>,
+) -> impl Responder {
+    let claims = match verify_token(&req, &app_state) {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+
+    if claims.impersonation.is_some() {
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Consent cannot be given while impersonating a user"
+        }));
+    }
+
+    let user_id = match user_id_for(&app_state.db, &claims.sub).await {
+        Ok(Some(id)) => id,
+        Ok(None) => {
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "User not found"
+            }));
+        }
+        Err(e) => {
+            error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    };
+
+    match current_version(&app_state.db).await {
+        Ok(Some(version)) if version == body.version => {}
+        Ok(_) => {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Only the current consent version can be accepted"
+            }));
+        }
+        Err(e) => {
+            error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    }
+
+    let locale_exists = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM consent_documents WHERE version = $1 AND locale = $2)",
+    )
+    .bind(body.version)
+    .bind(&body.locale)
+    .fetch_one(&app_state.db)
+    .await
+    .unwrap_or(false);
+
+    if !locale_exists {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Consent text not available in this locale"
+        }));
+    }
+
+    let ip = req.connection_info().realip_remote_addr().map(str::to_string);
+
+    let result = match body.student_user_id {
+        Some(student_user_id) => {
+            let is_parent = sqlx::query_scalar::<_, bool>(
+                "SELECT EXISTS (SELECT 1 FROM parent_student_relations
+                                WHERE parent_user_id = $1 AND student_user_id = $2)",
+            )
+            .bind(user_id)
+            .bind(student_user_id)
+            .fetch_one(&app_state.db)
+            .await
+            .unwrap_or(false);
+
+            if !is_parent {
+                return HttpResponse::Forbidden().json(serde_json::json!({
+                    "error": "You can only accept consent for your own children"
+                }));
+            }
+
+            record_acceptance(
+                &app_state.db,
+                student_user_id,
+                body.version,
+                &body.locale,
+                Some(user_id),
+                true,
+                ip,
+            )
+            .await
+        }
+        None => {
+            match is_minor(&app_state.db, user_id).await {
+                Ok(false) => {}
+                Ok(true) => {
+                    return HttpResponse::Forbidden().json(serde_json::json!({
+                        "error": "A parent or guardian has to accept on your behalf"
+                    }));
+                }
+                Err(e) => {
+                    error!("Database error: {}", e);
+                    return HttpResponse::InternalServerError().json(serde_json::json!({
+                        "error": "Internal server error"
+                    }));
+                }
+            }
+
+            record_acceptance(&app_state.db, user_id, body.version, &body.locale, None, false, ip).await
+        }
+    };
+
+    match result {
+        Ok(()) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Consent recorded",
+            "version": body.version
+        })),
+        Err(e) => {
+            error!("Failed to record consent: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to record consent"
+            }))
+        }
+    }
+}
+
+#[get("/api/admin/consent/documents")]
+async fn list_documents(req: HttpRequest, app_state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = verify_admin_claims(&req, &app_state) {
+        return response;
+    }
+
+    match sqlx::query_as::<_, ConsentDocument>(
+        "SELECT version, locale, title, body, created_at, published_at
+         FROM consent_documents ORDER BY version DESC, locale",
+    )
+    .fetch_all(&app_state.db)
+    .await
+    {
+        Ok(documents) => HttpResponse::Ok().json(documents),
+        Err(e) => {
+            error!("Database error: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to fetch consent documents"
+            }))
+        }
+    }
+}
+
+/// Create the next consent version as an unpublished draft
+#[post("/api/admin/consent/documents")]
+async fn create_version(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    body: web::Json<CreateConsentVersionRequest>,
+) -> impl Responder {
+    let claims = match verify_admin_claims(&req, &app_state) {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+
+    if body.texts.is_empty()
+        || body
+            .texts
+            .iter()
+            .any(|text| text.locale.trim().is_empty() || text.body.trim().is_empty())
+    {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Each text needs a locale and a body"
+        }));
+    }
+
+    let mut tx = match app_state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    };
+
+    // Serialise version numbering between concurrent admins
+    if let Err(e) = sqlx::query("SELECT pg_advisory_xact_lock(hashtext('consent_documents'))")
+        .execute(&mut *tx)
+        .await
+    {
+        let _ = tx.rollback().await;
+        error!("Database error: {}", e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Internal server error"
+        }));
+    }
+
+    let version = match sqlx::query_scalar::<_, i32>(
+        "SELECT COALESCE(MAX(version), 0) + 1 FROM consent_documents",
+    )
+    .fetch_one(&mut *tx)
+    .await
+    {
+        Ok(version) => version,
+        Err(e) => {
+            let _ = tx.rollback().await;
+            error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    };
+
+    for text in &body.texts {
+        if let Err(e) = sqlx::query(
+            "INSERT INTO consent_documents (version, locale, title, body, created_by)
+             VALUES ($1, $2, $3, $4, (SELECT id FROM users WHERE username = $5))",
+        )
+        .bind(version)
+        .bind(text.locale.trim())
+        .bind(&text.title)
+        .bind(&text.body)
+        .bind(&claims.sub)
+        .execute(&mut *tx)
+        .await
+        {
+            let _ = tx.rollback().await;
+            error!("Failed to create consent document: {}", e);
+            return HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Failed to create consent version (duplicate locale?)"
+            }));
+        }
+    }
+
+    if let Err(e) = tx.commit().await {
+        error!("Failed to commit consent version: {}", e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Internal server error"
+        }));
+    }
+
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("consent.create", "consent_version", Some(version)).after(Some(
+            serde_json::json!({ "locales": body.texts.iter().map(|t| t.locale.trim()).collect::<Vec<_>>() }),
+        )),
+    )
+    .await;
+
+    HttpResponse::Created().json(serde_json::json!({
+        "version": version
+    }))
+}
+
+/// Publish a draft version. Every user has to accept it before using the API again.
+#[post("/api/admin/consent/documents/{version}/publish")]
+async fn publish_version(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+) -> impl Responder {
+    if let Err(response) = verify_admin_claims(&req, &app_state) {
+        return response;
+    }
+    let version = path.into_inner();
+
+    let result = sqlx::query(
+        "UPDATE consent_documents SET published_at = NOW()
+         WHERE version = $1 AND published_at IS NULL
+           AND version > (SELECT COALESCE(MAX(version), 0) FROM consent_documents
+                          WHERE published_at IS NOT NULL)",
+    )
+    .bind(version)
+    .execute(&app_state.db)
+    .await;
+
+    match result {
+        Ok(result) if result.rows_affected() > 0 => {
+            audit::record(
+                &app_state,
+                &req,
+                AuditEvent::new("consent.publish", "consent_version", Some(version)),
+            )
+            .await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "Consent version published",
+                "version": version
+            }))
+        }
+        Ok(_) => HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Version does not exist, is already published or is older than the current version"
+        })),
+        Err(e) => {
+            error!("Database error: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to publish consent version"
+            }))
+        }
+    }
+}
+
+#[get("/api/admin/consent/users/{user_id}")]
+async fn list_user_consents(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+) -> impl Responder {
+    if let Err(response) = verify_admin_claims(&req, &app_state) {
+        return response;
+    }
+
+    match sqlx::query_as::<_, ConsentRecord>(
+        "SELECT uc.version, uc.locale, uc.accepted_by, a.username AS accepted_by_username,
+                uc.on_behalf_of_minor, uc.ip_address, uc.accepted_at
+         FROM user_consents uc
+         LEFT JOIN users a ON a.id = uc.accepted_by
+         WHERE uc.user_id = $1
+         ORDER BY uc.version DESC",
+    )
+    .bind(path.into_inner())
+    .fetch_all(&app_state.db)
+    .await
+    {
+        Ok(records) => HttpResponse::Ok().json(records),
+        Err(e) => {
+            error!("Database error: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to fetch consent records"
+            }))
+        }
+    }
+}
+
+/// Record consent given outside the app, e.g. a signed paper form
+#[post("/api/admin/consent/users/{user_id}/accept")]
+async fn record_consent_for_user(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+    body: web::Json<RecordConsentRequest>,
+) -> impl Responder {
+    let claims = match verify_admin_claims(&req, &app_state) {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let user_id = path.into_inner();
+
+    let version = match current_version(&app_state.db).await {
+        Ok(Some(version)) => version,
+        Ok(None) => {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "No consent document published"
+            }));
+        }
+        Err(e) => {
+            error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    };
+
+    let admin_id = match user_id_for(&app_state.db, &claims.sub).await {
+        Ok(id) => id,
+        Err(e) => {
+            error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    };
+    let minor = is_minor(&app_state.db, user_id).await.unwrap_or(false);
+    let ip = req.connection_info().realip_remote_addr().map(str::to_string);
+
+    if let Err(e) =
+        record_acceptance(&app_state.db, user_id, version, &body.locale, admin_id, minor, ip).await
+    {
+        error!("Failed to record consent: {}", e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Failed to record consent"
+        }));
+    }
+
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("consent.record", "user", Some(user_id))
+            .after(Some(serde_json::json!({ "version": version, "locale": body.locale }))),
+    )
+    .await;
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "message": "Consent recorded",
+        "version": version
+    }))
+}
+
+#[derive(FromRow)]
+struct EnforcementState {
+    current_version: Option<i32>,
+    accepted: bool,
+}
+
+/// Middleware that answers 428 Precondition Required on API calls of users who
+/// have not accepted the current consent version. Admins and impersonation
+/// sessions are not gated; requests without a valid token are left to the handler.
+pub async fn enforcement_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let path = req.path();
+    if !path.starts_with("/api/")
+        || req.method() == actix_web::http::Method::OPTIONS
+        || EXEMPT_PREFIXES.iter().any(|prefix| path.starts_with(prefix))
+    {
+        return next.call(req).await.map(|res| res.map_into_boxed_body());
+    }
+
+    let app_state = match req.app_data::<web::Data<AppState>>() {
+        Some(state) => state.clone(),
+        None => return next.call(req).await.map(|res| res.map_into_boxed_body()),
+    };
+
+    let claims = request_token(&req).and_then(|token| {
+        decode::<Claims>(
+            &token,
+            &DecodingKey::from_secret(app_state.jwt_secret.as_ref()),
+            &Validation::default(),
+        )
+        .ok()
+        .map(|data| data.claims)
+    });
+
+    let claims = match claims {
+        Some(claims) if claims.impersonation.is_none() && !claims.roles.contains(&"admin".to_string()) => claims,
+        _ => return next.call(req).await.map(|res| res.map_into_boxed_body()),
+    };
+
+    let state = sqlx::query_as::<_, EnforcementState>(
+        "SELECT cur.version AS current_version,
+                EXISTS (SELECT 1 FROM user_consents uc JOIN users u ON u.id = uc.user_id
+                        WHERE u.username = $1 AND uc.version = cur.version) AS accepted
+         FROM (SELECT MAX(version) AS version FROM consent_documents
+               WHERE published_at IS NOT NULL AND published_at <= NOW()) cur",
+    )
+    .bind(&claims.sub)
+    .fetch_one(&app_state.db)
+    .await;
+
+    let response = match state {
+        Ok(EnforcementState { current_version: None, .. })
+        | Ok(EnforcementState { accepted: true, .. }) => {
+            return next.call(req).await.map(|res| res.map_into_boxed_body());
+        }
+        Ok(EnforcementState { current_version: Some(version), .. }) => {
+            HttpResponse::PreconditionRequired().json(serde_json::json!({
+                "error": "The current consent version has to be accepted first",
+                "code": "consent_required",
+                "version": version
+            }))
+        }
+        Err(e) => {
+            error!("Failed to check consent: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }))
+        }
+    };
+
+    Ok(req.into_response(response))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_current_document)
+        .service(get_consent_status)
+        .service(accept_consent)
+        .service(list_documents)
+        .service(create_version)
+        .service(publish_version)
+        .service(list_user_consents)
+        .service(record_consent_for_user);
+}
@@ -1,7 +1,16 @@
-use lettre::message::header::ContentType;
-use lettre::transport::smtp::authentication::Credentials;
-use lettre::{Message, SmtpTransport, Transport};
+use lettre::message::Mailbox;
 use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::i18n::{tr, tr_args, Locale};
+use crate::notifications::NotificationBody;
+
+mod templates;
+mod transport;
+
+pub use templates::{Branding, EmailAction, EmailContent};
+pub use transport::{EmailTransport, MaildirTransport, MemoryTransport, OutgoingEmail, SmtpTransport};
 
 #[derive(Debug)]
 pub enum EmailError {
@@ -23,125 +32,248 @@ impl std::fmt::Display for EmailError {
 impl std::error::Error for EmailError {}
 
 pub struct EmailService {
-    from_email: String,
-    smtp_host: String,
-    smtp_username: String,
-    smtp_password: String,
+    transport: Arc<dyn EmailTransport>,
+    from: Mailbox,
+    branding: Branding,
     reset_url_base: String,
-    app_name: String,
+    magic_link_url_base: String,
+    verify_email_url_base: String,
+    app_url_base: String,
 }
 
 impl EmailService {
+    /// Whether the environment selects an email transport at all, with
+    /// `EMAIL_TRANSPORT` or any `SMTP_*` variable. Setups without either are
+    /// development machines; anything else has to configure email correctly.
+    pub fn is_configured() -> bool {
+        env::vars().any(|(name, _)| name == "EMAIL_TRANSPORT" || name.starts_with("SMTP_"))
+    }
+
+    /// Build the service from the environment.
+    ///
+    /// `EMAIL_TRANSPORT` selects `smtp` (default), `maildir` (writes to
+    /// `EMAIL_MAILDIR`) or `memory`. SMTP delivery requires `FROM_EMAIL`.
     pub fn from_env() -> Result<Self, EmailError> {
+        let transport_name = env::var("EMAIL_TRANSPORT").unwrap_or_else(|_| "smtp".to_string());
+
+        let transport: Arc<dyn EmailTransport> = match transport_name.as_str() {
+            "smtp" => {
+                if env::var("FROM_EMAIL").is_err() {
+                    return Err(EmailError::ConfigError("FROM_EMAIL not set".to_string()));
+                }
+                Arc::new(SmtpTransport::from_env()?)
+            }
+            "maildir" => {
+                let dir = env::var("EMAIL_MAILDIR")
+                    .map_err(|_| EmailError::ConfigError("EMAIL_MAILDIR not set".to_string()))?;
+                Arc::new(MaildirTransport::new(PathBuf::from(dir)).map_err(|e| {
+                    EmailError::ConfigError(format!("Cannot create maildir: {}", e))
+                })?)
+            }
+            "memory" => Arc::new(MemoryTransport::new()),
+            other => {
+                return Err(EmailError::ConfigError(format!(
+                    "Unknown EMAIL_TRANSPORT: {}",
+                    other
+                )))
+            }
+        };
+
+        Self::with_transport(transport)
+    }
+
+    /// Build the service around an explicit transport, reading addresses and
+    /// branding from the environment.
+    pub fn with_transport(transport: Arc<dyn EmailTransport>) -> Result<Self, EmailError> {
+        let api_base_url = env::var("API_BASE_URL")
+            .unwrap_or_else(|_| "http://localhost:8080".to_string());
+        let api_base_url = api_base_url.trim_end_matches('/');
+
+        let branding = Branding::from_env();
+        let from_email =
+            env::var("FROM_EMAIL").unwrap_or_else(|_| "noreply@localhost".to_string());
+        let from = format!("{} <{}>", branding.school_name, from_email)
+            .parse::<Mailbox>()
+            .or_else(|_| from_email.parse::<Mailbox>())
+            .map_err(|e| EmailError::ConfigError(format!("Invalid FROM_EMAIL: {}", e)))?;
+
         Ok(Self {
-            from_email: env::var("FROM_EMAIL")
-                .map_err(|_| EmailError::ConfigError("FROM_EMAIL not set".to_string()))?,
-            smtp_host: env::var("SMTP_HOST")
-                .map_err(|_| EmailError::ConfigError("SMTP_HOST not set".to_string()))?,
-            smtp_username: env::var("SMTP_USERNAME")
-                .map_err(|_| EmailError::ConfigError("SMTP_USERNAME not set".to_string()))?,
-            smtp_password: env::var("SMTP_PASSWORD")
-                .map_err(|_| EmailError::ConfigError("SMTP_PASSWORD not set".to_string()))?,
-            reset_url_base: format!(
-                "{}/reset-password",
-                env::var("API_BASE_URL")
-                    .unwrap_or_else(|_| "http://localhost:8080".to_string())
-                    .trim_end_matches('/')
-            ),
-            app_name: env::var("APP_NAME").unwrap_or_else(|_| "KlavierApp".to_string()),
+            transport,
+            from,
+            branding,
+            reset_url_base: format!("{}/reset-password", api_base_url),
+            magic_link_url_base: format!("{}/magic-login", api_base_url),
+            verify_email_url_base: format!("{}/verify-email", api_base_url),
+            app_url_base: api_base_url.to_string(),
         })
     }
 
-    pub fn send_password_reset_email(
+    pub fn transport(&self) -> Arc<dyn EmailTransport> {
+        self.transport.clone()
+    }
+
+    pub fn branding(&self) -> &Branding {
+        &self.branding
+    }
+
+    /// Public URL of the web app, used for links in emails
+    pub fn app_url(&self) -> &str {
+        &self.app_url_base
+    }
+
+    /// Render `content` with the school layout and deliver it.
+    pub async fn send(
         &self,
+        locale: Locale,
+        to_email: &str,
+        subject: String,
+        content: &EmailContent,
+    ) -> Result<(), EmailError> {
+        let (text, html) = templates::render(&self.branding, locale, content);
+        self.send_rendered(to_email, subject, text, html).await
+    }
+
+    /// Deliver a notification by email, rendering its content blocks as HTML.
+    pub async fn send_notification_email(
+        &self,
+        locale: Locale,
+        to_email: &str,
+        body: &NotificationBody,
+    ) -> Result<(), EmailError> {
+        let (text, html) =
+            templates::render_notification(&self.branding, locale, body, &self.app_url_base);
+        let subject = format!("{} - {}", body.title, self.branding.app_name);
+        self.send_rendered(to_email, subject, text, html).await
+    }
+
+    async fn send_rendered(
+        &self,
+        to_email: &str,
+        subject: String,
+        text: String,
+        html: String,
+    ) -> Result<(), EmailError> {
+        let to = to_email
+            .parse::<Mailbox>()
+            .map_err(|e| EmailError::BuildError(format!("Invalid to email: {}", e)))?;
+
+        self.transport
+            .send(&OutgoingEmail {
+                from: self.from.clone(),
+                to,
+                subject,
+                text,
+                html,
+            })
+            .await
+    }
+
+    pub async fn send_password_reset_email(
+        &self,
+        locale: Locale,
         to_email: &str,
         username: &str,
         token: &str,
     ) -> Result<(), EmailError> {
-        let reset_link = format!("{}/{}", self.reset_url_base, token);
-
-        let body = format!(
-            "Hello {},\n\n\
-            You requested a password reset for your {} account.\n\n\
-            Click the link below to reset your password:\n\
-            {}\n\n\
-            This link will expire in 1 hour.\n\n\
-            If you didn't request this, please ignore this email.\n\n\
-            Best regards,\n\
-            {} Team",
-            username, self.app_name, reset_link, self.app_name
-        );
-
-        let email = Message::builder()
-            .from(
-                self.from_email
-                    .parse()
-                    .map_err(|e| EmailError::BuildError(format!("Invalid from email: {}", e)))?,
-            )
-            .to(to_email
-                .parse()
-                .map_err(|e| EmailError::BuildError(format!("Invalid to email: {}", e)))?)
-            .subject(format!("Password Reset Request - {}", self.app_name))
-            .header(ContentType::TEXT_PLAIN)
-            .body(body)
-            .map_err(|e| EmailError::BuildError(e.to_string()))?;
-
-        let creds = Credentials::new(self.smtp_username.clone(), self.smtp_password.clone());
-
-        let mailer = SmtpTransport::relay(&self.smtp_host)
-            .map_err(|e| EmailError::SendError(format!("SMTP relay error: {}", e)))?
-            .credentials(creds)
-            .build();
-
-        mailer
-            .send(&email)
-            .map_err(|e| EmailError::SendError(e.to_string()))?;
-
-        Ok(())
+        let app = self.branding.app_name.as_str();
+        let content = EmailContent {
+            greeting: tr_args(locale, "email.greeting", &[("username", username)]),
+            paragraphs: vec![tr_args(locale, "email.reset.intro", &[("app", app)])],
+            action: Some(EmailAction {
+                label: tr(locale, "email.reset.action").to_string(),
+                url: format!("{}/{}", self.reset_url_base, token),
+            }),
+            notes: vec![
+                tr(locale, "email.reset.expiry").to_string(),
+                tr(locale, "email.ignore_notice").to_string(),
+            ],
+            signature: tr_args(locale, "email.signature", &[("app", app)]),
+        };
+
+        let subject = tr_args(locale, "email.reset.subject", &[("app", app)]);
+        self.send(locale, to_email, subject, &content).await
     }
 
-    pub fn send_admin_notification_email(
+    pub async fn send_magic_link_email(
         &self,
+        locale: Locale,
         to_email: &str,
         username: &str,
+        token: &str,
+        valid_minutes: i64,
     ) -> Result<(), EmailError> {
-        let body = format!(
-            "Hello Admin,\n\n\
-            User '{}' has requested a password reset but has no email address on file.\n\n\
-            Please check the admin panel to handle this request.\n\n\
-            Best regards,\n\
-            {} System",
-            username, self.app_name
-        );
-
-        let email = Message::builder()
-            .from(
-                self.from_email
-                    .parse()
-                    .map_err(|e| EmailError::BuildError(format!("Invalid from email: {}", e)))?,
-            )
-            .to(to_email
-                .parse()
-                .map_err(|e| EmailError::BuildError(format!("Invalid to email: {}", e)))?)
-            .subject(format!(
-                "Password Reset Request Pending - {}",
-                self.app_name
-            ))
-            .header(ContentType::TEXT_PLAIN)
-            .body(body)
-            .map_err(|e| EmailError::BuildError(e.to_string()))?;
-
-        let creds = Credentials::new(self.smtp_username.clone(), self.smtp_password.clone());
-
-        let mailer = SmtpTransport::relay(&self.smtp_host)
-            .map_err(|e| EmailError::SendError(format!("SMTP relay error: {}", e)))?
-            .credentials(creds)
-            .build();
-
-        mailer
-            .send(&email)
-            .map_err(|e| EmailError::SendError(e.to_string()))?;
-
-        Ok(())
+        let app = self.branding.app_name.as_str();
+        let content = EmailContent {
+            greeting: tr_args(locale, "email.greeting", &[("username", username)]),
+            paragraphs: vec![tr_args(locale, "email.magic_link.intro", &[("app", app)])],
+            action: Some(EmailAction {
+                label: tr(locale, "email.magic_link.action").to_string(),
+                url: format!("{}/{}", self.magic_link_url_base, token),
+            }),
+            notes: vec![
+                tr_args(
+                    locale,
+                    "email.magic_link.expiry",
+                    &[("minutes", &valid_minutes.to_string())],
+                ),
+                tr(locale, "email.ignore_notice").to_string(),
+            ],
+            signature: tr_args(locale, "email.signature", &[("app", app)]),
+        };
+
+        let subject = tr_args(locale, "email.magic_link.subject", &[("app", app)]);
+        self.send(locale, to_email, subject, &content).await
+    }
+
+    pub async fn send_email_verification_email(
+        &self,
+        locale: Locale,
+        to_email: &str,
+        username: &str,
+        token: &str,
+        valid_hours: i64,
+    ) -> Result<(), EmailError> {
+        let app = self.branding.app_name.as_str();
+        let content = EmailContent {
+            greeting: tr_args(locale, "email.greeting", &[("username", username)]),
+            paragraphs: vec![
+                tr_args(locale, "email.verify.intro", &[("email", to_email), ("app", app)]),
+                tr(locale, "email.verify.usage").to_string(),
+            ],
+            action: Some(EmailAction {
+                label: tr(locale, "email.verify.action").to_string(),
+                url: format!("{}/{}", self.verify_email_url_base, token),
+            }),
+            notes: vec![
+                tr_args(locale, "email.verify.expiry", &[("hours", &valid_hours.to_string())]),
+                tr(locale, "email.verify.ignore").to_string(),
+            ],
+            signature: tr_args(locale, "email.signature", &[("app", app)]),
+        };
+
+        let subject = tr_args(locale, "email.verify.subject", &[("app", app)]);
+        self.send(locale, to_email, subject, &content).await
+    }
+
+    pub async fn send_admin_notification_email(
+        &self,
+        locale: Locale,
+        to_email: &str,
+        username: &str,
+    ) -> Result<(), EmailError> {
+        let app = self.branding.app_name.as_str();
+        let content = EmailContent {
+            greeting: tr(locale, "email.admin_reset.greeting").to_string(),
+            paragraphs: vec![
+                tr_args(locale, "email.admin_reset.intro", &[("username", username)]),
+                tr(locale, "email.admin_reset.outro").to_string(),
+            ],
+            action: None,
+            notes: Vec::new(),
+            signature: tr_args(locale, "email.admin_reset.signature", &[("app", app)]),
+        };
+
+        let subject = tr_args(locale, "email.admin_reset.subject", &[("app", app)]);
+        self.send(locale, to_email, subject, &content).await
     }
 }
@@ -0,0 +1,315 @@
+use std::env;
+
+use crate::i18n::{tr, tr_args, Locale};
+use crate::notifications::{ContentBlock, NotificationBody};
+
+const DEFAULT_ACCENT_COLOR: &str = "#2F9C94";
+
+/// School branding applied to every HTML email.
+#[derive(Debug, Clone)]
+pub struct Branding {
+    pub app_name: String,
+    pub school_name: String,
+    pub accent_color: String,
+    pub logo_url: Option<String>,
+}
+
+impl Branding {
+    pub fn from_env() -> Self {
+        let app_name = env::var("APP_NAME").unwrap_or_else(|_| "KlavierApp".to_string());
+        let accent_color = env::var("EMAIL_ACCENT_COLOR")
+            .ok()
+            .filter(|value| is_hex_color(value))
+            .unwrap_or_else(|| DEFAULT_ACCENT_COLOR.to_string());
+
+        Self {
+            school_name: env::var("SCHOOL_NAME").unwrap_or_else(|_| app_name.clone()),
+            app_name,
+            accent_color,
+            logo_url: env::var("EMAIL_LOGO_URL").ok().filter(|url| !url.trim().is_empty()),
+        }
+    }
+}
+
+/// A call-to-action rendered as a button in HTML and as a bare link in text.
+#[derive(Debug, Clone)]
+pub struct EmailAction {
+    pub label: String,
+    pub url: String,
+}
+
+/// Localized content of an email; the layout is shared by all emails.
+#[derive(Debug, Clone)]
+pub struct EmailContent {
+    pub greeting: String,
+    pub paragraphs: Vec<String>,
+    pub action: Option<EmailAction>,
+    pub notes: Vec<String>,
+    pub signature: String,
+}
+
+/// Render the plain-text and HTML bodies for the given content.
+pub fn render(branding: &Branding, locale: Locale, content: &EmailContent) -> (String, String) {
+    (
+        render_text(branding, locale, content),
+        render_html(branding, locale, content),
+    )
+}
+
+fn render_text(branding: &Branding, locale: Locale, content: &EmailContent) -> String {
+    let mut blocks = vec![content.greeting.clone()];
+    blocks.extend(content.paragraphs.iter().cloned());
+    if let Some(action) = &content.action {
+        blocks.push(format!("{}:\n{}", action.label, action.url));
+    }
+    blocks.extend(content.notes.iter().cloned());
+    blocks.push(content.signature.clone());
+    blocks.push(format!("--\n{}", footer(branding, locale)));
+    blocks.join("\n\n")
+}
+
+fn render_html(branding: &Branding, locale: Locale, content: &EmailContent) -> String {
+    let accent = &branding.accent_color;
+
+    let mut body = paragraph(&content.greeting);
+    for text in &content.paragraphs {
+        body.push_str(&paragraph(text));
+    }
+    if let Some(action) = &content.action {
+        body.push_str(&button(accent, &action.label, &action.url));
+        body.push_str(&format!(
+            r#"<p style="margin:0 0 16px;font-size:13px;color:#666666;">{}<br><a href="{url}" style="color:{accent};word-break:break-all;">{url}</a></p>"#,
+            escape_html(tr(locale, "email.link_fallback")),
+            url = escape_html(&action.url),
+            accent = accent,
+        ));
+    }
+    for note in &content.notes {
+        body.push_str(&note_paragraph(note));
+    }
+    body.push_str(&paragraph(&content.signature));
+
+    layout(branding, locale, &body)
+}
+
+/// Render a notification's content blocks and action buttons.
+/// Relative image URLs and action routes are resolved against `base_url`.
+pub fn render_notification(
+    branding: &Branding,
+    locale: Locale,
+    body: &NotificationBody,
+    base_url: &str,
+) -> (String, String) {
+    let accent = &branding.accent_color;
+    let resolve = |target: &str| {
+        if target.starts_with("http://") || target.starts_with("https://") {
+            target.to_string()
+        } else {
+            format!("{}/{}", base_url, target.trim_start_matches('/'))
+        }
+    };
+
+    let mut text_parts = vec![body.title.clone()];
+    let mut html = format!(
+        r#"<h2 style="margin:0 0 16px;font-size:20px;">{}</h2>"#,
+        escape_html(&body.title)
+    );
+
+    for block in &body.content.blocks {
+        match block {
+            ContentBlock::Text { text, style } => {
+                if text.trim().is_empty() {
+                    continue;
+                }
+                text_parts.push(text.clone());
+                let escaped = escape_html(text).replace('\n', "<br>");
+                html.push_str(&match style.as_deref() {
+                    Some("title") => format!(
+                        r#"<p style="margin:0 0 12px;font-size:17px;font-weight:bold;">{}</p>"#,
+                        escaped
+                    ),
+                    Some("subtitle") => format!(
+                        r#"<p style="margin:0 0 12px;font-weight:bold;color:#444444;">{}</p>"#,
+                        escaped
+                    ),
+                    Some("caption") => format!(
+                        r#"<p style="margin:0 0 12px;font-size:13px;color:#666666;">{}</p>"#,
+                        escaped
+                    ),
+                    _ => format!(r#"<p style="margin:0 0 12px;">{}</p>"#, escaped),
+                });
+            }
+            ContentBlock::Image { url, alt, width, height } => {
+                let url = resolve(url);
+                let alt = alt.clone().unwrap_or_default();
+                text_parts.push(if alt.is_empty() {
+                    url.clone()
+                } else {
+                    format!("{}: {}", alt, url)
+                });
+                let mut size = String::new();
+                if let Some(width) = width {
+                    size.push_str(&format!(r#" width="{}""#, width));
+                }
+                if let Some(height) = height {
+                    size.push_str(&format!(r#" height="{}""#, height));
+                }
+                html.push_str(&format!(
+                    r#"<p style="margin:0 0 12px;"><img src="{}" alt="{}"{} style="max-width:100%;height:auto;border:0;border-radius:4px;"></p>"#,
+                    escape_html(&url),
+                    escape_html(&alt),
+                    size
+                ));
+            }
+            ContentBlock::Divider => {
+                text_parts.push("----".to_string());
+                html.push_str(
+                    r#"<hr style="border:0;border-top:1px solid #e0e0e0;margin:16px 0;">"#,
+                );
+            }
+            ContentBlock::Spacer { height } => {
+                html.push_str(&format!(
+                    r#"<div style="height:{}px;line-height:0;font-size:0;">&nbsp;</div>"#,
+                    height.unwrap_or(8).clamp(0, 64)
+                ));
+            }
+        }
+    }
+
+    // Only navigation actions make sense outside the app ("dismiss" etc. are skipped)
+    let mut links: Vec<(String, String, bool)> = body
+        .content
+        .actions
+        .iter()
+        .flatten()
+        .filter_map(|action| {
+            action
+                .route
+                .as_ref()
+                .map(|route| (action.label.clone(), resolve(route), action.primary))
+        })
+        .collect();
+    if links.is_empty() {
+        if let Some(route) = &body.route {
+            links.push((tr(locale, "email.notification.open").to_string(), resolve(route), true));
+        }
+    }
+
+    for (label, url, primary) in &links {
+        text_parts.push(format!("{}:\n{}", label, url));
+        if *primary {
+            html.push_str(&button(accent, label, url));
+        } else {
+            html.push_str(&format!(
+                r#"<p style="margin:0 0 12px;text-align:center;"><a href="{}" style="color:{};">{}</a></p>"#,
+                escape_html(url),
+                accent,
+                escape_html(label)
+            ));
+        }
+    }
+
+    let manage = tr(locale, "email.notification.manage");
+    text_parts.push(manage.to_string());
+    html.push_str(&note_paragraph(manage));
+
+    text_parts.push(format!("--\n{}", footer(branding, locale)));
+
+    (text_parts.join("\n\n"), layout(branding, locale, &html))
+}
+
+fn button(accent: &str, label: &str, url: &str) -> String {
+    format!(
+        r#"<p style="margin:24px 0;text-align:center;"><a href="{url}" style="display:inline-block;padding:12px 24px;background-color:{accent};color:#ffffff;text-decoration:none;border-radius:6px;font-weight:bold;">{label}</a></p>"#,
+        url = escape_html(url),
+        accent = accent,
+        label = escape_html(label),
+    )
+}
+
+fn note_paragraph(text: &str) -> String {
+    format!(
+        r#"<p style="margin:0 0 16px;font-size:13px;color:#666666;">{}</p>"#,
+        escape_html(text)
+    )
+}
+
+/// Wrap rendered body HTML in the branded page (header, card, footer).
+fn layout(branding: &Branding, locale: Locale, body: &str) -> String {
+    let accent = &branding.accent_color;
+
+    let header = match &branding.logo_url {
+        Some(logo_url) => format!(
+            r#"<img src="{}" alt="{}" style="max-height:48px;border:0;">"#,
+            escape_html(logo_url),
+            escape_html(&branding.school_name)
+        ),
+        None => format!(
+            r#"<span style="font-size:20px;font-weight:bold;color:#ffffff;">{}</span>"#,
+            escape_html(&branding.school_name)
+        ),
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+</head>
+<body style="margin:0;padding:0;background-color:#f4f6f6;font-family:Arial,Helvetica,sans-serif;color:#222222;">
+<table role="presentation" width="100%" cellpadding="0" cellspacing="0" style="background-color:#f4f6f6;padding:24px 0;">
+<tr><td align="center">
+<table role="presentation" width="600" cellpadding="0" cellspacing="0" style="max-width:600px;width:100%;background-color:#ffffff;border-radius:8px;overflow:hidden;">
+<tr><td style="background-color:{accent};padding:20px 32px;">{header}</td></tr>
+<tr><td style="padding:32px;font-size:15px;line-height:1.5;">{body}</td></tr>
+<tr><td style="padding:16px 32px;background-color:#fafafa;font-size:12px;color:#888888;">{footer}</td></tr>
+</table>
+</td></tr>
+</table>
+</body>
+</html>
+"#,
+        lang = locale.code(),
+        title = escape_html(&branding.school_name),
+        accent = accent,
+        header = header,
+        body = body,
+        footer = escape_html(&footer(branding, locale)),
+    )
+}
+
+fn footer(branding: &Branding, locale: Locale) -> String {
+    tr_args(locale, "email.footer", &[("school", &branding.school_name)])
+}
+
+fn paragraph(text: &str) -> String {
+    format!(
+        r#"<p style="margin:0 0 16px;">{}</p>"#,
+        escape_html(text).replace('\n', "<br>")
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+fn is_hex_color(value: &str) -> bool {
+    let hex = match value.strip_prefix('#') {
+        Some(hex) => hex,
+        None => return false,
+    };
+    matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit())
+}
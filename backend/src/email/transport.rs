@@ -0,0 +1,193 @@
+use async_trait::async_trait;
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::PoolConfig;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::env;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use super::EmailError;
+
+/// A fully rendered email, ready to hand to a transport.
+#[derive(Debug, Clone)]
+pub struct OutgoingEmail {
+    pub from: Mailbox,
+    pub to: Mailbox,
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+impl OutgoingEmail {
+    /// Build the multipart/alternative MIME message (text first, HTML preferred).
+    pub fn to_message(&self) -> Result<Message, EmailError> {
+        Message::builder()
+            .from(self.from.clone())
+            .to(self.to.clone())
+            .subject(self.subject.clone())
+            .multipart(MultiPart::alternative_plain_html(
+                self.text.clone(),
+                self.html.clone(),
+            ))
+            .map_err(|e| EmailError::BuildError(e.to_string()))
+    }
+}
+
+#[async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send(&self, email: &OutgoingEmail) -> Result<(), EmailError>;
+}
+
+/// Pooled async SMTP delivery.
+///
+/// `SMTP_USE_TLS=false` sends in plain text (local relays only), port 465 uses
+/// implicit TLS and any other port uses STARTTLS. Without `SMTP_PORT` the
+/// implicit TLS default of the relay is used.
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    pub fn from_env() -> Result<Self, EmailError> {
+        let host = env::var("SMTP_HOST")
+            .map_err(|_| EmailError::ConfigError("SMTP_HOST not set".to_string()))?;
+        let port = match env::var("SMTP_PORT") {
+            Ok(value) => Some(value.parse::<u16>().map_err(|_| {
+                EmailError::ConfigError(format!("Invalid SMTP_PORT: {}", value))
+            })?),
+            Err(_) => None,
+        };
+        let use_tls = env::var("SMTP_USE_TLS")
+            .map(|value| !matches!(value.to_lowercase().as_str(), "false" | "0" | "no"))
+            .unwrap_or(true);
+        let pool_size = env::var("SMTP_POOL_SIZE")
+            .ok()
+            .and_then(|value| value.parse::<u32>().ok())
+            .unwrap_or(4);
+
+        let mut builder = if !use_tls {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&host)
+        } else if port.is_none() || port == Some(465) {
+            AsyncSmtpTransport::<Tokio1Executor>::relay(&host)
+                .map_err(|e| EmailError::ConfigError(format!("SMTP relay error: {}", e)))?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)
+                .map_err(|e| EmailError::ConfigError(format!("SMTP relay error: {}", e)))?
+        };
+
+        if let Some(port) = port {
+            builder = builder.port(port);
+        }
+
+        if let Ok(username) = env::var("SMTP_USERNAME") {
+            let password = env::var("SMTP_PASSWORD").unwrap_or_default();
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Self {
+            mailer: builder
+                .pool_config(PoolConfig::new().max_size(pool_size))
+                .build(),
+        })
+    }
+}
+
+#[async_trait]
+impl EmailTransport for SmtpTransport {
+    async fn send(&self, email: &OutgoingEmail) -> Result<(), EmailError> {
+        let message = email.to_message()?;
+        self.mailer
+            .send(message)
+            .await
+            .map_err(|e| EmailError::SendError(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Writes every email into a maildir (`new/`), readable by any mail client.
+/// Meant for development so no SMTP server is needed.
+pub struct MaildirTransport {
+    root: PathBuf,
+}
+
+impl MaildirTransport {
+    pub fn new(root: PathBuf) -> std::io::Result<Self> {
+        for sub in ["tmp", "new", "cur"] {
+            std::fs::create_dir_all(root.join(sub))?;
+        }
+        Ok(Self { root })
+    }
+
+    pub fn root(&self) -> &PathBuf {
+        &self.root
+    }
+}
+
+#[async_trait]
+impl EmailTransport for MaildirTransport {
+    async fn send(&self, email: &OutgoingEmail) -> Result<(), EmailError> {
+        let message = email.to_message()?;
+        let file_name = format!(
+            "{}.{}.klavierapp",
+            chrono::Utc::now().timestamp(),
+            Uuid::new_v4().simple()
+        );
+
+        // Maildir delivery: write to tmp/ and rename into new/ once complete.
+        let tmp_path = self.root.join("tmp").join(&file_name);
+        tokio::fs::write(&tmp_path, message.formatted())
+            .await
+            .map_err(|e| EmailError::SendError(format!("Maildir write error: {}", e)))?;
+        tokio::fs::rename(&tmp_path, self.root.join("new").join(&file_name))
+            .await
+            .map_err(|e| EmailError::SendError(format!("Maildir rename error: {}", e)))?;
+
+        Ok(())
+    }
+}
+
+/// Keeps sent emails in memory so tests can inspect them.
+#[derive(Default)]
+pub struct MemoryTransport {
+    sent: Mutex<Vec<OutgoingEmail>>,
+}
+
+impl MemoryTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Emails sent so far, oldest first.
+    pub fn sent(&self) -> Vec<OutgoingEmail> {
+        self.sent.lock().map(|sent| sent.clone()).unwrap_or_default()
+    }
+
+    /// Emails sent to the given address, oldest first.
+    pub fn sent_to(&self, address: &str) -> Vec<OutgoingEmail> {
+        self.sent()
+            .into_iter()
+            .filter(|email| email.to.email.to_string().eq_ignore_ascii_case(address))
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut sent) = self.sent.lock() {
+            sent.clear();
+        }
+    }
+}
+
+#[async_trait]
+impl EmailTransport for MemoryTransport {
+    async fn send(&self, email: &OutgoingEmail) -> Result<(), EmailError> {
+        // Build the MIME message anyway so invalid emails fail like they would in production.
+        email.to_message()?;
+        self.sent
+            .lock()
+            .map_err(|_| EmailError::SendError("Memory transport poisoned".to_string()))?
+            .push(email.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,433 @@
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, PgPool};
+use std::sync::Arc;
+
+use log::{error, info};
+
+use crate::audit::{self, AuditEvent};
+use crate::email::{EmailError, EmailService};
+use crate::i18n::Locale;
+use crate::password_reset::generate_token;
+use crate::users::{verify_token, Claims};
+use crate::AppState;
+
+/// How long a confirmation link stays valid
+const LINK_TTL_HOURS: i64 = 48;
+/// Minimum time between two confirmation emails for the same address
+const RESEND_INTERVAL_MINUTES: i64 = 2;
+
+#[derive(Debug)]
+pub enum EmailVerificationError {
+    DatabaseError(String),
+    TokenInvalid,
+    NoEmail,
+    AlreadyVerified,
+    RateLimited,
+    EmailError(EmailError),
+}
+
+impl std::fmt::Display for EmailVerificationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmailVerificationError::DatabaseError(s) => write!(f, "Database error: {}", s),
+            EmailVerificationError::TokenInvalid => {
+                write!(f, "Invalid, expired or already used confirmation link")
+            }
+            EmailVerificationError::NoEmail => write!(f, "No email address on file"),
+            EmailVerificationError::AlreadyVerified => write!(f, "Email address already verified"),
+            EmailVerificationError::RateLimited => write!(f, "Too many confirmation requests"),
+            EmailVerificationError::EmailError(e) => write!(f, "Email error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EmailVerificationError {}
+
+impl From<sqlx::Error> for EmailVerificationError {
+    fn from(err: sqlx::Error) -> Self {
+        EmailVerificationError::DatabaseError(err.to_string())
+    }
+}
+
+impl From<EmailError> for EmailVerificationError {
+    fn from(err: EmailError) -> Self {
+        EmailVerificationError::EmailError(err)
+    }
+}
+
+/// Tokens are looked up directly, so a fast digest is used instead of Argon2
+fn hash_token(token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(token.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// The user's email address if it has been confirmed.
+/// Anything that emails a user (password reset, sign-in links, notifications) goes through this.
+pub async fn verified_email(pool: &PgPool, user_id: i32) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar::<_, String>(
+        "SELECT email FROM users
+         WHERE id = $1 AND email IS NOT NULL AND email <> '' AND email_verified_at IS NOT NULL",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Send a confirmation link for the user's current email address.
+pub async fn request_verification(
+    pool: &PgPool,
+    user_id: i32,
+    email_service: Arc<EmailService>,
+) -> Result<(), EmailVerificationError> {
+    #[derive(FromRow)]
+    struct UserQuery {
+        username: String,
+        email: Option<String>,
+        email_verified: bool,
+        locale: String,
+    }
+
+    let user = sqlx::query_as::<_, UserQuery>(
+        "SELECT username, email, email_verified_at IS NOT NULL AS email_verified, locale
+         FROM users WHERE id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(EmailVerificationError::NoEmail)?;
+
+    let email = user
+        .email
+        .map(|email| email.trim().to_string())
+        .filter(|email| !email.is_empty())
+        .ok_or(EmailVerificationError::NoEmail)?;
+
+    if user.email_verified {
+        return Err(EmailVerificationError::AlreadyVerified);
+    }
+
+    let recently_sent = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(
+             SELECT 1 FROM email_verification_tokens
+             WHERE user_id = $1 AND email = $2
+               AND created_at > NOW() - make_interval(mins => $3)
+         )",
+    )
+    .bind(user_id)
+    .bind(&email)
+    .bind(RESEND_INTERVAL_MINUTES as i32)
+    .fetch_one(pool)
+    .await?;
+
+    if recently_sent {
+        return Err(EmailVerificationError::RateLimited);
+    }
+
+    let token = generate_token();
+    let expires_at = Utc::now() + Duration::hours(LINK_TTL_HOURS);
+
+    sqlx::query(
+        "INSERT INTO email_verification_tokens (user_id, email, token_hash, expires_at)
+         VALUES ($1, $2, $3, $4)",
+    )
+    .bind(user_id)
+    .bind(&email)
+    .bind(hash_token(&token))
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+
+    // Send email in the background to avoid blocking the response.
+    let locale = Locale::from_code(&user.locale).unwrap_or_default();
+    actix_web::rt::spawn(async move {
+        if let Err(err) = email_service
+            .send_email_verification_email(locale, &email, &user.username, &token, LINK_TTL_HOURS)
+            .await
+        {
+            error!("Email verification email failed: {}", err);
+        }
+    });
+
+    Ok(())
+}
+
+/// Send a confirmation link after an email change, logging instead of failing.
+/// Used by the endpoints that let users or admins edit addresses.
+pub async fn request_verification_after_change(
+    pool: &PgPool,
+    user_id: i32,
+    email_service: Arc<EmailService>,
+) {
+    match request_verification(pool, user_id, email_service).await {
+        Ok(())
+        | Err(EmailVerificationError::NoEmail)
+        | Err(EmailVerificationError::AlreadyVerified)
+        | Err(EmailVerificationError::RateLimited) => {}
+        Err(e) => error!("Failed to send email confirmation for user {}: {}", user_id, e),
+    }
+}
+
+/// Send confirmation links to every unverified address that has no unexpired
+/// link pending, e.g. the addresses that existed before verification was
+/// introduced. Returns how many links were sent.
+pub async fn request_verification_for_unverified(
+    pool: &PgPool,
+    email_service: Arc<EmailService>,
+) -> Result<u64, EmailVerificationError> {
+    let user_ids = sqlx::query_scalar::<_, i32>(
+        "SELECT u.id FROM users u
+         WHERE u.email IS NOT NULL AND TRIM(u.email) <> '' AND u.email_verified_at IS NULL
+           AND NOT EXISTS (
+               SELECT 1 FROM email_verification_tokens t
+               WHERE t.user_id = u.id AND t.email = TRIM(u.email)
+                 AND t.used_at IS NULL AND t.expires_at > NOW()
+           )
+         ORDER BY u.id",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut sent = 0;
+    for user_id in user_ids {
+        match request_verification(pool, user_id, email_service.clone()).await {
+            Ok(()) => sent += 1,
+            Err(EmailVerificationError::RateLimited) => {}
+            Err(e) => error!("Failed to send email confirmation for user {}: {}", user_id, e),
+        }
+    }
+
+    info!("Sent {} email confirmation links to unverified addresses", sent);
+    Ok(sent)
+}
+
+/// Consume a confirmation link and mark the address it was sent to as verified.
+/// Links for an address the user has since changed are rejected.
+pub async fn confirm_email(pool: &PgPool, token: &str) -> Result<i32, EmailVerificationError> {
+    let mut tx = pool.begin().await?;
+
+    let (user_id, email) = sqlx::query_as::<_, (i32, String)>(
+        "UPDATE email_verification_tokens SET used_at = NOW()
+         WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+         RETURNING user_id, email",
+    )
+    .bind(hash_token(token))
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(EmailVerificationError::TokenInvalid)?;
+
+    let updated = sqlx::query(
+        "UPDATE users SET email_verified_at = COALESCE(email_verified_at, NOW())
+         WHERE id = $1 AND email = $2",
+    )
+    .bind(user_id)
+    .bind(&email)
+    .execute(&mut *tx)
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        let _ = tx.rollback().await;
+        return Err(EmailVerificationError::TokenInvalid);
+    }
+
+    tx.commit().await?;
+    info!("Email address of user {} verified", user_id);
+
+    Ok(user_id)
+}
+
+/// Cleanup expired confirmation links (maintenance function)
+pub async fn cleanup_expired_tokens(pool: &PgPool) -> Result<u64, EmailVerificationError> {
+    let result = sqlx::query(
+        "DELETE FROM email_verification_tokens WHERE expires_at < NOW() - INTERVAL '7 days'",
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+fn verify_admin_claims(req: &HttpRequest, app_state: &AppState) -> Result<Claims, HttpResponse> {
+    let claims = verify_token(req, app_state)?;
+
+    if claims.impersonation.is_some() || !claims.roles.contains(&"admin".to_string()) {
+        return Err(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Admin access required"
+        })));
+    }
+
+    Ok(claims)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConfirmEmailRequest {
+    pub token: String,
+}
+
+/// Resend the confirmation link for the current user's address
+#[post("/email/verification")]
+pub(crate) async fn resend_verification(
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+) -> impl Responder {
+    let claims = match verify_token(&req, &app_state) {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+
+    let user_id = match sqlx::query_scalar::<_, i32>("SELECT id FROM users WHERE username = $1")
+        .bind(&claims.sub)
+        .fetch_optional(&app_state.db)
+        .await
+    {
+        Ok(Some(id)) => id,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": "User not found"
+            }));
+        }
+        Err(e) => {
+            error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    };
+
+    match request_verification(&app_state.db, user_id, app_state.email_service.clone()).await {
+        Ok(()) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Confirmation link sent"
+        })),
+        Err(EmailVerificationError::NoEmail) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "No email address on file"
+        })),
+        Err(EmailVerificationError::AlreadyVerified) => {
+            HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Email address already verified"
+            }))
+        }
+        Err(EmailVerificationError::RateLimited) => {
+            HttpResponse::TooManyRequests().json(serde_json::json!({
+                "error": "A confirmation link was sent recently. Please check your inbox."
+            }))
+        }
+        Err(e) => {
+            error!("Email verification request error: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }))
+        }
+    }
+}
+
+/// Confirm an address with the token from the emailed link (no login required)
+#[post("/verify-email")]
+pub(crate) async fn verify_email(
+    app_state: web::Data<AppState>,
+    req: web::Json<ConfirmEmailRequest>,
+) -> impl Responder {
+    match confirm_email(&app_state.db, &req.token).await {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({
+            "message": "Email address verified"
+        })),
+        Err(EmailVerificationError::TokenInvalid) => {
+            HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Invalid or expired confirmation link"
+            }))
+        }
+        Err(e) => {
+            error!("Email verification error: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }))
+        }
+    }
+}
+
+/// Admin: send a confirmation link to a user's current address
+#[post("/api/admin/users/{user_id}/email-verification")]
+async fn admin_send_verification(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+) -> impl Responder {
+    if let Err(response) = verify_admin_claims(&req, &app_state) {
+        return response;
+    }
+
+    let user_id = path.into_inner();
+
+    match request_verification(&app_state.db, user_id, app_state.email_service.clone()).await {
+        Ok(()) => {
+            audit::record(
+                &app_state,
+                &req,
+                AuditEvent::new("user.email_verification_sent", "user", Some(user_id)),
+            )
+            .await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "Confirmation link sent"
+            }))
+        }
+        Err(EmailVerificationError::NoEmail) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "User has no email address"
+        })),
+        Err(EmailVerificationError::AlreadyVerified) => {
+            HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Email address already verified"
+            }))
+        }
+        Err(EmailVerificationError::RateLimited) => {
+            HttpResponse::TooManyRequests().json(serde_json::json!({
+                "error": "A confirmation link was sent recently"
+            }))
+        }
+        Err(e) => {
+            error!("Email verification request error: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }))
+        }
+    }
+}
+
+/// Admin: send confirmation links to all unverified addresses at once.
+/// Addresses with a link still pending are skipped, so this can be repeated.
+#[post("/api/admin/email-verification/send-all")]
+async fn admin_send_verification_to_all(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    if let Err(response) = verify_admin_claims(&req, &app_state) {
+        return response;
+    }
+
+    match request_verification_for_unverified(&app_state.db, app_state.email_service.clone()).await {
+        Ok(sent) => {
+            audit::record(
+                &app_state,
+                &req,
+                AuditEvent::new("user.email_verification_bulk_sent", "user", None)
+                    .after(Some(serde_json::json!({ "sent": sent }))),
+            )
+            .await;
+
+            HttpResponse::Ok().json(serde_json::json!({ "sent": sent }))
+        }
+        Err(e) => {
+            error!("Bulk email verification error: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }))
+        }
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(admin_send_verification)
+        .service(admin_send_verification_to_all);
+}
@@ -1,18 +1,17 @@
 use actix_web::{web, HttpRequest, HttpResponse, Result};
 use chrono::{DateTime, Utc};
 use log::error;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value as JsonValue};
 use sqlx::{FromRow, PgConnection, PgPool};
 use std::collections::{HashMap, HashSet};
 
+use crate::audit::{self, AuditEvent};
 use crate::chats::{ChatAttachmentInput, ChatAttachmentResponse};
 use crate::notification_builders::{build_feed_comment_notification, build_feed_post_notification};
-use crate::notifications::is_user_notification_eligible;
-use crate::notifications::NotificationBody;
-use crate::push;
 use crate::users::verify_token;
-use crate::websockets;
+use crate::websocket_protocol::ServerMessage;
 use crate::AppState;
 
 #[derive(Debug, Serialize, FromRow, Clone)]
@@ -81,7 +80,7 @@ pub struct FeedPostResponse {
     pub attachments: Vec<ChatAttachmentResponse>,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct FeedCommentResponse {
     pub id: i32,
     pub post_id: i32,
@@ -474,30 +473,6 @@ async fn store_comment_attachments(
     Ok(stored)
 }
 
-async fn insert_notification(db: &PgPool, user_id: i32, body: &NotificationBody, priority: &str) {
-    if !is_user_notification_eligible(db, user_id).await {
-        return;
-    }
-
-    let notification_id = sqlx::query_scalar::<_, i32>(
-        "INSERT INTO notifications (user_id, type, title, body, priority)
-         VALUES ($1, $2, $3, $4, $5)
-         RETURNING id",
-    )
-    .bind(user_id)
-    .bind(&body.body_type)
-    .bind(&body.title)
-    .bind(serde_json::to_value(body).unwrap_or_default())
-    .bind(priority)
-    .fetch_optional(db)
-    .await
-    .unwrap_or(None);
-
-    if let Some(notification_id) = notification_id {
-        push::send_notification_to_user(db, user_id, body, Some(notification_id)).await;
-    }
-}
-
 fn is_admin(claims: &crate::users::Claims) -> bool {
     claims.roles.iter().any(|role| role == "admin")
 }
@@ -522,17 +497,37 @@ async fn ensure_feed_access(
     user_id: i32,
     claims: &crate::users::Claims,
 ) -> Result<()> {
-    if is_admin(claims) {
-        return Ok(());
+    let has_access = can_view_feed(&app_state.db, feed, user_id, is_admin(claims))
+        .await
+        .map_err(|e| {
+            error!("Database error checking feed access: {:?}", e);
+            actix_web::error::ErrorInternalServerError("Failed to check access")
+        })?;
+
+    if has_access {
+        Ok(())
+    } else {
+        Err(actix_web::error::ErrorForbidden("Access denied"))
+    }
+}
+
+async fn can_view_feed(
+    db: &PgPool,
+    feed: &Feed,
+    user_id: i32,
+    is_admin: bool,
+) -> Result<bool, sqlx::Error> {
+    if is_admin {
+        return Ok(true);
     }
 
     if feed.owner_type == "school" {
-        return Ok(());
+        return Ok(true);
     }
 
     if feed.owner_type == "teacher" {
         if feed.owner_user_id == Some(user_id) {
-            return Ok(());
+            return Ok(true);
         }
 
         let has_access: bool = sqlx::query_scalar(
@@ -554,15 +549,11 @@ async fn ensure_feed_access(
         )
         .bind(feed.owner_user_id)
         .bind(user_id)
-        .fetch_one(&app_state.db)
-        .await
-        .map_err(|e| {
-            error!("Database error checking feed access: {:?}", e);
-            actix_web::error::ErrorInternalServerError("Failed to check access")
-        })?;
+        .fetch_one(db)
+        .await?;
 
         if has_access {
-            return Ok(());
+            return Ok(true);
         }
     }
 
@@ -597,19 +588,38 @@ async fn ensure_feed_access(
         )
         .bind(feed.owner_group_id)
         .bind(user_id)
-        .fetch_one(&app_state.db)
-        .await
-        .map_err(|e| {
-            error!("Database error checking group feed access: {:?}", e);
-            actix_web::error::ErrorInternalServerError("Failed to check access")
-        })?;
+        .fetch_one(db)
+        .await?;
 
         if has_access {
-            return Ok(());
+            return Ok(true);
         }
     }
 
-    Err(actix_web::error::ErrorForbidden("Access denied"))
+    Ok(false)
+}
+
+/// Whether the user may read a post and follow its comments, by the rules of
+/// `ensure_feed_access`. `false` for posts that do not exist.
+pub(crate) async fn can_view_post(
+    db: &PgPool,
+    post_id: i32,
+    user_id: i32,
+    is_admin: bool,
+) -> Result<bool, sqlx::Error> {
+    let feed = sqlx::query_as::<_, Feed>(
+        "SELECT f.id, f.owner_type::text as owner_type, f.owner_user_id, f.owner_group_id, f.title, f.created_at
+         FROM feeds f JOIN feed_posts fp ON fp.feed_id = f.id
+         WHERE fp.id = $1",
+    )
+    .bind(post_id)
+    .fetch_optional(db)
+    .await?;
+
+    match feed {
+        Some(feed) => can_view_feed(db, &feed, user_id, is_admin).await,
+        None => Ok(false),
+    }
 }
 
 async fn ensure_feed_owner(
@@ -1416,11 +1426,13 @@ pub async fn create_post(
 
     if !recipients.is_empty() {
         let post_title = post.title.as_deref().unwrap_or("");
-        let body = build_feed_post_notification(&feed.title, post_title, feed.id, post.id);
+        let body =
+            |locale| build_feed_post_notification(locale, &feed.title, post_title, feed.id, post.id);
         let priority = if post.is_important { "high" } else { "normal" };
-        for recipient_id in recipients {
-            insert_notification(&app_state.db, recipient_id, &body, priority).await;
-        }
+        app_state
+            .notifications
+            .notify_many(&recipients, body, priority)
+            .await;
     }
 
     let response = FeedPostResponse {
@@ -1622,10 +1634,12 @@ pub async fn create_comment(
 
     if !recipients.is_empty() {
         let post_title = post.title.as_deref().unwrap_or("");
-        let body = build_feed_comment_notification(&feed.title, post_title, feed.id, post.id);
-        for recipient_id in recipients {
-            insert_notification(&app_state.db, recipient_id, &body, "normal").await;
-        }
+        let body =
+            |locale| build_feed_comment_notification(locale, &feed.title, post_title, feed.id, post.id);
+        app_state
+            .notifications
+            .notify_many(&recipients, body, "normal")
+            .await;
     }
 
     let (author_name, author_profile_image) =
@@ -1644,14 +1658,9 @@ pub async fn create_comment(
         attachments: attachments.clone(),
     };
 
-    let comment_data =
-        serde_json::to_value(&comment_response).unwrap_or_else(|_| serde_json::json!({}));
-    let ws_message = websockets::WsMessage {
-        msg_type: "comment".to_string(),
-        user_id: Some(user_id),
-        thread_id: None,
-        post_id: Some(*post_id),
-        data: comment_data,
+    let ws_message = ServerMessage::Comment {
+        user_id,
+        comment: Box::new(comment_response.clone()),
     };
     app_state
         .ws_server
@@ -1924,13 +1933,9 @@ pub async fn update_comment(
         attachments: final_attachments,
     };
 
-    let comment_data = serde_json::to_value(&response).unwrap_or_else(|_| serde_json::json!({}));
-    let ws_message = websockets::WsMessage {
-        msg_type: "comment".to_string(),
-        user_id: Some(user_id),
-        thread_id: None,
-        post_id: Some(updated_comment.post_id),
-        data: comment_data,
+    let ws_message = ServerMessage::Comment {
+        user_id,
+        comment: Box::new(response.clone()),
     };
     app_state
         .ws_server
@@ -2169,6 +2174,14 @@ pub async fn delete_comment(
         actix_web::error::ErrorInternalServerError("Failed to delete comment")
     })?;
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("feed_comment.delete", "feed_comment", Some(comment.id))
+            .before(serde_json::to_value(&comment).ok()),
+    )
+    .await;
+
     Ok(HttpResponse::Ok().finish())
 }
 
@@ -2216,6 +2229,14 @@ pub async fn delete_post(
         return Err(actix_web::error::ErrorNotFound("Post not found"));
     }
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("feed_post.delete", "feed_post", Some(post.id))
+            .before(serde_json::to_value(&post).ok()),
+    )
+    .await;
+
     Ok(HttpResponse::Ok().finish())
 }
 
@@ -0,0 +1,1112 @@
+use std::fs::File;
+use std::io::{Seek, SeekFrom, Write};
+
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+use bytes::Bytes;
+use argon2::password_hash::{rand_core::OsRng, SaltString};
+use argon2::{Argon2, PasswordHasher};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::io::ReaderStream;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::audit::{self, AuditEvent};
+use crate::password_reset::generate_token;
+use crate::storage::StorageProvider;
+use crate::users::{verify_token, Claims};
+use crate::AppState;
+
+/// Uploaded media beyond this total is listed in `media.json` but not embedded
+const MAX_EXPORT_MEDIA_BYTES: u64 = 512 * 1024 * 1024;
+/// Exports a user may start per day
+const MAX_EXPORTS_PER_DAY: i64 = 3;
+/// An export still marked running after this long was abandoned
+const EXPORT_TIMEOUT_MINUTES: i32 = 60;
+/// Chunks queued for the archive writer; bounds the memory an export holds
+const ARCHIVE_QUEUE: usize = 16;
+
+/// Per-user JSON files in the export archive. Each query takes the user id as $1.
+const EXPORT_SECTIONS: &[(&str, &str)] = &[
+    (
+        "relations.json",
+        "SELECT 'parent_of' AS relation, student_user_id AS other_user_id, created_at
+         FROM parent_student_relations WHERE parent_user_id = $1
+         UNION ALL
+         SELECT 'child_of', parent_user_id, created_at
+         FROM parent_student_relations WHERE student_user_id = $1
+         UNION ALL
+         SELECT 'teacher_of', student_user_id, created_at
+         FROM teacher_student_relations WHERE teacher_user_id = $1
+         UNION ALL
+         SELECT 'student_of', teacher_user_id, created_at
+         FROM teacher_student_relations WHERE student_user_id = $1",
+    ),
+    (
+        "hometasks.json",
+        "SELECT h.id, h.teacher_id, h.student_id, h.title, h.description, h.status::text AS status,
+                h.hometask_type::text AS hometask_type, h.due_date, h.repeat_every_days,
+                h.created_at, h.updated_at
+         FROM hometasks h WHERE h.student_id = $1 OR h.teacher_id = $1
+         ORDER BY h.created_at",
+    ),
+    (
+        "hometask_submissions.json",
+        "SELECT id, hometask_id, submission_type::text AS submission_type, content, created_at
+         FROM hometask_submissions WHERE student_id = $1
+         ORDER BY created_at",
+    ),
+    (
+        "chat_messages.json",
+        "SELECT m.id, m.thread_id, m.body, m.created_at, m.updated_at,
+                COALESCE((SELECT jsonb_agg(a.media_id) FROM chat_message_attachments a
+                          WHERE a.message_id = m.id), '[]'::jsonb) AS media_ids
+         FROM chat_messages m WHERE m.sender_id = $1
+         ORDER BY m.created_at",
+    ),
+    (
+        "feed_posts.json",
+        "SELECT p.id, p.feed_id, p.title, p.content, p.is_important, p.allow_comments,
+                p.created_at, p.updated_at,
+                COALESCE((SELECT jsonb_agg(pm.media_id ORDER BY pm.sort_order) FROM feed_post_media pm
+                          WHERE pm.post_id = p.id), '[]'::jsonb) AS media_ids
+         FROM feed_posts p WHERE p.author_user_id = $1
+         ORDER BY p.created_at",
+    ),
+    (
+        "feed_comments.json",
+        "SELECT c.id, c.post_id, c.parent_comment_id, c.content, c.created_at, c.updated_at,
+                COALESCE((SELECT jsonb_agg(cm.media_id ORDER BY cm.sort_order) FROM feed_comment_media cm
+                          WHERE cm.comment_id = c.id), '[]'::jsonb) AS media_ids
+         FROM feed_comments c WHERE c.author_user_id = $1
+         ORDER BY c.created_at",
+    ),
+    (
+        "consents.json",
+        "SELECT uc.version, uc.locale, uc.on_behalf_of_minor, uc.accepted_at,
+                a.username AS accepted_by
+         FROM user_consents uc LEFT JOIN users a ON a.id = uc.accepted_by
+         WHERE uc.user_id = $1
+         ORDER BY uc.version",
+    ),
+    (
+        "notifications.json",
+        "SELECT id, type, title, body, priority, created_at, read_at, archived_at, snoozed_until
+         FROM notifications WHERE user_id = $1
+         ORDER BY created_at",
+    ),
+    (
+        "notification_preferences.json",
+        "SELECT notification_type, in_app, push, email, updated_at
+         FROM notification_channel_preferences WHERE user_id = $1
+         ORDER BY notification_type",
+    ),
+    (
+        "notification_digest.json",
+        "SELECT frequency, send_hour, send_weekday, channel, notification_types,
+                last_sent_at, updated_at
+         FROM notification_digest_settings WHERE user_id = $1",
+    ),
+    (
+        "notification_quiet_hours.json",
+        "SELECT enabled, start_time, end_time, updated_at
+         FROM notification_quiet_hours WHERE user_id = $1",
+    ),
+];
+
+/// Quill document that replaces chat messages, posts and comments of deleted accounts
+fn redacted_content() -> serde_json::Value {
+    serde_json::json!({ "ops": [{ "insert": "This content was removed at the author's request.\n" }] })
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    DatabaseError(String),
+    ArchiveError(String),
+    AlreadyRunning,
+    RateLimited,
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::DatabaseError(s) => write!(f, "Database error: {}", s),
+            ExportError::ArchiveError(s) => write!(f, "Archive error: {}", s),
+            ExportError::AlreadyRunning => write!(f, "A data export is already being prepared"),
+            ExportError::RateLimited => write!(f, "Too many data exports today"),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<sqlx::Error> for ExportError {
+    fn from(err: sqlx::Error) -> Self {
+        ExportError::DatabaseError(err.to_string())
+    }
+}
+
+impl From<zip::result::ZipError> for ExportError {
+    fn from(err: zip::result::ZipError) -> Self {
+        ExportError::ArchiveError(err.to_string())
+    }
+}
+
+impl From<std::io::Error> for ExportError {
+    fn from(err: std::io::Error) -> Self {
+        ExportError::ArchiveError(err.to_string())
+    }
+}
+
+/// Record the start of an export for `user_id`, refusing it while another is
+/// running or once the daily limit is reached
+async fn start_export(pool: &PgPool, user_id: i32) -> Result<i32, ExportError> {
+    // A crash mid-export leaves the row running; don't lock the user out for good
+    sqlx::query(
+        "UPDATE data_exports SET finished_at = NOW()
+         WHERE user_id = $1 AND finished_at IS NULL
+           AND started_at < NOW() - make_interval(mins => $2)",
+    )
+    .bind(user_id)
+    .bind(EXPORT_TIMEOUT_MINUTES)
+    .execute(pool)
+    .await?;
+
+    let exports_today = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM data_exports
+         WHERE user_id = $1 AND started_at > NOW() - INTERVAL '1 day'",
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    if exports_today >= MAX_EXPORTS_PER_DAY {
+        return Err(ExportError::RateLimited);
+    }
+
+    sqlx::query_scalar::<_, i32>(
+        "INSERT INTO data_exports (user_id) VALUES ($1)
+         ON CONFLICT (user_id) WHERE finished_at IS NULL DO NOTHING
+         RETURNING id",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(ExportError::AlreadyRunning)
+}
+
+async fn finish_export(pool: &PgPool, export_id: i32) {
+    if let Err(e) = sqlx::query("UPDATE data_exports SET finished_at = NOW() WHERE id = $1")
+        .bind(export_id)
+        .execute(pool)
+        .await
+    {
+        error!("Failed to mark data export {} finished: {}", export_id, e);
+    }
+}
+
+enum ArchiveCommand {
+    StartFile(String, SimpleFileOptions),
+    Write(Bytes),
+}
+
+/// Writes the export archive to an anonymous temporary file on a blocking
+/// thread, so neither the archive nor the zip compression sits on the
+/// request's worker
+struct ArchiveWriter {
+    commands: mpsc::Sender<ArchiveCommand>,
+    writer: JoinHandle<Result<File, ExportError>>,
+}
+
+impl ArchiveWriter {
+    fn new() -> Self {
+        let (commands, mut receiver) = mpsc::channel(ARCHIVE_QUEUE);
+        let writer = tokio::task::spawn_blocking(move || {
+            let mut archive = ZipWriter::new(tempfile::tempfile()?);
+            while let Some(command) = receiver.blocking_recv() {
+                match command {
+                    ArchiveCommand::StartFile(name, options) => archive.start_file(name, options)?,
+                    ArchiveCommand::Write(data) => archive.write_all(&data)?,
+                }
+            }
+            let mut file = archive.finish()?;
+            file.seek(SeekFrom::Start(0))?;
+            Ok(file)
+        });
+        ArchiveWriter { commands, writer }
+    }
+
+    async fn send(&mut self, command: ArchiveCommand) -> Result<(), ExportError> {
+        if self.commands.send(command).await.is_err() {
+            // The writer stopped early; its result says why
+            return Err(match (&mut self.writer).await {
+                Ok(Err(e)) => e,
+                _ => ExportError::ArchiveError("Archive writer stopped".to_string()),
+            });
+        }
+        Ok(())
+    }
+
+    async fn start_file(&mut self, name: String, options: SimpleFileOptions) -> Result<(), ExportError> {
+        self.send(ArchiveCommand::StartFile(name, options)).await
+    }
+
+    async fn write(&mut self, data: Bytes) -> Result<(), ExportError> {
+        self.send(ArchiveCommand::Write(data)).await
+    }
+
+    async fn write_json(
+        &mut self,
+        name: &str,
+        options: SimpleFileOptions,
+        value: &serde_json::Value,
+    ) -> Result<(), ExportError> {
+        self.start_file(name.to_string(), options).await?;
+        self.write(serde_json::to_vec_pretty(value).unwrap_or_default().into()).await
+    }
+
+    /// Stream a stored file into a new archive entry and return its size
+    async fn copy_stored_file(
+        &mut self,
+        provider: &dyn StorageProvider,
+        key: &str,
+        name: String,
+        options: SimpleFileOptions,
+    ) -> Result<Option<u64>, ExportError> {
+        let mut stream = match provider.get(key).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("File {} missing from export: {}", key, e);
+                return Ok(None);
+            }
+        };
+        self.start_file(name, options).await?;
+        let mut size = 0;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            size += chunk.len() as u64;
+            self.write(chunk).await?;
+        }
+        Ok(Some(size))
+    }
+
+    async fn finish(self) -> Result<File, ExportError> {
+        drop(self.commands);
+        self.writer
+            .await
+            .map_err(|e| ExportError::ArchiveError(e.to_string()))?
+    }
+}
+
+#[derive(Debug, FromRow)]
+struct ExportMedia {
+    id: i32,
+    storage_key: String,
+    media_type: String,
+    mime_type: String,
+    size_bytes: i32,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct DeletionRequest {
+    pub id: i32,
+    pub user_id: i32,
+    pub username: String,
+    pub reason: Option<String>,
+    pub status: String,
+    pub requested_at: DateTime<Utc>,
+    pub reviewed_by: Option<i32>,
+    pub reviewed_by_username: Option<String>,
+    pub reviewed_at: Option<DateTime<Utc>>,
+    pub admin_note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDeletionRequest {
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReviewDeletionRequest {
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeletionRequestsQuery {
+    pub status: Option<String>,
+}
+
+const SELECT_DELETION_REQUEST: &str = "SELECT d.id, d.user_id, d.username, d.reason, d.status, d.requested_at,
+        d.reviewed_by, r.username AS reviewed_by_username, d.reviewed_at, d.admin_note
+     FROM account_deletion_requests d
+     LEFT JOIN users r ON r.id = d.reviewed_by";
+
+/// Resolve the account behind a data-subject request.
+/// Impersonation sessions are refused: exports and deletions are for the user themselves.
+async fn self_service_user(req: &HttpRequest, app_state: &AppState) -> Result<(Claims, i32), HttpResponse> {
+    let claims = verify_token(req, app_state)?;
+
+    if claims.impersonation.is_some() {
+        return Err(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Not available while impersonating a user"
+        })));
+    }
+
+    match sqlx::query_scalar::<_, i32>("SELECT id FROM users WHERE username = $1")
+        .bind(&claims.sub)
+        .fetch_optional(&app_state.db)
+        .await
+    {
+        Ok(Some(user_id)) => Ok((claims, user_id)),
+        Ok(None) => Err(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "User not found"
+        }))),
+        Err(e) => {
+            error!("Database error: {}", e);
+            Err(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            })))
+        }
+    }
+}
+
+fn verify_admin_claims(req: &HttpRequest, app_state: &AppState) -> Result<Claims, HttpResponse> {
+    let claims = verify_token(req, app_state)?;
+
+    if claims.impersonation.is_some() || !claims.roles.contains(&"admin".to_string()) {
+        return Err(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Admin access required"
+        })));
+    }
+
+    Ok(claims)
+}
+
+async fn export_section(db: &PgPool, query: &str, user_id: i32) -> Result<serde_json::Value, sqlx::Error> {
+    sqlx::query_scalar::<_, serde_json::Value>(&format!(
+        "SELECT COALESCE(jsonb_agg(to_jsonb(t)), '[]'::jsonb) FROM ({}) t",
+        query
+    ))
+    .bind(user_id)
+    .fetch_one(db)
+    .await
+}
+
+/// Build a zip archive with everything stored about `user_id` in an anonymous
+/// temporary file, positioned at its start.
+pub async fn build_export_archive(app_state: &AppState, user_id: i32) -> Result<File, ExportError> {
+    let json_options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    // Uploaded media is already compressed
+    let media_options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut archive = ArchiveWriter::new();
+
+    let profile = sqlx::query_scalar::<_, serde_json::Value>(
+        "SELECT jsonb_build_object(
+            'id', u.id,
+            'username', u.username,
+            'full_name', u.full_name,
+            'email', u.email,
+            'email_verified_at', u.email_verified_at,
+            'phone', u.phone,
+            'locale', u.locale,
+            'timezone', u.timezone,
+            'profile_image', u.profile_image,
+            'created_at', u.created_at,
+            'roles', COALESCE((SELECT jsonb_agg(r.name ORDER BY r.name)
+                               FROM user_roles ur JOIN roles r ON r.id = ur.role_id
+                               WHERE ur.user_id = u.id), '[]'::jsonb),
+            'student', (SELECT jsonb_build_object('birthday', s.birthday, 'status', s.status::text,
+                                                  'created_at', s.created_at, 'archived_at', s.archived_at)
+                        FROM students s WHERE s.user_id = u.id),
+            'parent', (SELECT jsonb_build_object('status', p.status::text,
+                                                 'created_at', p.created_at, 'archived_at', p.archived_at)
+                       FROM parents p WHERE p.user_id = u.id),
+            'teacher', (SELECT jsonb_build_object('status', t.status::text,
+                                                  'created_at', t.created_at, 'archived_at', t.archived_at)
+                        FROM teachers t WHERE t.user_id = u.id),
+            'linked_identities', COALESCE((SELECT jsonb_agg(jsonb_build_object(
+                                               'provider', i.provider, 'email', i.email,
+                                               'linked_at', i.linked_at, 'last_login_at', i.last_login_at))
+                                           FROM user_oidc_identities i WHERE i.user_id = u.id), '[]'::jsonb)
+         )
+         FROM users u WHERE u.id = $1",
+    )
+    .bind(user_id)
+    .fetch_one(&app_state.db)
+    .await?;
+
+    archive.write_json("profile.json", json_options, &profile).await?;
+
+    for (filename, query) in EXPORT_SECTIONS {
+        let section = export_section(&app_state.db, query, user_id).await?;
+        archive.write_json(filename, json_options, &section).await?;
+    }
+
+    if let Some(image) = profile.get("profile_image").and_then(|value| value.as_str()) {
+        archive
+            .copy_stored_file(
+                app_state.storage.as_ref(),
+                image,
+                format!("profile_image/{}", image),
+                media_options,
+            )
+            .await?;
+    }
+
+    let media = sqlx::query_as::<_, ExportMedia>(
+        "SELECT id, storage_key, media_type::text AS media_type, mime_type, size_bytes, created_at
+         FROM media_files WHERE created_by_user_id = $1
+         ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(&app_state.db)
+    .await?;
+
+    let mut media_index = Vec::with_capacity(media.len());
+    let mut embedded_bytes: u64 = 0;
+    for file in media {
+        let mut archive_path = None;
+        if embedded_bytes + file.size_bytes.max(0) as u64 <= MAX_EXPORT_MEDIA_BYTES {
+            let path = format!("media/{}", file.storage_key);
+            if let Some(size) = archive
+                .copy_stored_file(
+                    app_state.media_storage.as_ref(),
+                    &file.storage_key,
+                    path.clone(),
+                    media_options,
+                )
+                .await?
+            {
+                embedded_bytes += size;
+                archive_path = Some(path);
+            }
+        }
+
+        media_index.push(serde_json::json!({
+            "id": file.id,
+            "media_type": file.media_type,
+            "mime_type": file.mime_type,
+            "size_bytes": file.size_bytes,
+            "created_at": file.created_at,
+            "url": app_state.media_storage.public_url(&file.storage_key),
+            "archive_path": archive_path,
+        }));
+    }
+
+    archive
+        .write_json("media.json", json_options, &serde_json::Value::from(media_index))
+        .await?;
+
+    let manifest = serde_json::json!({
+        "user_id": user_id,
+        "generated_at": Utc::now(),
+    });
+    archive.write_json("manifest.json", json_options, &manifest).await?;
+
+    archive.finish().await
+}
+
+/// Files to remove from storage once an anonymisation has been committed
+pub struct PurgedFiles {
+    pub profile_image: Option<String>,
+    pub media_keys: Vec<String>,
+}
+
+/// Anonymise an account inside `tx`.
+///
+/// The users row is kept under a placeholder name so chat threads, hometasks a
+/// teacher assigned and feed threads stay intact for the other participants.
+/// Content the user authored is redacted, everything only they use is deleted.
+pub async fn anonymise_user(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: i32,
+) -> Result<PurgedFiles, sqlx::Error> {
+    #[derive(FromRow)]
+    struct UserQuery {
+        username: String,
+        email: Option<String>,
+        profile_image: Option<String>,
+    }
+
+    let user = sqlx::query_as::<_, UserQuery>(
+        "SELECT username, email, profile_image FROM users WHERE id = $1 FOR UPDATE",
+    )
+    .bind(user_id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let media_keys = sqlx::query_scalar::<_, String>(
+        "SELECT storage_key FROM media_files WHERE created_by_user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let redacted = redacted_content();
+    sqlx::query("UPDATE chat_messages SET body = $2 WHERE sender_id = $1")
+        .bind(user_id)
+        .bind(&redacted)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query("UPDATE feed_posts SET title = NULL, content = $2 WHERE author_user_id = $1")
+        .bind(user_id)
+        .bind(&redacted)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query("UPDATE feed_comments SET content = $2 WHERE author_user_id = $1")
+        .bind(user_id)
+        .bind(&redacted)
+        .execute(&mut **tx)
+        .await?;
+
+    // Attachments go with the media rows through ON DELETE CASCADE
+    sqlx::query("DELETE FROM media_files WHERE created_by_user_id = $1")
+        .bind(user_id)
+        .execute(&mut **tx)
+        .await?;
+
+    // Own hometasks (and their submissions) only matter to the student
+    sqlx::query("DELETE FROM hometasks WHERE student_id = $1")
+        .bind(user_id)
+        .execute(&mut **tx)
+        .await?;
+
+    for table in [
+        "notifications",
+        "push_tokens",
+        "password_reset_tokens",
+        "magic_link_tokens",
+        "email_verification_tokens",
+        "user_oidc_identities",
+        "chat_presence",
+        "feed_user_settings",
+        "feed_post_subscriptions",
+        "feed_post_reads",
+        "notification_channel_preferences",
+        "notification_digest_settings",
+        "notification_quiet_hours",
+        "push_outbox",
+        "announcement_recipients",
+    ] {
+        sqlx::query(&format!("DELETE FROM {} WHERE user_id = $1", table))
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    sqlx::query("DELETE FROM password_reset_requests WHERE username = $1")
+        .bind(&user.username)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query("DELETE FROM magic_link_requests WHERE identifier = LOWER($1) OR identifier = LOWER($2)")
+        .bind(&user.username)
+        .bind(&user.email)
+        .execute(&mut **tx)
+        .await?;
+
+    sqlx::query(
+        "UPDATE students SET birthday = DATE '1900-01-01', status = 'archived',
+            archived_at = COALESCE(archived_at, NOW())
+         WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .execute(&mut **tx)
+    .await?;
+    for table in ["parents", "teachers"] {
+        sqlx::query(&format!(
+            "UPDATE {} SET status = 'archived', archived_at = COALESCE(archived_at, NOW()) WHERE user_id = $1",
+            table
+        ))
+        .bind(user_id)
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    sqlx::query(
+        "UPDATE impersonation_sessions SET ended_at = NOW()
+         WHERE target_user_id = $1 AND ended_at IS NULL",
+    )
+    .bind(user_id)
+    .execute(&mut **tx)
+    .await?;
+
+    // Random password nobody knows; login fails like for any wrong password
+    let salt = SaltString::generate(&mut OsRng);
+    let password_hash = Argon2::default()
+        .hash_password(generate_token().as_bytes(), &salt)
+        .map_err(|e| sqlx::Error::Protocol(format!("Password hashing error: {}", e)))?
+        .to_string();
+    let placeholder = format!("deleted-user-{}", user_id);
+
+    sqlx::query(
+        "UPDATE users SET username = $2, full_name = 'Deleted user', email = NULL, phone = NULL,
+            profile_image = NULL, password_hash = $3
+         WHERE id = $1",
+    )
+    .bind(user_id)
+    .bind(&placeholder)
+    .bind(&password_hash)
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query("UPDATE audit_log SET actor_username = $2 WHERE actor_user_id = $1")
+        .bind(user_id)
+        .bind(&placeholder)
+        .execute(&mut **tx)
+        .await?;
+    // Snapshots of the account taken by `audit::user_snapshot`
+    sqlx::query(
+        "UPDATE audit_log
+         SET before_data = before_data - '{username,full_name,email,phone}'::text[],
+             after_data = after_data - '{username,full_name,email,phone}'::text[]
+         WHERE entity_type = 'user' AND entity_id = $1",
+    )
+    .bind(user_id)
+    .execute(&mut **tx)
+    .await?;
+
+    // Replayable WebSocket events carry their name and message bodies
+    sqlx::query(
+        "DELETE FROM ws_events
+         WHERE message->>'sender_id' = $1::text OR message->>'user_id' = $1::text",
+    )
+    .bind(user_id)
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query("DELETE FROM ws_user_events WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(PurgedFiles {
+        profile_image: user.profile_image.filter(|image| !image.is_empty()),
+        media_keys,
+    })
+}
+
+/// Download everything stored about the current user as a zip archive.
+/// One export runs at a time per user, at most `MAX_EXPORTS_PER_DAY` a day.
+#[get("/export")]
+pub(crate) async fn export_data(app_state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    let (claims, user_id) = match self_service_user(&req, &app_state).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let export_id = match start_export(&app_state.db, user_id).await {
+        Ok(export_id) => export_id,
+        Err(e @ (ExportError::AlreadyRunning | ExportError::RateLimited)) => {
+            return HttpResponse::TooManyRequests().json(serde_json::json!({
+                "error": e.to_string()
+            }));
+        }
+        Err(e) => {
+            error!("Failed to start data export for user {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to build data export"
+            }));
+        }
+    };
+    let archive = build_export_archive(&app_state, user_id).await;
+    finish_export(&app_state.db, export_id).await;
+    let archive = match archive.and_then(|file| Ok((file.metadata()?.len(), file))) {
+        Ok(archive) => archive,
+        Err(e) => {
+            error!("Data export for user {} failed: {}", user_id, e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to build data export"
+            }));
+        }
+    };
+
+    audit::record(&app_state, &req, AuditEvent::new("user.data_export", "user", Some(user_id))).await;
+
+    let (length, file) = archive;
+    let body = ReaderStream::new(tokio::fs::File::from_std(file));
+    HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header((
+            "Content-Disposition",
+            format!(
+                "attachment; filename=\"{}-data-export-{}.zip\"",
+                claims.sub,
+                Utc::now().format("%Y-%m-%d")
+            ),
+        ))
+        .no_chunking(length)
+        .streaming(body)
+}
+
+/// Ask for the current account to be deleted; an admin has to approve it
+#[post("/deletion-request")]
+pub(crate) async fn request_deletion(
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<CreateDeletionRequest>,
+) -> impl Responder {
+    let (claims, user_id) = match self_service_user(&req, &app_state).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    if claims.roles.iter().any(|role| role == "admin") {
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Admin accounts have to be removed by another admin"
+        }));
+    }
+
+    let reason = body
+        .reason
+        .as_deref()
+        .map(str::trim)
+        .filter(|reason| !reason.is_empty());
+
+    let request_id = match sqlx::query_scalar::<_, i32>(
+        "INSERT INTO account_deletion_requests (user_id, username, reason)
+         VALUES ($1, $2, $3)
+         ON CONFLICT (user_id) WHERE status = 'pending' DO NOTHING
+         RETURNING id",
+    )
+    .bind(user_id)
+    .bind(&claims.sub)
+    .bind(reason)
+    .fetch_optional(&app_state.db)
+    .await
+    {
+        Ok(Some(id)) => id,
+        Ok(None) => {
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "A deletion request is already pending"
+            }));
+        }
+        Err(e) => {
+            error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to create deletion request"
+            }));
+        }
+    };
+
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("account_deletion_request.create", "user", Some(user_id))
+            .after(Some(serde_json::json!({ "request_id": request_id }))),
+    )
+    .await;
+
+    match sqlx::query_as::<_, DeletionRequest>(&format!("{} WHERE d.id = $1", SELECT_DELETION_REQUEST))
+        .bind(request_id)
+        .fetch_one(&app_state.db)
+        .await
+    {
+        Ok(request) => HttpResponse::Created().json(request),
+        Err(e) => {
+            error!("Database error: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }))
+        }
+    }
+}
+
+/// Latest deletion request of the current user
+#[get("/deletion-request")]
+pub(crate) async fn get_deletion_request(app_state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    let (_, user_id) = match self_service_user(&req, &app_state).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    match sqlx::query_as::<_, DeletionRequest>(&format!(
+        "{} WHERE d.user_id = $1 ORDER BY d.requested_at DESC LIMIT 1",
+        SELECT_DELETION_REQUEST
+    ))
+    .bind(user_id)
+    .fetch_optional(&app_state.db)
+    .await
+    {
+        Ok(Some(request)) => HttpResponse::Ok().json(request),
+        Ok(None) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No deletion request found"
+        })),
+        Err(e) => {
+            error!("Database error: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }))
+        }
+    }
+}
+
+/// Withdraw a pending deletion request
+#[delete("/deletion-request")]
+pub(crate) async fn cancel_deletion_request(app_state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    let (_, user_id) = match self_service_user(&req, &app_state).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let request_id = match sqlx::query_scalar::<_, i32>(
+        "UPDATE account_deletion_requests SET status = 'cancelled'
+         WHERE user_id = $1 AND status = 'pending'
+         RETURNING id",
+    )
+    .bind(user_id)
+    .fetch_optional(&app_state.db)
+    .await
+    {
+        Ok(Some(id)) => id,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": "No pending deletion request"
+            }));
+        }
+        Err(e) => {
+            error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to cancel deletion request"
+            }));
+        }
+    };
+
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("account_deletion_request.cancel", "user", Some(user_id))
+            .after(Some(serde_json::json!({ "request_id": request_id }))),
+    )
+    .await;
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "message": "Deletion request cancelled"
+    }))
+}
+
+#[get("/api/admin/account-deletion-requests")]
+async fn list_deletion_requests(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<DeletionRequestsQuery>,
+) -> impl Responder {
+    if let Err(response) = verify_admin_claims(&req, &app_state) {
+        return response;
+    }
+
+    match sqlx::query_as::<_, DeletionRequest>(&format!(
+        "{} WHERE ($1::TEXT IS NULL OR d.status = $1) ORDER BY d.requested_at DESC",
+        SELECT_DELETION_REQUEST
+    ))
+    .bind(&query.status)
+    .fetch_all(&app_state.db)
+    .await
+    {
+        Ok(requests) => HttpResponse::Ok().json(requests),
+        Err(e) => {
+            error!("Database error: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to fetch deletion requests"
+            }))
+        }
+    }
+}
+
+/// Approve a pending request and anonymise the account
+#[post("/api/admin/account-deletion-requests/{id}/approve")]
+async fn approve_deletion_request(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+    body: web::Json<ReviewDeletionRequest>,
+) -> impl Responder {
+    let claims = match verify_admin_claims(&req, &app_state) {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let request_id = path.into_inner();
+
+    let mut tx = match app_state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    };
+
+    let user_id = match sqlx::query_scalar::<_, i32>(
+        "SELECT user_id FROM account_deletion_requests WHERE id = $1 AND status = 'pending' FOR UPDATE",
+    )
+    .bind(request_id)
+    .fetch_optional(&mut *tx)
+    .await
+    {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => {
+            let _ = tx.rollback().await;
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Pending deletion request not found"
+            }));
+        }
+        Err(e) => {
+            let _ = tx.rollback().await;
+            error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    };
+
+    let is_admin = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM user_roles ur JOIN roles r ON r.id = ur.role_id
+                        WHERE ur.user_id = $1 AND r.name = 'admin')",
+    )
+    .bind(user_id)
+    .fetch_one(&mut *tx)
+    .await;
+
+    match is_admin {
+        Ok(false) => {}
+        Ok(true) => {
+            let _ = tx.rollback().await;
+            return HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Remove the admin role before deleting this account"
+            }));
+        }
+        Err(e) => {
+            let _ = tx.rollback().await;
+            error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    }
+
+    let purged = match anonymise_user(&mut tx, user_id).await {
+        Ok(purged) => purged,
+        Err(e) => {
+            let _ = tx.rollback().await;
+            error!("Failed to anonymise user {}: {}", user_id, e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to delete account data"
+            }));
+        }
+    };
+
+    if let Err(e) = sqlx::query(
+        "UPDATE account_deletion_requests
+         SET status = 'approved', reviewed_by = (SELECT id FROM users WHERE username = $2),
+             reviewed_at = NOW(), admin_note = $3
+         WHERE id = $1",
+    )
+    .bind(request_id)
+    .bind(&claims.sub)
+    .bind(&body.note)
+    .execute(&mut *tx)
+    .await
+    {
+        let _ = tx.rollback().await;
+        error!("Database error: {}", e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Internal server error"
+        }));
+    }
+
+    if let Err(e) = tx.commit().await {
+        error!("Failed to commit account deletion: {}", e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Internal server error"
+        }));
+    }
+
+    // Files are removed only after the commit, so a rollback never loses data
+    if let Some(image) = &purged.profile_image {
+        if let Err(e) = app_state.storage.delete(image).await {
+            error!("Failed to delete profile image {}: {}", image, e);
+        }
+    }
+    for key in &purged.media_keys {
+        if let Err(e) = app_state.media_storage.delete(key).await {
+            error!("Failed to delete media file {}: {}", key, e);
+        }
+    }
+
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("account_deletion_request.approve", "user", Some(user_id)).after(Some(
+            serde_json::json!({ "request_id": request_id, "media_files_removed": purged.media_keys.len() }),
+        )),
+    )
+    .await;
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "message": "Account anonymised",
+        "user_id": user_id
+    }))
+}
+
+#[post("/api/admin/account-deletion-requests/{id}/reject")]
+async fn reject_deletion_request(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+    body: web::Json<ReviewDeletionRequest>,
+) -> impl Responder {
+    let claims = match verify_admin_claims(&req, &app_state) {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let request_id = path.into_inner();
+
+    let user_id = match sqlx::query_scalar::<_, i32>(
+        "UPDATE account_deletion_requests
+         SET status = 'rejected', reviewed_by = (SELECT id FROM users WHERE username = $2),
+             reviewed_at = NOW(), admin_note = $3
+         WHERE id = $1 AND status = 'pending'
+         RETURNING user_id",
+    )
+    .bind(request_id)
+    .bind(&claims.sub)
+    .bind(&body.note)
+    .fetch_optional(&app_state.db)
+    .await
+    {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Pending deletion request not found"
+            }));
+        }
+        Err(e) => {
+            error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    };
+
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("account_deletion_request.reject", "user", Some(user_id))
+            .after(Some(serde_json::json!({ "request_id": request_id, "note": body.note }))),
+    )
+    .await;
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "message": "Deletion request rejected"
+    }))
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_deletion_requests)
+        .service(approve_deletion_request)
+        .service(reject_deletion_request);
+}
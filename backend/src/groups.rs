@@ -4,6 +4,7 @@ use log::error;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
+use crate::audit::{self, AuditEvent};
 use crate::users::{verify_token, Claims};
 use crate::AppState;
 
@@ -210,6 +211,26 @@ async fn load_group_students(app_state: &AppState, group_id: i32) -> Vec<GroupSt
     .unwrap_or_default()
 }
 
+async fn group_audit_snapshot(app_state: &AppState, group_id: i32) -> Option<serde_json::Value> {
+    sqlx::query_scalar::<_, serde_json::Value>(
+        "SELECT to_jsonb(g) || jsonb_build_object(
+            'student_ids',
+            COALESCE((SELECT jsonb_agg(gsr.student_user_id ORDER BY gsr.student_user_id)
+                      FROM group_student_relations gsr
+                      WHERE gsr.group_id = g.id), '[]'::jsonb)
+         )
+         FROM student_groups g
+         WHERE g.id = $1",
+    )
+    .bind(group_id)
+    .fetch_optional(&app_state.db)
+    .await
+    .unwrap_or_else(|e| {
+        error!("Failed to snapshot group {} for audit: {}", group_id, e);
+        None
+    })
+}
+
 #[get("/api/teachers/{teacher_id}/groups")]
 async fn list_teacher_groups(
     req: HttpRequest,
@@ -417,6 +438,14 @@ async fn create_teacher_group(
 
     let students = load_group_students(&app_state, group.id).await;
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("group.create", "student_group", Some(group_id))
+            .after(group_audit_snapshot(&app_state, group_id).await),
+    )
+    .await;
+
     HttpResponse::Created().json(GroupResponse {
         id: group.id,
         teacher_user_id: group.teacher_user_id,
@@ -486,6 +515,8 @@ async fn update_teacher_group(
         }
     }
 
+    let before = group_audit_snapshot(&app_state, group_id).await;
+
     let mut tx = match app_state.db.begin().await {
         Ok(tx) => tx,
         Err(e) => {
@@ -630,6 +661,15 @@ async fn update_teacher_group(
 
     let students = load_group_students(&app_state, group.id).await;
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("group.update", "student_group", Some(group_id))
+            .before(before)
+            .after(group_audit_snapshot(&app_state, group_id).await),
+    )
+    .await;
+
     HttpResponse::Ok().json(GroupResponse {
         id: group.id,
         teacher_user_id: group.teacher_user_id,
@@ -664,6 +704,8 @@ async fn delete_teacher_group(
         return response;
     }
 
+    let before = group_audit_snapshot(&app_state, group_id).await;
+
     let mut tx = match app_state.db.begin().await {
         Ok(tx) => tx,
         Err(e) => {
@@ -720,6 +762,13 @@ async fn delete_teacher_group(
         }));
     }
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("group.delete", "student_group", Some(group_id)).before(before),
+    )
+    .await;
+
     HttpResponse::Ok().json(serde_json::json!({
         "status": "deleted"
     }))
@@ -5,15 +5,14 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 use sqlx::{FromRow, PgPool};
 
+use crate::i18n::Locale;
 use crate::models::hometask::{HometaskStatus, HometaskType};
 use crate::notification_builders::{
     build_hometask_accomplished_notification, build_hometask_assigned_notification,
     build_hometask_completed_notification, build_hometask_refreshed_notification,
     build_hometask_reopened_notification,
 };
-use crate::notifications::is_user_notification_eligible;
 use crate::notifications::NotificationBody;
-use crate::push;
 use crate::roles::helpers::verify_can_access_student;
 use crate::users::{verify_token, Claims};
 use crate::AppState;
@@ -102,28 +101,19 @@ struct RepeatableHometask {
     next_reset_at: DateTime<Utc>,
 }
 
-async fn insert_notification(db: &PgPool, user_id: i32, body: &NotificationBody, priority: &str) {
-    if !is_user_notification_eligible(db, user_id).await {
-        return;
-    }
-
-    let notification_id = sqlx::query_scalar::<_, i32>(
-        "INSERT INTO notifications (user_id, type, title, body, priority)
-         VALUES ($1, $2, $3, $4, $5)
-         RETURNING id",
-    )
-    .bind(user_id)
-    .bind(&body.body_type)
-    .bind(&body.title)
-    .bind(serde_json::to_value(body).unwrap_or_default())
-    .bind(priority)
-    .fetch_optional(db)
-    .await
-    .unwrap_or(None);
-
-    if let Some(notification_id) = notification_id {
-        push::send_notification_to_user(db, user_id, body, Some(notification_id)).await;
-    }
+/// Notify a student and all of their parents, each in their own language
+async fn notify_student_and_parents(
+    app_state: &AppState,
+    student_id: i32,
+    build: impl Fn(Locale) -> NotificationBody,
+    priority: &str,
+) {
+    let mut recipients = vec![student_id];
+    recipients.extend(fetch_parent_ids(&app_state.db, student_id).await);
+    app_state
+        .notifications
+        .notify_many(&recipients, build, priority)
+        .await;
 }
 
 async fn fetch_teacher_name(db: &PgPool, teacher_id: i32) -> String {
@@ -204,7 +194,8 @@ async fn reset_hometask_items(db: &PgPool, content_id: i32, hometask_type: &Home
         .await;
 }
 
-async fn refresh_repeatable_hometasks(db: &PgPool, student_id: i32) {
+async fn refresh_repeatable_hometasks(app_state: &AppState, student_id: i32) {
+    let db = &app_state.db;
     let tasks = sqlx::query_as::<_, RepeatableHometask>(
         "SELECT id, teacher_id, student_id, title, hometask_type, content_id,
                 repeat_every_days, next_reset_at
@@ -258,17 +249,16 @@ async fn refresh_repeatable_hometasks(db: &PgPool, student_id: i32) {
         .await;
 
         let teacher_name = fetch_teacher_name(db, task.teacher_id).await;
-        let refreshed_body = build_hometask_refreshed_notification(
-            task.id,
-            &task.title,
-            &teacher_name,
-            task.student_id,
-        );
-        insert_notification(db, task.student_id, &refreshed_body, "normal").await;
-        let parent_ids = fetch_parent_ids(db, task.student_id).await;
-        for parent_id in parent_ids {
-            insert_notification(db, parent_id, &refreshed_body, "normal").await;
-        }
+        let refreshed_body = |locale| {
+            build_hometask_refreshed_notification(
+                locale,
+                task.id,
+                &task.title,
+                &teacher_name,
+                task.student_id,
+            )
+        };
+        notify_student_and_parents(app_state, task.student_id, refreshed_body, "normal").await;
     }
 }
 
@@ -710,23 +700,21 @@ async fn create_hometask(
     }
 
     let teacher_name = fetch_teacher_name(&app_state.db, current_user_id).await;
-    let due_date = payload.due_date.map(|date| date.format("%Y-%m-%d").to_string());
 
     for (index, student_id) in assigned_student_ids.iter().enumerate() {
         let hometask_id = created_hometask_ids[index];
-        let assigned_body = build_hometask_assigned_notification(
-            hometask_id,
-            &payload.title,
-            &teacher_name,
-            due_date.as_deref(),
-            *student_id,
-        );
-
-        insert_notification(&app_state.db, *student_id, &assigned_body, "normal").await;
-        let parent_ids = fetch_parent_ids(&app_state.db, *student_id).await;
-        for parent_id in parent_ids {
-            insert_notification(&app_state.db, parent_id, &assigned_body, "normal").await;
-        }
+        let assigned_body = |locale| {
+            build_hometask_assigned_notification(
+                locale,
+                hometask_id,
+                &payload.title,
+                &teacher_name,
+                payload.due_date,
+                *student_id,
+            )
+        };
+
+        notify_student_and_parents(&app_state, *student_id, assigned_body, "normal").await;
     }
 
     HttpResponse::Created().json(json!({
@@ -748,7 +736,7 @@ async fn list_student_hometasks(
         return response;
     }
 
-    refresh_repeatable_hometasks(&app_state.db, student_id).await;
+    refresh_repeatable_hometasks(&app_state, student_id).await;
 
     let claims = match verify_token(&req, &app_state) {
         Ok(claims) => claims,
@@ -1340,9 +1328,28 @@ async fn update_hometask_status(
     path: web::Path<i32>,
     payload: web::Json<UpdateHometaskStatusRequest>,
 ) -> impl Responder {
-    let hometask_id = path.into_inner();
+    let payload = payload.into_inner();
+    change_hometask_status(
+        &req,
+        &app_state,
+        path.into_inner(),
+        payload.status,
+        payload.apply_to_group.unwrap_or(false),
+    )
+    .await
+}
 
-    let claims = match verify_token(&req, &app_state) {
+/// Move a hometask (or its whole group assignment) to `status` on behalf of the
+/// requesting user, with the same permission checks and notifications as
+/// `PUT /api/hometasks/{id}/status`. Also used by notification actions.
+pub(crate) async fn change_hometask_status(
+    req: &HttpRequest,
+    app_state: &web::Data<AppState>,
+    hometask_id: i32,
+    status: HometaskStatus,
+    apply_to_group: bool,
+) -> HttpResponse {
+    let claims = match verify_token(req, app_state) {
         Ok(claims) => claims,
         Err(response) => return response,
     };
@@ -1397,17 +1404,17 @@ async fn update_hometask_status(
         group_assignment_id,
     ) = hometask;
 
-    match payload.status {
+    match status {
         HometaskStatus::CompletedByStudent => {
             error!(
                 "Hometask status update: user_id={}, student_id={}, roles={:?}, status={:?}",
-                current_user_id, student_id, claims.roles, payload.status
+                current_user_id, student_id, claims.roles, status
             );
 
             let is_student =
                 claims.roles.contains(&"student".to_string()) && current_user_id == student_id;
             let is_parent = claims.roles.contains(&"parent".to_string())
-                && verify_can_access_student(&req, &app_state, student_id)
+                && verify_can_access_student(req, app_state, student_id)
                     .await
                     .is_ok();
 
@@ -1499,10 +1506,10 @@ async fn update_hometask_status(
         }
     }
 
-    let apply_to_group = payload.apply_to_group.unwrap_or(false)
+    let apply_to_group = apply_to_group
         && group_assignment_id.is_some()
-        && (payload.status == HometaskStatus::AccomplishedByTeacher
-            || payload.status == HometaskStatus::Assigned);
+        && (status == HometaskStatus::AccomplishedByTeacher
+            || status == HometaskStatus::Assigned);
 
     let target_tasks: Vec<(i32, i32, String, Option<i32>, Option<DateTime<Utc>>)> =
         if apply_to_group {
@@ -1544,7 +1551,7 @@ async fn update_hometask_status(
 
     for (task_id, _student_id, _task_title, task_repeat_days, task_next_reset_at) in &target_tasks {
         let mut next_reset_update: Option<DateTime<Utc>> = None;
-        if payload.status == HometaskStatus::Assigned {
+        if status == HometaskStatus::Assigned {
             if let Some(repeat_days) = *task_repeat_days {
                 if repeat_days > 0 {
                     let now = Utc::now();
@@ -1558,14 +1565,14 @@ async fn update_hometask_status(
 
         let update_result = if let Some(next_reset_at) = next_reset_update {
             sqlx::query("UPDATE hometasks SET status = $1, next_reset_at = $2 WHERE id = $3")
-                .bind(payload.status.clone())
+                .bind(status.clone())
                 .bind(next_reset_at)
                 .bind(*task_id)
                 .execute(&mut *tx)
                 .await
         } else {
             sqlx::query("UPDATE hometasks SET status = $1 WHERE id = $2")
-                .bind(payload.status.clone())
+                .bind(status.clone())
                 .bind(*task_id)
                 .execute(&mut *tx)
                 .await
@@ -1587,51 +1594,53 @@ async fn update_hometask_status(
         }));
     }
 
-    match payload.status {
+    match status {
         HometaskStatus::CompletedByStudent => {
             let student_name = fetch_student_name(&app_state.db, student_id).await;
-            let completed_body = build_hometask_completed_notification(
-                hometask_id,
-                &task_title,
-                &student_name,
-                student_id,
-            );
-            insert_notification(&app_state.db, teacher_id, &completed_body, "normal").await;
+            let completed_body = |locale| {
+                build_hometask_completed_notification(
+                    locale,
+                    hometask_id,
+                    &task_title,
+                    &student_name,
+                    student_id,
+                )
+            };
+            app_state
+                .notifications
+                .notify(teacher_id, completed_body, "normal")
+                .await;
         }
         HometaskStatus::AccomplishedByTeacher => {
             let teacher_name = fetch_teacher_name(&app_state.db, teacher_id).await;
             for (task_id, task_student_id, task_title, _, _) in &target_tasks {
-                let accomplished_body = build_hometask_accomplished_notification(
-                    *task_id,
-                    task_title,
-                    &teacher_name,
-                    *task_student_id,
-                );
-                insert_notification(&app_state.db, *task_student_id, &accomplished_body, "normal")
+                let accomplished_body = |locale| {
+                    build_hometask_accomplished_notification(
+                        locale,
+                        *task_id,
+                        task_title,
+                        &teacher_name,
+                        *task_student_id,
+                    )
+                };
+                notify_student_and_parents(app_state, *task_student_id, accomplished_body, "normal")
                     .await;
-                let parent_ids = fetch_parent_ids(&app_state.db, *task_student_id).await;
-                for parent_id in parent_ids {
-                    insert_notification(&app_state.db, parent_id, &accomplished_body, "normal")
-                        .await;
-                }
             }
         }
         HometaskStatus::Assigned => {
             let teacher_name = fetch_teacher_name(&app_state.db, teacher_id).await;
             for (task_id, task_student_id, task_title, _, _) in &target_tasks {
-                let reopened_body = build_hometask_reopened_notification(
-                    *task_id,
-                    task_title,
-                    &teacher_name,
-                    *task_student_id,
-                );
-                insert_notification(&app_state.db, *task_student_id, &reopened_body, "normal")
+                let reopened_body = |locale| {
+                    build_hometask_reopened_notification(
+                        locale,
+                        *task_id,
+                        task_title,
+                        &teacher_name,
+                        *task_student_id,
+                    )
+                };
+                notify_student_and_parents(app_state, *task_student_id, reopened_body, "normal")
                     .await;
-                let parent_ids = fetch_parent_ids(&app_state.db, *task_student_id).await;
-                for parent_id in parent_ids {
-                    insert_notification(&app_state.db, parent_id, &reopened_body, "normal")
-                        .await;
-                }
             }
         }
     }
@@ -0,0 +1,225 @@
+use chrono::{DateTime, Utc};
+use log::error;
+use sqlx::PgPool;
+
+/// Languages the app is localized to. Matches the frontend ARB files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+    Ru,
+}
+
+impl Locale {
+    pub const SUPPORTED: [&'static str; 3] = ["en", "de", "ru"];
+
+    /// Parse a language code such as "de", "de-AT" or "ru_RU"
+    pub fn from_code(code: &str) -> Option<Self> {
+        let language = code
+            .split(['-', '_'])
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_lowercase();
+        match language.as_str() {
+            "en" => Some(Locale::En),
+            "de" => Some(Locale::De),
+            "ru" => Some(Locale::Ru),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+            Locale::Ru => "ru",
+        }
+    }
+}
+
+/// Message catalogue: (key, en, de, ru). Placeholders are written as `{name}`.
+static CATALOGUE: &[(&str, &str, &str, &str)] = &[
+    // Shared actions
+    ("action.view_user", "View User", "Benutzer anzeigen", "Открыть пользователя"),
+    ("action.view_task", "View Task", "Aufgabe anzeigen", "Открыть задание"),
+    ("action.dismiss", "Dismiss", "Ausblenden", "Скрыть"),
+    ("action.mark_done", "Mark as Done", "Als erledigt markieren", "Отметить выполненным"),
+    ("action.view_hometasks", "View Hometasks", "Hausaufgaben anzeigen", "Открыть домашние задания"),
+    ("action.review_hometasks", "Review Hometasks", "Hausaufgaben prüfen", "Проверить домашние задания"),
+    ("action.open_feeds", "Open Feeds", "Feeds öffnen", "Открыть ленты"),
+    ("action.change_password", "Change Password", "Passwort ändern", "Сменить пароль"),
+    ("action.view_schedule", "View Schedule", "Stundenplan anzeigen", "Открыть расписание"),
+    ("action.view_details", "View Details", "Details anzeigen", "Подробнее"),
+    // Password reset requests (admins)
+    ("password_reset_request.title", "Password Reset Request", "Anfrage zum Zurücksetzen des Passworts", "Запрос на сброс пароля"),
+    (
+        "password_reset_request.body",
+        "User **{username}** has requested a password reset.",
+        "Benutzer **{username}** hat das Zurücksetzen des Passworts angefordert.",
+        "Пользователь **{username}** запросил сброс пароля.",
+    ),
+    (
+        "password_reset_request.no_email",
+        "This user does not have an email address configured and requires admin assistance.",
+        "Für diesen Benutzer ist keine E-Mail-Adresse hinterlegt, er benötigt Hilfe eines Administrators.",
+        "У этого пользователя не указан адрес электронной почты, ему нужна помощь администратора.",
+    ),
+    // Homework tasks
+    ("task.title", "New Homework Task", "Neue Hausaufgabe", "Новое домашнее задание"),
+    ("task.assigned", "{teacher} assigned you a new task:", "{teacher} hat Ihnen eine neue Aufgabe gegeben:", "{teacher} дал(а) вам новое задание:"),
+    ("task.due", "📅 Due: {date}", "📅 Fällig: {date}", "📅 Срок: {date}"),
+    ("task.sheet_music", "Sheet music", "Noten", "Ноты"),
+    ("hometask.due", "Due: {date}", "Fällig: {date}", "Срок: {date}"),
+    ("hometask.no_due_date", "No due date", "Kein Fälligkeitsdatum", "Без срока"),
+    ("hometask.assigned.title", "New Hometask", "Neue Hausaufgabe", "Новое домашнее задание"),
+    ("hometask.assigned.body", "{teacher} assigned a new hometask:", "{teacher} hat eine neue Hausaufgabe gegeben:", "{teacher} задал(а) новое домашнее задание:"),
+    ("hometask.accomplished.title", "Hometask Accomplished", "Hausaufgabe erledigt", "Домашнее задание выполнено"),
+    (
+        "hometask.accomplished.body",
+        "{teacher} marked a hometask as accomplished:",
+        "{teacher} hat eine Hausaufgabe als erledigt markiert:",
+        "{teacher} отметил(а) домашнее задание как выполненное:",
+    ),
+    ("hometask.completed.title", "Hometask Completed", "Hausaufgabe abgeschlossen", "Домашнее задание сделано"),
+    ("hometask.completed.body", "{student} completed a hometask:", "{student} hat eine Hausaufgabe abgeschlossen:", "{student} сделал(а) домашнее задание:"),
+    ("hometask.reopened.title", "Hometask Reopened", "Hausaufgabe wieder geöffnet", "Домашнее задание возвращено"),
+    (
+        "hometask.reopened.body",
+        "{teacher} marked a hometask as uncompleted:",
+        "{teacher} hat eine Hausaufgabe als nicht erledigt markiert:",
+        "{teacher} отметил(а) домашнее задание как невыполненное:",
+    ),
+    ("hometask.refreshed.title", "Hometask Refreshed", "Hausaufgabe erneuert", "Домашнее задание обновлено"),
+    (
+        "hometask.refreshed.body",
+        "{teacher} refreshed a repeating hometask:",
+        "{teacher} hat eine wiederkehrende Hausaufgabe erneuert:",
+        "{teacher} обновил(а) повторяющееся домашнее задание:",
+    ),
+    // Feeds
+    ("feed.untitled_post", "Untitled post", "Beitrag ohne Titel", "Запись без названия"),
+    ("feed.post.title", "New post in {feed}", "Neuer Beitrag in {feed}", "Новая запись в «{feed}»"),
+    ("feed.post.body", "New post in {feed}:", "Neuer Beitrag in {feed}:", "Новая запись в «{feed}»:"),
+    ("feed.comment.title", "New comment in {feed}", "Neuer Kommentar in {feed}", "Новый комментарий в «{feed}»"),
+    (
+        "feed.comment.body",
+        "New comment on a post in {feed}:",
+        "Neuer Kommentar zu einem Beitrag in {feed}:",
+        "Новый комментарий к записи в «{feed}»:",
+    ),
+    // Chat
+    ("chat.title", "New message from {sender}", "Neue Nachricht von {sender}", "Новое сообщение от {sender}"),
+    ("chat.new_message", "New message", "Neue Nachricht", "Новое сообщение"),
+    // Account
+    ("password_issued.title", "Your Account Password", "Ihr Kontopasswort", "Пароль вашей учётной записи"),
+    ("password_issued.created", "{admin} has created your account.", "{admin} hat Ihr Konto angelegt.", "{admin} создал(а) вашу учётную запись."),
+    ("password_issued.temporary", "Your temporary password is:", "Ihr vorläufiges Passwort lautet:", "Ваш временный пароль:"),
+    (
+        "password_issued.change_now",
+        "⚠️ Please change your password immediately after logging in.",
+        "⚠️ Bitte ändern Sie Ihr Passwort direkt nach der Anmeldung.",
+        "⚠️ Пожалуйста, смените пароль сразу после входа.",
+    ),
+    // Schedule
+    ("schedule.cancelled.title", "Lesson Cancelled", "Unterricht abgesagt", "Урок отменён"),
+    ("schedule.rescheduled.title", "Lesson Rescheduled", "Unterricht verschoben", "Урок перенесён"),
+    ("schedule.cancelled.body", "Lesson with {student} has been cancelled.", "Der Unterricht mit {student} wurde abgesagt.", "Урок с {student} отменён."),
+    ("schedule.rescheduled.body", "Lesson with {student} has been rescheduled.", "Der Unterricht mit {student} wurde verschoben.", "Урок с {student} перенесён."),
+    ("schedule.date", "📅 Date: {date}", "📅 Datum: {date}", "📅 Дата: {date}"),
+    ("schedule.time", "🕐 Time: {time}", "🕐 Uhrzeit: {time}", "🕐 Время: {time}"),
+    ("schedule.reason", "Reason: {reason}", "Grund: {reason}", "Причина: {reason}"),
+    // Results
+    ("results.title", "New Results Available", "Neue Ergebnisse verfügbar", "Доступны новые результаты"),
+    ("results.body", "New results available for {student}", "Neue Ergebnisse für {student} verfügbar", "Доступны новые результаты для {student}"),
+    ("results.score", "Score: {score}/100", "Punkte: {score}/100", "Баллы: {score}/100"),
+    ("results.teacher_comment", "Teacher's comment:", "Kommentar der Lehrkraft:", "Комментарий преподавателя:"),
+    // Emails
+    ("email.greeting", "Hello {username},", "Hallo {username},", "Здравствуйте, {username}!"),
+    ("email.signature", "Best regards,\n{app} Team", "Viele Grüße\nIhr {app}-Team", "С уважением,\nкоманда {app}"),
+    ("email.ignore_notice", "If you didn't request this, please ignore this email.", "Falls Sie das nicht angefordert haben, ignorieren Sie diese E-Mail bitte.", "Если вы этого не запрашивали, просто проигнорируйте это письмо."),
+    ("email.link_fallback", "If the button does not work, copy this link into your browser:", "Falls die Schaltfläche nicht funktioniert, kopieren Sie diesen Link in Ihren Browser:", "Если кнопка не работает, скопируйте ссылку в браузер:"),
+    ("email.footer", "You are receiving this email because you have an account at {school}.", "Sie erhalten diese E-Mail, weil Sie ein Konto bei {school} haben.", "Вы получили это письмо, потому что у вас есть учётная запись в {school}."),
+    ("email.notification.open", "Open in the app", "In der App öffnen", "Открыть в приложении"),
+    ("email.notification.manage", "You can choose which notifications you receive by email in your notification settings.", "In Ihren Benachrichtigungseinstellungen können Sie festlegen, welche Benachrichtigungen Sie per E-Mail erhalten.", "В настройках уведомлений можно выбрать, какие уведомления приходят по электронной почте."),
+    ("email.reset.subject", "Password Reset Request - {app}", "Passwort zurücksetzen - {app}", "Сброс пароля - {app}"),
+    ("email.reset.intro", "You requested a password reset for your {app} account.", "Sie haben das Zurücksetzen des Passworts für Ihr {app}-Konto angefordert.", "Вы запросили сброс пароля для учётной записи {app}."),
+    ("email.reset.action", "Reset password", "Passwort zurücksetzen", "Сбросить пароль"),
+    ("email.reset.expiry", "This link will expire in 1 hour.", "Der Link ist 1 Stunde gültig.", "Ссылка действительна 1 час."),
+    ("email.magic_link.subject", "Your sign-in link - {app}", "Ihr Anmeldelink - {app}", "Ссылка для входа - {app}"),
+    ("email.magic_link.intro", "You requested a sign-in link for your {app} account.", "Sie haben einen Anmeldelink für Ihr {app}-Konto angefordert.", "Вы запросили ссылку для входа в учётную запись {app}."),
+    ("email.magic_link.action", "Sign in", "Anmelden", "Войти"),
+    ("email.magic_link.expiry", "This link can be used once and will expire in {minutes} minutes.", "Der Link kann einmal verwendet werden und ist {minutes} Minuten gültig.", "Ссылку можно использовать один раз, она действительна {minutes} минут."),
+    ("email.verify.subject", "Confirm your email address - {app}", "Bestätigen Sie Ihre E-Mail-Adresse - {app}", "Подтвердите адрес электронной почты - {app}"),
+    ("email.verify.intro", "Please confirm that {email} is your email address for your {app} account.", "Bitte bestätigen Sie, dass {email} Ihre E-Mail-Adresse für Ihr {app}-Konto ist.", "Пожалуйста, подтвердите, что {email} — ваш адрес электронной почты для учётной записи {app}."),
+    ("email.verify.usage", "Password reset links and notifications are only sent to confirmed addresses.", "Links zum Zurücksetzen des Passworts und Benachrichtigungen werden nur an bestätigte Adressen gesendet.", "Ссылки для сброса пароля и уведомления отправляются только на подтверждённые адреса."),
+    ("email.verify.action", "Confirm email address", "E-Mail-Adresse bestätigen", "Подтвердить адрес"),
+    ("email.verify.expiry", "This link will expire in {hours} hours.", "Der Link ist {hours} Stunden gültig.", "Ссылка действительна {hours} ч."),
+    ("email.verify.ignore", "If you didn't add this address, please ignore this email.", "Falls Sie diese Adresse nicht hinterlegt haben, ignorieren Sie diese E-Mail bitte.", "Если вы не указывали этот адрес, просто проигнорируйте это письмо."),
+    ("email.admin_reset.subject", "Password Reset Request Pending - {app}", "Offene Anfrage zum Zurücksetzen des Passworts - {app}", "Ожидает запрос на сброс пароля - {app}"),
+    ("email.admin_reset.greeting", "Hello Admin,", "Hallo Admin,", "Здравствуйте!"),
+    ("email.admin_reset.intro", "User '{username}' has requested a password reset but has no email address on file.", "Benutzer '{username}' hat das Zurücksetzen des Passworts angefordert, hat aber keine E-Mail-Adresse hinterlegt.", "Пользователь '{username}' запросил сброс пароля, но у него не указан адрес электронной почты."),
+    ("email.admin_reset.outro", "Please check the admin panel to handle this request.", "Bitte bearbeiten Sie die Anfrage im Admin-Bereich.", "Пожалуйста, обработайте запрос в панели администратора."),
+    ("email.admin_reset.signature", "Best regards,\n{app} System", "Viele Grüße\n{app} System", "С уважением,\nсистема {app}"),
+    ("digest.daily.title", "Your daily summary", "Ihre tägliche Zusammenfassung", "Ваша ежедневная сводка"),
+    ("digest.weekly.title", "Your weekly summary", "Ihre wöchentliche Zusammenfassung", "Ваша еженедельная сводка"),
+    ("digest.intro", "Here is what happened since your last summary:", "Das ist seit Ihrer letzten Zusammenfassung passiert:", "Вот что произошло с момента последней сводки:"),
+    ("digest.hometasks_new", "New hometasks: {count}", "Neue Hausaufgaben: {count}", "Новые домашние задания: {count}"),
+    ("digest.hometasks_completed", "Completed hometasks: {count}", "Erledigte Hausaufgaben: {count}", "Выполненные домашние задания: {count}"),
+    ("digest.feed_posts", "New posts: {count}", "Neue Beiträge: {count}", "Новые публикации: {count}"),
+    ("digest.feed_comments", "New comments: {count}", "Neue Kommentare: {count}", "Новые комментарии: {count}"),
+    ("digest.chat_unread", "Unread chat messages: {count}", "Ungelesene Chatnachrichten: {count}", "Непрочитанные сообщения в чате: {count}"),
+    ("digest.other", "Other notifications: {count}", "Weitere Benachrichtigungen: {count}", "Другие уведомления: {count}"),
+    ("digest.section.hometasks_new", "New hometasks", "Neue Hausaufgaben", "Новые домашние задания"),
+    ("digest.section.hometasks_completed", "Completed hometasks", "Erledigte Hausaufgaben", "Выполненные домашние задания"),
+    ("digest.section.feed_posts", "New posts", "Neue Beiträge", "Новые публикации"),
+    ("digest.more", "…and {count} more", "…und {count} weitere", "…и ещё {count}"),
+    ("digest.action", "Open the app", "App öffnen", "Открыть приложение"),
+    ("digest.manage", "You can change how often you receive summaries in your notification settings.", "In Ihren Benachrichtigungseinstellungen können Sie festlegen, wie oft Sie Zusammenfassungen erhalten.", "Частоту сводок можно изменить в настройках уведомлений."),
+];
+
+/// Look up `key` in the catalogue, falling back to English and then to the key itself
+pub fn tr(locale: Locale, key: &'static str) -> &'static str {
+    match CATALOGUE.iter().find(|(entry, ..)| *entry == key) {
+        Some((_, en, de, ru)) => match locale {
+            Locale::En => en,
+            Locale::De => de,
+            Locale::Ru => ru,
+        },
+        None => {
+            error!("Missing i18n key: {}", key);
+            key
+        }
+    }
+}
+
+/// Like `tr`, replacing `{name}` placeholders with the given values
+pub fn tr_args(locale: Locale, key: &'static str, args: &[(&str, &str)]) -> String {
+    let mut text = tr(locale, key).to_string();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), value);
+    }
+    text
+}
+
+pub fn format_date(locale: Locale, date: DateTime<Utc>) -> String {
+    match locale {
+        Locale::En => date.format("%Y-%m-%d").to_string(),
+        Locale::De | Locale::Ru => date.format("%d.%m.%Y").to_string(),
+    }
+}
+
+/// Preferred language of a user; English when unset or unknown
+pub async fn user_locale(db: &PgPool, user_id: i32) -> Locale {
+    sqlx::query_scalar::<_, String>("SELECT locale FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(db)
+        .await
+        .unwrap_or_else(|e| {
+            error!("Failed to load locale for user {}: {}", user_id, e);
+            None
+        })
+        .and_then(|code| Locale::from_code(&code))
+        .unwrap_or_default()
+}
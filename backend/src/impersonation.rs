@@ -0,0 +1,548 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::audit::{self, AuditEvent};
+use crate::users::{load_active_roles, verify_token, Claims};
+use crate::AppState;
+
+const DEFAULT_DURATION_MINUTES: i64 = 15;
+const MAX_DURATION_MINUTES: i64 = 60;
+/// Query parameters that carry credentials and are left out of the request log
+const CREDENTIAL_PARAMS: &[&str] = &["token", "access_token", "code"];
+
+/// Marker embedded in the JWT of an impersonation session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImpersonationClaim {
+    pub session_id: i32,
+    pub admin: String, // username of the impersonating admin
+    pub read_only: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartImpersonationRequest {
+    pub reason: Option<String>,
+    pub read_only: Option<bool>,
+    pub duration_minutes: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StartImpersonationResponse {
+    pub token: String,
+    pub session_id: i32,
+    pub target_user_id: i32,
+    pub target_username: String,
+    pub read_only: bool,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ImpersonationSession {
+    pub id: i32,
+    pub admin_user_id: i32,
+    pub admin_username: String,
+    pub target_user_id: i32,
+    pub target_username: String,
+    pub read_only: bool,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+    pub request_count: i64,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ImpersonationRequestLogEntry {
+    pub id: i64,
+    pub method: String,
+    pub path: String,
+    pub query: Option<String>,
+    pub status_code: Option<i32>,
+    pub blocked: bool,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SessionsQuery {
+    pub admin_user_id: Option<i32>,
+    pub target_user_id: Option<i32>,
+    pub active_only: Option<bool>,
+    pub limit: Option<i64>,
+}
+
+fn verify_admin_claims(req: &HttpRequest, app_state: &AppState) -> Result<Claims, HttpResponse> {
+    let claims = verify_token(req, app_state)?;
+
+    if claims.impersonation.is_some() || !claims.roles.contains(&"admin".to_string()) {
+        return Err(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Admin access required"
+        })));
+    }
+
+    Ok(claims)
+}
+
+/// Admin issues a short-lived token that acts as the target user
+#[post("/api/admin/users/{user_id}/impersonate")]
+async fn start_impersonation(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+    body: web::Json<StartImpersonationRequest>,
+) -> impl Responder {
+    let claims = match verify_admin_claims(&req, &app_state) {
+        Ok(claims) => claims,
+        Err(response) => return response,
+    };
+    let target_user_id = path.into_inner();
+
+    let admin_id = match sqlx::query_scalar::<_, i32>("SELECT id FROM users WHERE username = $1")
+        .bind(&claims.sub)
+        .fetch_optional(&app_state.db)
+        .await
+    {
+        Ok(Some(id)) => id,
+        Ok(None) => {
+            return HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "User not found"
+            }));
+        }
+        Err(e) => {
+            error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    };
+
+    if admin_id == target_user_id {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Cannot impersonate yourself"
+        }));
+    }
+
+    let target_username = match sqlx::query_scalar::<_, String>(
+        "SELECT username FROM users WHERE id = $1",
+    )
+    .bind(target_user_id)
+    .fetch_optional(&app_state.db)
+    .await
+    {
+        Ok(Some(username)) => username,
+        Ok(None) => {
+            return HttpResponse::NotFound().json(serde_json::json!({
+                "error": "User not found"
+            }));
+        }
+        Err(e) => {
+            error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    };
+
+    let roles = match load_active_roles(&app_state.db, target_user_id).await {
+        Ok(roles) => roles,
+        Err(e) => {
+            error!("Failed to fetch user roles: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    };
+
+    if roles.iter().any(|role| role == "admin") {
+        return HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Admin accounts cannot be impersonated"
+        }));
+    }
+
+    if roles.is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "All roles are archived"
+        }));
+    }
+
+    let read_only = body.read_only.unwrap_or(true);
+    let duration_minutes = body
+        .duration_minutes
+        .unwrap_or(DEFAULT_DURATION_MINUTES)
+        .clamp(1, MAX_DURATION_MINUTES);
+    let expires_at = Utc::now() + Duration::minutes(duration_minutes);
+    let reason = body
+        .reason
+        .as_ref()
+        .map(|r| r.trim().to_string())
+        .filter(|r| !r.is_empty());
+
+    let session_id = match sqlx::query_scalar::<_, i32>(
+        "INSERT INTO impersonation_sessions (admin_user_id, target_user_id, read_only, reason, expires_at)
+         VALUES ($1, $2, $3, $4, $5)
+         RETURNING id",
+    )
+    .bind(admin_id)
+    .bind(target_user_id)
+    .bind(read_only)
+    .bind(&reason)
+    .bind(expires_at)
+    .fetch_one(&app_state.db)
+    .await
+    {
+        Ok(id) => id,
+        Err(e) => {
+            error!("Failed to create impersonation session: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    };
+
+    let token_claims = Claims {
+        sub: target_username.clone(),
+        exp: expires_at.timestamp() as usize,
+        roles,
+        impersonation: Some(ImpersonationClaim {
+            session_id,
+            admin: claims.sub.clone(),
+            read_only,
+        }),
+    };
+
+    let token = match encode(
+        &Header::default(),
+        &token_claims,
+        &EncodingKey::from_secret(app_state.jwt_secret.as_ref()),
+    ) {
+        Ok(t) => t,
+        Err(e) => {
+            error!("JWT encoding error: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Could not generate token"
+            }));
+        }
+    };
+
+    info!(
+        "Admin {} started impersonation session {} for user {} (read_only={})",
+        claims.sub, session_id, target_username, read_only
+    );
+
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("impersonation.start", "user", Some(target_user_id)).after(Some(
+            serde_json::json!({
+                "session_id": session_id,
+                "read_only": read_only,
+                "reason": reason,
+                "expires_at": expires_at,
+            }),
+        )),
+    )
+    .await;
+
+    HttpResponse::Ok().json(StartImpersonationResponse {
+        token,
+        session_id,
+        target_user_id,
+        target_username,
+        read_only,
+        expires_at,
+    })
+}
+
+/// Admin ends an impersonation session before it expires
+#[post("/api/admin/impersonations/{session_id}/end")]
+async fn end_impersonation(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+) -> impl Responder {
+    if let Err(response) = verify_admin_claims(&req, &app_state) {
+        return response;
+    }
+    let session_id = path.into_inner();
+
+    let result = sqlx::query(
+        "UPDATE impersonation_sessions SET ended_at = NOW()
+         WHERE id = $1 AND ended_at IS NULL",
+    )
+    .bind(session_id)
+    .execute(&app_state.db)
+    .await;
+
+    match result {
+        Ok(res) if res.rows_affected() > 0 => {
+            audit::record(
+                &app_state,
+                &req,
+                AuditEvent::new("impersonation.end", "impersonation_session", Some(session_id)),
+            )
+            .await;
+
+            HttpResponse::Ok().json(serde_json::json!({
+                "message": "Impersonation session ended"
+            }))
+        }
+        Ok(_) => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Active session not found"
+        })),
+        Err(e) => {
+            error!("Failed to end impersonation session: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }))
+        }
+    }
+}
+
+/// List impersonation sessions, newest first
+#[get("/api/admin/impersonations")]
+async fn list_impersonations(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<SessionsQuery>,
+) -> impl Responder {
+    if let Err(response) = verify_admin_claims(&req, &app_state) {
+        return response;
+    }
+    let limit = query.limit.unwrap_or(100).clamp(1, 500);
+
+    let result = sqlx::query_as::<_, ImpersonationSession>(
+        "SELECT s.id, s.admin_user_id, a.username AS admin_username,
+                s.target_user_id, t.username AS target_username,
+                s.read_only, s.reason, s.created_at, s.expires_at, s.ended_at,
+                (SELECT COUNT(*) FROM impersonation_request_log l WHERE l.session_id = s.id) AS request_count
+         FROM impersonation_sessions s
+         JOIN users a ON a.id = s.admin_user_id
+         JOIN users t ON t.id = s.target_user_id
+         WHERE ($1::INT IS NULL OR s.admin_user_id = $1)
+           AND ($2::INT IS NULL OR s.target_user_id = $2)
+           AND (NOT $3 OR (s.ended_at IS NULL AND s.expires_at > NOW()))
+         ORDER BY s.created_at DESC
+         LIMIT $4",
+    )
+    .bind(query.admin_user_id)
+    .bind(query.target_user_id)
+    .bind(query.active_only.unwrap_or(false))
+    .bind(limit)
+    .fetch_all(&app_state.db)
+    .await;
+
+    match result {
+        Ok(sessions) => HttpResponse::Ok().json(sessions),
+        Err(e) => {
+            error!("Failed to list impersonation sessions: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }))
+        }
+    }
+}
+
+/// Requests recorded for one impersonation session
+#[get("/api/admin/impersonations/{session_id}/requests")]
+async fn list_impersonation_requests(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<i32>,
+) -> impl Responder {
+    if let Err(response) = verify_admin_claims(&req, &app_state) {
+        return response;
+    }
+
+    let result = sqlx::query_as::<_, ImpersonationRequestLogEntry>(
+        "SELECT id, method, path, query, status_code, blocked, ip_address, created_at
+         FROM impersonation_request_log
+         WHERE session_id = $1
+         ORDER BY created_at ASC, id ASC",
+    )
+    .bind(path.into_inner())
+    .fetch_all(&app_state.db)
+    .await;
+
+    match result {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => {
+            error!("Failed to list impersonation requests: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }))
+        }
+    }
+}
+
+pub(crate) fn request_token(req: &ServiceRequest) -> Option<String> {
+    if let Some(value) = req
+        .headers()
+        .get("Authorization")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+    {
+        return Some(value.to_string());
+    }
+
+    #[derive(Deserialize)]
+    struct TokenQuery {
+        token: Option<String>,
+    }
+
+    web::Query::<TokenQuery>::from_query(req.query_string())
+        .ok()
+        .and_then(|query| query.into_inner().token)
+}
+
+fn is_read_method(method: &actix_web::http::Method) -> bool {
+    use actix_web::http::Method;
+    *method == Method::GET || *method == Method::HEAD || *method == Method::OPTIONS
+}
+
+/// The query string without credential parameters, such as the `token` the
+/// WebSocket upgrade is authenticated with
+fn loggable_query(query: &str) -> Option<String> {
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|pair| {
+            let key = web::Query::<Vec<(String, String)>>::from_query(pair)
+                .ok()
+                .and_then(|pairs| pairs.into_inner().into_iter().next())
+                .map(|(key, _)| key);
+            !pair.is_empty() && !key.is_some_and(|key| CREDENTIAL_PARAMS.contains(&key.as_str()))
+        })
+        .collect();
+    if kept.is_empty() {
+        None
+    } else {
+        Some(kept.join("&"))
+    }
+}
+
+async fn record_request(
+    app_state: &AppState,
+    session_id: i32,
+    req: &HttpRequest,
+    status_code: u16,
+    blocked: bool,
+) {
+    let query = loggable_query(req.query_string());
+    let ip = req.connection_info().realip_remote_addr().map(str::to_string);
+
+    let result = sqlx::query(
+        "INSERT INTO impersonation_request_log (session_id, method, path, query, status_code, blocked, ip_address)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(session_id)
+    .bind(req.method().as_str())
+    .bind(req.path())
+    .bind(query)
+    .bind(status_code as i32)
+    .bind(blocked)
+    .bind(ip)
+    .execute(&app_state.db)
+    .await;
+
+    if let Err(e) = result {
+        error!("Failed to record impersonated request: {}", e);
+    }
+}
+
+/// Middleware that audits every request made with an impersonation token,
+/// rejects ended sessions and blocks writes on read-only sessions.
+pub async fn audit_middleware(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let app_state = match req.app_data::<web::Data<AppState>>() {
+        Some(state) => state.clone(),
+        None => return next.call(req).await.map(|res| res.map_into_boxed_body()),
+    };
+
+    let impersonation = request_token(&req).and_then(|token| {
+        decode::<Claims>(
+            &token,
+            &DecodingKey::from_secret(app_state.jwt_secret.as_ref()),
+            &Validation::default(),
+        )
+        .ok()
+        .and_then(|data| data.claims.impersonation)
+    });
+
+    let Some(impersonation) = impersonation else {
+        return next.call(req).await.map(|res| res.map_into_boxed_body());
+    };
+
+    let session_active = sqlx::query_scalar::<_, bool>(
+        "SELECT ended_at IS NULL AND expires_at > NOW() FROM impersonation_sessions WHERE id = $1",
+    )
+    .bind(impersonation.session_id)
+    .fetch_optional(&app_state.db)
+    .await;
+
+    let rejection = match session_active {
+        Ok(Some(true)) => None,
+        Ok(_) => Some(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Impersonation session has ended"
+        }))),
+        Err(e) => {
+            error!("Failed to check impersonation session: {}", e);
+            Some(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            })))
+        }
+    };
+
+    let rejection = rejection.or_else(|| {
+        if impersonation.read_only && !is_read_method(req.method()) {
+            warn!(
+                "Blocked {} {} in read-only impersonation session {}",
+                req.method(),
+                req.path(),
+                impersonation.session_id
+            );
+            Some(HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "Impersonation session is read-only"
+            })))
+        } else {
+            None
+        }
+    });
+
+    if let Some(response) = rejection {
+        record_request(
+            &app_state,
+            impersonation.session_id,
+            req.request(),
+            response.status().as_u16(),
+            true,
+        )
+        .await;
+        return Ok(req.into_response(response));
+    }
+
+    let res = next.call(req).await?;
+    record_request(
+        &app_state,
+        impersonation.session_id,
+        res.request(),
+        res.status().as_u16(),
+        false,
+    )
+    .await;
+
+    Ok(res.map_into_boxed_body())
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(start_impersonation)
+        .service(end_impersonation)
+        .service(list_impersonations)
+        .service(list_impersonation_requests);
+}
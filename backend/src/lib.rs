@@ -1,20 +1,35 @@
 use log::debug;
 pub mod admin;
+pub mod announcements;
+pub mod audit;
 pub mod chats;
+pub mod consent;
 pub mod email;
+pub mod email_verification;
 pub mod feeds;
+pub mod gdpr;
 pub mod groups;
 pub mod hometasks;
+pub mod i18n;
+pub mod impersonation;
+pub mod magic_link;
 pub mod media;
 pub mod models;
 pub mod notification_builders;
+pub mod notification_digests;
+pub mod notification_dispatcher;
+pub mod notification_preferences;
+pub mod notification_retention;
 pub mod notifications;
+pub mod oidc;
 pub mod password_reset;
 pub mod push;
 pub mod registration_tokens;
 pub mod roles;
 pub mod storage;
 pub mod users;
+pub mod websocket_bus;
+pub mod websocket_protocol;
 pub mod websockets;
 
 use actix_cors::Cors;
@@ -37,6 +52,7 @@ pub struct AppState {
     pub media_storage: Arc<dyn StorageProvider>,
     pub media_dir: PathBuf,
     pub ws_server: websockets::WsServerActor,
+    pub notifications: notification_dispatcher::NotificationDispatcher,
 }
 
 async fn ws_endpoint(
@@ -44,11 +60,23 @@ async fn ws_endpoint(
     stream: web::Payload,
     app_state: web::Data<AppState>,
 ) -> Result<actix_web::HttpResponse, actix_web::Error> {
-    #[derive(Deserialize)]
+    #[derive(Default, Deserialize)]
     struct WsQuery {
         token: Option<String>,
+        /// Protocol version, for clients that can't set `Sec-WebSocket-Protocol`
+        protocol: Option<String>,
     }
 
+    let query = web::Query::<WsQuery>::from_query(req.query_string())
+        .map(web::Query::into_inner)
+        .unwrap_or_default();
+    let subprotocols = req
+        .headers()
+        .get(actix_web::http::header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok());
+    let negotiated = websocket_protocol::negotiate(query.protocol.as_deref(), subprotocols)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
     // Extract user_id from JWT token
     let mut token: Option<String> = None;
 
@@ -61,9 +89,7 @@ async fn ws_endpoint(
     }
 
     if token.is_none() {
-        if let Ok(query) = web::Query::<WsQuery>::from_query(req.query_string()) {
-            token = query.into_inner().token;
-        }
+        token = query.token;
     }
 
     if let Some(token) = token {
@@ -83,11 +109,27 @@ async fn ws_endpoint(
             {
                 Ok(Some(user_id)) => {
                     debug!("[ws] authenticated user {}", user_id);
-                    let ws_session = websockets::WsSession {
+                    let mut ws_session = websockets::WsSession::new(
+                        websockets::next_session_id(),
                         user_id,
-                        server: app_state.ws_server.clone(),
-                    };
-
+                        token_data.claims.roles.iter().any(|role| role == "admin"),
+                        negotiated.version(),
+                        app_state.db.clone(),
+                        app_state.ws_server.clone(),
+                    );
+                    ws_session.read_only = token_data
+                        .claims
+                        .impersonation
+                        .as_ref()
+                        .is_some_and(|impersonation| impersonation.read_only);
+
+                    // A version chosen by subprotocol is confirmed in the handshake
+                    if let websocket_protocol::Negotiated::Subprotocol(version) = negotiated {
+                        let subprotocol = version.subprotocol();
+                        return ws::WsResponseBuilder::new(ws_session, &req, stream)
+                            .protocols(&[subprotocol.as_str()])
+                            .start();
+                    }
                     return ws::start(ws_session, &req, stream);
                 }
                 _ => {
@@ -104,6 +146,11 @@ async fn ws_endpoint(
     ))
 }
 
+/// JSON schema of the current WebSocket protocol version
+async fn ws_schema() -> actix_web::HttpResponse {
+    actix_web::HttpResponse::Ok().json(websocket_protocol::json_schema())
+}
+
 pub fn create_app(
     app_state: web::Data<AppState>,
 ) -> App<
@@ -123,6 +170,8 @@ pub fn create_app(
     App::new()
         .app_data(app_state)
         .app_data(web::PayloadConfig::new(10 * 1024 * 1024)) // 10MB max payload
+        .wrap(middleware::from_fn(consent::enforcement_middleware))
+        .wrap(middleware::from_fn(impersonation::audit_middleware))
         .wrap(
             Cors::default()
                 .allow_any_origin()
@@ -133,7 +182,14 @@ pub fn create_app(
         .wrap(middleware::Logger::new("%a %{User-Agent}i %r %s %b %Dms"))
         .configure(users::configure)
         .configure(admin::configure)
+        .configure(announcements::configure)
+        .configure(email_verification::configure)
+        .configure(audit::configure)
+        .configure(impersonation::configure)
+        .configure(gdpr::configure)
+        .configure(consent::configure)
         .configure(notifications::configure)
+        .configure(notification_retention::configure)
         .configure(roles::configure_routes)
         .configure(registration_tokens::configure_routes)
         .configure(hometasks::init_routes)
@@ -143,6 +199,7 @@ pub fn create_app(
         .configure(push::configure)
         .configure(groups::configure)
         .route("/ws", web::get().to(ws_endpoint))
+        .route("/ws/schema", web::get().to(ws_schema))
         .service(fs::Files::new("/uploads/profile_images", profile_images_dir).show_files_listing())
         .service(fs::Files::new("/uploads/media", media_dir).show_files_listing())
 }
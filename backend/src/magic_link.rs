@@ -0,0 +1,217 @@
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, PgPool};
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+
+use log::{error, info};
+
+use crate::email::{EmailError, EmailService};
+use crate::email_verification;
+use crate::i18n::Locale;
+use crate::password_reset::{self, generate_token};
+
+/// How long a sign-in link stays valid
+const LINK_TTL_MINUTES: i64 = 15;
+/// Maximum link requests per username/email within `IDENTIFIER_WINDOW_MINUTES`
+const MAX_REQUESTS_PER_IDENTIFIER: i64 = 3;
+const IDENTIFIER_WINDOW_MINUTES: i64 = 15;
+/// Maximum link requests per client IP within `IP_WINDOW_MINUTES`
+const MAX_REQUESTS_PER_IP: i64 = 10;
+const IP_WINDOW_MINUTES: i64 = 60;
+
+const CLEANUP_INTERVAL_SECS: u64 = 3600;
+
+#[derive(Debug)]
+pub enum MagicLinkError {
+    DatabaseError(String),
+    TokenInvalid,
+    RateLimited,
+    EmailError(EmailError),
+}
+
+impl std::fmt::Display for MagicLinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MagicLinkError::DatabaseError(s) => write!(f, "Database error: {}", s),
+            MagicLinkError::TokenInvalid => write!(f, "Invalid, expired or already used sign-in link"),
+            MagicLinkError::RateLimited => write!(f, "Too many sign-in link requests"),
+            MagicLinkError::EmailError(e) => write!(f, "Email error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MagicLinkError {}
+
+impl From<sqlx::Error> for MagicLinkError {
+    fn from(err: sqlx::Error) -> Self {
+        MagicLinkError::DatabaseError(err.to_string())
+    }
+}
+
+impl From<EmailError> for MagicLinkError {
+    fn from(err: EmailError) -> Self {
+        MagicLinkError::EmailError(err)
+    }
+}
+
+/// Tokens are looked up directly, so a fast digest is used instead of Argon2
+fn hash_token(token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(token.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Request a sign-in link by username or email address.
+///
+/// Unknown identifiers, accounts without a verified email and admin accounts are silently
+/// ignored so callers cannot enumerate users. Only the per-IP limit is reported.
+pub async fn request_magic_link(
+    pool: &PgPool,
+    identifier: &str,
+    ip_address: Option<&str>,
+    email_service: Arc<EmailService>,
+) -> Result<(), MagicLinkError> {
+    let identifier = identifier.trim().to_lowercase();
+
+    if let Some(ip) = ip_address {
+        let ip_requests = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM magic_link_requests
+             WHERE ip_address = $1 AND requested_at > NOW() - make_interval(mins => $2)",
+        )
+        .bind(ip)
+        .bind(IP_WINDOW_MINUTES as i32)
+        .fetch_one(pool)
+        .await?;
+
+        if ip_requests >= MAX_REQUESTS_PER_IP {
+            return Err(MagicLinkError::RateLimited);
+        }
+    }
+
+    let identifier_requests = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM magic_link_requests
+         WHERE identifier = $1 AND requested_at > NOW() - make_interval(mins => $2)",
+    )
+    .bind(&identifier)
+    .bind(IDENTIFIER_WINDOW_MINUTES as i32)
+    .fetch_one(pool)
+    .await?;
+
+    sqlx::query("INSERT INTO magic_link_requests (identifier, ip_address) VALUES ($1, $2)")
+        .bind(&identifier)
+        .bind(ip_address)
+        .execute(pool)
+        .await?;
+
+    if identifier_requests >= MAX_REQUESTS_PER_IDENTIFIER {
+        info!("Magic link request for '{}' dropped by rate limit", identifier);
+        return Ok(());
+    }
+
+    #[derive(FromRow)]
+    struct UserQuery {
+        id: i32,
+        username: String,
+        email: String,
+        locale: String,
+    }
+
+    let users = sqlx::query_as::<_, UserQuery>(
+        "SELECT u.id, u.username, u.email, u.locale FROM users u
+         WHERE (LOWER(u.username) = $1 OR LOWER(u.email) = $1)
+           AND u.email IS NOT NULL AND u.email <> '' AND u.email_verified_at IS NOT NULL
+           AND NOT EXISTS (
+               SELECT 1 FROM user_roles ur
+               JOIN roles r ON r.id = ur.role_id
+               WHERE ur.user_id = u.id AND r.name = 'admin'
+           )",
+    )
+    .bind(&identifier)
+    .fetch_all(pool)
+    .await?;
+
+    for user in users {
+        let token = generate_token();
+        let expires_at = Utc::now() + Duration::minutes(LINK_TTL_MINUTES);
+
+        sqlx::query(
+            "INSERT INTO magic_link_tokens (user_id, token_hash, requested_ip, expires_at)
+             VALUES ($1, $2, $3, $4)",
+        )
+        .bind(user.id)
+        .bind(hash_token(&token))
+        .bind(ip_address)
+        .bind(expires_at)
+        .execute(pool)
+        .await?;
+
+        // Send email in the background to avoid blocking the response.
+        let email_service = email_service.clone();
+        let locale = Locale::from_code(&user.locale).unwrap_or_default();
+        actix_web::rt::spawn(async move {
+            if let Err(err) = email_service
+                .send_magic_link_email(locale, &user.email, &user.username, &token, LINK_TTL_MINUTES)
+                .await
+            {
+                error!("Magic link email failed: {}", err);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Consume a sign-in link and return the user id it belongs to.
+/// The token is marked used in the same statement, so it works exactly once.
+pub async fn consume_magic_link(pool: &PgPool, token: &str) -> Result<i32, MagicLinkError> {
+    sqlx::query_scalar::<_, i32>(
+        "UPDATE magic_link_tokens SET used_at = NOW()
+         WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+         RETURNING user_id",
+    )
+    .bind(hash_token(token))
+    .fetch_optional(pool)
+    .await?
+    .ok_or(MagicLinkError::TokenInvalid)
+}
+
+/// Cleanup expired links and old request logs (maintenance function)
+pub async fn cleanup_expired_links(pool: &PgPool) -> Result<u64, MagicLinkError> {
+    let tokens = sqlx::query(
+        "DELETE FROM magic_link_tokens WHERE expires_at < NOW() - INTERVAL '24 hours'",
+    )
+    .execute(pool)
+    .await?;
+
+    let requests = sqlx::query(
+        "DELETE FROM magic_link_requests WHERE requested_at < NOW() - INTERVAL '24 hours'",
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(tokens.rows_affected() + requests.rows_affected())
+}
+
+/// Delete expired sign-in, password reset and confirmation links and old request logs hourly
+pub async fn run_cleanup(pool: PgPool) {
+    let mut interval = actix_web::rt::time::interval(StdDuration::from_secs(CLEANUP_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        match cleanup_expired_links(&pool).await {
+            Ok(0) => {}
+            Ok(deleted) => info!("Deleted {} expired sign-in links and requests", deleted),
+            Err(e) => error!("Failed to clean up sign-in links: {}", e),
+        }
+        match password_reset::cleanup_expired_tokens(&pool).await {
+            Ok(0) => {}
+            Ok(deleted) => info!("Deleted {} expired password reset tokens", deleted),
+            Err(e) => error!("Failed to clean up password reset tokens: {}", e),
+        }
+        match email_verification::cleanup_expired_tokens(&pool).await {
+            Ok(0) => {}
+            Ok(deleted) => info!("Deleted {} expired email confirmation tokens", deleted),
+            Err(e) => error!("Failed to clean up email confirmation tokens: {}", e),
+        }
+    }
+}
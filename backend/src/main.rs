@@ -1,5 +1,5 @@
 use actix_web::{web, HttpServer};
-use music_school_app_backend::{create_app, init_db, AppState, email::EmailService, websockets};
+use music_school_app_backend::{announcements, create_app, init_db, magic_link, AppState, email::{EmailService, MaildirTransport}, notification_digests, notification_dispatcher::NotificationDispatcher, notification_retention, push, websocket_bus, websockets};
 use music_school_app_backend::storage::LocalStorage;
 use std::env;
 use std::path::PathBuf;
@@ -46,20 +46,33 @@ async fn main() -> std::io::Result<()> {
 
     info!("Database initialized successfully");
 
-    // Initialize email service
-    let email_service = EmailService::from_env()
-        .map(Arc::new)
-        .unwrap_or_else(|e| {
-            warn!("Email service not configured: {}", e);
-            warn!("Password reset emails will not be sent. Set SMTP environment variables to enable email.");
-            // For development, we'll create a dummy service with empty values
-            // In production, you should ensure proper email configuration
-            std::env::set_var("FROM_EMAIL", "noreply@example.com");
-            std::env::set_var("SMTP_HOST", "localhost");
-            std::env::set_var("SMTP_USERNAME", "user");
-            std::env::set_var("SMTP_PASSWORD", "pass");
-            Arc::new(EmailService::from_env().expect("Failed to create fallback email service"))
-        });
+    // Initialize email service. Without any email configuration, emails are
+    // written to a local maildir so every email path still works in development.
+    // A broken configuration stops startup instead of silently dropping mail.
+    let email_service = if EmailService::is_configured() {
+        Arc::new(EmailService::from_env()
+            .map_err(|e| std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                e.to_string()
+            ))?)
+    } else {
+        warn!("Email service not configured (no EMAIL_TRANSPORT or SMTP_* variables)");
+        let maildir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .parent()
+            .unwrap()
+            .join("mail");
+        let transport = MaildirTransport::new(maildir.clone())
+            .map_err(|e| std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Failed to create maildir: {}", e)
+            ))?;
+        warn!("Emails will be written to {:?} instead of being sent", maildir);
+        Arc::new(EmailService::with_transport(Arc::new(transport))
+            .map_err(|e| std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                e.to_string()
+            ))?)
+    };
 
     // Create upload directory
     let upload_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
@@ -94,6 +107,12 @@ async fn main() -> std::io::Result<()> {
         "/uploads/media".to_string(),
     ));
 
+    let (ws_bus, ws_bus_worker) = websocket_bus::new(db_pool.clone());
+    let ws_server =
+        websockets::WsServerActor::with_event_log(db_pool.clone()).with_bus(ws_bus.clone());
+    let notifications =
+        NotificationDispatcher::new(db_pool.clone(), email_service.clone(), ws_server.clone());
+
     // Create application state
     let app_state = web::Data::new(AppState {
         db: db_pool,
@@ -103,14 +122,42 @@ async fn main() -> std::io::Result<()> {
         profile_images_dir,
         media_storage,
         media_dir,
-        ws_server: websockets::WsServerActor::new(),
+        ws_server,
+        notifications,
     });
 
+    // Deliver queued pushes with retries
+    actix_web::rt::spawn(push::run_worker(
+        app_state.db.clone(),
+        push::PushProviders::from_env(),
+    ));
+
+    // Send daily/weekly notification digests at each user's local send time
+    actix_web::rt::spawn(notification_digests::run_scheduler(app_state.clone()));
+
+    // Send scheduled announcements once they are due
+    actix_web::rt::spawn(announcements::run_scheduler(app_state.clone()));
+
+    // Delete or archive notifications past their retention window
+    actix_web::rt::spawn(notification_retention::run_worker(app_state.db.clone()));
+
+    // Delete expired sign-in, password reset and email confirmation links
+    actix_web::rt::spawn(magic_link::run_cleanup(app_state.db.clone()));
+
+    // Forget WebSocket events once they are too old to be replayed
+    actix_web::rt::spawn(websockets::run_event_pruner(app_state.db.clone()));
+
+    // Relay WebSocket events and presence between backend replicas
+    actix_web::rt::spawn(ws_bus_worker.run(app_state.ws_server.clone()));
+
     info!("Starting server at http://0.0.0.0:8080");
     HttpServer::new(move || create_app(app_state.clone()))
         .bind(("0.0.0.0", 8080))?
         .run()
-        .await
+        .await?;
+
+    ws_bus.shutdown().await;
+    Ok(())
     
 
 }
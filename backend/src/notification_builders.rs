@@ -1,32 +1,37 @@
 use super::notifications::{NotificationBody, NotificationContent, ContentBlock, ActionButton};
+use chrono::{DateTime, Utc};
 use serde_json::json;
 
-/// Helper functions to build common notification types
+use crate::i18n::{format_date, tr, tr_args, Locale};
+
+/// Helper functions to build common notification types.
+/// Every builder renders its texts in the recipient's `locale`.
 
 /// Create a password reset request notification for admins
 pub fn build_password_reset_request_notification(
+    locale: Locale,
     username: &str,
     request_id: i32,
 ) -> NotificationBody {
     NotificationBody {
         body_type: "password_reset_request".to_string(),
-        title: "Password Reset Request".to_string(),
+        title: tr(locale, "password_reset_request.title").to_string(),
         route: Some(format!("/admin/users/{}", username)),
         content: NotificationContent {
             blocks: vec![
                 ContentBlock::Text {
-                    text: format!("User **{}** has requested a password reset.", username),
+                    text: tr_args(locale, "password_reset_request.body", &[("username", username)]),
                     style: Some("body".to_string()),
                 },
                 ContentBlock::Spacer { height: Some(8) },
                 ContentBlock::Text {
-                    text: "This user does not have an email address configured and requires admin assistance.".to_string(),
+                    text: tr(locale, "password_reset_request.no_email").to_string(),
                     style: Some("caption".to_string()),
                 },
             ],
             actions: Some(vec![
                 ActionButton {
-                    label: "View User".to_string(),
+                    label: tr(locale, "action.view_user").to_string(),
                     route: Some(format!("/admin/users/{}", username)),
                     action: None,
                     primary: true,
@@ -38,11 +43,13 @@ pub fn build_password_reset_request_notification(
             "request_id": request_id,
             "username": username,
         })),
+        collapse_key: None,
     }
 }
 
 /// Create a task assignment notification for students
 pub fn build_task_notification(
+    locale: Locale,
     task_id: i32,
     task_title: &str,
     teacher_name: &str,
@@ -52,7 +59,7 @@ pub fn build_task_notification(
 ) -> NotificationBody {
     let mut blocks = vec![
         ContentBlock::Text {
-            text: format!("{} assigned you a new task:", teacher_name),
+            text: tr_args(locale, "task.assigned", &[("teacher", teacher_name)]),
             style: Some("body".to_string()),
         },
         ContentBlock::Text {
@@ -70,7 +77,7 @@ pub fn build_task_notification(
     
     blocks.push(ContentBlock::Spacer { height: Some(8) });
     blocks.push(ContentBlock::Text {
-        text: format!("📅 Due: {}", due_date),
+        text: tr_args(locale, "task.due", &[("date", due_date)]),
         style: Some("caption".to_string()),
     });
     
@@ -78,7 +85,7 @@ pub fn build_task_notification(
         blocks.push(ContentBlock::Spacer { height: Some(12) });
         blocks.push(ContentBlock::Image {
             url: url.to_string(),
-            alt: Some("Sheet music".to_string()),
+            alt: Some(tr(locale, "task.sheet_music").to_string()),
             width: None,
             height: Some(200),
         });
@@ -86,20 +93,20 @@ pub fn build_task_notification(
     
     NotificationBody {
         body_type: "task_assigned".to_string(),
-        title: "New Homework Task".to_string(),
+        title: tr(locale, "task.title").to_string(),
         route: Some(format!("/student/tasks/{}", task_id)),
         content: NotificationContent {
             blocks,
             actions: Some(vec![
                 ActionButton {
-                    label: "View Task".to_string(),
+                    label: tr(locale, "action.view_task").to_string(),
                     route: Some(format!("/student/tasks/{}", task_id)),
                     action: None,
                     primary: true,
                     icon: Some("task".to_string()),
                 },
                 ActionButton {
-                    label: "Dismiss".to_string(),
+                    label: tr(locale, "action.dismiss").to_string(),
                     route: None,
                     action: Some("dismiss".to_string()),
                     primary: false,
@@ -111,26 +118,31 @@ pub fn build_task_notification(
             "task_id": task_id,
             "teacher_name": teacher_name,
         })),
+        collapse_key: None,
     }
 }
 
 pub fn build_hometask_assigned_notification(
+    locale: Locale,
     hometask_id: i32,
     task_title: &str,
     teacher_name: &str,
-    due_date: Option<&str>,
+    due_date: Option<DateTime<Utc>>,
     student_id: i32,
 ) -> NotificationBody {
-    let due_label = due_date.unwrap_or("No due date");
+    let due_label = match due_date {
+        Some(date) => format_date(locale, date),
+        None => tr(locale, "hometask.no_due_date").to_string(),
+    };
 
     NotificationBody {
         body_type: "hometask_assigned".to_string(),
-        title: "New Hometask".to_string(),
+        title: tr(locale, "hometask.assigned.title").to_string(),
         route: Some("/hometasks".to_string()),
         content: NotificationContent {
             blocks: vec![
                 ContentBlock::Text {
-                    text: format!("{} assigned a new hometask:", teacher_name),
+                    text: tr_args(locale, "hometask.assigned.body", &[("teacher", teacher_name)]),
                     style: Some("body".to_string()),
                 },
                 ContentBlock::Text {
@@ -139,18 +151,25 @@ pub fn build_hometask_assigned_notification(
                 },
                 ContentBlock::Spacer { height: Some(8) },
                 ContentBlock::Text {
-                    text: format!("Due: {}", due_label),
+                    text: tr_args(locale, "hometask.due", &[("date", &due_label)]),
                     style: Some("caption".to_string()),
                 },
             ],
             actions: Some(vec![
                 ActionButton {
-                    label: "View Hometasks".to_string(),
+                    label: tr(locale, "action.view_hometasks").to_string(),
                     route: Some("/hometasks".to_string()),
                     action: None,
                     primary: true,
                     icon: Some("task".to_string()),
                 },
+                ActionButton {
+                    label: tr(locale, "action.mark_done").to_string(),
+                    route: None,
+                    action: Some("complete_hometask".to_string()),
+                    primary: false,
+                    icon: Some("check".to_string()),
+                },
             ]),
         },
         metadata: Some(json!({
@@ -158,10 +177,12 @@ pub fn build_hometask_assigned_notification(
             "student_id": student_id,
             "teacher_name": teacher_name,
         })),
+        collapse_key: None,
     }
 }
 
 pub fn build_hometask_accomplished_notification(
+    locale: Locale,
     hometask_id: i32,
     task_title: &str,
     teacher_name: &str,
@@ -169,12 +190,12 @@ pub fn build_hometask_accomplished_notification(
 ) -> NotificationBody {
     NotificationBody {
         body_type: "hometask_accomplished".to_string(),
-        title: "Hometask Accomplished".to_string(),
+        title: tr(locale, "hometask.accomplished.title").to_string(),
         route: Some("/hometasks".to_string()),
         content: NotificationContent {
             blocks: vec![
                 ContentBlock::Text {
-                    text: format!("{} marked a hometask as accomplished:", teacher_name),
+                    text: tr_args(locale, "hometask.accomplished.body", &[("teacher", teacher_name)]),
                     style: Some("body".to_string()),
                 },
                 ContentBlock::Text {
@@ -184,7 +205,7 @@ pub fn build_hometask_accomplished_notification(
             ],
             actions: Some(vec![
                 ActionButton {
-                    label: "View Hometasks".to_string(),
+                    label: tr(locale, "action.view_hometasks").to_string(),
                     route: Some("/hometasks".to_string()),
                     action: None,
                     primary: true,
@@ -197,10 +218,12 @@ pub fn build_hometask_accomplished_notification(
             "student_id": student_id,
             "teacher_name": teacher_name,
         })),
+        collapse_key: None,
     }
 }
 
 pub fn build_hometask_completed_notification(
+    locale: Locale,
     hometask_id: i32,
     task_title: &str,
     student_name: &str,
@@ -208,12 +231,12 @@ pub fn build_hometask_completed_notification(
 ) -> NotificationBody {
     NotificationBody {
         body_type: "hometask_completed".to_string(),
-        title: "Hometask Completed".to_string(),
+        title: tr(locale, "hometask.completed.title").to_string(),
         route: Some("/hometasks".to_string()),
         content: NotificationContent {
             blocks: vec![
                 ContentBlock::Text {
-                    text: format!("{} completed a hometask:", student_name),
+                    text: tr_args(locale, "hometask.completed.body", &[("student", student_name)]),
                     style: Some("body".to_string()),
                 },
                 ContentBlock::Text {
@@ -223,7 +246,7 @@ pub fn build_hometask_completed_notification(
             ],
             actions: Some(vec![
                 ActionButton {
-                    label: "Review Hometasks".to_string(),
+                    label: tr(locale, "action.review_hometasks").to_string(),
                     route: Some("/hometasks".to_string()),
                     action: None,
                     primary: true,
@@ -236,10 +259,12 @@ pub fn build_hometask_completed_notification(
             "student_id": student_id,
             "student_name": student_name,
         })),
+        collapse_key: None,
     }
 }
 
 pub fn build_feed_post_notification(
+    locale: Locale,
     feed_title: &str,
     post_title: &str,
     feed_id: i32,
@@ -247,19 +272,19 @@ pub fn build_feed_post_notification(
 ) -> NotificationBody {
     let clean_feed = feed_title.trim();
     let clean_post = if post_title.trim().is_empty() {
-        "Untitled post"
+        tr(locale, "feed.untitled_post")
     } else {
         post_title
     };
 
     NotificationBody {
         body_type: "feed_post".to_string(),
-        title: format!("New post in {}", clean_feed),
+        title: tr_args(locale, "feed.post.title", &[("feed", clean_feed)]),
         route: Some("/feeds".to_string()),
         content: NotificationContent {
             blocks: vec![
                 ContentBlock::Text {
-                    text: format!("New post in {}:", clean_feed),
+                    text: tr_args(locale, "feed.post.body", &[("feed", clean_feed)]),
                     style: Some("body".to_string()),
                 },
                 ContentBlock::Text {
@@ -268,7 +293,7 @@ pub fn build_feed_post_notification(
                 },
             ],
             actions: Some(vec![ActionButton {
-                label: "Open Feeds".to_string(),
+                label: tr(locale, "action.open_feeds").to_string(),
                 route: Some("/feeds".to_string()),
                 action: None,
                 primary: true,
@@ -279,10 +304,12 @@ pub fn build_feed_post_notification(
             "feed_id": feed_id,
             "post_id": post_id,
         })),
+        collapse_key: None,
     }
 }
 
 pub fn build_feed_comment_notification(
+    locale: Locale,
     feed_title: &str,
     post_title: &str,
     feed_id: i32,
@@ -290,19 +317,19 @@ pub fn build_feed_comment_notification(
 ) -> NotificationBody {
     let clean_feed = feed_title.trim();
     let clean_post = if post_title.trim().is_empty() {
-        "Untitled post"
+        tr(locale, "feed.untitled_post")
     } else {
         post_title
     };
 
     NotificationBody {
         body_type: "feed_comment".to_string(),
-        title: format!("New comment in {}", clean_feed),
+        title: tr_args(locale, "feed.comment.title", &[("feed", clean_feed)]),
         route: Some("/feeds".to_string()),
         content: NotificationContent {
             blocks: vec![
                 ContentBlock::Text {
-                    text: format!("New comment on a post in {}:", clean_feed),
+                    text: tr_args(locale, "feed.comment.body", &[("feed", clean_feed)]),
                     style: Some("body".to_string()),
                 },
                 ContentBlock::Text {
@@ -311,7 +338,7 @@ pub fn build_feed_comment_notification(
                 },
             ],
             actions: Some(vec![ActionButton {
-                label: "Open Feeds".to_string(),
+                label: tr(locale, "action.open_feeds").to_string(),
                 route: Some("/feeds".to_string()),
                 action: None,
                 primary: true,
@@ -322,10 +349,12 @@ pub fn build_feed_comment_notification(
             "feed_id": feed_id,
             "post_id": post_id,
         })),
+        collapse_key: Some(format!("feed_comment:{}", post_id)),
     }
 }
 
 pub fn build_hometask_reopened_notification(
+    locale: Locale,
     hometask_id: i32,
     task_title: &str,
     teacher_name: &str,
@@ -333,12 +362,12 @@ pub fn build_hometask_reopened_notification(
 ) -> NotificationBody {
     NotificationBody {
         body_type: "hometask_reopened".to_string(),
-        title: "Hometask Reopened".to_string(),
+        title: tr(locale, "hometask.reopened.title").to_string(),
         route: Some("/hometasks".to_string()),
         content: NotificationContent {
             blocks: vec![
                 ContentBlock::Text {
-                    text: format!("{} marked a hometask as uncompleted:", teacher_name),
+                    text: tr_args(locale, "hometask.reopened.body", &[("teacher", teacher_name)]),
                     style: Some("body".to_string()),
                 },
                 ContentBlock::Text {
@@ -348,12 +377,19 @@ pub fn build_hometask_reopened_notification(
             ],
             actions: Some(vec![
                 ActionButton {
-                    label: "View Hometasks".to_string(),
+                    label: tr(locale, "action.view_hometasks").to_string(),
                     route: Some("/hometasks".to_string()),
                     action: None,
                     primary: true,
                     icon: Some("task".to_string()),
                 },
+                ActionButton {
+                    label: tr(locale, "action.mark_done").to_string(),
+                    route: None,
+                    action: Some("complete_hometask".to_string()),
+                    primary: false,
+                    icon: Some("check".to_string()),
+                },
             ]),
         },
         metadata: Some(json!({
@@ -361,10 +397,12 @@ pub fn build_hometask_reopened_notification(
             "student_id": student_id,
             "teacher_name": teacher_name,
         })),
+        collapse_key: None,
     }
 }
 
 pub fn build_hometask_refreshed_notification(
+    locale: Locale,
     hometask_id: i32,
     task_title: &str,
     teacher_name: &str,
@@ -372,12 +410,12 @@ pub fn build_hometask_refreshed_notification(
 ) -> NotificationBody {
     NotificationBody {
         body_type: "hometask_refreshed".to_string(),
-        title: "Hometask Refreshed".to_string(),
+        title: tr(locale, "hometask.refreshed.title").to_string(),
         route: Some("/hometasks".to_string()),
         content: NotificationContent {
             blocks: vec![
                 ContentBlock::Text {
-                    text: format!("{} refreshed a repeating hometask:", teacher_name),
+                    text: tr_args(locale, "hometask.refreshed.body", &[("teacher", teacher_name)]),
                     style: Some("body".to_string()),
                 },
                 ContentBlock::Text {
@@ -387,12 +425,19 @@ pub fn build_hometask_refreshed_notification(
             ],
             actions: Some(vec![
                 ActionButton {
-                    label: "View Hometasks".to_string(),
+                    label: tr(locale, "action.view_hometasks").to_string(),
                     route: Some("/hometasks".to_string()),
                     action: None,
                     primary: true,
                     icon: Some("task".to_string()),
                 },
+                ActionButton {
+                    label: tr(locale, "action.mark_done").to_string(),
+                    route: None,
+                    action: Some("complete_hometask".to_string()),
+                    primary: false,
+                    icon: Some("check".to_string()),
+                },
             ]),
         },
         metadata: Some(json!({
@@ -400,27 +445,29 @@ pub fn build_hometask_refreshed_notification(
             "student_id": student_id,
             "teacher_name": teacher_name,
         })),
+        collapse_key: None,
     }
 }
 
 /// Create a password issued notification for new users
 pub fn build_password_issued_notification(
+    locale: Locale,
     admin_name: &str,
     temporary_password: &str,
 ) -> NotificationBody {
     NotificationBody {
         body_type: "password_issued".to_string(),
-        title: "Your Account Password".to_string(),
+        title: tr(locale, "password_issued.title").to_string(),
         route: Some("/profile/change-password".to_string()),
         content: NotificationContent {
             blocks: vec![
                 ContentBlock::Text {
-                    text: format!("{} has created your account.", admin_name),
+                    text: tr_args(locale, "password_issued.created", &[("admin", admin_name)]),
                     style: Some("body".to_string()),
                 },
                 ContentBlock::Spacer { height: Some(12) },
                 ContentBlock::Text {
-                    text: "Your temporary password is:".to_string(),
+                    text: tr(locale, "password_issued.temporary").to_string(),
                     style: Some("caption".to_string()),
                 },
                 ContentBlock::Text {
@@ -429,13 +476,13 @@ pub fn build_password_issued_notification(
                 },
                 ContentBlock::Spacer { height: Some(12) },
                 ContentBlock::Text {
-                    text: "⚠️ Please change your password immediately after logging in.".to_string(),
+                    text: tr(locale, "password_issued.change_now").to_string(),
                     style: Some("body".to_string()),
                 },
             ],
             actions: Some(vec![
                 ActionButton {
-                    label: "Change Password".to_string(),
+                    label: tr(locale, "action.change_password").to_string(),
                     route: Some("/profile/change-password".to_string()),
                     action: None,
                     primary: true,
@@ -447,34 +494,36 @@ pub fn build_password_issued_notification(
             "admin_name": admin_name,
             "requires_action": true,
         })),
+        collapse_key: None,
     }
 }
 
 /// Create a schedule change notification for teachers
 pub fn build_schedule_change_notification(
+    locale: Locale,
     lesson_date: &str,
     lesson_time: &str,
     student_name: &str,
     change_type: &str, // "rescheduled", "cancelled"
     reason: Option<&str>,
 ) -> NotificationBody {
-    let title = match change_type {
-        "cancelled" => "Lesson Cancelled",
-        _ => "Lesson Rescheduled",
+    let (title_key, body_key) = match change_type {
+        "cancelled" => ("schedule.cancelled.title", "schedule.cancelled.body"),
+        _ => ("schedule.rescheduled.title", "schedule.rescheduled.body"),
     };
     
     let mut blocks = vec![
         ContentBlock::Text {
-            text: format!("Lesson with {} has been {}.", student_name, change_type),
+            text: tr_args(locale, body_key, &[("student", student_name)]),
             style: Some("body".to_string()),
         },
         ContentBlock::Spacer { height: Some(8) },
         ContentBlock::Text {
-            text: format!("📅 Date: {}", lesson_date),
+            text: tr_args(locale, "schedule.date", &[("date", lesson_date)]),
             style: Some("body".to_string()),
         },
         ContentBlock::Text {
-            text: format!("🕐 Time: {}", lesson_time),
+            text: tr_args(locale, "schedule.time", &[("time", lesson_time)]),
             style: Some("body".to_string()),
         },
     ];
@@ -484,20 +533,20 @@ pub fn build_schedule_change_notification(
         blocks.push(ContentBlock::Divider);
         blocks.push(ContentBlock::Spacer { height: Some(8) });
         blocks.push(ContentBlock::Text {
-            text: format!("Reason: {}", r),
+            text: tr_args(locale, "schedule.reason", &[("reason", r)]),
             style: Some("caption".to_string()),
         });
     }
     
     NotificationBody {
         body_type: "schedule_change".to_string(),
-        title: title.to_string(),
+        title: tr(locale, title_key).to_string(),
         route: Some("/teacher/schedule".to_string()),
         content: NotificationContent {
             blocks,
             actions: Some(vec![
                 ActionButton {
-                    label: "View Schedule".to_string(),
+                    label: tr(locale, "action.view_schedule").to_string(),
                     route: Some("/teacher/schedule".to_string()),
                     action: None,
                     primary: true,
@@ -509,11 +558,13 @@ pub fn build_schedule_change_notification(
             "student_name": student_name,
             "change_type": change_type,
         })),
+        collapse_key: None,
     }
 }
 
 /// Create a results available notification for parents
 pub fn build_results_notification(
+    locale: Locale,
     student_name: &str,
     assessment_title: &str,
     score: Option<f32>,
@@ -521,7 +572,7 @@ pub fn build_results_notification(
 ) -> NotificationBody {
     let mut blocks = vec![
         ContentBlock::Text {
-            text: format!("New results available for {}", student_name),
+            text: tr_args(locale, "results.body", &[("student", student_name)]),
             style: Some("body".to_string()),
         },
         ContentBlock::Spacer { height: Some(12) },
@@ -534,7 +585,7 @@ pub fn build_results_notification(
     if let Some(s) = score {
         blocks.push(ContentBlock::Spacer { height: Some(8) });
         blocks.push(ContentBlock::Text {
-            text: format!("Score: {:.1}/100", s),
+            text: tr_args(locale, "results.score", &[("score", &format!("{:.1}", s))]),
             style: Some("subtitle".to_string()),
         });
     }
@@ -544,7 +595,7 @@ pub fn build_results_notification(
         blocks.push(ContentBlock::Divider);
         blocks.push(ContentBlock::Spacer { height: Some(12) });
         blocks.push(ContentBlock::Text {
-            text: "Teacher's comment:".to_string(),
+            text: tr(locale, "results.teacher_comment").to_string(),
             style: Some("caption".to_string()),
         });
         blocks.push(ContentBlock::Text {
@@ -555,13 +606,13 @@ pub fn build_results_notification(
     
     NotificationBody {
         body_type: "results_available".to_string(),
-        title: "New Results Available".to_string(),
+        title: tr(locale, "results.title").to_string(),
         route: Some(format!("/parent/student/{}/results", student_name)),
         content: NotificationContent {
             blocks,
             actions: Some(vec![
                 ActionButton {
-                    label: "View Details".to_string(),
+                    label: tr(locale, "action.view_details").to_string(),
                     route: Some(format!("/parent/student/{}/results", student_name)),
                     action: None,
                     primary: true,
@@ -574,6 +625,7 @@ pub fn build_results_notification(
             "assessment_title": assessment_title,
             "score": score,
         })),
+        collapse_key: None,
     }
 }
 
@@ -625,5 +677,6 @@ pub fn build_announcement_notification(
             actions,
         },
         metadata: None,
+        collapse_key: None,
     }
 }
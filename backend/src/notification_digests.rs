@@ -0,0 +1,532 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use std::time::Duration;
+
+use crate::email::{EmailAction, EmailContent};
+use crate::email_verification;
+use crate::i18n::{self, tr, tr_args, Locale};
+use crate::notifications::{
+    extract_user_id_from_token, ContentBlock, NotificationBody, NotificationContent,
+};
+use crate::push;
+use crate::AppState;
+
+/// Notification types that can be collected into a digest
+pub const DIGESTIBLE_TYPES: &[&str] = &[
+    "chat_message",
+    "feed_comment",
+    "feed_post",
+    "hometask_accomplished",
+    "hometask_assigned",
+    "hometask_completed",
+    "hometask_refreshed",
+    "hometask_reopened",
+    "task_assigned",
+];
+
+/// How often the scheduler looks for due digests
+const CHECK_INTERVAL_SECS: u64 = 300;
+/// Notifications older than this are not summarised any more
+const MAX_DIGEST_AGE_DAYS: i32 = 8;
+/// Titles listed per section in digest emails
+const MAX_ITEMS_PER_SECTION: usize = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DigestSettings {
+    pub frequency: String,
+    pub send_hour: i16,
+    pub send_weekday: i16,
+    pub channel: String,
+    pub notification_types: Vec<String>,
+    #[serde(skip_deserializing)]
+    pub last_sent_at: Option<DateTime<Utc>>,
+}
+
+impl Default for DigestSettings {
+    fn default() -> Self {
+        Self {
+            frequency: "off".to_string(),
+            send_hour: 18,
+            send_weekday: 7,
+            channel: "email".to_string(),
+            notification_types: Vec::new(),
+            last_sent_at: None,
+        }
+    }
+}
+
+/// Whether a user with these digest settings collects `notification_type` into
+/// their digest instead of receiving it individually by push or email.
+pub(crate) fn collects(frequency: &str, notification_types: &[String], notification_type: &str) -> bool {
+    frequency != "off"
+        && DIGESTIBLE_TYPES.contains(&notification_type)
+        && (notification_types.is_empty() || notification_types.iter().any(|t| t == notification_type))
+}
+
+async fn load_settings(db: &PgPool, user_id: i32) -> Result<DigestSettings, sqlx::Error> {
+    Ok(sqlx::query_as::<_, DigestSettings>(
+        "SELECT frequency, send_hour, send_weekday, channel, notification_types, last_sent_at
+         FROM notification_digest_settings WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?
+    .unwrap_or_default())
+}
+
+#[derive(Debug, Serialize)]
+pub struct DigestSettingsResponse {
+    #[serde(flatten)]
+    pub settings: DigestSettings,
+    pub timezone: String,
+    pub available_types: &'static [&'static str],
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateDigestSettingsRequest {
+    pub frequency: String,
+    pub send_hour: Option<i16>,
+    pub send_weekday: Option<i16>,
+    pub channel: Option<String>,
+    pub notification_types: Option<Vec<String>>,
+}
+
+async fn settings_response(db: &PgPool, user_id: i32) -> Result<DigestSettingsResponse> {
+    let settings = load_settings(db, user_id).await.map_err(|e| {
+        error!("Database error fetching digest settings: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to fetch digest settings")
+    })?;
+
+    let timezone = sqlx::query_scalar::<_, String>("SELECT timezone FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(db)
+        .await
+        .map_err(|e| {
+            error!("Database error fetching user timezone: {:?}", e);
+            actix_web::error::ErrorInternalServerError("Failed to fetch digest settings")
+        })?;
+
+    Ok(DigestSettingsResponse {
+        settings,
+        timezone,
+        available_types: DIGESTIBLE_TYPES,
+    })
+}
+
+/// Get the authenticated user's digest schedule
+pub async fn get_digest_settings(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let user_id = extract_user_id_from_token(&req, &app_state).await?;
+    let response = settings_response(&app_state.db, user_id).await?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Opt in to (or out of) daily or weekly digests
+pub async fn update_digest_settings(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    payload: web::Json<UpdateDigestSettingsRequest>,
+) -> Result<HttpResponse> {
+    let user_id = extract_user_id_from_token(&req, &app_state).await?;
+    let current = load_settings(&app_state.db, user_id).await.map_err(|e| {
+        error!("Database error fetching digest settings: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to update digest settings")
+    })?;
+
+    let bad_request = |message: String| {
+        Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": message })))
+    };
+
+    if !matches!(payload.frequency.as_str(), "off" | "daily" | "weekly") {
+        return bad_request("frequency must be one of: off, daily, weekly".to_string());
+    }
+    let send_hour = payload.send_hour.unwrap_or(current.send_hour);
+    if !(0..=23).contains(&send_hour) {
+        return bad_request("send_hour must be between 0 and 23".to_string());
+    }
+    let send_weekday = payload.send_weekday.unwrap_or(current.send_weekday);
+    if !(1..=7).contains(&send_weekday) {
+        return bad_request("send_weekday must be between 1 (Monday) and 7 (Sunday)".to_string());
+    }
+    let channel = payload.channel.clone().unwrap_or(current.channel);
+    if !matches!(channel.as_str(), "email" | "push") {
+        return bad_request("channel must be one of: email, push".to_string());
+    }
+    let notification_types = payload
+        .notification_types
+        .clone()
+        .unwrap_or(current.notification_types);
+    if let Some(unknown) = notification_types
+        .iter()
+        .find(|t| !DIGESTIBLE_TYPES.contains(&t.as_str()))
+    {
+        return bad_request(format!("Notification type cannot be digested: {}", unknown));
+    }
+
+    sqlx::query(
+        "INSERT INTO notification_digest_settings
+             (user_id, frequency, send_hour, send_weekday, channel, notification_types, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $6, NOW())
+         ON CONFLICT (user_id) DO UPDATE SET
+             frequency = EXCLUDED.frequency,
+             send_hour = EXCLUDED.send_hour,
+             send_weekday = EXCLUDED.send_weekday,
+             channel = EXCLUDED.channel,
+             notification_types = EXCLUDED.notification_types,
+             updated_at = NOW()",
+    )
+    .bind(user_id)
+    .bind(&payload.frequency)
+    .bind(send_hour)
+    .bind(send_weekday)
+    .bind(&channel)
+    .bind(&notification_types)
+    .execute(&app_state.db)
+    .await
+    .map_err(|e| {
+        error!("Database error updating digest settings: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to update digest settings")
+    })?;
+
+    let response = settings_response(&app_state.db, user_id).await?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[derive(Debug, FromRow)]
+struct DueDigest {
+    user_id: i32,
+    frequency: String,
+    channel: String,
+    notification_types: Vec<String>,
+}
+
+#[derive(Debug, FromRow)]
+struct PendingNotification {
+    id: i32,
+    notification_type: String,
+    title: String,
+    body: serde_json::Value,
+    collapsed_count: i32,
+}
+
+/// Counts per digest section
+#[derive(Debug, Default)]
+struct DigestSummary {
+    /// Notifications summarised, marked as digested once the digest is sent
+    notification_ids: Vec<i32>,
+    new_hometasks: Vec<String>,
+    completed_hometasks: Vec<String>,
+    feed_posts: Vec<String>,
+    feed_comments: usize,
+    other: usize,
+    unread_chat_messages: i64,
+}
+
+impl DigestSummary {
+    fn is_empty(&self) -> bool {
+        self.new_hometasks.is_empty()
+            && self.completed_hometasks.is_empty()
+            && self.feed_posts.is_empty()
+            && self.feed_comments == 0
+            && self.other == 0
+            && self.unread_chat_messages == 0
+    }
+
+    /// One localized line per non-empty section
+    fn lines(&self, locale: Locale) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut push_count = |key: &'static str, count: usize| {
+            if count > 0 {
+                lines.push(tr_args(locale, key, &[("count", &count.to_string())]));
+            }
+        };
+        push_count("digest.hometasks_new", self.new_hometasks.len());
+        push_count("digest.hometasks_completed", self.completed_hometasks.len());
+        push_count("digest.feed_posts", self.feed_posts.len());
+        push_count("digest.feed_comments", self.feed_comments);
+        push_count("digest.chat_unread", self.unread_chat_messages as usize);
+        push_count("digest.other", self.other);
+        lines
+    }
+}
+
+/// First text block of a stored notification, used as the item line in digests
+fn notification_headline(notification: &PendingNotification) -> String {
+    serde_json::from_value::<NotificationBody>(notification.body.clone())
+        .ok()
+        .and_then(|body| {
+            body.content.blocks.into_iter().find_map(|block| match block {
+                ContentBlock::Text { text, .. } if !text.trim().is_empty() => Some(text),
+                _ => None,
+            })
+        })
+        .unwrap_or_else(|| notification.title.clone())
+}
+
+/// Claim users whose digest is due in their local time. Claiming sets
+/// `last_sent_at`, so concurrent schedulers never send the same digest twice.
+async fn claim_due_digests(db: &PgPool) -> Result<Vec<DueDigest>, sqlx::Error> {
+    sqlx::query_as::<_, DueDigest>(
+        "UPDATE notification_digest_settings s
+         SET last_sent_at = NOW()
+         WHERE s.user_id IN (
+               SELECT ds.user_id
+               FROM notification_digest_settings ds
+               JOIN users du ON du.id = ds.user_id
+               WHERE ds.frequency <> 'off'
+                 AND EXTRACT(HOUR FROM NOW() AT TIME ZONE du.timezone) >= ds.send_hour
+                 AND (ds.frequency = 'daily'
+                      OR EXTRACT(ISODOW FROM NOW() AT TIME ZONE du.timezone) = ds.send_weekday)
+                 AND (ds.last_sent_at IS NULL
+                      OR (ds.last_sent_at AT TIME ZONE du.timezone)::date
+                         < (NOW() AT TIME ZONE du.timezone)::date)
+               FOR UPDATE OF ds SKIP LOCKED
+           )
+         RETURNING s.user_id, s.frequency, s.channel, s.notification_types",
+    )
+    .fetch_all(db)
+    .await
+}
+
+async fn collect_summary(
+    db: &PgPool,
+    user_id: i32,
+    notification_types: &[String],
+) -> Result<DigestSummary, sqlx::Error> {
+    let types: Vec<String> = if notification_types.is_empty() {
+        DIGESTIBLE_TYPES.iter().map(|t| t.to_string()).collect()
+    } else {
+        notification_types.to_vec()
+    };
+
+    let pending = sqlx::query_as::<_, PendingNotification>(
+        "SELECT id, type AS notification_type, title, body, collapsed_count
+         FROM notifications
+         WHERE user_id = $1
+           AND digested_at IS NULL
+           AND read_at IS NULL
+           AND (snoozed_until IS NULL OR snoozed_until <= NOW())
+           AND type = ANY($2)
+           AND created_at > NOW() - make_interval(days => $3)
+         ORDER BY created_at",
+    )
+    .bind(user_id)
+    .bind(&types)
+    .bind(MAX_DIGEST_AGE_DAYS)
+    .fetch_all(db)
+    .await?;
+
+    let mut summary = DigestSummary {
+        notification_ids: pending.iter().map(|notification| notification.id).collect(),
+        ..Default::default()
+    };
+    for notification in &pending {
+        match notification.notification_type.as_str() {
+            "hometask_assigned" | "hometask_refreshed" | "hometask_reopened" | "task_assigned" => {
+                summary.new_hometasks.push(notification_headline(notification))
+            }
+            "hometask_completed" | "hometask_accomplished" => summary
+                .completed_hometasks
+                .push(notification_headline(notification)),
+            "feed_post" => summary.feed_posts.push(notification_headline(notification)),
+            "feed_comment" => summary.feed_comments += notification.collapsed_count.max(1) as usize,
+            // Chat is reported as a live unread count below
+            "chat_message" => {}
+            _ => summary.other += 1,
+        }
+    }
+
+    if types.iter().any(|t| t == "chat_message") {
+        summary.unread_chat_messages = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM message_receipts WHERE recipient_id = $1 AND state <> 'read'",
+        )
+        .bind(user_id)
+        .fetch_one(db)
+        .await?;
+    }
+
+    Ok(summary)
+}
+
+fn digest_title(locale: Locale, frequency: &str) -> &'static str {
+    if frequency == "weekly" {
+        tr(locale, "digest.weekly.title")
+    } else {
+        tr(locale, "digest.daily.title")
+    }
+}
+
+/// Mark the summarised notifications so the next digest doesn't repeat them
+async fn mark_digested(db: &PgPool, notification_ids: &[i32]) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE notifications SET digested_at = NOW() WHERE id = ANY($1)")
+        .bind(notification_ids)
+        .execute(db)
+        .await?;
+    Ok(())
+}
+
+/// Returns whether the email went out
+async fn send_email_digest(
+    app_state: &AppState,
+    user_id: i32,
+    locale: Locale,
+    frequency: &str,
+    summary: &DigestSummary,
+) -> bool {
+    let email = match email_verification::verified_email(&app_state.db, user_id).await {
+        Ok(Some(email)) => email,
+        Ok(None) => {
+            info!("Digest for user {} skipped: no verified email", user_id);
+            return false;
+        }
+        Err(e) => {
+            error!("Database error loading email for user {}: {:?}", user_id, e);
+            return false;
+        }
+    };
+
+    let username = sqlx::query_scalar::<_, String>(
+        "SELECT COALESCE(NULLIF(full_name, ''), username) FROM users WHERE id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(&app_state.db)
+    .await
+    .ok()
+    .flatten()
+    .unwrap_or_default();
+
+    let branding = app_state.email_service.branding();
+    let mut paragraphs = vec![tr(locale, "digest.intro").to_string()];
+    paragraphs.push(summary.lines(locale).join("\n"));
+    for (heading, items) in [
+        ("digest.section.hometasks_new", &summary.new_hometasks),
+        ("digest.section.hometasks_completed", &summary.completed_hometasks),
+        ("digest.section.feed_posts", &summary.feed_posts),
+    ] {
+        if items.is_empty() {
+            continue;
+        }
+        let mut section = vec![tr(locale, heading).to_string()];
+        section.extend(
+            items
+                .iter()
+                .take(MAX_ITEMS_PER_SECTION)
+                .map(|item| format!("• {}", item)),
+        );
+        if items.len() > MAX_ITEMS_PER_SECTION {
+            section.push(tr_args(
+                locale,
+                "digest.more",
+                &[("count", &(items.len() - MAX_ITEMS_PER_SECTION).to_string())],
+            ));
+        }
+        paragraphs.push(section.join("\n"));
+    }
+
+    let content = EmailContent {
+        greeting: tr_args(locale, "email.greeting", &[("username", &username)]),
+        paragraphs,
+        action: Some(EmailAction {
+            label: tr(locale, "digest.action").to_string(),
+            url: app_state.email_service.app_url().to_string(),
+        }),
+        notes: vec![tr(locale, "digest.manage").to_string()],
+        signature: tr_args(locale, "email.signature", &[("app", &branding.app_name)]),
+    };
+
+    let subject = format!("{} - {}", digest_title(locale, frequency), branding.app_name);
+    match app_state
+        .email_service
+        .send(locale, &email, subject, &content)
+        .await
+    {
+        Ok(()) => true,
+        Err(e) => {
+            error!("Digest email to user {} failed: {}", user_id, e);
+            false
+        }
+    }
+}
+
+async fn send_push_digest(
+    app_state: &AppState,
+    user_id: i32,
+    locale: Locale,
+    frequency: &str,
+    summary: &DigestSummary,
+) {
+    let body = NotificationBody {
+        body_type: "digest".to_string(),
+        title: digest_title(locale, frequency).to_string(),
+        route: Some("/notifications".to_string()),
+        content: NotificationContent {
+            blocks: vec![ContentBlock::Text {
+                text: summary.lines(locale).join(" · "),
+                style: Some("body".to_string()),
+            }],
+            actions: None,
+        },
+        metadata: Some(serde_json::json!({ "frequency": frequency })),
+        collapse_key: None,
+    };
+
+    push::send_notification_to_user(&app_state.db, user_id, &body, None).await;
+}
+
+/// Send all digests that are due now
+pub async fn send_due_digests(app_state: &AppState) {
+    let due = match claim_due_digests(&app_state.db).await {
+        Ok(due) => due,
+        Err(e) => {
+            error!("Database error claiming due digests: {:?}", e);
+            return;
+        }
+    };
+
+    for digest in due {
+        let summary =
+            match collect_summary(&app_state.db, digest.user_id, &digest.notification_types).await
+            {
+                Ok(summary) => summary,
+                Err(e) => {
+                    error!("Database error collecting digest for user {}: {:?}", digest.user_id, e);
+                    continue;
+                }
+            };
+
+        if summary.is_empty() {
+            continue;
+        }
+
+        let locale = i18n::user_locale(&app_state.db, digest.user_id).await;
+        // Pushes are queued in the outbox, which retries them itself
+        let sent = if digest.channel == "push" {
+            send_push_digest(app_state, digest.user_id, locale, &digest.frequency, &summary).await;
+            true
+        } else {
+            send_email_digest(app_state, digest.user_id, locale, &digest.frequency, &summary).await
+        };
+
+        // Unsent notifications stay pending for the next digest
+        if sent {
+            if let Err(e) = mark_digested(&app_state.db, &summary.notification_ids).await {
+                error!("Database error marking digest for user {}: {:?}", digest.user_id, e);
+            }
+        }
+    }
+}
+
+/// Background loop that sends digests at each user's configured local time
+pub async fn run_scheduler(app_state: web::Data<AppState>) {
+    let mut interval = actix_web::rt::time::interval(Duration::from_secs(CHECK_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        send_due_digests(&app_state).await;
+    }
+}
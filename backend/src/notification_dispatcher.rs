@@ -0,0 +1,369 @@
+use futures_util::stream::{self, StreamExt};
+use log::{debug, error};
+use sqlx::{FromRow, PgPool};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::email::EmailService;
+use crate::i18n::Locale;
+use crate::notification_digests;
+use crate::notification_preferences::{ChannelSelection, DEFAULT_TYPE};
+use crate::notifications::{self, Notification, NotificationBody};
+use crate::push::{self, PushDelivery};
+use crate::websocket_protocol::{NotificationChange, ServerMessage};
+use crate::websockets::WsServerActor;
+
+/// Recipients resolved and stored per database round trip
+const BATCH_SIZE: usize = 500;
+/// Notification emails handed to the transport at once
+const EMAIL_CONCURRENCY: usize = 4;
+
+/// Everything needed to decide how one recipient gets a notification,
+/// loaded for a whole batch of recipients in one query.
+#[derive(Debug, FromRow)]
+struct RecipientContext {
+    user_id: i32,
+    locale: String,
+    eligible: bool,
+    type_in_app: Option<bool>,
+    type_push: Option<bool>,
+    type_email: Option<bool>,
+    default_in_app: Option<bool>,
+    default_push: Option<bool>,
+    default_email: Option<bool>,
+    digest_frequency: Option<String>,
+    digest_types: Option<Vec<String>>,
+    quiet: bool,
+    verified_email: Option<String>,
+}
+
+impl RecipientContext {
+    /// Channels for this notification after preferences, digest and quiet hours
+    fn channels(&self, notification_type: &str) -> ChannelSelection {
+        let fallback = ChannelSelection::default();
+        let mut channels = ChannelSelection {
+            in_app: self.type_in_app.or(self.default_in_app).unwrap_or(fallback.in_app),
+            push: self.type_push.or(self.default_push).unwrap_or(fallback.push),
+            email: self.type_email.or(self.default_email).unwrap_or(fallback.email),
+        };
+        if channels.is_none() {
+            return channels;
+        }
+
+        // Digested types are stored for the next summary instead of pushed or emailed now
+        let digested = match (&self.digest_frequency, &self.digest_types) {
+            (Some(frequency), Some(types)) => {
+                notification_digests::collects(frequency, types, notification_type)
+            }
+            _ => false,
+        };
+        if digested {
+            channels.in_app = true;
+            channels.push = false;
+            channels.email = false;
+        }
+
+        // Quiet hours hold back push only; the notification is still stored for the app
+        if self.quiet {
+            channels.push = false;
+        }
+
+        // Only confirmed addresses receive notification emails
+        if self.verified_email.is_none() {
+            channels.email = false;
+        }
+
+        channels
+    }
+}
+
+/// Single entry point for sending notifications to users.
+///
+/// Handles eligibility, channel preferences, digests and quiet hours, stores
+/// in-app notifications, delivers them to open WebSocket connections, queues
+/// pushes in the outbox and sends emails in the background.
+#[derive(Clone)]
+pub struct NotificationDispatcher {
+    db: PgPool,
+    email_service: Arc<EmailService>,
+    ws_server: WsServerActor,
+}
+
+impl NotificationDispatcher {
+    pub fn new(db: PgPool, email_service: Arc<EmailService>, ws_server: WsServerActor) -> Self {
+        Self {
+            db,
+            email_service,
+            ws_server,
+        }
+    }
+
+    /// Notify one user. `build` is called with the recipient's locale.
+    /// Returns the stored notification when in-app delivery is enabled.
+    pub async fn notify(
+        &self,
+        user_id: i32,
+        build: impl Fn(Locale) -> NotificationBody,
+        priority: &str,
+    ) -> Option<Notification> {
+        self.notify_many(&[user_id], build, priority).await.pop()
+    }
+
+    /// Notify many users with one body per locale. Recipients are resolved and
+    /// stored in batches; push and email are delivered after this returns.
+    /// Returns the notifications stored for in-app delivery.
+    pub async fn notify_many(
+        &self,
+        user_ids: &[i32],
+        build: impl Fn(Locale) -> NotificationBody,
+        priority: &str,
+    ) -> Vec<Notification> {
+        let mut seen = HashSet::new();
+        let recipients: Vec<i32> = user_ids
+            .iter()
+            .copied()
+            .filter(|user_id| seen.insert(*user_id))
+            .collect();
+
+        // The type decides preferences and is the same in every language
+        let default_body = build(Locale::default());
+        let notification_type = default_body.body_type.clone();
+        let mut bodies = HashMap::from([(Locale::default(), default_body)]);
+
+        let mut stored = Vec::new();
+        let mut pushes = Vec::new();
+        let mut emails = Vec::new();
+
+        for batch in recipients.chunks(BATCH_SIZE) {
+            let contexts = match self.load_contexts(batch, &notification_type).await {
+                Ok(contexts) => contexts,
+                Err(e) => {
+                    error!("Database error resolving notification recipients: {:?}", e);
+                    continue;
+                }
+            };
+
+            let mut to_store = Vec::new();
+            for context in contexts.iter().filter(|context| context.eligible) {
+                let channels = context.channels(&notification_type);
+                if channels.is_none() {
+                    continue;
+                }
+                let locale = Locale::from_code(&context.locale).unwrap_or_default();
+                bodies.entry(locale).or_insert_with(|| build(locale));
+                if channels.in_app {
+                    to_store.push((context.user_id, locale));
+                }
+                if channels.push {
+                    pushes.push((context.user_id, locale));
+                }
+                if let (true, Some(email)) = (channels.email, &context.verified_email) {
+                    emails.push((email.clone(), locale));
+                }
+            }
+
+            match self.store(&to_store, &bodies, priority).await {
+                Ok(notifications) => stored.extend(notifications),
+                Err(e) => error!("Database error storing notifications: {:?}", e),
+            }
+        }
+
+        self.send_created(&stored).await;
+
+        if !pushes.is_empty() {
+            debug!("[NOTIFY] Queueing {} pushes", pushes.len());
+            let stored_ids: HashMap<i32, i32> = stored
+                .iter()
+                .map(|notification| (notification.user_id, notification.id))
+                .collect();
+            let pushes = pushes
+                .into_iter()
+                .map(|(user_id, locale)| PushDelivery {
+                    user_id,
+                    body: bodies[&locale].clone(),
+                    notification_id: stored_ids.get(&user_id).copied(),
+                })
+                .collect();
+            push::enqueue(&self.db, pushes).await;
+        }
+
+        if !emails.is_empty() {
+            debug!("[NOTIFY] Sending {} emails", emails.len());
+            let emails: Vec<(String, Locale, NotificationBody)> = emails
+                .into_iter()
+                .map(|(email, locale)| (email, locale, bodies[&locale].clone()))
+                .collect();
+
+            let email_service = self.email_service.clone();
+            actix_web::rt::spawn(async move {
+                stream::iter(emails)
+                    .for_each_concurrent(EMAIL_CONCURRENCY, |(email, locale, body)| {
+                        let email_service = email_service.clone();
+                        async move {
+                            if let Err(e) = email_service
+                                .send_notification_email(locale, &email, &body)
+                                .await
+                            {
+                                error!("Notification email to {} failed: {}", email, e);
+                            }
+                        }
+                    })
+                    .await;
+            });
+        }
+
+        stored
+    }
+
+    /// Send `notification` events to recipients that are online, with their
+    /// new unread count so badges update without polling.
+    async fn send_created(&self, stored: &[Notification]) {
+        let user_ids: Vec<i32> = stored.iter().map(|notification| notification.user_id).collect();
+        let online = self.ws_server.connected_users(&user_ids).await;
+        if online.is_empty() {
+            return;
+        }
+
+        let unread_counts = notifications::unread_counts(&self.db, &online)
+            .await
+            .unwrap_or_else(|e| {
+                error!("Database error getting unread counts: {:?}", e);
+                HashMap::new()
+            });
+
+        let online: HashSet<i32> = online.into_iter().collect();
+        for notification in stored.iter().filter(|n| online.contains(&n.user_id)) {
+            let message = ServerMessage::Notification {
+                notification: Box::new(notification.clone()),
+                unread_count: unread_counts.get(&notification.user_id).copied().unwrap_or(0),
+            };
+            self.ws_server.send_to_user(notification.user_id, message).await;
+        }
+    }
+
+    /// Tell the user's open connections that notifications were read, archived,
+    /// snoozed or deleted, with the new unread count for the badge.
+    pub async fn publish_change(
+        &self,
+        user_id: i32,
+        change: NotificationChange,
+        notification_ids: &[i32],
+    ) {
+        if notification_ids.is_empty() {
+            return;
+        }
+
+        let unread_count = match notifications::unread_count(&self.db, user_id).await {
+            Ok(count) => count,
+            Err(e) => {
+                error!("Database error getting unread count: {:?}", e);
+                return;
+            }
+        };
+
+        let message = ServerMessage::NotificationsChanged {
+            user_id,
+            change,
+            notification_ids: notification_ids.to_vec(),
+            unread_count,
+        };
+        self.ws_server.send_to_user(user_id, message).await;
+    }
+
+    async fn load_contexts(
+        &self,
+        user_ids: &[i32],
+        notification_type: &str,
+    ) -> Result<Vec<RecipientContext>, sqlx::Error> {
+        sqlx::query_as::<_, RecipientContext>(
+            "SELECT u.id AS user_id, u.locale,
+                    (EXISTS(SELECT 1 FROM user_roles ur JOIN roles r ON ur.role_id = r.id
+                            WHERE ur.user_id = u.id AND r.name = 'admin')
+                     OR EXISTS(SELECT 1 FROM students s WHERE s.user_id = u.id AND s.status = 'active')
+                     OR EXISTS(SELECT 1 FROM parents p WHERE p.user_id = u.id AND p.status = 'active')
+                     OR EXISTS(SELECT 1 FROM teachers t WHERE t.user_id = u.id AND t.status = 'active')
+                    ) AS eligible,
+                    tp.in_app AS type_in_app, tp.push AS type_push, tp.email AS type_email,
+                    dp.in_app AS default_in_app, dp.push AS default_push, dp.email AS default_email,
+                    ds.frequency AS digest_frequency, ds.notification_types AS digest_types,
+                    COALESCE(CASE
+                        WHEN q.start_time < q.end_time THEN
+                            (NOW() AT TIME ZONE u.timezone)::time >= q.start_time
+                            AND (NOW() AT TIME ZONE u.timezone)::time < q.end_time
+                        ELSE
+                            (NOW() AT TIME ZONE u.timezone)::time >= q.start_time
+                            OR (NOW() AT TIME ZONE u.timezone)::time < q.end_time
+                    END, FALSE) AS quiet,
+                    CASE WHEN u.email_verified_at IS NOT NULL AND u.email <> '' THEN u.email END
+                        AS verified_email
+             FROM users u
+             LEFT JOIN notification_channel_preferences tp
+               ON tp.user_id = u.id AND tp.notification_type = $2
+             LEFT JOIN notification_channel_preferences dp
+               ON dp.user_id = u.id AND dp.notification_type = $3
+             LEFT JOIN notification_digest_settings ds ON ds.user_id = u.id
+             LEFT JOIN notification_quiet_hours q ON q.user_id = u.id AND q.enabled
+             WHERE u.id = ANY($1)",
+        )
+        .bind(user_ids)
+        .bind(notification_type)
+        .bind(DEFAULT_TYPE)
+        .fetch_all(&self.db)
+        .await
+    }
+
+    /// Insert or collapse one row per recipient in a single statement
+    async fn store(
+        &self,
+        recipients: &[(i32, Locale)],
+        bodies: &HashMap<Locale, NotificationBody>,
+        priority: &str,
+    ) -> Result<Vec<Notification>, sqlx::Error> {
+        if recipients.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut user_ids = Vec::with_capacity(recipients.len());
+        let mut types = Vec::with_capacity(recipients.len());
+        let mut titles = Vec::with_capacity(recipients.len());
+        let mut payloads = Vec::with_capacity(recipients.len());
+        let mut collapse_keys = Vec::with_capacity(recipients.len());
+        for (user_id, locale) in recipients {
+            let body = &bodies[locale];
+            user_ids.push(*user_id);
+            types.push(body.body_type.clone());
+            titles.push(body.title.clone());
+            payloads.push(serde_json::to_value(body).unwrap_or_default());
+            collapse_keys.push(body.collapse_key.clone());
+        }
+
+        // An unread notification with the same collapse key takes the latest
+        // content and counts the occurrence instead of a new row being added;
+        // a new occurrence also brings a snoozed row back
+        sqlx::query_as::<_, Notification>(
+            "INSERT INTO notifications AS n (user_id, type, title, body, priority, collapse_key)
+             SELECT user_id, type, title, body, $5, collapse_key
+             FROM UNNEST($1::int[], $2::text[], $3::text[], $4::jsonb[], $6::text[])
+                  AS u(user_id, type, title, body, collapse_key)
+             ON CONFLICT (user_id, collapse_key) WHERE read_at IS NULL AND collapse_key IS NOT NULL
+             DO UPDATE SET title = EXCLUDED.title,
+                           body = EXCLUDED.body,
+                           priority = EXCLUDED.priority,
+                           collapsed_count = n.collapsed_count + 1,
+                           created_at = NOW(),
+                           digested_at = NULL,
+                           snoozed_until = NULL
+             RETURNING id, user_id, type AS notification_type, title, body,
+                       created_at, read_at, priority, collapse_key, collapsed_count,
+                       archived_at, snoozed_until",
+        )
+        .bind(&user_ids)
+        .bind(&types)
+        .bind(&titles)
+        .bind(&payloads)
+        .bind(priority)
+        .bind(&collapse_keys)
+        .fetch_all(&self.db)
+        .await
+    }
+}
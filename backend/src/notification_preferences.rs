@@ -0,0 +1,357 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use chrono::NaiveTime;
+use log::error;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+
+use crate::notifications::extract_user_id_from_token;
+use crate::AppState;
+
+/// Notification types users can configure, matching `NotificationBody.body_type`
+pub const NOTIFICATION_TYPES: &[&str] = &[
+    "announcement",
+    "chat_message",
+    "feed_comment",
+    "feed_post",
+    "hometask_accomplished",
+    "hometask_assigned",
+    "hometask_completed",
+    "hometask_refreshed",
+    "hometask_reopened",
+    "password_issued",
+    "password_reset_request",
+    "results_available",
+    "schedule_change",
+    "task_assigned",
+];
+
+/// Preference row that applies to every type without its own row
+pub const DEFAULT_TYPE: &str = "default";
+
+/// Where a notification is delivered. All channels off means "none".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct ChannelSelection {
+    pub in_app: bool,
+    pub push: bool,
+    pub email: bool,
+}
+
+impl Default for ChannelSelection {
+    fn default() -> Self {
+        Self {
+            in_app: true,
+            push: true,
+            email: false,
+        }
+    }
+}
+
+impl ChannelSelection {
+    pub fn is_none(&self) -> bool {
+        !self.in_app && !self.push && !self.email
+    }
+}
+
+/// Daily window in the user's timezone during which push is held back
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct QuietHours {
+    pub enabled: bool,
+    #[serde(with = "time_of_day")]
+    pub start_time: NaiveTime,
+    #[serde(with = "time_of_day")]
+    pub end_time: NaiveTime,
+}
+
+/// Times are exchanged as "HH:MM"
+mod time_of_day {
+    use chrono::NaiveTime;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(time: &NaiveTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&time.format("%H:%M").to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        NaiveTime::parse_from_str(&value, "%H:%M").map_err(serde::de::Error::custom)
+    }
+}
+
+fn is_configurable_type(notification_type: &str) -> bool {
+    notification_type == DEFAULT_TYPE || NOTIFICATION_TYPES.contains(&notification_type)
+}
+
+#[derive(Debug, FromRow)]
+struct PreferenceRow {
+    notification_type: String,
+    in_app: bool,
+    push: bool,
+    email: bool,
+}
+
+#[derive(Debug, FromRow)]
+struct QuietHoursRow {
+    enabled: Option<bool>,
+    start_time: Option<NaiveTime>,
+    end_time: Option<NaiveTime>,
+    timezone: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TypePreference {
+    #[serde(rename = "type")]
+    pub notification_type: String,
+    #[serde(flatten)]
+    pub channels: ChannelSelection,
+    /// True when the type has no row of its own and follows the default
+    pub inherited: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreferencesResponse {
+    pub default: ChannelSelection,
+    pub types: Vec<TypePreference>,
+    pub quiet_hours: Option<QuietHours>,
+    pub timezone: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreferenceUpdate {
+    #[serde(rename = "type")]
+    pub notification_type: String,
+    pub in_app: bool,
+    pub push: bool,
+    pub email: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePreferencesRequest {
+    #[serde(default)]
+    pub preferences: Vec<PreferenceUpdate>,
+    /// `null` removes quiet hours, omitting the field keeps them unchanged
+    #[serde(default, deserialize_with = "double_option")]
+    pub quiet_hours: Option<Option<QuietHours>>,
+}
+
+fn double_option<'de, D, T>(deserializer: D) -> std::result::Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+async fn load_preferences(db: &PgPool, user_id: i32) -> Result<PreferencesResponse> {
+    let rows = sqlx::query_as::<_, PreferenceRow>(
+        "SELECT notification_type, in_app, push, email
+         FROM notification_channel_preferences WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_all(db)
+    .await
+    .map_err(|e| {
+        error!("Database error fetching notification preferences: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to fetch notification preferences")
+    })?;
+
+    let channels_of = |row: &PreferenceRow| ChannelSelection {
+        in_app: row.in_app,
+        push: row.push,
+        email: row.email,
+    };
+
+    let default = rows
+        .iter()
+        .find(|row| row.notification_type == DEFAULT_TYPE)
+        .map(channels_of)
+        .unwrap_or_default();
+
+    let types = NOTIFICATION_TYPES
+        .iter()
+        .map(|notification_type| {
+            match rows.iter().find(|row| row.notification_type == *notification_type) {
+                Some(row) => TypePreference {
+                    notification_type: notification_type.to_string(),
+                    channels: channels_of(row),
+                    inherited: false,
+                },
+                None => TypePreference {
+                    notification_type: notification_type.to_string(),
+                    channels: default,
+                    inherited: true,
+                },
+            }
+        })
+        .collect();
+
+    let settings = sqlx::query_as::<_, QuietHoursRow>(
+        "SELECT q.enabled, q.start_time, q.end_time, u.timezone
+         FROM users u LEFT JOIN notification_quiet_hours q ON q.user_id = u.id
+         WHERE u.id = $1",
+    )
+    .bind(user_id)
+    .fetch_one(db)
+    .await
+    .map_err(|e| {
+        error!("Database error fetching quiet hours: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to fetch notification preferences")
+    })?;
+
+    let quiet_hours = match (settings.enabled, settings.start_time, settings.end_time) {
+        (Some(enabled), Some(start_time), Some(end_time)) => Some(QuietHours {
+            enabled,
+            start_time,
+            end_time,
+        }),
+        _ => None,
+    };
+
+    Ok(PreferencesResponse {
+        default,
+        types,
+        quiet_hours,
+        timezone: settings.timezone,
+    })
+}
+
+/// Get the authenticated user's delivery channels per notification type
+pub async fn get_preferences(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+) -> Result<HttpResponse> {
+    let user_id = extract_user_id_from_token(&req, &app_state).await?;
+    let preferences = load_preferences(&app_state.db, user_id).await?;
+
+    Ok(HttpResponse::Ok().json(preferences))
+}
+
+/// Set delivery channels for the default and/or individual notification types,
+/// and optionally the quiet hours
+pub async fn update_preferences(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    payload: web::Json<UpdatePreferencesRequest>,
+) -> Result<HttpResponse> {
+    let user_id = extract_user_id_from_token(&req, &app_state).await?;
+
+    if let Some(unknown) = payload
+        .preferences
+        .iter()
+        .find(|preference| !is_configurable_type(&preference.notification_type))
+    {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Unknown notification type: {}", unknown.notification_type)
+        })));
+    }
+
+    if let Some(Some(quiet_hours)) = &payload.quiet_hours {
+        if quiet_hours.start_time == quiet_hours.end_time {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Quiet hours must start and end at different times"
+            })));
+        }
+    }
+
+    let mut tx = app_state.db.begin().await.map_err(|e| {
+        error!("Failed to start transaction: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to update notification preferences")
+    })?;
+
+    for preference in &payload.preferences {
+        if let Err(e) = sqlx::query(
+            "INSERT INTO notification_channel_preferences
+                 (user_id, notification_type, in_app, push, email, updated_at)
+             VALUES ($1, $2, $3, $4, $5, NOW())
+             ON CONFLICT (user_id, notification_type)
+             DO UPDATE SET in_app = EXCLUDED.in_app, push = EXCLUDED.push,
+                           email = EXCLUDED.email, updated_at = NOW()",
+        )
+        .bind(user_id)
+        .bind(&preference.notification_type)
+        .bind(preference.in_app)
+        .bind(preference.push)
+        .bind(preference.email)
+        .execute(&mut *tx)
+        .await
+        {
+            error!("Database error updating notification preferences: {:?}", e);
+            let _ = tx.rollback().await;
+            return Err(actix_web::error::ErrorInternalServerError(
+                "Failed to update notification preferences",
+            ));
+        }
+    }
+
+    let quiet_hours_result = match &payload.quiet_hours {
+        Some(Some(quiet_hours)) => {
+            sqlx::query(
+                "INSERT INTO notification_quiet_hours
+                     (user_id, enabled, start_time, end_time, updated_at)
+                 VALUES ($1, $2, $3, $4, NOW())
+                 ON CONFLICT (user_id)
+                 DO UPDATE SET enabled = EXCLUDED.enabled, start_time = EXCLUDED.start_time,
+                               end_time = EXCLUDED.end_time, updated_at = NOW()",
+            )
+            .bind(user_id)
+            .bind(quiet_hours.enabled)
+            .bind(quiet_hours.start_time)
+            .bind(quiet_hours.end_time)
+            .execute(&mut *tx)
+            .await
+        }
+        Some(None) => {
+            sqlx::query("DELETE FROM notification_quiet_hours WHERE user_id = $1")
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await
+        }
+        None => Ok(Default::default()),
+    };
+
+    if let Err(e) = quiet_hours_result {
+        error!("Database error updating quiet hours: {:?}", e);
+        let _ = tx.rollback().await;
+        return Err(actix_web::error::ErrorInternalServerError(
+            "Failed to update notification preferences",
+        ));
+    }
+
+    tx.commit().await.map_err(|e| {
+        error!("Failed to commit notification preferences: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to update notification preferences")
+    })?;
+
+    let preferences = load_preferences(&app_state.db, user_id).await?;
+    Ok(HttpResponse::Ok().json(preferences))
+}
+
+/// Drop a type's own row so it follows the default again
+pub async fn reset_preference(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    notification_type: web::Path<String>,
+) -> Result<HttpResponse> {
+    let user_id = extract_user_id_from_token(&req, &app_state).await?;
+
+    if !is_configurable_type(&notification_type) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Unknown notification type: {}", notification_type)
+        })));
+    }
+
+    sqlx::query(
+        "DELETE FROM notification_channel_preferences
+         WHERE user_id = $1 AND notification_type = $2",
+    )
+    .bind(user_id)
+    .bind(notification_type.as_str())
+    .execute(&app_state.db)
+    .await
+    .map_err(|e| {
+        error!("Database error resetting notification preference: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to reset notification preference")
+    })?;
+
+    let preferences = load_preferences(&app_state.db, user_id).await?;
+    Ok(HttpResponse::Ok().json(preferences))
+}
@@ -0,0 +1,390 @@
+use actix_web::{get, post, put, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::audit::{self, AuditEvent};
+use crate::users::{verify_token, Claims};
+use crate::AppState;
+
+/// How often expired notifications are cleaned up
+const CHECK_INTERVAL_SECS: u64 = 60 * 60;
+/// Notifications deleted or archived per statement
+const BATCH_SIZE: i64 = 1000;
+const PRIORITIES: [&str; 4] = ["low", "normal", "high", "urgent"];
+const EXPIRE_ACTIONS: [&str; 2] = ["delete", "archive"];
+
+/// How long notifications of a type and/or priority are kept. `None` in
+/// `notification_type` or `priority` matches everything; the most specific
+/// policy applies.
+#[derive(Debug, Serialize, FromRow)]
+pub struct RetentionPolicy {
+    pub id: i32,
+    pub notification_type: Option<String>,
+    pub priority: Option<String>,
+    pub read_retention_days: i32,
+    pub unread_retention_days: Option<i32>,
+    pub archived_retention_days: Option<i32>,
+    pub expire_action: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RetentionPolicyInput {
+    pub notification_type: Option<String>,
+    pub priority: Option<String>,
+    pub read_retention_days: i32,
+    pub unread_retention_days: Option<i32>,
+    /// Days after archiving until archived notifications are deleted; `None` keeps them
+    pub archived_retention_days: Option<i32>,
+    /// "delete" (default) or "archive"
+    pub expire_action: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateRetentionRequest {
+    pub policies: Vec<RetentionPolicyInput>,
+}
+
+/// Outcome of one retention pass
+#[derive(Debug, Default, Serialize)]
+pub struct RetentionReport {
+    pub deleted: i64,
+    pub archived: i64,
+}
+
+/// Delete or archive every notification past its retention window. Archived
+/// notifications only expire through the archive window and are then deleted.
+pub async fn apply_retention(db: &PgPool) -> Result<RetentionReport, sqlx::Error> {
+    let mut report = RetentionReport::default();
+    loop {
+        let (deleted, archived) = sqlx::query_as::<_, (i64, i64)>(
+            "WITH expired AS (
+                 SELECT n.id,
+                        CASE WHEN n.archived_at IS NULL THEN p.expire_action ELSE 'delete' END
+                            AS expire_action
+                 FROM notifications n
+                 CROSS JOIN LATERAL (
+                     SELECT rp.read_retention_days, rp.unread_retention_days,
+                            rp.archived_retention_days, rp.expire_action
+                     FROM notification_retention_policies rp
+                     WHERE (rp.notification_type IS NULL OR rp.notification_type = n.type)
+                       AND (rp.priority IS NULL OR rp.priority = n.priority)
+                     ORDER BY rp.notification_type IS NULL, rp.priority IS NULL
+                     LIMIT 1
+                 ) p
+                 WHERE (n.archived_at IS NULL
+                        AND ((n.read_at IS NOT NULL
+                              AND n.read_at <= NOW() - make_interval(days => p.read_retention_days))
+                          OR (n.read_at IS NULL AND p.unread_retention_days IS NOT NULL
+                              AND n.created_at <= NOW() - make_interval(days => p.unread_retention_days))))
+                    OR (n.archived_at IS NOT NULL AND p.archived_retention_days IS NOT NULL
+                        AND n.archived_at <= NOW() - make_interval(days => p.archived_retention_days))
+                 LIMIT $1
+             ),
+             deleted AS (
+                 DELETE FROM notifications
+                 WHERE id IN (SELECT id FROM expired WHERE expire_action = 'delete')
+                 RETURNING id
+             ),
+             archived AS (
+                 UPDATE notifications
+                 SET archived_at = NOW(), read_at = COALESCE(read_at, NOW()), snoozed_until = NULL
+                 WHERE id IN (SELECT id FROM expired WHERE expire_action = 'archive')
+                 RETURNING id
+             )
+             SELECT (SELECT COUNT(*) FROM deleted), (SELECT COUNT(*) FROM archived)",
+        )
+        .bind(BATCH_SIZE)
+        .fetch_one(db)
+        .await?;
+
+        report.deleted += deleted;
+        report.archived += archived;
+        if deleted + archived < BATCH_SIZE {
+            return Ok(report);
+        }
+    }
+}
+
+/// Background loop applying the retention policies
+pub async fn run_worker(db: PgPool) {
+    let mut interval = actix_web::rt::time::interval(Duration::from_secs(CHECK_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        match apply_retention(&db).await {
+            Ok(report) if report.deleted > 0 || report.archived > 0 => info!(
+                "Notification retention: {} deleted, {} archived",
+                report.deleted, report.archived
+            ),
+            Ok(_) => {}
+            Err(e) => error!("Database error applying notification retention: {:?}", e),
+        }
+    }
+}
+
+fn verify_admin_claims(req: &HttpRequest, app_state: &AppState) -> Result<Claims, HttpResponse> {
+    let claims = verify_token(req, app_state)?;
+
+    if claims.impersonation.is_some() || !claims.roles.contains(&"admin".to_string()) {
+        return Err(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Admin access required"
+        })));
+    }
+
+    Ok(claims)
+}
+
+async fn load_policies(db: &PgPool) -> Result<Vec<RetentionPolicy>, sqlx::Error> {
+    sqlx::query_as::<_, RetentionPolicy>(
+        "SELECT id, notification_type, priority, read_retention_days, unread_retention_days,
+                archived_retention_days, expire_action, updated_at
+         FROM notification_retention_policies
+         ORDER BY notification_type NULLS FIRST, priority NULLS FIRST",
+    )
+    .fetch_all(db)
+    .await
+}
+
+fn validate_policy(policy: &RetentionPolicyInput) -> Result<(), &'static str> {
+    if policy.notification_type.as_deref().is_some_and(|t| t.trim().is_empty()) {
+        return Err("Notification type must not be empty");
+    }
+    if let Some(priority) = &policy.priority {
+        if !PRIORITIES.contains(&priority.as_str()) {
+            return Err("Invalid priority");
+        }
+    }
+    if policy.read_retention_days <= 0
+        || policy.unread_retention_days.is_some_and(|days| days <= 0)
+        || policy.archived_retention_days.is_some_and(|days| days <= 0)
+    {
+        return Err("Retention windows must be at least one day");
+    }
+    if let Some(action) = &policy.expire_action {
+        if !EXPIRE_ACTIONS.contains(&action.as_str()) {
+            return Err("Expire action must be 'delete' or 'archive'");
+        }
+    }
+    Ok(())
+}
+
+/// Admin: the retention policies in effect
+#[get("/api/admin/notifications/retention")]
+async fn get_retention_policies(req: HttpRequest, app_state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = verify_admin_claims(&req, &app_state) {
+        return response;
+    }
+
+    match load_policies(&app_state.db).await {
+        Ok(policies) => HttpResponse::Ok().json(policies),
+        Err(e) => {
+            error!("Database error loading retention policies: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }))
+        }
+    }
+}
+
+/// Admin: replace all retention policies
+#[put("/api/admin/notifications/retention")]
+async fn update_retention_policies(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    body: web::Json<UpdateRetentionRequest>,
+) -> impl Responder {
+    if let Err(response) = verify_admin_claims(&req, &app_state) {
+        return response;
+    }
+
+    if let Some(message) = body.policies.iter().find_map(|p| validate_policy(p).err()) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": message }));
+    }
+
+    let before = load_policies(&app_state.db).await.ok();
+
+    let mut types = Vec::with_capacity(body.policies.len());
+    let mut priorities = Vec::with_capacity(body.policies.len());
+    let mut read_days = Vec::with_capacity(body.policies.len());
+    let mut unread_days = Vec::with_capacity(body.policies.len());
+    let mut archived_days = Vec::with_capacity(body.policies.len());
+    let mut actions = Vec::with_capacity(body.policies.len());
+    for policy in &body.policies {
+        types.push(policy.notification_type.as_deref().map(str::trim));
+        priorities.push(policy.priority.as_deref());
+        read_days.push(policy.read_retention_days);
+        unread_days.push(policy.unread_retention_days);
+        archived_days.push(policy.archived_retention_days);
+        actions.push(policy.expire_action.as_deref().unwrap_or("delete"));
+    }
+
+    let mut tx = match app_state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => {
+            error!("Failed to start transaction: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    };
+
+    if let Err(e) = sqlx::query("DELETE FROM notification_retention_policies")
+        .execute(&mut *tx)
+        .await
+    {
+        let _ = tx.rollback().await;
+        error!("Database error clearing retention policies: {:?}", e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Internal server error"
+        }));
+    }
+
+    if let Err(e) = sqlx::query(
+        "INSERT INTO notification_retention_policies
+            (notification_type, priority, read_retention_days, unread_retention_days,
+             archived_retention_days, expire_action)
+         SELECT * FROM UNNEST($1::text[], $2::text[], $3::int[], $4::int[], $5::int[], $6::text[])",
+    )
+    .bind(&types)
+    .bind(&priorities)
+    .bind(&read_days)
+    .bind(&unread_days)
+    .bind(&archived_days)
+    .bind(&actions)
+    .execute(&mut *tx)
+    .await
+    {
+        let _ = tx.rollback().await;
+        error!("Failed to store retention policies: {:?}", e);
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Failed to store retention policies (duplicate type and priority?)"
+        }));
+    }
+
+    if let Err(e) = tx.commit().await {
+        error!("Failed to commit retention policies: {}", e);
+        return HttpResponse::InternalServerError().json(serde_json::json!({
+            "error": "Internal server error"
+        }));
+    }
+
+    let policies = match load_policies(&app_state.db).await {
+        Ok(policies) => policies,
+        Err(e) => {
+            error!("Database error loading retention policies: {:?}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }));
+        }
+    };
+
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("notification_retention.update", "notification_retention", None)
+            .before(before.and_then(|p| serde_json::to_value(p).ok()))
+            .after(serde_json::to_value(&policies).ok()),
+    )
+    .await;
+
+    HttpResponse::Ok().json(policies)
+}
+
+/// Admin: apply the retention policies now instead of waiting for the worker
+#[post("/api/admin/notifications/retention/run")]
+async fn run_retention(req: HttpRequest, app_state: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = verify_admin_claims(&req, &app_state) {
+        return response;
+    }
+
+    match apply_retention(&app_state.db).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(e) => {
+            error!("Database error applying notification retention: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    /// Window for `created_recently` in hours (default 24, max 30 days)
+    pub hours: Option<i32>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+struct TypeVolume {
+    #[serde(rename = "type")]
+    notification_type: String,
+    total: i64,
+    unread: i64,
+    archived: i64,
+    snoozed: i64,
+    created_recently: i64,
+    oldest_at: Option<DateTime<Utc>>,
+}
+
+/// Admin: stored notification volume per type and priority
+#[get("/api/admin/notifications/stats")]
+async fn notification_stats(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<StatsQuery>,
+) -> impl Responder {
+    if let Err(response) = verify_admin_claims(&req, &app_state) {
+        return response;
+    }
+
+    let hours = query.hours.unwrap_or(24).clamp(1, 24 * 30);
+    let db = &app_state.db;
+
+    let by_type = sqlx::query_as::<_, TypeVolume>(
+        "SELECT type AS notification_type,
+                COUNT(*) AS total,
+                COUNT(*) FILTER (WHERE read_at IS NULL) AS unread,
+                COUNT(*) FILTER (WHERE archived_at IS NOT NULL) AS archived,
+                COUNT(*) FILTER (WHERE snoozed_until > NOW()) AS snoozed,
+                COUNT(*) FILTER (WHERE created_at > NOW() - make_interval(hours => $1)) AS created_recently,
+                MIN(created_at) AS oldest_at
+         FROM notifications
+         GROUP BY type
+         ORDER BY total DESC, type",
+    )
+    .bind(hours)
+    .fetch_all(db)
+    .await;
+
+    let by_priority = sqlx::query_as::<_, (String, i64)>(
+        "SELECT priority, COUNT(*) FROM notifications GROUP BY priority",
+    )
+    .fetch_all(db)
+    .await;
+
+    match (by_type, by_priority) {
+        (Ok(by_type), Ok(by_priority)) => HttpResponse::Ok().json(serde_json::json!({
+            "window_hours": hours,
+            "total": by_type.iter().map(|t| t.total).sum::<i64>(),
+            "by_type": by_type,
+            "by_priority": by_priority.into_iter().collect::<HashMap<_, _>>(),
+        })),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Database error loading notification stats: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }))
+        }
+    }
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(get_retention_policies)
+        .service(update_retention_policies)
+        .service(run_retention)
+        .service(notification_stats);
+}
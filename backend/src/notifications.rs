@@ -1,12 +1,18 @@
 use actix_web::{web, HttpRequest, HttpResponse, Result};
 use chrono::{DateTime, Utc};
 use log::error;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use sqlx::PgPool;
+use std::collections::HashMap;
 
-use crate::push;
+use crate::hometasks;
+use crate::models::hometask::HometaskStatus;
+use crate::notification_digests;
+use crate::notification_preferences;
 use crate::users::verify_token;
+use crate::websocket_protocol::NotificationChange;
 use crate::AppState;
 
 pub async fn is_user_notification_eligible(db: &PgPool, user_id: i32) -> bool {
@@ -42,7 +48,7 @@ pub async fn is_user_notification_eligible(db: &PgPool, user_id: i32) -> bool {
     user_exists && (is_admin || has_active_student || has_active_parent || has_active_teacher)
 }
 
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, JsonSchema)]
 pub struct Notification {
     pub id: i32,
     pub user_id: i32,
@@ -53,6 +59,12 @@ pub struct Notification {
     pub created_at: DateTime<Utc>,
     pub read_at: Option<DateTime<Utc>>,
     pub priority: String,
+    pub collapse_key: Option<String>,
+    /// Number of notifications merged into this row through its collapse key
+    pub collapsed_count: i32,
+    pub archived_at: Option<DateTime<Utc>>,
+    /// Hidden from the inbox and the badge until this time
+    pub snoozed_until: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -75,6 +87,10 @@ pub struct NotificationBody {
     pub content: NotificationContent,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub metadata: Option<JsonValue>,
+    /// Unread notifications with the same key (e.g. "chat:12") are merged into
+    /// one row, and devices replace the previous push instead of stacking them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collapse_key: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -118,7 +134,7 @@ pub struct ActionButton {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub route: Option<String>, // Frontend route
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub action: Option<String>, // e.g., "dismiss", "archive"
+    pub action: Option<String>, // Run via POST /api/notifications/{id}/actions/{action}
     #[serde(default)]
     pub primary: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -132,6 +148,8 @@ pub struct NotificationQuery {
     pub unread_only: Option<bool>,
     #[serde(rename = "type")]
     pub notification_type: Option<String>,
+    /// List the archive instead of the inbox
+    pub archived: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -139,6 +157,38 @@ pub struct MarkAsReadRequest {
     pub notification_ids: Vec<i32>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct NotificationIdsRequest {
+    pub notification_ids: Vec<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TypeFilterQuery {
+    #[serde(rename = "type")]
+    pub notification_type: Option<String>,
+}
+
+/// Either an absolute time or a number of minutes; defaults to an hour
+#[derive(Debug, Default, Deserialize)]
+pub struct SnoozeOptions {
+    pub until: Option<DateTime<Utc>>,
+    pub minutes: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnoozeRequest {
+    pub notification_ids: Vec<i32>,
+    #[serde(flatten)]
+    pub options: SnoozeOptions,
+}
+
+const DEFAULT_SNOOZE_MINUTES: i64 = 60;
+const MAX_SNOOZE_DAYS: i64 = 30;
+
+/// Actions every notification supports; any other action has to be offered by
+/// one of the notification's buttons
+const BUILTIN_ACTIONS: &[&str] = &["mark_read", "dismiss", "archive", "snooze", "delete"];
+
 /// Get notifications for the authenticated user
 pub async fn get_notifications(
     req: HttpRequest,
@@ -151,11 +201,18 @@ pub async fn get_notifications(
     let offset = query.offset.unwrap_or(0);
 
     let mut query_builder = sqlx::QueryBuilder::new(
-        "SELECT id, user_id, type as notification_type, title, body, created_at, read_at, priority \
+        "SELECT id, user_id, type as notification_type, title, body, created_at, read_at, priority, \
+                collapse_key, collapsed_count, archived_at, snoozed_until \
          FROM notifications WHERE user_id = "
     );
     query_builder.push_bind(user_id);
 
+    if let Some(true) = query.archived {
+        query_builder.push(" AND archived_at IS NOT NULL");
+    } else {
+        query_builder.push(" AND archived_at IS NULL AND (snoozed_until IS NULL OR snoozed_until <= NOW())");
+    }
+
     if let Some(true) = query.unread_only {
         query_builder.push(" AND read_at IS NULL");
     }
@@ -191,27 +248,55 @@ pub async fn mark_as_read(
 ) -> Result<HttpResponse> {
     let user_id = extract_user_id_from_token(&req, &app_state).await?;
 
-    let result = sqlx::query(
-        r#"
-        UPDATE notifications 
-        SET read_at = NOW()
-        WHERE id = ANY($1) AND user_id = $2 AND read_at IS NULL
-        "#,
-    )
-    .bind(&payload.notification_ids)
-    .bind(user_id)
-    .execute(&app_state.db)
-    .await
-    .map_err(|e| {
-        error!("Database error marking notifications as read: {:?}", e);
-        actix_web::error::ErrorInternalServerError("Failed to mark notifications as read")
-    })?;
+    let marked = mark_read(&app_state.db, user_id, &payload.notification_ids)
+        .await
+        .map_err(|e| {
+            error!("Database error marking notifications as read: {:?}", e);
+            actix_web::error::ErrorInternalServerError("Failed to mark notifications as read")
+        })?;
+
+    app_state
+        .notifications
+        .publish_change(user_id, NotificationChange::Read, &marked)
+        .await;
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "marked_as_read": result.rows_affected()
+        "marked_as_read": marked.len()
     })))
 }
 
+/// Notifications counted in the badge (chat has its own unread counts).
+/// Archived notifications are always read; snoozed ones count again once due.
+const UNREAD_BADGE_FILTER: &str = "read_at IS NULL AND type <> 'chat_message'
+    AND (snoozed_until IS NULL OR snoozed_until <= NOW())";
+
+/// Unread notifications shown in the badge
+pub async fn unread_count(db: &PgPool, user_id: i32) -> Result<i64, sqlx::Error> {
+    let query = format!(
+        "SELECT COUNT(*) FROM notifications WHERE user_id = $1 AND {}",
+        UNREAD_BADGE_FILTER
+    );
+    sqlx::query_scalar::<_, i64>(&query)
+        .bind(user_id)
+        .fetch_one(db)
+        .await
+}
+
+/// Badge counts of several users; users without unread notifications are left out
+pub async fn unread_counts(db: &PgPool, user_ids: &[i32]) -> Result<HashMap<i32, i64>, sqlx::Error> {
+    let query = format!(
+        "SELECT user_id, COUNT(*) FROM notifications
+         WHERE user_id = ANY($1) AND {}
+         GROUP BY user_id",
+        UNREAD_BADGE_FILTER
+    );
+    let counts = sqlx::query_as::<_, (i32, i64)>(&query)
+        .bind(user_ids)
+        .fetch_all(db)
+        .await?;
+    Ok(counts.into_iter().collect())
+}
+
 /// Get unread notification count
 pub async fn get_unread_count(
     req: HttpRequest,
@@ -219,13 +304,7 @@ pub async fn get_unread_count(
 ) -> Result<HttpResponse> {
     let user_id = extract_user_id_from_token(&req, &app_state).await?;
 
-    let count = sqlx::query_scalar::<_, i64>(
-        "SELECT COUNT(*) FROM notifications WHERE user_id = $1 AND read_at IS NULL AND type <> 'chat_message'"
-    )
-    .bind(user_id)
-    .fetch_one(&app_state.db)
-    .await
-    .map_err(|e| {
+    let count = unread_count(&app_state.db, user_id).await.map_err(|e| {
         error!("Database error getting unread count: {:?}", e);
         actix_web::error::ErrorInternalServerError("Failed to get unread count")
     })?;
@@ -235,15 +314,23 @@ pub async fn get_unread_count(
     })))
 }
 
-/// Create a new notification (typically called internally or by admins)
+/// Create a new notification (admins only), delivered like any other notification
 pub async fn create_notification(
-    db: web::Data<PgPool>,
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
     payload: web::Json<CreateNotification>,
 ) -> Result<HttpResponse> {
+    let claims = verify_token(&req, &app_state)
+        .map_err(|_| actix_web::error::ErrorUnauthorized("Invalid or missing token"))?;
+    if claims.impersonation.is_some() || !claims.roles.contains(&"admin".to_string()) {
+        return Err(actix_web::error::ErrorForbidden("Admin access required"));
+    }
+
+    let db = &app_state.db;
     let user_exists =
         sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM users WHERE id = $1)")
             .bind(payload.user_id)
-            .fetch_one(db.get_ref())
+            .fetch_one(db)
             .await
             .map_err(|e| {
                 error!("Database error checking user existence: {:?}", e);
@@ -254,43 +341,28 @@ pub async fn create_notification(
         return Err(actix_web::error::ErrorNotFound("User not found"));
     }
 
-    if !is_user_notification_eligible(db.get_ref(), payload.user_id).await {
+    if !is_user_notification_eligible(db, payload.user_id).await {
         return Err(actix_web::error::ErrorBadRequest(
             "Cannot create notification for user without active roles",
         ));
     }
 
-    let body_json = serde_json::to_value(&payload.body).map_err(|e| {
-        error!("Failed to serialize notification body: {:?}", e);
-        actix_web::error::ErrorBadRequest("Invalid notification body format")
-    })?;
-
-    let notification = sqlx::query_as::<_, Notification>(
-        r#"
-        INSERT INTO notifications (user_id, type, title, body, priority)
-        VALUES ($1, $2, $3, $4, $5)
-        RETURNING id, user_id, type as notification_type, title, body, created_at, read_at, priority
-        "#,
-    )
-    .bind(payload.user_id)
-    .bind(&payload.notification_type)
-    .bind(&payload.title)
-    .bind(body_json)
-    .bind(payload.priority.as_ref().unwrap_or(&"normal".to_string()))
-    .fetch_one(db.get_ref())
-    .await
-    .map_err(|e| {
-        error!("Database error creating notification: {:?}", e);
-        actix_web::error::ErrorInternalServerError("Failed to create notification")
-    })?;
-
-    push::send_notification_to_user(
-        db.get_ref(),
-        notification.user_id,
-        &payload.body,
-        Some(notification.id),
-    )
-    .await;
+    let mut body = payload.body.clone();
+    body.body_type = payload.notification_type.clone();
+    body.title = payload.title.clone();
+    let priority = payload.priority.clone().unwrap_or_else(|| "normal".to_string());
+
+    let notification = app_state
+        .notifications
+        .notify(payload.user_id, |_| body.clone(), &priority)
+        .await;
+
+    let Some(notification) = notification else {
+        // The recipient turned off in-app delivery for this type
+        return Ok(HttpResponse::Accepted().json(serde_json::json!({
+            "message": "Notification delivered without being stored"
+        })));
+    };
 
     Ok(HttpResponse::Created().json(notification))
 }
@@ -314,14 +386,314 @@ pub async fn delete_notification(
         })?;
 
     if result.rows_affected() > 0 {
+        app_state
+            .notifications
+            .publish_change(user_id, NotificationChange::Deleted, &[*notification_id])
+            .await;
+
         Ok(HttpResponse::Ok().json(serde_json::json!({"deleted": true})))
     } else {
         Ok(HttpResponse::NotFound().json(serde_json::json!({"error": "Notification not found"})))
     }
 }
 
+async fn mark_read(db: &PgPool, user_id: i32, ids: &[i32]) -> Result<Vec<i32>, sqlx::Error> {
+    sqlx::query_scalar::<_, i32>(
+        "UPDATE notifications
+         SET read_at = NOW()
+         WHERE id = ANY($1) AND user_id = $2 AND read_at IS NULL
+         RETURNING id",
+    )
+    .bind(ids)
+    .bind(user_id)
+    .fetch_all(db)
+    .await
+}
+
+/// Archiving also marks the notification read and ends any snooze
+async fn archive(db: &PgPool, user_id: i32, ids: &[i32]) -> Result<Vec<i32>, sqlx::Error> {
+    sqlx::query_scalar::<_, i32>(
+        "UPDATE notifications
+         SET archived_at = NOW(), read_at = COALESCE(read_at, NOW()), snoozed_until = NULL
+         WHERE id = ANY($1) AND user_id = $2 AND archived_at IS NULL
+         RETURNING id",
+    )
+    .bind(ids)
+    .bind(user_id)
+    .fetch_all(db)
+    .await
+}
+
+async fn snooze(
+    db: &PgPool,
+    user_id: i32,
+    ids: &[i32],
+    until: DateTime<Utc>,
+) -> Result<Vec<i32>, sqlx::Error> {
+    sqlx::query_scalar::<_, i32>(
+        "UPDATE notifications
+         SET snoozed_until = $3
+         WHERE id = ANY($1) AND user_id = $2 AND archived_at IS NULL
+         RETURNING id",
+    )
+    .bind(ids)
+    .bind(user_id)
+    .bind(until)
+    .fetch_all(db)
+    .await
+}
+
+async fn delete(db: &PgPool, user_id: i32, ids: &[i32]) -> Result<Vec<i32>, sqlx::Error> {
+    sqlx::query_scalar::<_, i32>(
+        "DELETE FROM notifications WHERE id = ANY($1) AND user_id = $2 RETURNING id",
+    )
+    .bind(ids)
+    .bind(user_id)
+    .fetch_all(db)
+    .await
+}
+
+fn snooze_until(options: &SnoozeOptions) -> Result<DateTime<Utc>> {
+    let now = Utc::now();
+    let until = match (options.until, options.minutes) {
+        (Some(until), _) => until,
+        (None, Some(minutes)) if minutes > 0 => now + chrono::Duration::minutes(minutes),
+        (None, Some(_)) => {
+            return Err(actix_web::error::ErrorBadRequest("Snooze minutes must be positive"))
+        }
+        (None, None) => now + chrono::Duration::minutes(DEFAULT_SNOOZE_MINUTES),
+    };
+
+    if until <= now {
+        return Err(actix_web::error::ErrorBadRequest("Snooze time must be in the future"));
+    }
+    if until > now + chrono::Duration::days(MAX_SNOOZE_DAYS) {
+        return Err(actix_web::error::ErrorBadRequest(format!(
+            "Notifications can be snoozed for at most {} days",
+            MAX_SNOOZE_DAYS
+        )));
+    }
+    Ok(until)
+}
+
+/// Mark every unread notification in the inbox as read, optionally only one type
+pub async fn mark_all_as_read(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<TypeFilterQuery>,
+) -> Result<HttpResponse> {
+    let user_id = extract_user_id_from_token(&req, &app_state).await?;
+
+    let marked = sqlx::query_scalar::<_, i32>(
+        "UPDATE notifications
+         SET read_at = NOW()
+         WHERE user_id = $1 AND read_at IS NULL AND type <> 'chat_message'
+           AND ($2::text IS NULL OR type = $2)
+           AND (snoozed_until IS NULL OR snoozed_until <= NOW())
+         RETURNING id",
+    )
+    .bind(user_id)
+    .bind(&query.notification_type)
+    .fetch_all(&app_state.db)
+    .await
+    .map_err(|e| {
+        error!("Database error marking all notifications as read: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to mark notifications as read")
+    })?;
+
+    app_state
+        .notifications
+        .publish_change(user_id, NotificationChange::Read, &marked)
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "marked_as_read": marked.len()
+    })))
+}
+
+/// Move notifications out of the inbox into the archive
+pub async fn archive_notifications(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    payload: web::Json<NotificationIdsRequest>,
+) -> Result<HttpResponse> {
+    let user_id = extract_user_id_from_token(&req, &app_state).await?;
+
+    let archived = archive(&app_state.db, user_id, &payload.notification_ids)
+        .await
+        .map_err(|e| {
+            error!("Database error archiving notifications: {:?}", e);
+            actix_web::error::ErrorInternalServerError("Failed to archive notifications")
+        })?;
+
+    app_state
+        .notifications
+        .publish_change(user_id, NotificationChange::Archived, &archived)
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "archived": archived.len()
+    })))
+}
+
+/// Hide notifications until a later time
+pub async fn snooze_notifications(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    payload: web::Json<SnoozeRequest>,
+) -> Result<HttpResponse> {
+    let user_id = extract_user_id_from_token(&req, &app_state).await?;
+    let until = snooze_until(&payload.options)?;
+
+    let snoozed = snooze(&app_state.db, user_id, &payload.notification_ids, until)
+        .await
+        .map_err(|e| {
+            error!("Database error snoozing notifications: {:?}", e);
+            actix_web::error::ErrorInternalServerError("Failed to snooze notifications")
+        })?;
+
+    app_state
+        .notifications
+        .publish_change(user_id, NotificationChange::Snoozed, &snoozed)
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "snoozed": snoozed.len(),
+        "snoozed_until": until,
+    })))
+}
+
+/// Delete all notifications of one type
+pub async fn delete_notifications_by_type(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<TypeFilterQuery>,
+) -> Result<HttpResponse> {
+    let user_id = extract_user_id_from_token(&req, &app_state).await?;
+
+    let Some(notification_type) = query.notification_type.as_deref() else {
+        return Err(actix_web::error::ErrorBadRequest("Notification type is required"));
+    };
+
+    let deleted = sqlx::query_scalar::<_, i32>(
+        "DELETE FROM notifications WHERE user_id = $1 AND type = $2 RETURNING id",
+    )
+    .bind(user_id)
+    .bind(notification_type)
+    .fetch_all(&app_state.db)
+    .await
+    .map_err(|e| {
+        error!("Database error deleting notifications by type: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to delete notifications")
+    })?;
+
+    app_state
+        .notifications
+        .publish_change(user_id, NotificationChange::Deleted, &deleted)
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "deleted": deleted.len()
+    })))
+}
+
+/// Run an action button of a notification. Domain actions go through the same
+/// handlers (and permission checks) as the rest of the API and mark the
+/// notification read once they succeed.
+pub async fn execute_action(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    path: web::Path<(i32, String)>,
+    payload: Option<web::Json<SnoozeOptions>>,
+) -> Result<HttpResponse> {
+    let user_id = extract_user_id_from_token(&req, &app_state).await?;
+    let (notification_id, action) = path.into_inner();
+    let db = &app_state.db;
+
+    let body = sqlx::query_scalar::<_, JsonValue>(
+        "SELECT body FROM notifications WHERE id = $1 AND user_id = $2",
+    )
+    .bind(notification_id)
+    .bind(user_id)
+    .fetch_optional(db)
+    .await
+    .map_err(|e| {
+        error!("Database error loading notification: {:?}", e);
+        actix_web::error::ErrorInternalServerError("Failed to load notification")
+    })?
+    .ok_or_else(|| actix_web::error::ErrorNotFound("Notification not found"))?;
+
+    let offered = body["content"]["actions"]
+        .as_array()
+        .map(|buttons| buttons.iter().any(|button| button["action"] == action.as_str()))
+        .unwrap_or(false);
+    if !offered && !BUILTIN_ACTIONS.contains(&action.as_str()) {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Action is not available for this notification",
+        ));
+    }
+
+    let ids = [notification_id];
+    let (change, result) = match action.as_str() {
+        "mark_read" => (NotificationChange::Read, mark_read(db, user_id, &ids).await),
+        "dismiss" | "archive" => (
+            NotificationChange::Archived,
+            archive(db, user_id, &ids).await,
+        ),
+        "snooze" => {
+            let options = payload.map(web::Json::into_inner).unwrap_or_default();
+            let until = snooze_until(&options)?;
+            (NotificationChange::Snoozed, snooze(db, user_id, &ids, until).await)
+        }
+        "delete" => (NotificationChange::Deleted, delete(db, user_id, &ids).await),
+        "complete_hometask" => {
+            let hometask_id = body["metadata"]["hometask_id"]
+                .as_i64()
+                .and_then(|id| i32::try_from(id).ok())
+                .ok_or_else(|| {
+                    actix_web::error::ErrorBadRequest("Notification does not refer to a hometask")
+                })?;
+
+            let response = hometasks::change_hometask_status(
+                &req,
+                &app_state,
+                hometask_id,
+                HometaskStatus::CompletedByStudent,
+                false,
+            )
+            .await;
+            if !response.status().is_success() {
+                return Ok(response);
+            }
+
+            (NotificationChange::Read, mark_read(db, user_id, &ids).await)
+        }
+        _ => {
+            return Err(actix_web::error::ErrorBadRequest(format!(
+                "Unsupported notification action: {}",
+                action
+            )))
+        }
+    };
+
+    let changed = result.map_err(|e| {
+        error!("Database error running notification action {}: {:?}", action, e);
+        actix_web::error::ErrorInternalServerError("Failed to run notification action")
+    })?;
+
+    app_state
+        .notifications
+        .publish_change(user_id, change, &changed)
+        .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "notification_id": notification_id,
+        "action": action,
+    })))
+}
+
 /// Extract user_id from JWT token
-async fn extract_user_id_from_token(req: &HttpRequest, app_state: &AppState) -> Result<i32> {
+pub(crate) async fn extract_user_id_from_token(req: &HttpRequest, app_state: &AppState) -> Result<i32> {
     // Verify JWT token and get claims
     let claims = match verify_token(req, app_state) {
         Ok(claims) => claims,
@@ -352,7 +724,32 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .route("", web::get().to(get_notifications))
             .route("", web::post().to(create_notification))
             .route("/unread-count", web::get().to(get_unread_count))
+            .route(
+                "/preferences",
+                web::get().to(notification_preferences::get_preferences),
+            )
+            .route(
+                "/preferences",
+                web::put().to(notification_preferences::update_preferences),
+            )
+            .route(
+                "/preferences/{type}",
+                web::delete().to(notification_preferences::reset_preference),
+            )
+            .route(
+                "/digest",
+                web::get().to(notification_digests::get_digest_settings),
+            )
+            .route(
+                "/digest",
+                web::put().to(notification_digests::update_digest_settings),
+            )
+            .route("", web::delete().to(delete_notifications_by_type))
             .route("/mark-read", web::post().to(mark_as_read))
+            .route("/mark-all-read", web::post().to(mark_all_as_read))
+            .route("/archive", web::post().to(archive_notifications))
+            .route("/snooze", web::post().to(snooze_notifications))
+            .route("/{id}/actions/{action}", web::post().to(execute_action))
             .route("/{id}", web::delete().to(delete_notification)),
     );
 }
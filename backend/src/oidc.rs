@@ -0,0 +1,674 @@
+use actix_web::cookie::{time::Duration as CookieDuration, Cookie, SameSite};
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use log::{error, info, warn};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::env;
+use std::sync::OnceLock;
+use std::time::Duration as StdDuration;
+use tokio::sync::Mutex;
+
+use crate::users::{issue_login_token, load_active_roles, LoginResponse};
+use crate::AppState;
+
+const LOGIN_STATE_TTL_MINUTES: i64 = 10;
+const METADATA_CACHE_MINUTES: i64 = 60;
+/// Holds the `state` of the login started in this browser, so a callback URL
+/// produced by someone else's login is rejected
+const STATE_COOKIE: &str = "oidc_state";
+const STATE_COOKIE_PATH: &str = "/api/auth/oidc";
+
+/// One configured identity provider.
+///
+/// Providers are listed in `OIDC_PROVIDERS` (comma separated names) and each is
+/// configured with `OIDC_<NAME>_ISSUER`, `OIDC_<NAME>_CLIENT_ID`,
+/// `OIDC_<NAME>_CLIENT_SECRET` and optionally `OIDC_<NAME>_DISPLAY_NAME`,
+/// `OIDC_<NAME>_SCOPES`, `OIDC_<NAME>_REDIRECT_URI` and `OIDC_<NAME>_TRUST_EMAIL`.
+#[derive(Debug, Clone)]
+pub struct OidcProviderConfig {
+    pub name: String,
+    pub display_name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub scopes: String,
+    pub redirect_uri: String,
+    /// Treat the provider's email claim as verified even without `email_verified`
+    /// (for self-hosted providers such as Nextcloud that do not send it)
+    pub trust_email: bool,
+}
+
+#[derive(Debug)]
+pub enum OidcError {
+    UnknownProvider,
+    HttpError(String),
+    InvalidResponse(String),
+    InvalidState,
+    InvalidIdToken(String),
+    EmailNotVerified,
+    NoMatchingUser,
+    AmbiguousEmail,
+    RolesArchived,
+    DatabaseError(String),
+}
+
+impl std::fmt::Display for OidcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OidcError::UnknownProvider => write!(f, "Unknown identity provider"),
+            OidcError::HttpError(s) => write!(f, "Identity provider request failed: {}", s),
+            OidcError::InvalidResponse(s) => write!(f, "Invalid identity provider response: {}", s),
+            OidcError::InvalidState => write!(f, "Invalid or expired login attempt"),
+            OidcError::InvalidIdToken(s) => write!(f, "Invalid ID token: {}", s),
+            OidcError::EmailNotVerified => write!(f, "The provider did not confirm a verified email"),
+            OidcError::NoMatchingUser => write!(f, "No account matches this email address"),
+            OidcError::AmbiguousEmail => write!(f, "Several accounts share this email address"),
+            OidcError::RolesArchived => write!(f, "All roles are archived"),
+            OidcError::DatabaseError(s) => write!(f, "Database error: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for OidcError {}
+
+impl OidcError {
+    /// Stable machine-readable code passed to the frontend on redirect
+    pub fn code(&self) -> &'static str {
+        match self {
+            OidcError::UnknownProvider => "unknown_provider",
+            OidcError::HttpError(_) | OidcError::InvalidResponse(_) => "provider_error",
+            OidcError::InvalidState => "invalid_state",
+            OidcError::InvalidIdToken(_) => "invalid_id_token",
+            OidcError::EmailNotVerified => "email_not_verified",
+            OidcError::NoMatchingUser => "no_matching_user",
+            OidcError::AmbiguousEmail => "ambiguous_email",
+            OidcError::RolesArchived => "roles_archived",
+            OidcError::DatabaseError(_) => "server_error",
+        }
+    }
+}
+
+impl From<sqlx::Error> for OidcError {
+    fn from(err: sqlx::Error) -> Self {
+        OidcError::DatabaseError(err.to_string())
+    }
+}
+
+impl From<reqwest::Error> for OidcError {
+    fn from(err: reqwest::Error) -> Self {
+        OidcError::HttpError(err.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenEndpointResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    email: Option<String>,
+    email_verified: Option<serde_json::Value>, // bool, or "true" from some providers
+    nonce: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OidcProviderSummary {
+    pub name: String,
+    pub display_name: String,
+}
+
+/// A started login: the provider URL and the state the callback must carry
+#[derive(Debug)]
+pub struct LoginRedirect {
+    pub url: String,
+    pub state: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+static PROVIDERS: OnceLock<Vec<OidcProviderConfig>> = OnceLock::new();
+type MetadataCache = Mutex<HashMap<String, (DateTime<Utc>, ProviderMetadata)>>;
+
+static METADATA_CACHE: OnceLock<MetadataCache> = OnceLock::new();
+
+fn provider_env(name: &str, key: &str) -> Option<String> {
+    env::var(format!("OIDC_{}_{}", name.to_uppercase(), key))
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn load_providers() -> &'static [OidcProviderConfig] {
+    PROVIDERS.get_or_init(|| {
+        let names = env::var("OIDC_PROVIDERS").unwrap_or_default();
+        let api_base_url = env::var("API_BASE_URL")
+            .unwrap_or_else(|_| "http://localhost:8080".to_string());
+        let api_base_url = api_base_url.trim_end_matches('/');
+
+        names
+            .split(',')
+            .map(|name| name.trim().to_lowercase())
+            .filter(|name| !name.is_empty())
+            .filter_map(|name| {
+                let (issuer, client_id) = match (
+                    provider_env(&name, "ISSUER"),
+                    provider_env(&name, "CLIENT_ID"),
+                ) {
+                    (Some(issuer), Some(client_id)) => (issuer, client_id),
+                    _ => {
+                        warn!(
+                            "OIDC provider '{}' is missing OIDC_{}_ISSUER or OIDC_{}_CLIENT_ID; skipping",
+                            name,
+                            name.to_uppercase(),
+                            name.to_uppercase()
+                        );
+                        return None;
+                    }
+                };
+
+                info!("OIDC provider '{}' configured with issuer {}", name, issuer);
+                Some(OidcProviderConfig {
+                    display_name: provider_env(&name, "DISPLAY_NAME")
+                        .unwrap_or_else(|| name.clone()),
+                    issuer: issuer.trim_end_matches('/').to_string(),
+                    client_id,
+                    client_secret: provider_env(&name, "CLIENT_SECRET"),
+                    scopes: provider_env(&name, "SCOPES")
+                        .unwrap_or_else(|| "openid email profile".to_string()),
+                    redirect_uri: provider_env(&name, "REDIRECT_URI").unwrap_or_else(|| {
+                        format!("{}/api/auth/oidc/{}/callback", api_base_url, name)
+                    }),
+                    trust_email: provider_env(&name, "TRUST_EMAIL")
+                        .map(|value| value.eq_ignore_ascii_case("true") || value == "1")
+                        .unwrap_or(false),
+                    name,
+                })
+            })
+            .collect()
+    })
+}
+
+fn find_provider(name: &str) -> Result<&'static OidcProviderConfig, OidcError> {
+    load_providers()
+        .iter()
+        .find(|provider| provider.name == name)
+        .ok_or(OidcError::UnknownProvider)
+}
+
+fn oidc_http_client() -> Result<reqwest::Client, OidcError> {
+    reqwest::Client::builder()
+        .connect_timeout(StdDuration::from_secs(5))
+        .timeout(StdDuration::from_secs(10))
+        .build()
+        .map_err(OidcError::from)
+}
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+async fn provider_metadata(provider: &OidcProviderConfig) -> Result<ProviderMetadata, OidcError> {
+    let cache = METADATA_CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    {
+        let guard = cache.lock().await;
+        if let Some((fetched_at, metadata)) = guard.get(&provider.name) {
+            if *fetched_at + Duration::minutes(METADATA_CACHE_MINUTES) > Utc::now() {
+                return Ok(metadata.clone());
+            }
+        }
+    }
+
+    let url = format!("{}/.well-known/openid-configuration", provider.issuer);
+    let response = oidc_http_client()?.get(&url).send().await?;
+    if !response.status().is_success() {
+        return Err(OidcError::HttpError(format!(
+            "discovery returned {}",
+            response.status()
+        )));
+    }
+
+    let metadata = response
+        .json::<ProviderMetadata>()
+        .await
+        .map_err(|e| OidcError::InvalidResponse(e.to_string()))?;
+
+    if metadata.issuer.trim_end_matches('/') != provider.issuer {
+        return Err(OidcError::InvalidResponse(format!(
+            "issuer mismatch: expected {}, got {}",
+            provider.issuer, metadata.issuer
+        )));
+    }
+
+    cache
+        .lock()
+        .await
+        .insert(provider.name.clone(), (Utc::now(), metadata.clone()));
+
+    Ok(metadata)
+}
+
+/// Start a login: persist state, nonce and PKCE verifier and return the
+/// provider URL the browser should be sent to.
+pub async fn begin_login(pool: &PgPool, provider_name: &str) -> Result<LoginRedirect, OidcError> {
+    let provider = find_provider(provider_name)?;
+    let metadata = provider_metadata(provider).await?;
+
+    let state = random_string(48);
+    let nonce = random_string(48);
+    let code_verifier = random_string(64);
+    let expires_at = Utc::now() + Duration::minutes(LOGIN_STATE_TTL_MINUTES);
+
+    sqlx::query("DELETE FROM oidc_login_states WHERE expires_at < NOW()")
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        "INSERT INTO oidc_login_states (state, provider, code_verifier, nonce, expires_at)
+         VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(&state)
+    .bind(&provider.name)
+    .bind(&code_verifier)
+    .bind(&nonce)
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+
+    let mut url = reqwest::Url::parse(&metadata.authorization_endpoint)
+        .map_err(|e| OidcError::InvalidResponse(e.to_string()))?;
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &provider.client_id)
+        .append_pair("redirect_uri", &provider.redirect_uri)
+        .append_pair("scope", &provider.scopes)
+        .append_pair("state", &state)
+        .append_pair("nonce", &nonce)
+        .append_pair("code_challenge", &pkce_challenge(&code_verifier))
+        .append_pair("code_challenge_method", "S256");
+
+    Ok(LoginRedirect {
+        url: url.to_string(),
+        state,
+    })
+}
+
+async fn verify_id_token(
+    provider: &OidcProviderConfig,
+    metadata: &ProviderMetadata,
+    id_token: &str,
+    expected_nonce: &str,
+) -> Result<IdTokenClaims, OidcError> {
+    let header = decode_header(id_token).map_err(|e| OidcError::InvalidIdToken(e.to_string()))?;
+
+    if matches!(
+        header.alg,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    ) {
+        return Err(OidcError::InvalidIdToken(
+            "symmetric signing algorithms are not accepted".to_string(),
+        ));
+    }
+
+    let jwks = oidc_http_client()?
+        .get(&metadata.jwks_uri)
+        .send()
+        .await?
+        .json::<JwkSet>()
+        .await
+        .map_err(|e| OidcError::InvalidResponse(e.to_string()))?;
+
+    let jwk = match header.kid.as_deref() {
+        Some(kid) => jwks.find(kid),
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }
+    .ok_or_else(|| OidcError::InvalidIdToken("signing key not found".to_string()))?;
+
+    let key = DecodingKey::from_jwk(jwk).map_err(|e| OidcError::InvalidIdToken(e.to_string()))?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_audience(&[provider.client_id.as_str()]);
+    validation.set_issuer(&[provider.issuer.as_str(), metadata.issuer.as_str()]);
+
+    let claims = decode::<IdTokenClaims>(id_token, &key, &validation)
+        .map_err(|e| OidcError::InvalidIdToken(e.to_string()))?
+        .claims;
+
+    if claims.nonce.as_deref() != Some(expected_nonce) {
+        return Err(OidcError::InvalidIdToken("nonce mismatch".to_string()));
+    }
+
+    Ok(claims)
+}
+
+/// Finish a login: validate state, exchange the code, verify the ID token and
+/// resolve (or link) the local account. Returns the local user id and username.
+pub async fn complete_login(
+    pool: &PgPool,
+    provider_name: &str,
+    code: &str,
+    state: &str,
+) -> Result<(i32, String), OidcError> {
+    let provider = find_provider(provider_name)?;
+
+    let (code_verifier, nonce) = sqlx::query_as::<_, (String, String)>(
+        "DELETE FROM oidc_login_states
+         WHERE state = $1 AND provider = $2 AND expires_at > NOW()
+         RETURNING code_verifier, nonce",
+    )
+    .bind(state)
+    .bind(&provider.name)
+    .fetch_optional(pool)
+    .await?
+    .ok_or(OidcError::InvalidState)?;
+
+    let metadata = provider_metadata(provider).await?;
+
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", provider.redirect_uri.as_str()),
+        ("client_id", provider.client_id.as_str()),
+        ("code_verifier", code_verifier.as_str()),
+    ];
+    if let Some(secret) = provider.client_secret.as_deref() {
+        form.push(("client_secret", secret));
+    }
+
+    let response = oidc_http_client()?
+        .post(&metadata.token_endpoint)
+        .form(&form)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(OidcError::HttpError(format!(
+            "token endpoint returned {}: {}",
+            status, body
+        )));
+    }
+
+    let tokens = response
+        .json::<TokenEndpointResponse>()
+        .await
+        .map_err(|e| OidcError::InvalidResponse(e.to_string()))?;
+
+    let claims = verify_id_token(provider, &metadata, &tokens.id_token, &nonce).await?;
+
+    // Already linked identity
+    let linked = sqlx::query_as::<_, (i32, String)>(
+        "SELECT u.id, u.username FROM user_oidc_identities i
+         JOIN users u ON u.id = i.user_id
+         WHERE i.provider = $1 AND i.subject = $2",
+    )
+    .bind(&provider.name)
+    .bind(&claims.sub)
+    .fetch_optional(pool)
+    .await?;
+
+    if let Some((user_id, username)) = linked {
+        sqlx::query(
+            "UPDATE user_oidc_identities SET last_login_at = NOW(), email = COALESCE($3, email)
+             WHERE provider = $1 AND subject = $2",
+        )
+        .bind(&provider.name)
+        .bind(&claims.sub)
+        .bind(&claims.email)
+        .execute(pool)
+        .await?;
+        return Ok((user_id, username));
+    }
+
+    // First login with this identity: link by verified email. Only accounts
+    // whose own address was confirmed are linked, and never admin accounts,
+    // like magic links.
+    let email_verified = match &claims.email_verified {
+        Some(serde_json::Value::Bool(value)) => *value,
+        Some(serde_json::Value::String(value)) => value.eq_ignore_ascii_case("true"),
+        _ => false,
+    };
+    let email = claims
+        .email
+        .as_ref()
+        .map(|email| email.trim().to_lowercase())
+        .filter(|email| !email.is_empty())
+        .ok_or(OidcError::EmailNotVerified)?;
+
+    if !email_verified && !provider.trust_email {
+        return Err(OidcError::EmailNotVerified);
+    }
+
+    let candidates = sqlx::query_as::<_, (i32, String)>(
+        "SELECT u.id, u.username FROM users u
+         WHERE LOWER(u.email) = $1 AND u.email_verified_at IS NOT NULL
+           AND NOT EXISTS (
+               SELECT 1 FROM user_roles ur
+               JOIN roles r ON r.id = ur.role_id
+               WHERE ur.user_id = u.id AND r.name = 'admin'
+           )
+         LIMIT 2",
+    )
+    .bind(&email)
+    .fetch_all(pool)
+    .await?;
+
+    let (user_id, username) = match candidates.as_slice() {
+        [] => return Err(OidcError::NoMatchingUser),
+        [single] => single.clone(),
+        _ => return Err(OidcError::AmbiguousEmail),
+    };
+
+    sqlx::query(
+        "INSERT INTO user_oidc_identities (user_id, provider, subject, email, last_login_at)
+         VALUES ($1, $2, $3, $4, NOW())",
+    )
+    .bind(user_id)
+    .bind(&provider.name)
+    .bind(&claims.sub)
+    .bind(&email)
+    .execute(pool)
+    .await?;
+
+    info!(
+        "Linked {} identity {} to user {}",
+        provider.name, claims.sub, username
+    );
+
+    Ok((user_id, username))
+}
+
+/// Where the browser is sent after the callback; the JWT is passed in the
+/// URL fragment. Without it the callback answers with JSON.
+fn login_redirect_url() -> Option<String> {
+    env::var("OIDC_LOGIN_REDIRECT_URL")
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn state_cookie(provider: &OidcProviderConfig, state: String) -> Cookie<'static> {
+    Cookie::build(STATE_COOKIE, state)
+        .path(STATE_COOKIE_PATH)
+        .http_only(true)
+        // Lax, because the provider sends the browser back with a top-level GET
+        .same_site(SameSite::Lax)
+        .secure(provider.redirect_uri.starts_with("https://"))
+        .max_age(CookieDuration::minutes(LOGIN_STATE_TTL_MINUTES))
+        .finish()
+}
+
+/// The callback response, with the state cookie removed
+fn without_state_cookie(mut response: HttpResponse) -> HttpResponse {
+    let cookie = Cookie::build(STATE_COOKIE, "")
+        .path(STATE_COOKIE_PATH)
+        .finish();
+    if let Err(e) = response.add_removal_cookie(&cookie) {
+        warn!("Failed to clear OIDC state cookie: {}", e);
+    }
+    response
+}
+
+fn callback_failure(error: &OidcError) -> HttpResponse {
+    without_state_cookie(failure_response(error))
+}
+
+fn failure_response(error: &OidcError) -> HttpResponse {
+    if let Some(redirect) = login_redirect_url() {
+        return HttpResponse::Found()
+            .append_header(("Location", format!("{}#error={}", redirect, error.code())))
+            .finish();
+    }
+
+    let body = serde_json::json!({ "error": error.to_string() });
+    match error {
+        OidcError::UnknownProvider => HttpResponse::NotFound().json(body),
+        OidcError::InvalidState => HttpResponse::BadRequest().json(body),
+        OidcError::EmailNotVerified
+        | OidcError::NoMatchingUser
+        | OidcError::AmbiguousEmail
+        | OidcError::RolesArchived
+        | OidcError::InvalidIdToken(_) => HttpResponse::Unauthorized().json(body),
+        OidcError::HttpError(_) | OidcError::InvalidResponse(_) => {
+            HttpResponse::BadGateway().json(body)
+        }
+        OidcError::DatabaseError(_) => HttpResponse::InternalServerError().json(body),
+    }
+}
+
+#[get("/oidc/providers")]
+pub(crate) async fn list_providers() -> impl Responder {
+    let providers: Vec<OidcProviderSummary> = load_providers()
+        .iter()
+        .map(|provider| OidcProviderSummary {
+            name: provider.name.clone(),
+            display_name: provider.display_name.clone(),
+        })
+        .collect();
+
+    HttpResponse::Ok().json(providers)
+}
+
+#[get("/oidc/{provider}/authorize")]
+pub(crate) async fn authorize(
+    app_state: web::Data<AppState>,
+    provider: web::Path<String>,
+) -> impl Responder {
+    let Ok(config) = find_provider(&provider) else {
+        return HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Unknown identity provider"
+        }));
+    };
+
+    match begin_login(&app_state.db, &provider).await {
+        Ok(login) => HttpResponse::Found()
+            .cookie(state_cookie(config, login.state))
+            .append_header(("Location", login.url))
+            .finish(),
+        Err(e) => {
+            error!("Failed to start OIDC login for {}: {}", provider, e);
+            HttpResponse::BadGateway().json(serde_json::json!({
+                "error": "Identity provider is unavailable"
+            }))
+        }
+    }
+}
+
+#[get("/oidc/{provider}/callback")]
+pub(crate) async fn callback(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    provider: web::Path<String>,
+    query: web::Query<OidcCallbackQuery>,
+) -> impl Responder {
+    if let Some(error) = &query.error {
+        warn!(
+            "OIDC provider {} returned error {}: {}",
+            provider,
+            error,
+            query.error_description.as_deref().unwrap_or("")
+        );
+        return callback_failure(&OidcError::InvalidResponse(error.clone()));
+    }
+
+    let (code, state) = match (&query.code, &query.state) {
+        (Some(code), Some(state)) => (code, state),
+        _ => return callback_failure(&OidcError::InvalidState),
+    };
+
+    // The login must have been started in this browser
+    if req.cookie(STATE_COOKIE).map(|cookie| cookie.value().to_string()).as_deref()
+        != Some(state.as_str())
+    {
+        warn!("OIDC callback via {} without a matching state cookie", provider);
+        return callback_failure(&OidcError::InvalidState);
+    }
+
+    let result = async {
+        let (user_id, username) = complete_login(&app_state.db, &provider, code, state).await?;
+        let roles = load_active_roles(&app_state.db, user_id).await?;
+        if roles.is_empty() {
+            return Err(OidcError::RolesArchived);
+        }
+        Ok((username, roles))
+    }
+    .await;
+
+    let (username, roles) = match result {
+        Ok(value) => value,
+        Err(e) => {
+            warn!("OIDC login via {} failed: {}", provider, e);
+            return callback_failure(&e);
+        }
+    };
+
+    let token = match issue_login_token(&app_state.jwt_secret, &username, roles) {
+        Ok(token) => token,
+        Err(e) => {
+            error!("JWT encoding error: {}", e);
+            return without_state_cookie(HttpResponse::InternalServerError().json(
+                serde_json::json!({ "error": "Could not generate token" }),
+            ));
+        }
+    };
+
+    without_state_cookie(match login_redirect_url() {
+        Some(redirect) => HttpResponse::Found()
+            .append_header(("Location", format!("{}#token={}", redirect, token)))
+            .finish(),
+        None => HttpResponse::Ok().json(LoginResponse { token }),
+    })
+}
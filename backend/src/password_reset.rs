@@ -7,13 +7,12 @@ use rand::{distributions::Alphanumeric, Rng};
 use sqlx::{FromRow, PgPool};
 use std::sync::Arc;
 
-use actix_web::rt::task::spawn_blocking;
 use log::error;
 
 use crate::email::{EmailError, EmailService};
+use crate::i18n::Locale;
 use crate::notification_builders::build_password_reset_request_notification;
-use crate::notifications::is_user_notification_eligible;
-use crate::push;
+use crate::notification_dispatcher::NotificationDispatcher;
 
 #[derive(Debug)]
 pub enum PasswordResetError {
@@ -108,6 +107,7 @@ pub async fn request_password_reset(
     pool: &PgPool,
     username: &str,
     email_service: Arc<EmailService>,
+    notifications: &NotificationDispatcher,
 ) -> Result<(), PasswordResetError> {
     // Find user by username
     #[derive(FromRow)]
@@ -115,10 +115,15 @@ pub async fn request_password_reset(
         id: i32,
         username: String,
         email: Option<String>,
+        locale: String,
     }
     
+    // Unconfirmed addresses are treated like a missing one and go to an admin
     let user = sqlx::query_as::<_, UserQuery>(
-        "SELECT id, username, email FROM users WHERE username = $1"
+        "SELECT id, username,
+                CASE WHEN email_verified_at IS NOT NULL AND email <> '' THEN email END AS email,
+                locale
+         FROM users WHERE username = $1"
     )
     .bind(username)
     .fetch_optional(pool)
@@ -146,8 +151,12 @@ pub async fn request_password_reset(
         let email_service = email_service.clone();
         let username = user.username.clone();
         let token = token.clone();
-        spawn_blocking(move || {
-            if let Err(err) = email_service.send_password_reset_email(&email, &username, &token) {
+        let locale = Locale::from_code(&user.locale).unwrap_or_default();
+        actix_web::rt::spawn(async move {
+            if let Err(err) = email_service
+                .send_password_reset_email(locale, &email, &username, &token)
+                .await
+            {
                 error!("Password reset email failed: {}", err);
             }
         });
@@ -163,32 +172,14 @@ pub async fn request_password_reset(
         // Get all admin user IDs
         let admin_ids = get_admin_user_ids(pool).await?;
         
-        // Create notification for each admin
-        let notification_body = build_password_reset_request_notification(username, request_id);
-        
-        for admin_id in admin_ids {
-            if !is_user_notification_eligible(pool, admin_id).await {
-                continue;
-            }
-
-            let notification_id = sqlx::query_scalar::<_, i32>(
-                "INSERT INTO notifications (user_id, type, title, body, priority)
-                 VALUES ($1, $2, $3, $4, $5)
-                 RETURNING id"
+        // Notify every admin
+        notifications
+            .notify_many(
+                &admin_ids,
+                |locale| build_password_reset_request_notification(locale, username, request_id),
+                "high", // High priority for admin action items
             )
-            .bind(admin_id)
-            .bind(&notification_body.body_type)
-            .bind(&notification_body.title)
-            .bind(serde_json::to_value(&notification_body).unwrap_or_default())
-            .bind("high") // High priority for admin action items
-            .fetch_optional(pool)
-            .await
-            .unwrap_or(None);
-
-            if let Some(notification_id) = notification_id {
-                push::send_notification_to_user(pool, admin_id, &notification_body, Some(notification_id)).await;
-            }
-        }
+            .await;
     }
 
     Ok(())
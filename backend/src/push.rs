@@ -1,19 +1,30 @@
-use actix_web::{post, web, HttpRequest, HttpResponse, Result};
-use chrono::{DateTime, Duration, Utc};
-use jsonwebtoken::{EncodingKey, Header};
-use log::{debug, error, info, warn};
-use serde::{Deserialize, Serialize};
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Result};
+use log::{debug, error, info};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use sqlx::PgPool;
-use std::env;
-use std::fs;
-use std::sync::OnceLock;
 use std::time::Duration as StdDuration;
-use tokio::sync::Mutex;
 
 use crate::notifications::{ContentBlock, NotificationBody};
 use crate::users::verify_token;
 use crate::AppState;
 
+mod apns;
+mod fcm;
+mod outbox;
+mod provider;
+mod web_push;
+
+pub use apns::ApnsProvider;
+pub use fcm::FcmProvider;
+pub use outbox::{cleanup_finished, enqueue, process_due, run_worker};
+pub use provider::{
+    PushMessage, PushOutcome, PushProvider, PushProviders, RecordedPush, RecordingProvider,
+};
+pub use web_push::{encrypt_payload, MessageKeys, Subscription, WebPushProvider};
+
 #[derive(Debug, Deserialize)]
 pub struct RegisterPushTokenRequest {
     pub token: String,
@@ -25,172 +36,33 @@ pub struct RevokePushTokenRequest {
     pub token: String,
 }
 
-#[derive(Debug, Deserialize)]
-struct ServiceAccountKey {
-    project_id: String,
-    private_key: String,
-    client_email: String,
-    token_uri: String,
-}
-
-#[derive(Debug, Serialize)]
-struct TokenRequest<'a> {
-    iss: &'a str,
-    sub: &'a str,
-    aud: &'a str,
-    iat: i64,
-    exp: i64,
-    scope: &'a str,
-}
-
-#[derive(Debug, Deserialize)]
-struct TokenResponse {
-    access_token: String,
-    expires_in: i64,
-}
-
-#[derive(Debug)]
-struct CachedAccessToken {
-    token: String,
-    expires_at: DateTime<Utc>,
-}
-
-static SERVICE_ACCOUNT: OnceLock<Option<ServiceAccountKey>> = OnceLock::new();
-static ACCESS_TOKEN: OnceLock<Mutex<Option<CachedAccessToken>>> = OnceLock::new();
-
-fn fcm_http_client() -> Result<reqwest::Client> {
+/// HTTP client shared by a provider; `None` disables the provider
+fn http_client() -> Option<reqwest::Client> {
     reqwest::Client::builder()
         .connect_timeout(StdDuration::from_secs(5))
         .timeout(StdDuration::from_secs(10))
         .build()
-        .map_err(|e| {
-            error!("Failed to build FCM HTTP client: {}", e);
-            actix_web::error::ErrorInternalServerError("Failed to initialize push transport")
-        })
+        .map_err(|e| error!("Failed to build push HTTP client: {}", e))
+        .ok()
 }
 
-fn load_service_account() -> Option<&'static ServiceAccountKey> {
-    SERVICE_ACCOUNT
-        .get_or_init(|| {
-            let path = match env::var("FCM_SERVICE_ACCOUNT_PATH") {
-                Ok(value) => {
-                    debug!("FCM_SERVICE_ACCOUNT_PATH is set: {}", value);
-                    value
-                }
-                Err(_) => {
-                    warn!("FCM_SERVICE_ACCOUNT_PATH is not set; push delivery disabled");
-                    return None;
-                }
-            };
-
-            let contents = match fs::read_to_string(&path) {
-                Ok(value) => {
-                    info!("Successfully loaded service account file from: {}", path);
-                    value
-                }
-                Err(err) => {
-                    warn!("Failed to read service account file at {}: {}", path, err);
-                    return None;
-                }
-            };
-
-            match serde_json::from_str::<ServiceAccountKey>(&contents) {
-                Ok(value) => {
-                    info!(
-                        "Service account parsed successfully for project: {}",
-                        value.project_id
-                    );
-                    Some(value)
-                }
-                Err(err) => {
-                    warn!("Failed to parse service account JSON: {}", err);
-                    None
-                }
-            }
-        })
-        .as_ref()
+/// Start of a device token for logs
+fn token_prefix(token: &str) -> &str {
+    let end = token
+        .char_indices()
+        .nth(20)
+        .map(|(index, _)| index)
+        .unwrap_or(token.len());
+    &token[..end]
 }
 
-async fn get_access_token(service_account: &ServiceAccountKey) -> Result<String> {
-    let cache = ACCESS_TOKEN.get_or_init(|| Mutex::new(None));
-    {
-        let guard = cache.lock().await;
-        if let Some(cached) = guard.as_ref() {
-            if cached.expires_at > Utc::now() + Duration::seconds(30) {
-                debug!(
-                    "Using cached FCM access token (expires at {})",
-                    cached.expires_at
-                );
-                return Ok(cached.token.clone());
-            }
-        }
-    }
-
-    debug!("Requesting new FCM access token...");
-    let now = Utc::now();
-    let token_request = TokenRequest {
-        iss: &service_account.client_email,
-        sub: &service_account.client_email,
-        aud: &service_account.token_uri,
-        iat: now.timestamp(),
-        exp: (now + Duration::minutes(55)).timestamp(),
-        scope: "https://www.googleapis.com/auth/firebase.messaging",
-    };
-
-    let header = Header::new(jsonwebtoken::Algorithm::RS256);
-    let key = EncodingKey::from_rsa_pem(service_account.private_key.as_bytes()).map_err(|e| {
-        error!("Failed to create encoding key: {}", e);
-        actix_web::error::ErrorInternalServerError("Invalid FCM credentials")
-    })?;
-
-    let assertion = jsonwebtoken::encode(&header, &token_request, &key).map_err(|e| {
-        error!("Failed to sign JWT: {}", e);
-        actix_web::error::ErrorInternalServerError("Failed to authenticate FCM")
-    })?;
-
-    let client = fcm_http_client()?;
-    debug!(
-        "Sending JWT assertion to FCM token endpoint: {}",
-        service_account.token_uri
-    );
-    let response = client
-        .post(&service_account.token_uri)
-        .form(&[
-            ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
-            ("assertion", assertion.as_str()),
-        ])
-        .send()
-        .await
-        .map_err(|e| {
-            error!("Failed to request access token: {}", e);
-            actix_web::error::ErrorInternalServerError("Failed to authenticate FCM")
-        })?;
-
-    let status = response.status();
-    if !status.is_success() {
-        let body = response.text().await.unwrap_or_default();
-        error!("FCM token request failed: {} {}", status, body);
-        return Err(actix_web::error::ErrorInternalServerError(
-            "Failed to authenticate FCM",
-        ));
-    }
-
-    info!("FCM access token obtained successfully");
-    let token_response = response.json::<TokenResponse>().await.map_err(|e| {
-        error!("Failed to parse access token: {}", e);
-        actix_web::error::ErrorInternalServerError("Failed to authenticate FCM")
-    })?;
-
-    let expires_at = Utc::now() + Duration::seconds(token_response.expires_in);
-    let cached = CachedAccessToken {
-        token: token_response.access_token.clone(),
-        expires_at,
-    };
-
-    let mut guard = cache.lock().await;
-    *guard = Some(cached);
-
-    Ok(token_response.access_token)
+/// Collapse key as an opaque id of at most 32 URL-safe characters, for
+/// services that restrict the key's length or alphabet
+fn hashed_collapse_id(collapse_key: &str) -> String {
+    let digest = Sha256::digest(collapse_key.as_bytes());
+    let mut id = URL_SAFE_NO_PAD.encode(digest);
+    id.truncate(32);
+    id
 }
 
 fn notification_preview(body: &NotificationBody) -> String {
@@ -205,161 +77,24 @@ fn notification_preview(body: &NotificationBody) -> String {
     body.title.clone()
 }
 
-enum SendOutcome {
-    Delivered,
-    Unregistered,
-}
-
-async fn send_fcm_message(
-    service_account: &ServiceAccountKey,
-    access_token: &str,
-    token: &str,
-    body: &NotificationBody,
-    notification_id: Option<i32>,
-) -> Result<SendOutcome> {
-    debug!(
-        "[FCM] Preparing message for token: {}... (type: {})",
-        &token[..token.len().min(20)],
-        body.body_type
-    );
-
-    let preview = notification_preview(body);
-
-    #[derive(Serialize)]
-    struct FcmMessage<'a> {
-        message: FcmMessageBody<'a>,
-    }
-
-    #[derive(Serialize)]
-    struct FcmMessageBody<'a> {
-        token: &'a str,
-        notification: FcmNotification<'a>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        data: Option<serde_json::Value>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        android: Option<FcmAndroid<'a>>,
-    }
-
-    #[derive(Serialize)]
-    struct FcmNotification<'a> {
-        title: &'a str,
-        body: &'a str,
-    }
-
-    #[derive(Serialize)]
-    struct FcmAndroid<'a> {
-        notification: FcmAndroidNotification<'a>,
-    }
-
-    #[derive(Serialize)]
-    struct FcmAndroidNotification<'a> {
-        icon: &'a str,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        color: Option<&'a str>,
-    }
-
-    let mut data_map = serde_json::Map::new();
-    if let Some(route) = &body.route {
-        data_map.insert(
-            "route".to_string(),
-            serde_json::Value::String(route.clone()),
-        );
-    }
-    if let Some(id) = notification_id {
-        data_map.insert(
-            "notification_id".to_string(),
-            serde_json::Value::String(id.to_string()),
-        );
-    }
-    data_map.insert(
-        "type".to_string(),
-        serde_json::Value::String(body.body_type.clone()),
-    );
-    if let Some(metadata) = &body.metadata {
-        data_map.insert(
-            "metadata".to_string(),
-            serde_json::Value::String(metadata.to_string()),
-        );
-    }
-
-    let data = serde_json::Value::Object(data_map);
-
-    let icon = if body.body_type == "chat_message" || body.body_type == "feed_comment" {
-        "ic_notif_message"
-    } else {
-        "ic_notif_bell"
-    };
-
-    let payload = FcmMessage {
-        message: FcmMessageBody {
-            token,
-            notification: FcmNotification {
-                title: &body.title,
-                body: &preview,
-            },
-            data: Some(data),
-            android: Some(FcmAndroid {
-                notification: FcmAndroidNotification {
-                    icon,
-                    color: Some("#c4161d"),
-                },
-            }),
-        },
-    };
-
-    let url = format!(
-        "https://fcm.googleapis.com/v1/projects/{}/messages:send",
-        service_account.project_id
-    );
-
-    debug!("[FCM] Sending to: {}", url);
-    let client = fcm_http_client()?;
-    let response = client
-        .post(&url)
-        .bearer_auth(access_token)
-        .json(&payload)
-        .send()
-        .await
-        .map_err(|e| {
-            error!("[FCM] Failed to send FCM message: {}", e);
-            actix_web::error::ErrorInternalServerError("Failed to send push")
-        })?;
-
-    if response.status().is_success() {
-        info!(
-            "[FCM] Message sent successfully to token: {}...",
-            &token[..token.len().min(20)]
-        );
-        return Ok(SendOutcome::Delivered);
-    }
-
-    let status = response.status();
-    let body_text = response.text().await.unwrap_or_default();
-    error!("[FCM] Send failed: {} {}", status, body_text);
-
-    if body_text.contains("UNREGISTERED") || body_text.contains("NOT_FOUND") {
-        warn!(
-            "[FCM] Token is unregistered: {}...",
-            &token[..token.len().min(20)]
-        );
-        return Ok(SendOutcome::Unregistered);
-    }
-
-    Err(actix_web::error::ErrorInternalServerError(
-        "FCM send failed",
-    ))
-}
-
-async fn revoke_token(db: &PgPool, token: &str) {
-    debug!("[FCM] Revoking token: {}...", &token[..token.len().min(20)]);
+async fn revoke_tokens(db: &PgPool, tokens: &[String]) {
+    debug!("[PUSH] Revoking {} tokens", tokens.len());
     let _ = sqlx::query(
-        "UPDATE push_tokens SET revoked_at = NOW() WHERE token = $1 AND revoked_at IS NULL",
+        "UPDATE push_tokens SET revoked_at = NOW() WHERE token = ANY($1) AND revoked_at IS NULL",
     )
-    .bind(token)
+    .bind(tokens)
     .execute(db)
     .await;
 }
 
+/// One notification pushed to all devices of one user
+pub struct PushDelivery {
+    pub user_id: i32,
+    pub body: NotificationBody,
+    pub notification_id: Option<i32>,
+}
+
+/// Queue a push to all of a user's devices; the outbox worker delivers it
 pub async fn send_notification_to_user(
     db: &PgPool,
     user_id: i32,
@@ -367,85 +102,19 @@ pub async fn send_notification_to_user(
     notification_id: Option<i32>,
 ) {
     debug!(
-        "[PUSH] Attempting to send notification to user {} (type: {}, id: {:?})",
+        "[PUSH] Queueing notification for user {} (type: {}, id: {:?})",
         user_id, body.body_type, notification_id
     );
 
-    let service_account = match load_service_account() {
-        Some(account) => {
-            debug!("[PUSH] Service account loaded");
-            account
-        }
-        None => {
-            warn!("[PUSH] Push skipped: missing service account");
-            return;
-        }
-    };
-
-    let tokens = match sqlx::query_scalar::<_, String>(
-        "SELECT token FROM push_tokens WHERE user_id = $1 AND revoked_at IS NULL",
-    )
-    .bind(user_id)
-    .fetch_all(db)
-    .await
-    {
-        Ok(values) => {
-            debug!("[PUSH] Loaded {} tokens for user {}", values.len(), user_id);
-            values
-        }
-        Err(err) => {
-            error!(
-                "[PUSH] Failed to load push tokens for user {}: {}",
-                user_id, err
-            );
-            return;
-        }
-    };
-
-    if tokens.is_empty() {
-        warn!("[PUSH] Push skipped: no tokens for user {}", user_id);
-        return;
-    }
-
-    let access_token = match get_access_token(service_account).await {
-        Ok(token) => {
-            debug!("[PUSH] Access token obtained");
-            token
-        }
-        Err(_) => {
-            error!("[PUSH] Failed to obtain access token");
-            return;
-        }
-    };
-
-    for token in tokens {
-        match send_fcm_message(
-            service_account,
-            &access_token,
-            &token,
-            body,
+    outbox::enqueue(
+        db,
+        vec![PushDelivery {
+            user_id,
+            body: body.clone(),
             notification_id,
-        )
-        .await
-        {
-            Ok(SendOutcome::Delivered) => {
-                debug!(
-                    "[PUSH] Delivered to token: {}...",
-                    &token[..token.len().min(20)]
-                );
-            }
-            Ok(SendOutcome::Unregistered) => {
-                warn!(
-                    "[PUSH] Token unregistered, revoking: {}...",
-                    &token[..token.len().min(20)]
-                );
-                revoke_token(db, &token).await;
-            }
-            Err(err) => {
-                error!("[PUSH] FCM delivery failed: {:?}", err);
-            }
-        }
-    }
+        }],
+    )
+    .await;
 }
 
 #[post("/api/push/tokens")]
@@ -487,6 +156,15 @@ pub async fn register_token(
         .clone()
         .unwrap_or_else(|| "unknown".to_string());
 
+    // Web Push tokens are the browser's subscription JSON
+    if platform == "webpush" {
+        if let Err(reason) = web_push::Subscription::parse(&payload.token) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": reason
+            })));
+        }
+    }
+
     info!(
         "[PUSH] Registering token for user {} (platform: {})",
         user_id, platform
@@ -562,6 +240,22 @@ pub async fn revoke_token_endpoint(
     })))
 }
 
+/// Application server key for `pushManager.subscribe()` in web clients
+#[get("/api/push/vapid-public-key")]
+pub async fn vapid_public_key() -> HttpResponse {
+    match web_push::vapid_public_key() {
+        Some(public_key) => HttpResponse::Ok().json(serde_json::json!({
+            "public_key": public_key
+        })),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Web Push is not configured"
+        })),
+    }
+}
+
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(register_token).service(revoke_token_endpoint);
+    cfg.service(register_token)
+        .service(revoke_token_endpoint)
+        .service(vapid_public_key)
+        .service(outbox::delivery_stats);
 }
@@ -0,0 +1,282 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{EncodingKey, Header};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use tokio::sync::Mutex;
+
+use super::provider::{PushMessage, PushOutcome, PushProvider};
+use super::{hashed_collapse_id, http_client, notification_preview, token_prefix};
+
+/// Apple refuses provider tokens older than an hour
+const TOKEN_LIFETIME_MINUTES: i64 = 50;
+/// Largest payload APNs accepts for regular notifications
+const MAX_PAYLOAD: usize = 4096;
+/// Longest `apns-collapse-id` APNs accepts
+const MAX_COLLAPSE_ID: usize = 64;
+/// How long APNs keeps the notification for an offline device
+const EXPIRATION_SECS: i64 = 24 * 60 * 60;
+
+#[derive(Serialize)]
+struct ProviderClaims<'a> {
+    iss: &'a str,
+    iat: i64,
+}
+
+struct CachedProviderToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct ApnsPayload<'a> {
+    aps: Aps<'a>,
+    #[serde(rename = "type")]
+    body_type: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    route: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notification_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<&'a serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct Aps<'a> {
+    alert: ApsAlert<'a>,
+    sound: &'a str,
+    #[serde(rename = "thread-id")]
+    thread_id: &'a str,
+}
+
+#[derive(Serialize)]
+struct ApsAlert<'a> {
+    title: &'a str,
+    body: &'a str,
+}
+
+#[derive(Deserialize)]
+struct ApnsError {
+    reason: String,
+}
+
+/// Apple Push Notification service over HTTP/2 with token (.p8) authentication
+pub struct ApnsProvider {
+    key: EncodingKey,
+    key_id: String,
+    team_id: String,
+    topic: String,
+    base_url: String,
+    client: reqwest::Client,
+    provider_token: Mutex<Option<CachedProviderToken>>,
+}
+
+impl ApnsProvider {
+    /// Configure from `APNS_KEY_PATH`, `APNS_KEY_ID`, `APNS_TEAM_ID` and `APNS_TOPIC`
+    /// (the app's bundle id); `None` disables APNs. `APNS_SANDBOX=true` targets the
+    /// development environment and `APNS_BASE_URL` overrides the endpoint entirely.
+    pub fn from_env() -> Option<Self> {
+        let Ok(path) = env::var("APNS_KEY_PATH") else {
+            warn!("APNS_KEY_PATH is not set; APNs delivery disabled");
+            return None;
+        };
+        let (Ok(key_id), Ok(team_id), Ok(topic)) = (
+            env::var("APNS_KEY_ID"),
+            env::var("APNS_TEAM_ID"),
+            env::var("APNS_TOPIC"),
+        ) else {
+            warn!("APNS_KEY_ID, APNS_TEAM_ID and APNS_TOPIC are required; APNs delivery disabled");
+            return None;
+        };
+
+        let key = match fs::read(&path) {
+            Ok(contents) => match EncodingKey::from_ec_pem(&contents) {
+                Ok(key) => key,
+                Err(err) => {
+                    warn!("Failed to parse APNs key at {}: {}", path, err);
+                    return None;
+                }
+            },
+            Err(err) => {
+                warn!("Failed to read APNs key at {}: {}", path, err);
+                return None;
+            }
+        };
+
+        let base_url = match env::var("APNS_BASE_URL") {
+            Ok(url) => url.trim_end_matches('/').to_string(),
+            Err(_) if env::var("APNS_SANDBOX").map(|v| v == "true").unwrap_or(false) => {
+                "https://api.sandbox.push.apple.com".to_string()
+            }
+            Err(_) => "https://api.push.apple.com".to_string(),
+        };
+        info!("APNs configured for topic {} at {}", topic, base_url);
+
+        Some(Self {
+            key,
+            key_id,
+            team_id,
+            topic,
+            base_url,
+            client: http_client()?,
+            provider_token: Mutex::new(None),
+        })
+    }
+
+    async fn provider_token(&self) -> Result<String, String> {
+        let mut cache = self.provider_token.lock().await;
+        if let Some(cached) = cache.as_ref() {
+            if cached.expires_at > Utc::now() {
+                return Ok(cached.token.clone());
+            }
+        }
+
+        let mut header = Header::new(jsonwebtoken::Algorithm::ES256);
+        header.kid = Some(self.key_id.clone());
+        let now = Utc::now();
+        let token = jsonwebtoken::encode(
+            &header,
+            &ProviderClaims {
+                iss: &self.team_id,
+                iat: now.timestamp(),
+            },
+            &self.key,
+        )
+        .map_err(|e| {
+            error!("Failed to sign APNs provider token: {}", e);
+            "Failed to authenticate APNs".to_string()
+        })?;
+
+        *cache = Some(CachedProviderToken {
+            token: token.clone(),
+            expires_at: now + Duration::minutes(TOKEN_LIFETIME_MINUTES),
+        });
+        Ok(token)
+    }
+
+    async fn forget_provider_token(&self) {
+        *self.provider_token.lock().await = None;
+    }
+
+    fn payload(message: &PushMessage<'_>) -> Result<Vec<u8>, String> {
+        let body = message.body;
+        let preview = notification_preview(body);
+        let mut payload = ApnsPayload {
+            aps: Aps {
+                alert: ApsAlert {
+                    title: &body.title,
+                    body: &preview,
+                },
+                sound: "default",
+                thread_id: &body.body_type,
+            },
+            body_type: &body.body_type,
+            route: body.route.as_deref(),
+            notification_id: message.notification_id,
+            metadata: body.metadata.as_ref(),
+        };
+
+        let mut bytes = serde_json::to_vec(&payload).map_err(|e| e.to_string())?;
+        if bytes.len() > MAX_PAYLOAD {
+            // Metadata is optional for the client; drop it before giving up
+            payload.metadata = None;
+            bytes = serde_json::to_vec(&payload).map_err(|e| e.to_string())?;
+        }
+        if bytes.len() > MAX_PAYLOAD {
+            return Err(format!("Payload too large for APNs ({} bytes)", bytes.len()));
+        }
+        Ok(bytes)
+    }
+}
+
+#[async_trait]
+impl PushProvider for ApnsProvider {
+    fn name(&self) -> &'static str {
+        "apns"
+    }
+
+    async fn send(&self, token: &str, message: &PushMessage<'_>) -> PushOutcome {
+        debug!(
+            "[APNS] Preparing message for token: {}... (type: {})",
+            token_prefix(token),
+            message.body.body_type
+        );
+
+        if token.is_empty() || !token.chars().all(|c| c.is_ascii_hexdigit()) {
+            warn!("[APNS] Dropping malformed device token");
+            return PushOutcome::Unregistered;
+        }
+
+        let payload = match Self::payload(message) {
+            Ok(payload) => payload,
+            Err(reason) => {
+                error!("[APNS] Failed to prepare message: {}", reason);
+                return PushOutcome::Rejected(reason);
+            }
+        };
+        let provider_token = match self.provider_token().await {
+            Ok(provider_token) => provider_token,
+            Err(reason) => return PushOutcome::Retry(reason),
+        };
+
+        let url = format!("{}/3/device/{}", self.base_url, token);
+        let expiration = (Utc::now() + Duration::seconds(EXPIRATION_SECS)).timestamp();
+        let mut request = self
+            .client
+            .post(&url)
+            .bearer_auth(&provider_token)
+            .header("apns-topic", &self.topic)
+            .header("apns-push-type", "alert")
+            .header("apns-priority", "10")
+            .header("apns-expiration", expiration.to_string())
+            .header("Content-Type", "application/json");
+        // The device replaces a shown notification with the same collapse id
+        if let Some(collapse_key) = &message.body.collapse_key {
+            let collapse_id = if collapse_key.len() <= MAX_COLLAPSE_ID {
+                collapse_key.clone()
+            } else {
+                hashed_collapse_id(collapse_key)
+            };
+            request = request.header("apns-collapse-id", collapse_id);
+        }
+
+        let response = match request.body(payload).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                error!("[APNS] Failed to send message: {}", e);
+                return PushOutcome::Retry(e.to_string());
+            }
+        };
+
+        let status = response.status();
+        if status.is_success() {
+            info!(
+                "[APNS] Message sent successfully to token: {}...",
+                token_prefix(token)
+            );
+            return PushOutcome::Delivered;
+        }
+
+        let body_text = response.text().await.unwrap_or_default();
+        let reason = serde_json::from_str::<ApnsError>(&body_text)
+            .map(|error| error.reason)
+            .unwrap_or_else(|_| body_text.clone());
+        error!("[APNS] Send failed: {} {}", status, reason);
+
+        match (status.as_u16(), reason.as_str()) {
+            (410, _) | (400, "BadDeviceToken") | (400, "DeviceTokenNotForTopic") => {
+                warn!("[APNS] Token is unregistered: {}...", token_prefix(token));
+                PushOutcome::Unregistered
+            }
+            (403, "ExpiredProviderToken") | (403, "InvalidProviderToken") => {
+                self.forget_provider_token().await;
+                PushOutcome::Retry(format!("{} {}", status, reason))
+            }
+            (429, _) => PushOutcome::Retry(format!("{} {}", status, reason)),
+            _ if status.is_server_error() => PushOutcome::Retry(format!("{} {}", status, reason)),
+            _ => PushOutcome::Rejected(format!("{} {}", status, reason)),
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{EncodingKey, Header};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use tokio::sync::Mutex;
+
+use super::provider::{PushMessage, PushOutcome, PushProvider};
+use super::{hashed_collapse_id, http_client, notification_preview, token_prefix};
+
+#[derive(Debug, Deserialize)]
+struct ServiceAccountKey {
+    project_id: String,
+    private_key: String,
+    client_email: String,
+    token_uri: String,
+}
+
+#[derive(Debug, Serialize)]
+struct TokenRequest<'a> {
+    iss: &'a str,
+    sub: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+    scope: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: i64,
+}
+
+#[derive(Debug)]
+struct CachedAccessToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Firebase Cloud Messaging (HTTP v1) for Android, iOS and Firebase web clients
+pub struct FcmProvider {
+    service_account: ServiceAccountKey,
+    client: reqwest::Client,
+    base_url: String,
+    access_token: Mutex<Option<CachedAccessToken>>,
+}
+
+impl FcmProvider {
+    /// Load the service account from `FCM_SERVICE_ACCOUNT_PATH`; `None` disables FCM.
+    /// `FCM_API_BASE_URL` overrides the endpoint, e.g. for a local mock server.
+    pub fn from_env() -> Option<Self> {
+        let path = match env::var("FCM_SERVICE_ACCOUNT_PATH") {
+            Ok(value) => {
+                debug!("FCM_SERVICE_ACCOUNT_PATH is set: {}", value);
+                value
+            }
+            Err(_) => {
+                warn!("FCM_SERVICE_ACCOUNT_PATH is not set; FCM delivery disabled");
+                return None;
+            }
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(value) => {
+                info!("Successfully loaded service account file from: {}", path);
+                value
+            }
+            Err(err) => {
+                warn!("Failed to read service account file at {}: {}", path, err);
+                return None;
+            }
+        };
+
+        let service_account = match serde_json::from_str::<ServiceAccountKey>(&contents) {
+            Ok(value) => {
+                info!(
+                    "Service account parsed successfully for project: {}",
+                    value.project_id
+                );
+                value
+            }
+            Err(err) => {
+                warn!("Failed to parse service account JSON: {}", err);
+                return None;
+            }
+        };
+
+        let base_url = env::var("FCM_API_BASE_URL")
+            .map(|url| url.trim_end_matches('/').to_string())
+            .unwrap_or_else(|_| "https://fcm.googleapis.com".to_string());
+
+        Some(Self {
+            service_account,
+            client: http_client()?,
+            base_url,
+            access_token: Mutex::new(None),
+        })
+    }
+
+    async fn get_access_token(&self) -> Result<String, String> {
+        let mut cache = self.access_token.lock().await;
+        if let Some(cached) = cache.as_ref() {
+            if cached.expires_at > Utc::now() + Duration::seconds(30) {
+                debug!(
+                    "Using cached FCM access token (expires at {})",
+                    cached.expires_at
+                );
+                return Ok(cached.token.clone());
+            }
+        }
+
+        debug!("Requesting new FCM access token...");
+        let service_account = &self.service_account;
+        let now = Utc::now();
+        let token_request = TokenRequest {
+            iss: &service_account.client_email,
+            sub: &service_account.client_email,
+            aud: &service_account.token_uri,
+            iat: now.timestamp(),
+            exp: (now + Duration::minutes(55)).timestamp(),
+            scope: "https://www.googleapis.com/auth/firebase.messaging",
+        };
+
+        let header = Header::new(jsonwebtoken::Algorithm::RS256);
+        let key = EncodingKey::from_rsa_pem(service_account.private_key.as_bytes()).map_err(|e| {
+            error!("Failed to create encoding key: {}", e);
+            "Invalid FCM credentials".to_string()
+        })?;
+
+        let assertion = jsonwebtoken::encode(&header, &token_request, &key).map_err(|e| {
+            error!("Failed to sign JWT: {}", e);
+            "Failed to authenticate FCM".to_string()
+        })?;
+
+        debug!(
+            "Sending JWT assertion to FCM token endpoint: {}",
+            service_account.token_uri
+        );
+        let response = self
+            .client
+            .post(&service_account.token_uri)
+            .form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                ("assertion", assertion.as_str()),
+            ])
+            .send()
+            .await
+            .map_err(|e| {
+                error!("Failed to request access token: {}", e);
+                "Failed to authenticate FCM".to_string()
+            })?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            error!("FCM token request failed: {} {}", status, body);
+            return Err("Failed to authenticate FCM".to_string());
+        }
+
+        info!("FCM access token obtained successfully");
+        let token_response = response.json::<TokenResponse>().await.map_err(|e| {
+            error!("Failed to parse access token: {}", e);
+            "Failed to authenticate FCM".to_string()
+        })?;
+
+        *cache = Some(CachedAccessToken {
+            token: token_response.access_token.clone(),
+            expires_at: Utc::now() + Duration::seconds(token_response.expires_in),
+        });
+
+        Ok(token_response.access_token)
+    }
+}
+
+#[derive(Serialize)]
+struct FcmMessage<'a> {
+    message: FcmMessageBody<'a>,
+}
+
+#[derive(Serialize)]
+struct FcmMessageBody<'a> {
+    token: &'a str,
+    notification: FcmNotification<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    android: Option<FcmAndroid<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    apns: Option<FcmHeaders>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    webpush: Option<FcmHeaders>,
+}
+
+#[derive(Serialize)]
+struct FcmNotification<'a> {
+    title: &'a str,
+    body: &'a str,
+}
+
+#[derive(Serialize)]
+struct FcmAndroid<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    collapse_key: Option<&'a str>,
+    notification: FcmAndroidNotification<'a>,
+}
+
+#[derive(Serialize)]
+struct FcmAndroidNotification<'a> {
+    icon: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<&'a str>,
+    /// A shown notification with the same tag is replaced
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<&'a str>,
+}
+
+/// Platform overrides that only set HTTP headers (`apns`, `webpush`)
+#[derive(Serialize)]
+struct FcmHeaders {
+    headers: HashMap<&'static str, String>,
+}
+
+#[async_trait]
+impl PushProvider for FcmProvider {
+    fn name(&self) -> &'static str {
+        "fcm"
+    }
+
+    async fn send(&self, token: &str, message: &PushMessage<'_>) -> PushOutcome {
+        let body = message.body;
+        debug!(
+            "[FCM] Preparing message for token: {}... (type: {})",
+            token_prefix(token),
+            body.body_type
+        );
+
+        let access_token = match self.get_access_token().await {
+            Ok(access_token) => access_token,
+            Err(reason) => return PushOutcome::Retry(reason),
+        };
+
+        let preview = notification_preview(body);
+
+        // FCM data values must be strings
+        let mut data_map = serde_json::Map::new();
+        if let Some(route) = &body.route {
+            data_map.insert(
+                "route".to_string(),
+                serde_json::Value::String(route.clone()),
+            );
+        }
+        if let Some(id) = message.notification_id {
+            data_map.insert(
+                "notification_id".to_string(),
+                serde_json::Value::String(id.to_string()),
+            );
+        }
+        data_map.insert(
+            "type".to_string(),
+            serde_json::Value::String(body.body_type.clone()),
+        );
+        if let Some(metadata) = &body.metadata {
+            data_map.insert(
+                "metadata".to_string(),
+                serde_json::Value::String(metadata.to_string()),
+            );
+        }
+        if let Some(collapse_key) = &body.collapse_key {
+            data_map.insert(
+                "collapse_key".to_string(),
+                serde_json::Value::String(collapse_key.clone()),
+            );
+        }
+
+        // Let devices replace the previous notification of the same group
+        let collapse_key = body.collapse_key.as_deref();
+        let collapse_id = collapse_key.map(hashed_collapse_id);
+        let apns = collapse_id.as_ref().map(|id| FcmHeaders {
+            headers: [("apns-collapse-id", id.clone())].into_iter().collect(),
+        });
+        let webpush = collapse_id.as_ref().map(|id| FcmHeaders {
+            headers: [("Topic", id.clone())].into_iter().collect(),
+        });
+
+        let icon = if body.body_type == "chat_message" || body.body_type == "feed_comment" {
+            "ic_notif_message"
+        } else {
+            "ic_notif_bell"
+        };
+
+        let payload = FcmMessage {
+            message: FcmMessageBody {
+                token,
+                notification: FcmNotification {
+                    title: &body.title,
+                    body: &preview,
+                },
+                data: Some(serde_json::Value::Object(data_map)),
+                android: Some(FcmAndroid {
+                    collapse_key,
+                    notification: FcmAndroidNotification {
+                        icon,
+                        color: Some("#c4161d"),
+                        tag: collapse_key,
+                    },
+                }),
+                apns,
+                webpush,
+            },
+        };
+
+        let url = format!(
+            "{}/v1/projects/{}/messages:send",
+            self.base_url, self.service_account.project_id
+        );
+
+        debug!("[FCM] Sending to: {}", url);
+        let response = match self
+            .client
+            .post(&url)
+            .bearer_auth(&access_token)
+            .json(&payload)
+            .send()
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                error!("[FCM] Failed to send FCM message: {}", e);
+                return PushOutcome::Retry(e.to_string());
+            }
+        };
+
+        if response.status().is_success() {
+            info!(
+                "[FCM] Message sent successfully to token: {}...",
+                token_prefix(token)
+            );
+            return PushOutcome::Delivered;
+        }
+
+        let status = response.status();
+        let body_text = response.text().await.unwrap_or_default();
+        error!("[FCM] Send failed: {} {}", status, body_text);
+
+        if body_text.contains("UNREGISTERED") || body_text.contains("NOT_FOUND") {
+            warn!("[FCM] Token is unregistered: {}...", token_prefix(token));
+            return PushOutcome::Unregistered;
+        }
+
+        let reason = format!("{} {}", status, body_text);
+        if status.as_u16() == 429 || status.is_server_error() {
+            PushOutcome::Retry(reason)
+        } else {
+            PushOutcome::Rejected(reason)
+        }
+    }
+}
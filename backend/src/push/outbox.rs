@@ -0,0 +1,571 @@
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Duration, Utc};
+use futures_util::stream::{self, StreamExt};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use sqlx::{FromRow, PgPool};
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+use std::time::{Duration as StdDuration, Instant};
+use tokio::sync::Notify;
+
+use super::provider::{PushMessage, PushOutcome, PushProviders};
+use super::{revoke_tokens, PushDelivery};
+use crate::notifications::NotificationBody;
+use crate::users::{verify_token, Claims};
+use crate::AppState;
+
+/// Queued pushes picked up per worker round
+const CLAIM_BATCH: i64 = 100;
+/// Provider requests in flight at once
+const SEND_CONCURRENCY: usize = 16;
+/// Attempts before a push is given up as failed
+const MAX_ATTEMPTS: i32 = 6;
+/// First retry delay; doubled on every further attempt
+const BASE_BACKOFF_SECS: i64 = 30;
+const MAX_BACKOFF_SECS: i64 = 3600;
+/// How long a claimed push is reserved for the worker that claimed it
+const LEASE_SECS: i32 = 300;
+/// The worker also wakes up on its own in case a wake-up was missed
+const POLL_INTERVAL_SECS: u64 = 15;
+/// Finished pushes are kept this long for the delivery stats
+const RETENTION_DAYS: i32 = 30;
+/// How often the worker deletes expired pushes
+const CLEANUP_INTERVAL_SECS: u64 = 60 * 60;
+/// Pushes deleted per statement
+const CLEANUP_BATCH: i64 = 1000;
+
+static WAKE: OnceLock<Notify> = OnceLock::new();
+
+fn wake() -> &'static Notify {
+    WAKE.get_or_init(Notify::new)
+}
+
+/// Persist pushes for the outbox worker. Pushes for users without a device of
+/// a configured provider end up `skipped`.
+pub async fn enqueue(db: &PgPool, deliveries: Vec<PushDelivery>) {
+    if deliveries.is_empty() {
+        return;
+    }
+
+    let mut user_ids = Vec::with_capacity(deliveries.len());
+    let mut notification_ids = Vec::with_capacity(deliveries.len());
+    let mut bodies = Vec::with_capacity(deliveries.len());
+    for delivery in &deliveries {
+        user_ids.push(delivery.user_id);
+        notification_ids.push(delivery.notification_id);
+        bodies.push(serde_json::to_value(&delivery.body).unwrap_or_default());
+    }
+
+    // A queued push is pointless once a newer one with the same collapse key follows
+    let (collapse_users, collapse_keys): (Vec<i32>, Vec<String>) = deliveries
+        .iter()
+        .filter_map(|delivery| {
+            let key = delivery.body.collapse_key.clone()?;
+            Some((delivery.user_id, key))
+        })
+        .unzip();
+    if !collapse_keys.is_empty() {
+        if let Err(e) = sqlx::query(
+            "UPDATE push_outbox o
+             SET status = 'skipped', last_error = 'Superseded by a newer push', updated_at = NOW()
+             FROM UNNEST($1::int[], $2::text[]) AS n(user_id, collapse_key)
+             WHERE o.status = 'pending' AND o.user_id = n.user_id
+               AND o.body ? 'collapse_key' AND o.body->>'collapse_key' = n.collapse_key",
+        )
+        .bind(&collapse_users)
+        .bind(&collapse_keys)
+        .execute(db)
+        .await
+        {
+            error!("[PUSH] Failed to supersede queued pushes: {:?}", e);
+        }
+    }
+
+    match sqlx::query(
+        "INSERT INTO push_outbox (user_id, notification_id, body)
+         SELECT * FROM UNNEST($1::int[], $2::int[], $3::jsonb[])",
+    )
+    .bind(&user_ids)
+    .bind(&notification_ids)
+    .bind(&bodies)
+    .execute(db)
+    .await
+    {
+        Ok(result) => {
+            debug!("[PUSH] Queued {} pushes", result.rows_affected());
+            wake().notify_one();
+        }
+        Err(e) => error!("[PUSH] Failed to queue pushes: {:?}", e),
+    }
+}
+
+#[derive(Debug, FromRow)]
+struct OutboxEntry {
+    id: i64,
+    user_id: i32,
+    notification_id: Option<i32>,
+    body: JsonValue,
+    attempts: i32,
+}
+
+/// Reserve due pushes. A crashed worker's reservations expire with the lease.
+async fn claim(db: &PgPool) -> Result<Vec<OutboxEntry>, sqlx::Error> {
+    sqlx::query_as::<_, OutboxEntry>(
+        "UPDATE push_outbox
+         SET status = 'sending',
+             attempts = attempts + 1,
+             next_attempt_at = NOW() + make_interval(secs => $2),
+             updated_at = NOW()
+         WHERE id IN (
+             SELECT id FROM push_outbox
+             WHERE status IN ('pending', 'sending') AND next_attempt_at <= NOW()
+             ORDER BY next_attempt_at
+             LIMIT $1
+             FOR UPDATE SKIP LOCKED
+         )
+         RETURNING id, user_id, notification_id, body, attempts",
+    )
+    .bind(CLAIM_BATCH)
+    .bind(LEASE_SECS as f64)
+    .fetch_all(db)
+    .await
+}
+
+fn backoff(attempts: i32) -> Duration {
+    let exponent = (attempts - 1).clamp(0, 16) as u32;
+    Duration::seconds((BASE_BACKOFF_SECS * 2_i64.pow(exponent)).min(MAX_BACKOFF_SECS))
+}
+
+/// Final state of one queued push after a delivery round
+struct EntryResult {
+    id: i64,
+    status: &'static str,
+    next_attempt_at: DateTime<Utc>,
+    last_error: Option<String>,
+}
+
+/// Token outcome recorded in `push_deliveries`
+struct TokenResult {
+    outbox_id: i64,
+    token: String,
+    status: &'static str,
+    error: Option<String>,
+}
+
+/// Reschedule claimed pushes after a failure that affected the whole round
+async fn retry_all(db: &PgPool, entries: &[OutboxEntry], reason: &str) {
+    let results: Vec<EntryResult> = entries
+        .iter()
+        .map(|entry| retry_or_fail(entry, reason.to_string()))
+        .collect();
+    store_entry_results(db, &results).await;
+}
+
+fn retry_or_fail(entry: &OutboxEntry, error: String) -> EntryResult {
+    if entry.attempts >= MAX_ATTEMPTS {
+        EntryResult {
+            id: entry.id,
+            status: "failed",
+            next_attempt_at: Utc::now(),
+            last_error: Some(error),
+        }
+    } else {
+        EntryResult {
+            id: entry.id,
+            status: "pending",
+            next_attempt_at: Utc::now() + backoff(entry.attempts),
+            last_error: Some(error),
+        }
+    }
+}
+
+/// Deliver one batch of due pushes. Returns the number of pushes processed.
+pub async fn process_due(db: &PgPool, providers: &PushProviders) -> usize {
+    let entries = match claim(db).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("[PUSH] Failed to claim queued pushes: {:?}", e);
+            return 0;
+        }
+    };
+    if entries.is_empty() {
+        return 0;
+    }
+    debug!("[PUSH] Processing {} queued pushes", entries.len());
+
+    let user_ids: Vec<i32> = entries.iter().map(|entry| entry.user_id).collect();
+    let entry_ids: Vec<i64> = entries.iter().map(|entry| entry.id).collect();
+
+    let tokens = sqlx::query_as::<_, (i32, String, String)>(
+        "SELECT user_id, token, platform FROM push_tokens
+         WHERE user_id = ANY($1) AND revoked_at IS NULL",
+    )
+    .bind(&user_ids)
+    .fetch_all(db)
+    .await;
+    // Tokens finished in an earlier attempt are not sent to again
+    let finished = sqlx::query_as::<_, (i64, String, String)>(
+        "SELECT outbox_id, token, status FROM push_deliveries
+         WHERE outbox_id = ANY($1) AND status IN ('delivered', 'rejected', 'unregistered')",
+    )
+    .bind(&entry_ids)
+    .fetch_all(db)
+    .await;
+
+    let (tokens, finished) = match (tokens, finished) {
+        (Ok(tokens), Ok(finished)) => (tokens, finished),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("[PUSH] Failed to load push tokens: {:?}", e);
+            retry_all(db, &entries, "Failed to load push tokens").await;
+            return entries.len();
+        }
+    };
+
+    // Devices whose push service is not configured are left alone
+    let mut tokens_by_user: HashMap<i32, Vec<(String, String)>> = HashMap::new();
+    for (user_id, token, platform) in tokens {
+        if providers.for_platform(&platform).is_some() {
+            tokens_by_user
+                .entry(user_id)
+                .or_default()
+                .push((token, platform));
+        }
+    }
+    let mut delivered_before: HashSet<i64> = HashSet::new();
+    let finished: HashSet<(i64, String)> = finished
+        .into_iter()
+        .map(|(outbox_id, token, status)| {
+            if status == "delivered" {
+                delivered_before.insert(outbox_id);
+            }
+            (outbox_id, token)
+        })
+        .collect();
+
+    let mut bodies: HashMap<i64, NotificationBody> = HashMap::new();
+    let mut results = Vec::new();
+    for entry in &entries {
+        match serde_json::from_value::<NotificationBody>(entry.body.clone()) {
+            Ok(body) => {
+                bodies.insert(entry.id, body);
+            }
+            Err(e) => results.push(EntryResult {
+                id: entry.id,
+                status: "failed",
+                next_attempt_at: Utc::now(),
+                last_error: Some(format!("Invalid notification body: {}", e)),
+            }),
+        }
+    }
+
+    let messages = entries
+        .iter()
+        .filter(|entry| bodies.contains_key(&entry.id))
+        .flat_map(|entry| {
+            tokens_by_user
+                .get(&entry.user_id)
+                .into_iter()
+                .flatten()
+                .filter(|(token, _)| !finished.contains(&(entry.id, token.to_string())))
+                .map(move |(token, platform)| (entry, token, platform))
+        });
+
+    let token_results: Vec<TokenResult> = stream::iter(messages)
+        .map(|(entry, token, platform)| {
+            let body = &bodies[&entry.id];
+            async move {
+                let message = PushMessage {
+                    body,
+                    notification_id: entry.notification_id,
+                };
+                let outcome = match providers.for_platform(platform) {
+                    Some(provider) => provider.send(token, &message).await,
+                    None => PushOutcome::Rejected(format!("No provider for {}", platform)),
+                };
+                let (status, error) = match outcome {
+                    PushOutcome::Delivered => ("delivered", None),
+                    PushOutcome::Unregistered => ("unregistered", None),
+                    PushOutcome::Retry(reason) => ("failed", Some(reason)),
+                    PushOutcome::Rejected(reason) => ("rejected", Some(reason)),
+                };
+                TokenResult {
+                    outbox_id: entry.id,
+                    token: token.clone(),
+                    status,
+                    error,
+                }
+            }
+        })
+        .buffer_unordered(SEND_CONCURRENCY)
+        .collect()
+        .await;
+
+    for entry in entries.iter().filter(|entry| bodies.contains_key(&entry.id)) {
+        let outcomes: Vec<&TokenResult> = token_results
+            .iter()
+            .filter(|result| result.outbox_id == entry.id)
+            .collect();
+        let retry_error = outcomes
+            .iter()
+            .find(|result| result.status == "failed")
+            .and_then(|result| result.error.clone());
+        let delivered = delivered_before.contains(&entry.id)
+            || outcomes.iter().any(|result| result.status == "delivered");
+
+        results.push(if let Some(error) = retry_error {
+            retry_or_fail(entry, error)
+        } else if delivered {
+            EntryResult {
+                id: entry.id,
+                status: "delivered",
+                next_attempt_at: Utc::now(),
+                last_error: None,
+            }
+        } else if outcomes.is_empty() {
+            // The user has no (remaining) devices
+            EntryResult {
+                id: entry.id,
+                status: "skipped",
+                next_attempt_at: Utc::now(),
+                last_error: None,
+            }
+        } else {
+            EntryResult {
+                id: entry.id,
+                status: "failed",
+                next_attempt_at: Utc::now(),
+                last_error: outcomes.iter().find_map(|result| result.error.clone()),
+            }
+        });
+    }
+
+    store_token_results(db, &token_results).await;
+    store_entry_results(db, &results).await;
+
+    let unregistered: Vec<String> = token_results
+        .iter()
+        .filter(|result| result.status == "unregistered")
+        .map(|result| result.token.clone())
+        .collect();
+    if !unregistered.is_empty() {
+        revoke_tokens(db, &unregistered).await;
+    }
+
+    entries.len()
+}
+
+async fn store_token_results(db: &PgPool, results: &[TokenResult]) {
+    if results.is_empty() {
+        return;
+    }
+
+    let outbox_ids: Vec<i64> = results.iter().map(|result| result.outbox_id).collect();
+    let tokens: Vec<String> = results.iter().map(|result| result.token.clone()).collect();
+    let statuses: Vec<String> = results.iter().map(|result| result.status.to_string()).collect();
+    let errors: Vec<Option<String>> = results.iter().map(|result| result.error.clone()).collect();
+
+    if let Err(e) = sqlx::query(
+        "INSERT INTO push_deliveries (outbox_id, token, status, last_error, delivered_at)
+         SELECT outbox_id, token, status, last_error,
+                CASE WHEN status = 'delivered' THEN NOW() END
+         FROM UNNEST($1::bigint[], $2::text[], $3::text[], $4::text[])
+              AS r(outbox_id, token, status, last_error)
+         ON CONFLICT (outbox_id, token) DO UPDATE SET
+             status = EXCLUDED.status,
+             attempts = push_deliveries.attempts + 1,
+             last_error = EXCLUDED.last_error,
+             delivered_at = EXCLUDED.delivered_at,
+             updated_at = NOW()",
+    )
+    .bind(&outbox_ids)
+    .bind(&tokens)
+    .bind(&statuses)
+    .bind(&errors)
+    .execute(db)
+    .await
+    {
+        error!("[PUSH] Failed to record delivery results: {:?}", e);
+    }
+}
+
+async fn store_entry_results(db: &PgPool, results: &[EntryResult]) {
+    if results.is_empty() {
+        return;
+    }
+
+    let ids: Vec<i64> = results.iter().map(|result| result.id).collect();
+    let statuses: Vec<String> = results.iter().map(|result| result.status.to_string()).collect();
+    let next_attempts: Vec<DateTime<Utc>> =
+        results.iter().map(|result| result.next_attempt_at).collect();
+    let errors: Vec<Option<String>> =
+        results.iter().map(|result| result.last_error.clone()).collect();
+
+    if let Err(e) = sqlx::query(
+        "UPDATE push_outbox o
+         SET status = r.status, next_attempt_at = r.next_attempt_at,
+             last_error = r.last_error, updated_at = NOW()
+         FROM UNNEST($1::bigint[], $2::text[], $3::timestamptz[], $4::text[])
+              AS r(id, status, next_attempt_at, last_error)
+         WHERE o.id = r.id",
+    )
+    .bind(&ids)
+    .bind(&statuses)
+    .bind(&next_attempts)
+    .bind(&errors)
+    .execute(db)
+    .await
+    {
+        error!("[PUSH] Failed to update queued pushes: {:?}", e);
+    }
+}
+
+/// Delete delivered, failed and skipped pushes (and their per-token results)
+/// finished more than `RETENTION_DAYS` ago. Returns the number deleted.
+pub async fn cleanup_finished(db: &PgPool) -> Result<u64, sqlx::Error> {
+    let mut deleted = 0;
+    loop {
+        let result = sqlx::query(
+            "DELETE FROM push_outbox
+             WHERE id IN (
+                 SELECT id FROM push_outbox
+                 WHERE status IN ('delivered', 'failed', 'skipped')
+                   AND updated_at <= NOW() - make_interval(days => $1)
+                 LIMIT $2
+             )",
+        )
+        .bind(RETENTION_DAYS)
+        .bind(CLEANUP_BATCH)
+        .execute(db)
+        .await?;
+
+        deleted += result.rows_affected();
+        if (result.rows_affected() as i64) < CLEANUP_BATCH {
+            return Ok(deleted);
+        }
+    }
+}
+
+/// Background loop draining the push outbox and deleting old pushes
+pub async fn run_worker(db: PgPool, providers: PushProviders) {
+    info!("[PUSH] Outbox worker started");
+    let mut next_cleanup = Instant::now();
+    loop {
+        if Instant::now() >= next_cleanup {
+            match cleanup_finished(&db).await {
+                Ok(0) => {}
+                Ok(deleted) => info!("[PUSH] Deleted {} finished pushes", deleted),
+                Err(e) => error!("[PUSH] Failed to delete finished pushes: {:?}", e),
+            }
+            next_cleanup = Instant::now() + StdDuration::from_secs(CLEANUP_INTERVAL_SECS);
+        }
+
+        let processed = process_due(&db, &providers).await;
+        if processed as i64 >= CLAIM_BATCH {
+            // More pushes may be due right away
+            continue;
+        }
+
+        tokio::select! {
+            _ = wake().notified() => {}
+            _ = actix_web::rt::time::sleep(StdDuration::from_secs(POLL_INTERVAL_SECS)) => {}
+        }
+    }
+}
+
+fn verify_admin_claims(req: &HttpRequest, app_state: &AppState) -> Result<Claims, HttpResponse> {
+    let claims = verify_token(req, app_state)?;
+
+    if claims.impersonation.is_some() || !claims.roles.contains(&"admin".to_string()) {
+        return Err(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Admin access required"
+        })));
+    }
+
+    Ok(claims)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    /// Size of the reporting window in hours (default 24, max 30 days)
+    pub hours: Option<i32>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+struct RecentFailure {
+    id: i64,
+    user_id: i32,
+    notification_id: Option<i32>,
+    attempts: i32,
+    last_error: Option<String>,
+    updated_at: DateTime<Utc>,
+}
+
+/// Admin: push queue and delivery statistics
+#[get("/api/admin/push/stats")]
+async fn delivery_stats(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    query: web::Query<StatsQuery>,
+) -> impl Responder {
+    if let Err(response) = verify_admin_claims(&req, &app_state) {
+        return response;
+    }
+
+    let hours = query.hours.unwrap_or(24).clamp(1, 24 * 30);
+    let db = &app_state.db;
+
+    let queue = sqlx::query_as::<_, (String, i64)>(
+        "SELECT status, COUNT(*) FROM push_outbox
+         WHERE created_at > NOW() - make_interval(hours => $1)
+         GROUP BY status",
+    )
+    .bind(hours)
+    .fetch_all(db)
+    .await;
+
+    let tokens = sqlx::query_as::<_, (String, i64)>(
+        "SELECT status, COUNT(*) FROM push_deliveries
+         WHERE updated_at > NOW() - make_interval(hours => $1)
+         GROUP BY status",
+    )
+    .bind(hours)
+    .fetch_all(db)
+    .await;
+
+    let backlog = sqlx::query_as::<_, (i64, Option<DateTime<Utc>>)>(
+        "SELECT COUNT(*), MIN(created_at) FROM push_outbox WHERE status IN ('pending', 'sending')",
+    )
+    .fetch_one(db)
+    .await;
+
+    let failures = sqlx::query_as::<_, RecentFailure>(
+        "SELECT id, user_id, notification_id, attempts, last_error, updated_at
+         FROM push_outbox
+         WHERE status = 'failed' OR (status = 'pending' AND last_error IS NOT NULL)
+         ORDER BY updated_at DESC
+         LIMIT 20",
+    )
+    .fetch_all(db)
+    .await;
+
+    match (queue, tokens, backlog, failures) {
+        (Ok(queue), Ok(tokens), Ok((backlog, oldest_pending_at)), Ok(failures)) => {
+            HttpResponse::Ok().json(serde_json::json!({
+                "window_hours": hours,
+                "queue": queue.into_iter().collect::<HashMap<_, _>>(),
+                "tokens": tokens.into_iter().collect::<HashMap<_, _>>(),
+                "backlog": backlog,
+                "oldest_pending_at": oldest_pending_at,
+                "recent_failures": failures,
+            }))
+        }
+        (Err(e), ..) | (_, Err(e), ..) | (_, _, Err(e), _) | (.., Err(e)) => {
+            error!("Database error loading push stats: {:?}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Internal server error"
+            }))
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use async_trait::async_trait;
+use log::info;
+use std::sync::{Arc, Mutex};
+
+use super::apns::ApnsProvider;
+use super::fcm::FcmProvider;
+use super::web_push::WebPushProvider;
+use crate::notifications::NotificationBody;
+
+/// A notification as handed to a push provider for one device token.
+#[derive(Debug, Clone)]
+pub struct PushMessage<'a> {
+    pub body: &'a NotificationBody,
+    pub notification_id: Option<i32>,
+}
+
+/// Result of delivering to one device token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PushOutcome {
+    Delivered,
+    /// The token is no longer valid and should be revoked
+    Unregistered,
+    /// Temporary failure (network, throttling, service unavailable); worth retrying
+    Retry(String),
+    /// The service refused the message; retrying will not help
+    Rejected(String),
+}
+
+/// A push service (FCM, Web Push, APNs) that delivers to device tokens.
+#[async_trait]
+pub trait PushProvider: Send + Sync {
+    /// Short name used in logs
+    fn name(&self) -> &'static str;
+
+    async fn send(&self, token: &str, message: &PushMessage<'_>) -> PushOutcome;
+}
+
+/// Configured providers, routed by `push_tokens.platform`:
+/// `webpush` tokens are Web Push subscriptions, `apns` tokens are APNs device
+/// tokens and everything else (android, ios, web) is an FCM registration token.
+#[derive(Clone, Default)]
+pub struct PushProviders {
+    fcm: Option<Arc<dyn PushProvider>>,
+    web_push: Option<Arc<dyn PushProvider>>,
+    apns: Option<Arc<dyn PushProvider>>,
+}
+
+impl PushProviders {
+    /// Enable every provider whose configuration is present in the environment.
+    pub fn from_env() -> Self {
+        let providers = Self {
+            fcm: FcmProvider::from_env().map(|p| Arc::new(p) as Arc<dyn PushProvider>),
+            web_push: WebPushProvider::from_env().map(|p| Arc::new(p) as Arc<dyn PushProvider>),
+            apns: ApnsProvider::from_env().map(|p| Arc::new(p) as Arc<dyn PushProvider>),
+        };
+        info!(
+            "[PUSH] Providers enabled: fcm={}, web_push={}, apns={}",
+            providers.fcm.is_some(),
+            providers.web_push.is_some(),
+            providers.apns.is_some()
+        );
+        providers
+    }
+
+    /// Route every platform to the same provider, e.g. a `RecordingProvider` in tests.
+    pub fn single(provider: Arc<dyn PushProvider>) -> Self {
+        Self {
+            fcm: Some(provider.clone()),
+            web_push: Some(provider.clone()),
+            apns: Some(provider),
+        }
+    }
+
+    pub fn with_fcm(mut self, provider: Arc<dyn PushProvider>) -> Self {
+        self.fcm = Some(provider);
+        self
+    }
+
+    pub fn with_web_push(mut self, provider: Arc<dyn PushProvider>) -> Self {
+        self.web_push = Some(provider);
+        self
+    }
+
+    pub fn with_apns(mut self, provider: Arc<dyn PushProvider>) -> Self {
+        self.apns = Some(provider);
+        self
+    }
+
+    /// Provider for a token's platform; `None` when that service is not configured.
+    pub fn for_platform(&self, platform: &str) -> Option<&Arc<dyn PushProvider>> {
+        match platform {
+            "webpush" => self.web_push.as_ref(),
+            "apns" => self.apns.as_ref(),
+            _ => self.fcm.as_ref(),
+        }
+    }
+}
+
+/// A push recorded by `RecordingProvider`.
+#[derive(Debug, Clone)]
+pub struct RecordedPush {
+    pub token: String,
+    pub body: NotificationBody,
+    pub notification_id: Option<i32>,
+}
+
+/// Provider that records pushes instead of sending them, for tests.
+/// Every send returns the configured outcome (`Delivered` by default).
+pub struct RecordingProvider {
+    sent: Mutex<Vec<RecordedPush>>,
+    outcome: Mutex<PushOutcome>,
+}
+
+impl Default for RecordingProvider {
+    fn default() -> Self {
+        Self {
+            sent: Mutex::new(Vec::new()),
+            outcome: Mutex::new(PushOutcome::Delivered),
+        }
+    }
+}
+
+impl RecordingProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pushes sent so far, oldest first.
+    pub fn sent(&self) -> Vec<RecordedPush> {
+        self.sent.lock().map(|sent| sent.clone()).unwrap_or_default()
+    }
+
+    /// Pushes sent to the given token, oldest first.
+    pub fn sent_to(&self, token: &str) -> Vec<RecordedPush> {
+        self.sent()
+            .into_iter()
+            .filter(|push| push.token == token)
+            .collect()
+    }
+
+    /// Outcome returned by subsequent sends.
+    pub fn set_outcome(&self, outcome: PushOutcome) {
+        if let Ok(mut current) = self.outcome.lock() {
+            *current = outcome;
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut sent) = self.sent.lock() {
+            sent.clear();
+        }
+    }
+}
+
+#[async_trait]
+impl PushProvider for RecordingProvider {
+    fn name(&self) -> &'static str {
+        "recording"
+    }
+
+    async fn send(&self, token: &str, message: &PushMessage<'_>) -> PushOutcome {
+        if let Ok(mut sent) = self.sent.lock() {
+            sent.push(RecordedPush {
+                token: token.to_string(),
+                body: message.body.clone(),
+                notification_id: message.notification_id,
+            });
+        }
+        self.outcome
+            .lock()
+            .map(|outcome| outcome.clone())
+            .unwrap_or(PushOutcome::Delivered)
+    }
+}
@@ -0,0 +1,363 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes128Gcm, Nonce};
+use async_trait::async_trait;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig, URL_SAFE_NO_PAD};
+use base64::engine::DecodePaddingMode;
+use base64::{alphabet, Engine};
+use chrono::{Duration, Utc};
+use hkdf::Hkdf;
+use log::{debug, error, info, warn};
+use p256::ecdh::diffie_hellman;
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::env;
+
+use super::provider::{PushMessage, PushOutcome, PushProvider};
+use super::{hashed_collapse_id, http_client, notification_preview};
+
+/// Subscription keys are base64url; browsers differ on padding
+const BASE64_URL_LENIENT: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Record size announced in the aes128gcm header; the payload fits in one record
+const RECORD_SIZE: u32 = 4096;
+/// Push services accept at most 4096 bytes of body: 86 bytes of header,
+/// 16 bytes of authentication tag and 1 delimiter byte leave this much payload
+const MAX_PLAINTEXT: usize = 3993;
+/// How long the push service keeps the message for an offline browser
+const TTL_SECS: i64 = 24 * 60 * 60;
+/// VAPID tokens may be valid for at most 24 hours
+const VAPID_EXPIRY_HOURS: i64 = 12;
+
+/// A browser's `PushSubscription.toJSON()`, stored as the token of `webpush` devices
+#[derive(Debug, Deserialize)]
+pub struct Subscription {
+    pub endpoint: String,
+    pub keys: SubscriptionKeys,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubscriptionKeys {
+    pub p256dh: String,
+    pub auth: String,
+}
+
+impl Subscription {
+    /// Parse and check a subscription as registered by a web client.
+    pub fn parse(token: &str) -> Result<Self, String> {
+        let subscription = serde_json::from_str::<Subscription>(token)
+            .map_err(|e| format!("Invalid push subscription: {}", e))?;
+
+        let endpoint = reqwest::Url::parse(&subscription.endpoint)
+            .map_err(|e| format!("Invalid subscription endpoint: {}", e))?;
+        if endpoint.scheme() != "https" {
+            return Err("Subscription endpoint must use https".to_string());
+        }
+
+        subscription.public_key()?;
+        subscription.auth_secret()?;
+        Ok(subscription)
+    }
+
+    fn public_key(&self) -> Result<PublicKey, String> {
+        let bytes = BASE64_URL_LENIENT
+            .decode(&self.keys.p256dh)
+            .map_err(|_| "Invalid p256dh key encoding".to_string())?;
+        PublicKey::from_sec1_bytes(&bytes).map_err(|_| "Invalid p256dh key".to_string())
+    }
+
+    fn auth_secret(&self) -> Result<Vec<u8>, String> {
+        let bytes = BASE64_URL_LENIENT
+            .decode(&self.keys.auth)
+            .map_err(|_| "Invalid auth secret encoding".to_string())?;
+        if bytes.len() != 16 {
+            return Err("Auth secret must be 16 bytes".to_string());
+        }
+        Ok(bytes)
+    }
+
+    /// `scheme://host[:port]` of the endpoint, the audience of the VAPID token
+    fn origin(&self) -> Result<String, String> {
+        let url = reqwest::Url::parse(&self.endpoint)
+            .map_err(|e| format!("Invalid subscription endpoint: {}", e))?;
+        Ok(url.origin().ascii_serialization())
+    }
+}
+
+/// Sender key pair and salt used for one message. Every message gets fresh
+/// random ones; fixed ones reproduce known test vectors.
+pub struct MessageKeys {
+    pub secret: SecretKey,
+    pub salt: [u8; 16],
+}
+
+impl MessageKeys {
+    pub fn random() -> Self {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        Self {
+            secret: SecretKey::random(&mut OsRng),
+            salt,
+        }
+    }
+}
+
+/// Encrypt a payload for a subscription (RFC 8291, `aes128gcm` content coding).
+pub fn encrypt_payload(
+    subscription: &Subscription,
+    plaintext: &[u8],
+    keys: &MessageKeys,
+) -> Result<Vec<u8>, String> {
+    let ua_public = subscription.public_key()?;
+    let auth_secret = subscription.auth_secret()?;
+
+    let as_public = keys.secret.public_key().to_encoded_point(false);
+    let ua_public_bytes = ua_public.to_encoded_point(false);
+    let shared = diffie_hellman(keys.secret.to_nonzero_scalar(), ua_public.as_affine());
+
+    // IKM = HKDF(auth_secret, ecdh_secret, "WebPush: info" || 0x00 || ua_public || as_public)
+    let mut ikm = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&auth_secret), shared.raw_secret_bytes())
+        .expand_multi_info(
+            &[
+                b"WebPush: info\0",
+                ua_public_bytes.as_bytes(),
+                as_public.as_bytes(),
+            ],
+            &mut ikm,
+        )
+        .map_err(|_| "Key derivation failed".to_string())?;
+
+    let salt = &keys.salt;
+    let content = Hkdf::<Sha256>::new(Some(salt), &ikm);
+    let mut cek = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    content
+        .expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+        .and_then(|_| content.expand(b"Content-Encoding: nonce\0", &mut nonce))
+        .map_err(|_| "Key derivation failed".to_string())?;
+
+    // A single record, terminated by the last-record delimiter
+    let mut record = Vec::with_capacity(plaintext.len() + 1);
+    record.extend_from_slice(plaintext);
+    record.push(0x02);
+
+    let cipher = Aes128Gcm::new_from_slice(&cek).map_err(|_| "Invalid content key".to_string())?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), record.as_slice())
+        .map_err(|_| "Payload encryption failed".to_string())?;
+
+    // Header: salt || record size || key id length || key id (our public key)
+    let as_public = as_public.as_bytes();
+    let mut body = Vec::with_capacity(16 + 4 + 1 + as_public.len() + ciphertext.len());
+    body.extend_from_slice(salt);
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(as_public.len() as u8);
+    body.extend_from_slice(as_public);
+    body.extend_from_slice(&ciphertext);
+    Ok(body)
+}
+
+#[derive(Serialize)]
+struct VapidClaims<'a> {
+    aud: &'a str,
+    exp: i64,
+    sub: &'a str,
+}
+
+/// Payload read by the service worker's `push` handler
+#[derive(Serialize)]
+struct WebPushPayload<'a> {
+    title: &'a str,
+    body: &'a str,
+    #[serde(rename = "type")]
+    body_type: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    route: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    notification_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<&'a serde_json::Value>,
+    /// For the `tag` of `showNotification`, so a newer notification replaces the shown one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    collapse_key: Option<&'a str>,
+}
+
+fn decode_private_key(value: &str) -> Option<SigningKey> {
+    let bytes = BASE64_URL_LENIENT.decode(value.trim()).ok()?;
+    SigningKey::from_slice(&bytes).ok()
+}
+
+/// Application server key handed to `pushManager.subscribe()`, if Web Push is configured
+pub fn vapid_public_key() -> Option<String> {
+    let key = decode_private_key(&env::var("VAPID_PRIVATE_KEY").ok()?)?;
+    Some(URL_SAFE_NO_PAD.encode(key.verifying_key().to_encoded_point(false).as_bytes()))
+}
+
+/// Standards-based Web Push with VAPID authentication (RFC 8030, 8291, 8292)
+pub struct WebPushProvider {
+    signing_key: SigningKey,
+    /// Uncompressed public key, base64url
+    public_key: String,
+    subject: String,
+    client: reqwest::Client,
+}
+
+impl WebPushProvider {
+    /// Configure from `VAPID_PRIVATE_KEY` (raw P-256 scalar, base64url) and
+    /// `VAPID_SUBJECT` (a `mailto:` or `https:` contact); `None` disables Web Push.
+    pub fn from_env() -> Option<Self> {
+        let Ok(private_key) = env::var("VAPID_PRIVATE_KEY") else {
+            warn!("VAPID_PRIVATE_KEY is not set; Web Push delivery disabled");
+            return None;
+        };
+        let Some(signing_key) = decode_private_key(&private_key) else {
+            warn!("VAPID_PRIVATE_KEY is not a valid P-256 key; Web Push delivery disabled");
+            return None;
+        };
+        let subject = match env::var("VAPID_SUBJECT") {
+            Ok(subject) if subject.starts_with("mailto:") || subject.starts_with("https:") => {
+                subject
+            }
+            _ => {
+                warn!("VAPID_SUBJECT must be a mailto: or https: URI; Web Push delivery disabled");
+                return None;
+            }
+        };
+
+        let public_key =
+            URL_SAFE_NO_PAD.encode(signing_key.verifying_key().to_encoded_point(false).as_bytes());
+        info!("Web Push configured with VAPID key {}", public_key);
+
+        Some(Self {
+            signing_key,
+            public_key,
+            subject,
+            client: http_client()?,
+        })
+    }
+
+    fn vapid_token(&self, audience: &str) -> Result<String, String> {
+        let header = URL_SAFE_NO_PAD.encode(br#"{"typ":"JWT","alg":"ES256"}"#);
+        let claims = serde_json::to_vec(&VapidClaims {
+            aud: audience,
+            exp: (Utc::now() + Duration::hours(VAPID_EXPIRY_HOURS)).timestamp(),
+            sub: &self.subject,
+        })
+        .map_err(|e| e.to_string())?;
+
+        let signing_input = format!("{}.{}", header, URL_SAFE_NO_PAD.encode(claims));
+        let signature: Signature = self.signing_key.sign(signing_input.as_bytes());
+        Ok(format!(
+            "{}.{}",
+            signing_input,
+            URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        ))
+    }
+
+    fn payload(message: &PushMessage<'_>) -> Result<Vec<u8>, String> {
+        let body = message.body;
+        let preview = notification_preview(body);
+        let mut payload = WebPushPayload {
+            title: &body.title,
+            body: &preview,
+            body_type: &body.body_type,
+            route: body.route.as_deref(),
+            notification_id: message.notification_id,
+            metadata: body.metadata.as_ref(),
+            collapse_key: body.collapse_key.as_deref(),
+        };
+
+        let mut bytes = serde_json::to_vec(&payload).map_err(|e| e.to_string())?;
+        if bytes.len() > MAX_PLAINTEXT {
+            // Metadata is optional for the client; drop it before giving up
+            payload.metadata = None;
+            bytes = serde_json::to_vec(&payload).map_err(|e| e.to_string())?;
+        }
+        if bytes.len() > MAX_PLAINTEXT {
+            return Err(format!("Payload too large for Web Push ({} bytes)", bytes.len()));
+        }
+        Ok(bytes)
+    }
+}
+
+#[async_trait]
+impl PushProvider for WebPushProvider {
+    fn name(&self) -> &'static str {
+        "web_push"
+    }
+
+    async fn send(&self, token: &str, message: &PushMessage<'_>) -> PushOutcome {
+        let subscription = match Subscription::parse(token) {
+            Ok(subscription) => subscription,
+            Err(reason) => {
+                warn!("[WEBPUSH] Dropping unusable subscription: {}", reason);
+                return PushOutcome::Unregistered;
+            }
+        };
+        debug!(
+            "[WEBPUSH] Preparing message for {} (type: {})",
+            subscription.endpoint, message.body.body_type
+        );
+
+        let prepared = Self::payload(message)
+            .and_then(|payload| encrypt_payload(&subscription, &payload, &MessageKeys::random()))
+            .and_then(|body| Ok((body, self.vapid_token(&subscription.origin()?)?)));
+        let (body, jwt) = match prepared {
+            Ok(prepared) => prepared,
+            Err(reason) => {
+                error!("[WEBPUSH] Failed to prepare message: {}", reason);
+                return PushOutcome::Rejected(reason);
+            }
+        };
+
+        let mut request = self
+            .client
+            .post(&subscription.endpoint)
+            .header("TTL", TTL_SECS.to_string())
+            .header("Content-Encoding", "aes128gcm")
+            .header("Content-Type", "application/octet-stream")
+            .header("Urgency", "normal")
+            .header(
+                "Authorization",
+                format!("vapid t={}, k={}", jwt, self.public_key),
+            );
+        // The push service replaces an undelivered message with the same topic
+        if let Some(collapse_key) = &message.body.collapse_key {
+            request = request.header("Topic", hashed_collapse_id(collapse_key));
+        }
+
+        let response = match request.body(body).send().await {
+            Ok(response) => response,
+            Err(e) => {
+                error!("[WEBPUSH] Failed to send message: {}", e);
+                return PushOutcome::Retry(e.to_string());
+            }
+        };
+
+        let status = response.status();
+        if status.is_success() {
+            info!("[WEBPUSH] Message accepted by {}", subscription.endpoint);
+            return PushOutcome::Delivered;
+        }
+
+        let body_text = response.text().await.unwrap_or_default();
+        error!("[WEBPUSH] Send failed: {} {}", status, body_text);
+
+        let reason = format!("{} {}", status, body_text);
+        match status.as_u16() {
+            // The subscription expired or the user unsubscribed
+            404 | 410 => PushOutcome::Unregistered,
+            429 => PushOutcome::Retry(reason),
+            _ if status.is_server_error() => PushOutcome::Retry(reason),
+            _ => PushOutcome::Rejected(reason),
+        }
+    }
+}
@@ -8,6 +8,9 @@ use argon2::{Argon2, PasswordHasher};
 use argon2::password_hash::{SaltString, rand_core::OsRng};
 use log::{error};
 
+use crate::audit::{self, AuditEvent};
+use crate::consent::{self, RegistrationConsent};
+use crate::email_verification;
 use crate::AppState;
 use crate::users::verify_token;
 
@@ -48,6 +51,9 @@ pub struct RegisterWithTokenRequest {
     pub full_name: String,
     // Student-specific fields
     pub birthday: Option<String>,
+    /// Consent version shown on the registration form and accepted there
+    pub consent_version: Option<i32>,
+    pub consent_locale: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -163,9 +169,10 @@ async fn create_registration_token(
     let expires_at = Utc::now() + Duration::hours(48);
     
     // Store token
-    match sqlx::query(
+    match sqlx::query_scalar::<_, i32>(
            "INSERT INTO registration_tokens (token_hash, created_by_user_id, role, related_student_id, related_teacher_id, expires_at)
-            VALUES ($1, $2, $3, $4, $5, $6)"
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id"
     )
     .bind(&token_hash)
     .bind(admin_id)
@@ -173,10 +180,23 @@ async fn create_registration_token(
     .bind(token_req.related_student_id)
         .bind(token_req.related_teacher_id)
     .bind(expires_at)
-    .execute(&app_state.db)
+    .fetch_one(&app_state.db)
     .await
     {
-        Ok(_) => {
+        Ok(token_id) => {
+            audit::record(
+                &app_state,
+                &req,
+                AuditEvent::new("registration_token.create", "registration_token", Some(token_id))
+                    .after(Some(serde_json::json!({
+                        "role": token_req.role,
+                        "related_student_id": token_req.related_student_id,
+                        "related_teacher_id": token_req.related_teacher_id,
+                        "expires_at": expires_at,
+                    }))),
+            )
+            .await;
+
             HttpResponse::Created().json(CreateRegistrationTokenResponse {
                 token,
                 expires_at,
@@ -255,18 +275,31 @@ async fn create_parent_token_from_student(
     let expires_at = Utc::now() + Duration::hours(48);
     
     // Store token
-    match sqlx::query(
+    match sqlx::query_scalar::<_, i32>(
            "INSERT INTO registration_tokens (token_hash, created_by_user_id, role, related_student_id, related_teacher_id, expires_at)
-            VALUES ($1, $2, 'parent', $3, NULL, $4)"
+            VALUES ($1, $2, 'parent', $3, NULL, $4)
+            RETURNING id"
     )
     .bind(&token_hash)
     .bind(current_user_id)
     .bind(student_id)
     .bind(expires_at)
-    .execute(&app_state.db)
+    .fetch_one(&app_state.db)
     .await
     {
-        Ok(_) => {
+        Ok(token_id) => {
+            audit::record(
+                &app_state,
+                &req,
+                AuditEvent::new("registration_token.create", "registration_token", Some(token_id))
+                    .after(Some(serde_json::json!({
+                        "role": "parent",
+                        "related_student_id": student_id,
+                        "expires_at": expires_at,
+                    }))),
+            )
+            .await;
+
             HttpResponse::Created().json(CreateRegistrationTokenResponse {
                 token,
                 expires_at,
@@ -335,21 +368,36 @@ async fn create_student_token_from_teacher(
     let token_hash = format!("{:x}", hasher.finalize());
     let expires_at = Utc::now() + Duration::hours(48);
 
-    match sqlx::query(
+    match sqlx::query_scalar::<_, i32>(
         "INSERT INTO registration_tokens (token_hash, created_by_user_id, role, related_student_id, related_teacher_id, expires_at)
-         VALUES ($1, $2, 'student', NULL, $3, $4)"
+         VALUES ($1, $2, 'student', NULL, $3, $4)
+         RETURNING id"
     )
     .bind(&token_hash)
     .bind(current_user_id)
     .bind(teacher_id)
     .bind(expires_at)
-    .execute(&app_state.db)
+    .fetch_one(&app_state.db)
     .await
     {
-        Ok(_) => HttpResponse::Created().json(CreateRegistrationTokenResponse {
-            token,
-            expires_at,
-        }),
+        Ok(token_id) => {
+            audit::record(
+                &app_state,
+                &req,
+                AuditEvent::new("registration_token.create", "registration_token", Some(token_id))
+                    .after(Some(serde_json::json!({
+                        "role": "student",
+                        "related_teacher_id": teacher_id,
+                        "expires_at": expires_at,
+                    }))),
+            )
+            .await;
+
+            HttpResponse::Created().json(CreateRegistrationTokenResponse {
+                token,
+                expires_at,
+            })
+        }
         Err(e) => {
             error!("Failed to create student registration token: {}", e);
             HttpResponse::InternalServerError().json(serde_json::json!({
@@ -471,6 +519,7 @@ async fn get_token_info(
 /// Register a new user with a token
 #[post("/api/register-with-token")]
 async fn register_with_token(
+    req: HttpRequest,
     app_state: web::Data<AppState>,
     register_req: web::Json<RegisterWithTokenRequest>,
 ) -> impl Responder {
@@ -725,6 +774,38 @@ async fn register_with_token(
         }
     }
     
+    // Consent given on the form, so the first request isn't asked for it again
+    let ip = req.connection_info().realip_remote_addr().map(str::to_string);
+    let consent_error = match consent::accept_on_registration(
+        &mut tx,
+        user_id,
+        register_req.consent_version,
+        register_req.consent_locale.as_deref(),
+        ip,
+    )
+    .await
+    {
+        Ok(RegistrationConsent::Recorded | RegistrationConsent::NotRequired) => None,
+        Ok(RegistrationConsent::Missing(version)) => Some(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "The current consent version has to be accepted",
+            "code": "consent_required",
+            "version": version
+        }))),
+        Ok(RegistrationConsent::UnknownLocale) => Some(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Consent text not available in this locale"
+        }))),
+        Err(e) => {
+            error!("Failed to record consent: {}", e);
+            Some(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to record consent"
+            })))
+        }
+    };
+    if let Some(response) = consent_error {
+        let _ = tx.rollback().await;
+        return response;
+    }
+
     // Mark token as used
     if let Err(e) = sqlx::query(
         "UPDATE registration_tokens SET used_at = NOW(), used_by_user_id = $1 
@@ -747,6 +828,21 @@ async fn register_with_token(
         }));
     }
     
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("registration_token.redeem", "user", Some(user_id))
+            .after(audit::user_snapshot(&app_state.db, user_id).await),
+    )
+    .await;
+
+    email_verification::request_verification_after_change(
+        &app_state.db,
+        user_id,
+        app_state.email_service.clone(),
+    )
+    .await;
+
     HttpResponse::Created().json(serde_json::json!({
         "id": user_id,
         "username": register_req.username,
@@ -7,6 +7,8 @@ use super::models::{
     AddParentStudentRelationRequest, CreateParentRequest, ParentWithUserInfo, StudentWithUserInfo,
     UpdateParentRequest,
 };
+use crate::audit::{self, AuditEvent};
+use crate::email_verification;
 use crate::users::verify_token;
 use crate::AppState;
 
@@ -163,6 +165,21 @@ pub(crate) async fn create_parent(
         }));
     }
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("parent.create", "user", Some(user_id))
+            .after(audit::user_snapshot(&app_state.db, user_id).await),
+    )
+    .await;
+
+    email_verification::request_verification_after_change(
+        &app_state.db,
+        user_id,
+        app_state.email_service.clone(),
+    )
+    .await;
+
     HttpResponse::Created().json(serde_json::json!({
         "id": user_id,
         "username": parent_req.username
@@ -292,6 +309,8 @@ pub(crate) async fn update_parent(
         }));
     }
 
+    let before = audit::user_snapshot(&app_state.db, user_id).await;
+
     // Start transaction
     let mut tx = match app_state.db.begin().await {
         Ok(tx) => tx,
@@ -362,6 +381,24 @@ pub(crate) async fn update_parent(
         }));
     }
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("parent.update", "user", Some(user_id))
+            .before(before)
+            .after(audit::user_snapshot(&app_state.db, user_id).await),
+    )
+    .await;
+
+    if update_req.email.is_some() {
+        email_verification::request_verification_after_change(
+            &app_state.db,
+            user_id,
+            app_state.email_service.clone(),
+        )
+        .await;
+    }
+
     HttpResponse::Ok().json(serde_json::json!({
         "message": "Parent updated successfully"
     }))
@@ -426,9 +463,22 @@ pub(crate) async fn add_parent_student_relation(
     .execute(&app_state.db)
     .await
     {
-        Ok(_) => HttpResponse::Created().json(serde_json::json!({
-            "message": "Relation created successfully"
-        })),
+        Ok(_) => {
+            audit::record(
+                &app_state,
+                &req,
+                AuditEvent::new("parent_student_relation.add", "user", Some(parent_user_id))
+                    .after(Some(serde_json::json!({
+                        "parent_user_id": parent_user_id,
+                        "student_user_id": relation_req.student_id,
+                    }))),
+            )
+            .await;
+
+            HttpResponse::Created().json(serde_json::json!({
+                "message": "Relation created successfully"
+            }))
+        }
         Err(e) => {
             error!("Failed to create relation: {}", e);
             HttpResponse::Conflict().json(serde_json::json!({
@@ -462,6 +512,17 @@ pub(crate) async fn remove_parent_student_relation(
     {
         Ok(result) => {
             if result.rows_affected() > 0 {
+                audit::record(
+                    &app_state,
+                    &req,
+                    AuditEvent::new("parent_student_relation.remove", "user", Some(parent_user_id))
+                        .before(Some(serde_json::json!({
+                            "parent_user_id": parent_user_id,
+                            "student_user_id": student_user_id,
+                        }))),
+                )
+                .await;
+
                 HttpResponse::Ok().json(serde_json::json!({
                     "message": "Relation removed successfully"
                 }))
@@ -500,6 +561,7 @@ pub(crate) async fn archive_parent_role(
     };
 
     let user_id = user_id.into_inner();
+    let before = audit::user_snapshot(&app_state.db, user_id).await;
 
     // Get the admin user ID from username
     let admin_user_id: Option<(i32,)> = sqlx::query_as("SELECT id FROM users WHERE username = $1")
@@ -533,6 +595,15 @@ pub(crate) async fn archive_parent_role(
                     "error": "Parent role not found"
                 }));
             }
+            audit::record(
+                &app_state,
+                &req,
+                AuditEvent::new("parent.archive", "user", Some(user_id))
+                    .before(before)
+                    .after(audit::user_snapshot(&app_state.db, user_id).await),
+            )
+            .await;
+
             HttpResponse::Ok().json(serde_json::json!({
                 "message": "Parent role archived successfully"
             }))
@@ -557,6 +628,7 @@ pub(crate) async fn unarchive_parent_role(
     }
 
     let user_id = user_id.into_inner();
+    let before = audit::user_snapshot(&app_state.db, user_id).await;
 
     // Unarchive the parent role
     let result = sqlx::query(
@@ -573,6 +645,15 @@ pub(crate) async fn unarchive_parent_role(
                     "error": "Parent role not found"
                 }));
             }
+            audit::record(
+                &app_state,
+                &req,
+                AuditEvent::new("parent.unarchive", "user", Some(user_id))
+                    .before(before)
+                    .after(audit::user_snapshot(&app_state.db, user_id).await),
+            )
+            .await;
+
             HttpResponse::Ok().json(serde_json::json!({
                 "message": "Parent role unarchived successfully"
             }))
@@ -7,6 +7,8 @@ use super::helpers::{
     verify_can_access_student, verify_can_edit_student,
 };
 use super::models::{ParentSummary, StudentWithUserInfo, TeacherWithUserInfo, CreateStudentRequest, UpdateStudentRequest};
+use crate::audit::{self, AuditEvent};
+use crate::email_verification;
 use crate::users::verify_token;
 use crate::AppState;
 
@@ -30,6 +32,7 @@ pub(crate) async fn archive_student_role(
     };
 
     let user_id = user_id.into_inner();
+    let before = audit::user_snapshot(&app_state.db, user_id).await;
 
     // Start transaction
     let mut tx = match app_state.db.begin().await {
@@ -94,9 +97,20 @@ pub(crate) async fn archive_student_role(
             }
 
             match tx.commit().await {
-                Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-                    "message": "Student role archived successfully"
-                })),
+                Ok(_) => {
+                    audit::record(
+                        &app_state,
+                        &req,
+                        AuditEvent::new("student.archive", "user", Some(user_id))
+                            .before(before)
+                            .after(audit::user_snapshot(&app_state.db, user_id).await),
+                    )
+                    .await;
+
+                    HttpResponse::Ok().json(serde_json::json!({
+                        "message": "Student role archived successfully"
+                    }))
+                }
                 Err(e) => {
                     error!("Failed to commit transaction: {}", e);
                     HttpResponse::InternalServerError().json(serde_json::json!({
@@ -126,6 +140,7 @@ pub(crate) async fn unarchive_student_role(
     }
 
     let user_id = user_id.into_inner();
+    let before = audit::user_snapshot(&app_state.db, user_id).await;
 
     let mut tx = match app_state.db.begin().await {
         Ok(tx) => tx,
@@ -163,9 +178,20 @@ pub(crate) async fn unarchive_student_role(
             }
 
             match tx.commit().await {
-                Ok(_) => HttpResponse::Ok().json(serde_json::json!({
-                    "message": "Student role unarchived successfully"
-                })),
+                Ok(_) => {
+                    audit::record(
+                        &app_state,
+                        &req,
+                        AuditEvent::new("student.unarchive", "user", Some(user_id))
+                            .before(before)
+                            .after(audit::user_snapshot(&app_state.db, user_id).await),
+                    )
+                    .await;
+
+                    HttpResponse::Ok().json(serde_json::json!({
+                        "message": "Student role unarchived successfully"
+                    }))
+                }
                 Err(e) => {
                     error!("Failed to commit transaction: {}", e);
                     HttpResponse::InternalServerError().json(serde_json::json!({
@@ -314,6 +340,21 @@ pub(crate) async fn create_student(
         }));
     }
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("student.create", "user", Some(user_id))
+            .after(audit::user_snapshot(&app_state.db, user_id).await),
+    )
+    .await;
+
+    email_verification::request_verification_after_change(
+        &app_state.db,
+        user_id,
+        app_state.email_service.clone(),
+    )
+    .await;
+
     HttpResponse::Created().json(serde_json::json!({
         "id": user_id,
         "username": student_req.username
@@ -425,6 +466,8 @@ pub(crate) async fn update_student(
         return response;
     }
 
+    let before = audit::user_snapshot(&app_state.db, user_id).await;
+
     // Start transaction
     let mut tx = match app_state.db.begin().await {
         Ok(tx) => tx,
@@ -539,6 +582,24 @@ pub(crate) async fn update_student(
         }));
     }
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("student.update", "user", Some(user_id))
+            .before(before)
+            .after(audit::user_snapshot(&app_state.db, user_id).await),
+    )
+    .await;
+
+    if update_req.email.is_some() {
+        email_verification::request_verification_after_change(
+            &app_state.db,
+            user_id,
+            app_state.email_service.clone(),
+        )
+        .await;
+    }
+
     HttpResponse::Ok().json(serde_json::json!({
         "message": "Student updated successfully"
     }))
@@ -656,6 +717,17 @@ pub(crate) async fn remove_student_teacher_relation(
         }));
     }
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("teacher_student_relation.remove", "user", Some(student_id))
+            .before(Some(serde_json::json!({
+                "teacher_user_id": teacher_id,
+                "student_user_id": student_id,
+            }))),
+    )
+    .await;
+
     HttpResponse::Ok().json(serde_json::json!({
         "message": "Relation removed successfully"
     }))
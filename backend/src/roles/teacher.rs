@@ -7,6 +7,8 @@ use super::models::{
     AddTeacherStudentRelationRequest, CreateTeacherRequest, StudentWithUserInfo,
      UpdateTeacherRequest,
 };
+use crate::audit::{self, AuditEvent};
+use crate::email_verification;
 use crate::users::verify_token;
 use crate::AppState;
 
@@ -144,6 +146,21 @@ pub(crate) async fn create_teacher(
         }));
     }
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("teacher.create", "user", Some(user_id))
+            .after(audit::user_snapshot(&app_state.db, user_id).await),
+    )
+    .await;
+
+    email_verification::request_verification_after_change(
+        &app_state.db,
+        user_id,
+        app_state.email_service.clone(),
+    )
+    .await;
+
     HttpResponse::Created().json(serde_json::json!({
         "id": user_id,
         "username": teacher_req.username
@@ -233,6 +250,8 @@ pub(crate) async fn update_teacher(
         }));
     }
 
+    let before = audit::user_snapshot(&app_state.db, user_id).await;
+
     // Start transaction
     let mut tx = match app_state.db.begin().await {
         Ok(tx) => tx,
@@ -303,6 +322,24 @@ pub(crate) async fn update_teacher(
         }));
     }
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("teacher.update", "user", Some(user_id))
+            .before(before)
+            .after(audit::user_snapshot(&app_state.db, user_id).await),
+    )
+    .await;
+
+    if update_req.email.is_some() {
+        email_verification::request_verification_after_change(
+            &app_state.db,
+            user_id,
+            app_state.email_service.clone(),
+        )
+        .await;
+    }
+
     HttpResponse::Ok().json(serde_json::json!({
         "message": "Teacher updated successfully"
     }))
@@ -477,9 +514,22 @@ pub(crate) async fn add_teacher_student_relation(
     .execute(&app_state.db)
     .await
     {
-        Ok(_) => HttpResponse::Created().json(serde_json::json!({
-            "message": "Relation created successfully"
-        })),
+        Ok(_) => {
+            audit::record(
+                &app_state,
+                &req,
+                AuditEvent::new("teacher_student_relation.add", "user", Some(teacher_id))
+                    .after(Some(serde_json::json!({
+                        "teacher_user_id": teacher_id,
+                        "student_user_id": relation_req.student_id,
+                    }))),
+            )
+            .await;
+
+            HttpResponse::Created().json(serde_json::json!({
+                "message": "Relation created successfully"
+            }))
+        }
         Err(e) => {
             error!("Failed to create relation: {}", e);
             HttpResponse::Conflict().json(serde_json::json!({
@@ -584,6 +634,17 @@ pub(crate) async fn remove_teacher_student_relation(
         }));
     }
 
+    audit::record(
+        &app_state,
+        &req,
+        AuditEvent::new("teacher_student_relation.remove", "user", Some(teacher_id))
+            .before(Some(serde_json::json!({
+                "teacher_user_id": teacher_id,
+                "student_user_id": student_id,
+            }))),
+    )
+    .await;
+
     HttpResponse::Ok().json(serde_json::json!({
         "message": "Relation removed successfully"
     }))
@@ -609,6 +670,7 @@ pub(crate) async fn archive_teacher_role(
     };
 
     let user_id = user_id.into_inner();
+    let before = audit::user_snapshot(&app_state.db, user_id).await;
 
     // Get the admin user ID from username
     let admin_user_id: Option<(i32,)> = sqlx::query_as(
@@ -644,6 +706,15 @@ pub(crate) async fn archive_teacher_role(
                     "error": "Teacher role not found"
                 }));
             }
+            audit::record(
+                &app_state,
+                &req,
+                AuditEvent::new("teacher.archive", "user", Some(user_id))
+                    .before(before)
+                    .after(audit::user_snapshot(&app_state.db, user_id).await),
+            )
+            .await;
+
             HttpResponse::Ok().json(serde_json::json!({
                 "message": "Teacher role archived successfully"
             }))
@@ -668,6 +739,7 @@ pub(crate) async fn unarchive_teacher_role(
     }
 
     let user_id = user_id.into_inner();
+    let before = audit::user_snapshot(&app_state.db, user_id).await;
 
     // Unarchive the teacher role
     let result = sqlx::query(
@@ -684,6 +756,15 @@ pub(crate) async fn unarchive_teacher_role(
                     "error": "Teacher role not found"
                 }));
             }
+            audit::record(
+                &app_state,
+                &req,
+                AuditEvent::new("teacher.unarchive", "user", Some(user_id))
+                    .before(before)
+                    .after(audit::user_snapshot(&app_state.db, user_id).await),
+            )
+            .await;
+
             HttpResponse::Ok().json(serde_json::json!({
                 "message": "Teacher role unarchived successfully"
             }))
@@ -9,6 +9,12 @@ use serde::{Deserialize, Serialize};
 use sqlx::{FromRow, PgPool};
 
 use crate::storage::{MediaError, MediaService};
+use crate::email_verification;
+use crate::gdpr;
+use crate::i18n::Locale;
+use crate::impersonation::ImpersonationClaim;
+use crate::magic_link::{self, MagicLinkError};
+use crate::oidc;
 use crate::{password_reset, AppState};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -32,6 +38,8 @@ pub struct Claims {
     pub sub: String,        // username
     pub exp: usize,         // expiration time
     pub roles: Vec<String>, // user roles
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub impersonation: Option<ImpersonationClaim>, // set on admin "view as user" tokens
 }
 
 /// Extract and validate JWT token from request
@@ -81,7 +89,7 @@ struct User {
     password_hash: String,
 }
 
-async fn load_active_roles(pool: &PgPool, user_id: i32) -> Result<Vec<String>, sqlx::Error> {
+pub(crate) async fn load_active_roles(pool: &PgPool, user_id: i32) -> Result<Vec<String>, sqlx::Error> {
     let roles = sqlx::query_scalar::<_, String>(
         "SELECT r.name FROM roles r 
          INNER JOIN user_roles ur ON r.id = ur.role_id 
@@ -126,6 +134,31 @@ async fn load_active_roles(pool: &PgPool, user_id: i32) -> Result<Vec<String>, s
     Ok(active_roles)
 }
 
+/// Issue the regular 24h session JWT for a user
+pub(crate) fn issue_login_token(
+    jwt_secret: &str,
+    username: &str,
+    roles: Vec<String>,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let expiration = Utc::now()
+        .checked_add_signed(Duration::hours(24))
+        .expect("valid timestamp")
+        .timestamp() as usize;
+
+    let claims = Claims {
+        sub: username.to_string(),
+        exp: expiration,
+        roles,
+        impersonation: None,
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(jwt_secret.as_ref()),
+    )
+}
+
 #[post("/login")]
 async fn login(
     app_state: web::Data<AppState>,
@@ -192,22 +225,7 @@ async fn login(
     }
 
     // Generate JWT token
-    let expiration = Utc::now()
-        .checked_add_signed(Duration::hours(24))
-        .expect("valid timestamp")
-        .timestamp() as usize;
-
-    let claims = Claims {
-        sub: user.username.clone(),
-        exp: expiration,
-        roles,
-    };
-
-    let token = match encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(app_state.jwt_secret.as_ref()),
-    ) {
+    let token = match issue_login_token(&app_state.jwt_secret, &user.username, roles) {
         Ok(t) => t,
         Err(e) => {
             error!("JWT encoding error: {}", e);
@@ -240,6 +258,7 @@ async fn validate_token_endpoint(
             "valid": true,
             "username": claims.sub,
             "roles": claims.roles,
+            "impersonation": claims.impersonation,
         })),
         Ok(None) => HttpResponse::Unauthorized().json(serde_json::json!({
             "error": "User not found",
@@ -268,6 +287,16 @@ pub struct ResetPasswordRequest {
     pub password: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MagicLinkRequest {
+    pub identifier: String, // username or email address
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MagicLinkLoginRequest {
+    pub token: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ValidateTokenResponse {
     pub valid: bool,
@@ -283,6 +312,7 @@ async fn forgot_password(
         &app_state.db,
         &req.username,
         app_state.email_service.clone(),
+        &app_state.notifications,
     )
     .await {
         Ok(_) => {
@@ -351,6 +381,122 @@ async fn reset_password(
     }
 }
 
+/// Address of the client for rate limiting. The forwarding headers are set by
+/// the client unless a proxy in front of the backend overwrites them, so they
+/// are only used with `TRUST_PROXY_HEADERS=true`, taking the address the
+/// proxy appended last.
+fn client_ip(req: &HttpRequest) -> Option<String> {
+    let trust_proxy = std::env::var("TRUST_PROXY_HEADERS").is_ok_and(|value| value == "true");
+    if trust_proxy {
+        let forwarded = req
+            .headers()
+            .get("X-Forwarded-For")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .map(str::trim)
+            .filter(|ip| !ip.is_empty());
+        if let Some(ip) = forwarded {
+            return Some(ip.to_string());
+        }
+    }
+    req.peer_addr().map(|addr| addr.ip().to_string())
+}
+
+#[post("/magic-link")]
+async fn request_magic_link(
+    http_req: HttpRequest,
+    app_state: web::Data<AppState>,
+    req: web::Json<MagicLinkRequest>,
+) -> impl Responder {
+    let message = "If an account with an email address matches, a sign-in link has been sent.";
+    let ip = client_ip(&http_req);
+
+    match magic_link::request_magic_link(
+        &app_state.db,
+        &req.identifier,
+        ip.as_deref(),
+        app_state.email_service.clone(),
+    )
+    .await
+    {
+        Ok(_) => HttpResponse::Ok().json(serde_json::json!({ "message": message })),
+        Err(MagicLinkError::RateLimited) => HttpResponse::TooManyRequests().json(ErrorResponse {
+            error: "Too many sign-in link requests. Please try again later.".to_string(),
+        }),
+        Err(e) => {
+            error!("Magic link request error: {}", e);
+            // Always return success to prevent username enumeration
+            HttpResponse::Ok().json(serde_json::json!({ "message": message }))
+        }
+    }
+}
+
+#[post("/magic-link/login")]
+async fn login_with_magic_link(
+    app_state: web::Data<AppState>,
+    req: web::Json<MagicLinkLoginRequest>,
+) -> impl Responder {
+    let user_id = match magic_link::consume_magic_link(&app_state.db, &req.token).await {
+        Ok(user_id) => user_id,
+        Err(MagicLinkError::TokenInvalid) => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Invalid or expired sign-in link".to_string(),
+            });
+        }
+        Err(e) => {
+            error!("Magic link login error: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Internal server error".to_string(),
+            });
+        }
+    };
+
+    let username = match sqlx::query_scalar::<_, String>("SELECT username FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(&app_state.db)
+        .await
+    {
+        Ok(Some(username)) => username,
+        Ok(None) => {
+            return HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Invalid or expired sign-in link".to_string(),
+            });
+        }
+        Err(e) => {
+            error!("Database error: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Internal server error".to_string(),
+            });
+        }
+    };
+
+    let roles = match load_active_roles(&app_state.db, user_id).await {
+        Ok(roles) => roles,
+        Err(e) => {
+            error!("Failed to fetch user roles: {}", e);
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Internal server error".to_string(),
+            });
+        }
+    };
+
+    if roles.is_empty() {
+        return HttpResponse::Unauthorized().json(ErrorResponse {
+            error: "All roles are archived".to_string(),
+        });
+    }
+
+    match issue_login_token(&app_state.jwt_secret, &username, roles) {
+        Ok(token) => HttpResponse::Ok().json(LoginResponse { token }),
+        Err(e) => {
+            error!("JWT encoding error: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Could not generate token".to_string(),
+            })
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StudentData {
     pub full_name: String,
@@ -390,6 +536,9 @@ pub struct UserProfile {
     pub email: Option<String>,
     pub phone: Option<String>,
     pub profile_image: Option<String>,
+    pub locale: String,
+    pub timezone: String,
+    pub email_verified: bool,
     #[serde(with = "chrono::serde::ts_seconds")]
     pub created_at: chrono::DateTime<chrono::Utc>,
     #[sqlx(skip)]
@@ -413,6 +562,8 @@ pub struct UpdateProfileRequest {
     // Role-specific fields
     pub full_name: Option<String>,
     pub birthday: Option<String>, // YYYY-MM-DD format
+    pub locale: Option<String>,   // "en", "de" or "ru"
+    pub timezone: Option<String>, // IANA name, e.g. "Europe/Berlin"
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -430,7 +581,9 @@ async fn get_profile(app_state: web::Data<AppState>, req: HttpRequest) -> impl R
     };
 
     let mut profile = match sqlx::query_as::<_, UserProfile>(
-        "SELECT id, username, full_name, email, phone, profile_image, created_at FROM users WHERE username = $1"
+        "SELECT id, username, full_name, email, phone, profile_image, locale, timezone,
+                email_verified_at IS NOT NULL AS email_verified, created_at
+         FROM users WHERE username = $1"
     )
     .bind(&claims.sub)
     .fetch_optional(&app_state.db)
@@ -565,13 +718,15 @@ async fn update_profile(
         Err(response) => return response,
     };
 
-    // Get user ID
-    let user_id = match sqlx::query_scalar::<_, i32>("SELECT id FROM users WHERE username = $1")
-        .bind(&claims.sub)
-        .fetch_optional(&app_state.db)
-        .await
+    // Get user ID and current email
+    let (user_id, previous_email) = match sqlx::query_as::<_, (i32, Option<String>)>(
+        "SELECT id, email FROM users WHERE username = $1",
+    )
+    .bind(&claims.sub)
+    .fetch_optional(&app_state.db)
+    .await
     {
-        Ok(Some(id)) => id,
+        Ok(Some(row)) => row,
         _ => {
             return HttpResponse::NotFound().json(ErrorResponse {
                 error: "User not found".to_string(),
@@ -605,6 +760,67 @@ async fn update_profile(
         });
     }
 
+    if let Some(ref locale) = update_req.locale {
+        let Some(locale) = Locale::from_code(locale) else {
+            let _ = tx.rollback().await;
+            return HttpResponse::BadRequest().json(ErrorResponse {
+                error: format!("Unsupported locale. Use one of: {}", Locale::SUPPORTED.join(", ")),
+            });
+        };
+
+        if let Err(e) = sqlx::query("UPDATE users SET locale = $1 WHERE id = $2")
+            .bind(locale.code())
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+        {
+            error!("Failed to update user locale: {}", e);
+            let _ = tx.rollback().await;
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to update profile".to_string(),
+            });
+        }
+    }
+
+    if let Some(ref timezone) = update_req.timezone {
+        let known = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS(SELECT 1 FROM pg_timezone_names WHERE name = $1)",
+        )
+        .bind(timezone)
+        .fetch_one(&mut *tx)
+        .await;
+
+        match known {
+            Ok(true) => {}
+            Ok(false) => {
+                let _ = tx.rollback().await;
+                return HttpResponse::BadRequest().json(ErrorResponse {
+                    error: "Unknown timezone. Use an IANA name such as Europe/Berlin".to_string(),
+                });
+            }
+            Err(e) => {
+                error!("Failed to validate timezone: {}", e);
+                let _ = tx.rollback().await;
+                return HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: "Failed to update profile".to_string(),
+                });
+            }
+        }
+
+        if let Err(e) = sqlx::query("UPDATE users SET timezone = $1 WHERE id = $2")
+            .bind(timezone)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+        {
+            error!("Failed to update user timezone: {}", e);
+            let _ = tx.rollback().await;
+            return HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "Failed to update profile".to_string(),
+            });
+        }
+    }
+
     if let Some(ref full_name) = update_req.full_name {
         if let Err(e) = sqlx::query("UPDATE users SET full_name = $1 WHERE id = $2")
             .bind(full_name)
@@ -687,6 +903,16 @@ async fn update_profile(
         });
     }
 
+    // A new address has to be confirmed before it is used
+    if update_req.email.is_some() && update_req.email != previous_email {
+        email_verification::request_verification_after_change(
+            &app_state.db,
+            user_id,
+            app_state.email_service.clone(),
+        )
+        .await;
+    }
+
     HttpResponse::Ok().json(serde_json::json!({
         "message": "Profile updated successfully"
     }))
@@ -957,7 +1183,13 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .service(validate_token_endpoint)
             .service(forgot_password)
             .service(validate_reset_token)
-            .service(reset_password),
+            .service(reset_password)
+            .service(request_magic_link)
+            .service(login_with_magic_link)
+            .service(email_verification::verify_email)
+            .service(oidc::list_providers)
+            .service(oidc::authorize)
+            .service(oidc::callback),
     );
     cfg.service(
         web::scope("/api/profile")
@@ -965,6 +1197,11 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
             .service(update_profile)
             .service(change_password)
             .service(upload_profile_image)
-            .service(delete_profile_image),
+            .service(delete_profile_image)
+            .service(email_verification::resend_verification)
+            .service(gdpr::export_data)
+            .service(gdpr::request_deletion)
+            .service(gdpr::get_deletion_request)
+            .service(gdpr::cancel_deletion_request),
     );
 }
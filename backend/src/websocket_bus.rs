@@ -0,0 +1,520 @@
+//! Relays WebSocket events between backend replicas over Postgres
+//! LISTEN/NOTIFY, so a broadcast on one instance reaches the sessions on all
+//! of them, and tracks which replica each user is connected to.
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::websocket_protocol::{ServerFrame, ServerMessage};
+use crate::websockets::WsServerActor;
+
+const CHANNEL: &str = "ws_bus";
+/// Postgres rejects NOTIFY payloads of 8000 bytes or more
+const MAX_PAYLOAD: usize = 7900;
+const HEARTBEAT_INTERVAL_SECS: u64 = 30;
+/// Replicas that miss heartbeats for this long are considered gone
+const INSTANCE_TIMEOUT_SECS: i32 = 90;
+const RETRY_DELAY_SECS: u64 = 5;
+/// How long frames for a user are kept for the other replicas to read
+const USER_EVENT_RETENTION_MINUTES: i32 = 10;
+
+/// What replicas tell each other. Logged events only carry their sequence
+/// number and are read back from `ws_events`, and frames for a user are read
+/// back from `ws_user_events`, which keeps messages of any size under the
+/// payload limit.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum ClusterEvent {
+    Thread {
+        thread_id: i32,
+        seq: Option<i64>,
+        message: Option<ServerFrame>,
+    },
+    Post {
+        post_id: i32,
+        seq: Option<i64>,
+        message: Option<ServerFrame>,
+    },
+    User {
+        user_id: i32,
+        id: i64,
+    },
+    Presence {
+        user_id: i32,
+        is_online: bool,
+        threads: Vec<i32>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    origin: String,
+    #[serde(flatten)]
+    event: ClusterEvent,
+}
+
+enum Command {
+    Publish(ClusterEvent),
+    /// Store a frame for the user's sessions and publish its id
+    PublishToUser {
+        user_id: i32,
+        message: ServerFrame,
+    },
+    /// The user's first session on this replica opened
+    Connected {
+        user_id: i32,
+        threads: Vec<i32>,
+    },
+    /// The user's last session on this replica closed
+    Disconnected {
+        user_id: i32,
+        threads: Vec<i32>,
+    },
+    /// Stop the worker; answered once this replica's presence is removed
+    Shutdown(oneshot::Sender<()>),
+}
+
+/// Handle the WebSocket server publishes through. Commands are processed in
+/// order by the [`WsBusWorker`].
+#[derive(Clone)]
+pub struct WsBus {
+    instance_id: String,
+    db: PgPool,
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+/// Publishes this replica's events and delivers the other replicas' events
+/// to local sessions
+pub struct WsBusWorker {
+    bus: WsBus,
+    commands: mpsc::UnboundedReceiver<Command>,
+}
+
+pub fn new(db: PgPool) -> (WsBus, WsBusWorker) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let bus = WsBus {
+        instance_id: uuid::Uuid::new_v4().to_string(),
+        db,
+        commands: sender,
+    };
+    let worker = WsBusWorker {
+        bus: bus.clone(),
+        commands: receiver,
+    };
+    (bus, worker)
+}
+
+impl WsBus {
+    fn send(&self, command: Command) {
+        if self.commands.send(command).is_err() {
+            warn!("WebSocket bus worker is not running; event stays on this replica");
+        }
+    }
+
+    pub(crate) fn publish_thread(&self, thread_id: i32, message: &ServerFrame) {
+        self.send(Command::Publish(ClusterEvent::Thread {
+            thread_id,
+            seq: message.seq,
+            message: message.seq.is_none().then(|| message.clone()),
+        }));
+    }
+
+    pub(crate) fn publish_post(&self, post_id: i32, message: &ServerFrame) {
+        self.send(Command::Publish(ClusterEvent::Post {
+            post_id,
+            seq: message.seq,
+            message: message.seq.is_none().then(|| message.clone()),
+        }));
+    }
+
+    pub(crate) fn publish_to_user(&self, user_id: i32, message: &ServerFrame) {
+        self.send(Command::PublishToUser {
+            user_id,
+            message: message.clone(),
+        });
+    }
+
+    pub(crate) fn user_connected(&self, user_id: i32, threads: Vec<i32>) {
+        self.send(Command::Connected { user_id, threads });
+    }
+
+    pub(crate) fn user_disconnected(&self, user_id: i32, threads: Vec<i32>) {
+        self.send(Command::Disconnected { user_id, threads });
+    }
+
+    /// Stop relaying and drop this replica's presence rows, e.g. once the
+    /// HTTP server has stopped. Other replicas stop counting its users as
+    /// online right away instead of after the instance timeout.
+    pub async fn shutdown(&self) {
+        let (done, finished) = oneshot::channel();
+        self.send(Command::Shutdown(done));
+        let _ = finished.await;
+    }
+
+    /// The subset of `user_ids` connected to any live replica
+    pub(crate) async fn online_users(&self, user_ids: &[i32]) -> Result<Vec<i32>, sqlx::Error> {
+        sqlx::query_scalar::<_, i32>(
+            "SELECT DISTINCT p.user_id
+             FROM ws_presence p
+             JOIN ws_instances i ON i.id = p.instance_id
+             WHERE p.user_id = ANY($1)
+               AND i.last_seen_at > NOW() - make_interval(secs => $2)",
+        )
+        .bind(user_ids)
+        .bind(INSTANCE_TIMEOUT_SECS)
+        .fetch_all(&self.db)
+        .await
+    }
+
+    /// Whether a live replica other than this one has a session for the user
+    async fn online_elsewhere(&self, user_id: i32) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (
+                 SELECT 1 FROM ws_presence p
+                 JOIN ws_instances i ON i.id = p.instance_id
+                 WHERE p.user_id = $1 AND p.instance_id <> $2
+                   AND i.last_seen_at > NOW() - make_interval(secs => $3)
+             )",
+        )
+        .bind(user_id)
+        .bind(&self.instance_id)
+        .bind(INSTANCE_TIMEOUT_SECS)
+        .fetch_one(&self.db)
+        .await
+    }
+
+    async fn notify(&self, event: ClusterEvent) {
+        let envelope = Envelope {
+            origin: self.instance_id.clone(),
+            event,
+        };
+        let payload = match serde_json::to_string(&envelope) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("Failed to serialize WebSocket bus event: {}", e);
+                return;
+            }
+        };
+        if payload.len() > MAX_PAYLOAD {
+            warn!(
+                "WebSocket bus event of {} bytes is too large for NOTIFY; delivered on this replica only",
+                payload.len()
+            );
+            return;
+        }
+
+        if let Err(e) = sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(CHANNEL)
+            .bind(&payload)
+            .execute(&self.db)
+            .await
+        {
+            error!("Database error publishing WebSocket bus event: {:?}", e);
+        }
+    }
+}
+
+impl WsBusWorker {
+    /// Runs for the lifetime of the server
+    pub async fn run(mut self, server: WsServerActor) {
+        let bus = self.bus.clone();
+        info!("WebSocket bus started as instance {}", bus.instance_id);
+        let listener = actix_web::rt::spawn(listen(bus.clone(), server.clone()));
+
+        // Register this replica before recording any presence against it
+        let mut heartbeat =
+            actix_web::rt::time::interval(Duration::from_secs(HEARTBEAT_INTERVAL_SECS));
+        heartbeat.tick().await;
+        if let Err(e) = send_heartbeat(&bus, &server).await {
+            error!("Database error registering WebSocket instance: {:?}", e);
+        }
+
+        loop {
+            tokio::select! {
+                command = self.commands.recv() => match command {
+                    Some(Command::Shutdown(done)) => {
+                        listener.abort();
+                        let removed = sqlx::query("DELETE FROM ws_instances WHERE id = $1")
+                            .bind(&bus.instance_id)
+                            .execute(&bus.db)
+                            .await;
+                        if let Err(e) = removed {
+                            error!("Database error removing WebSocket instance: {:?}", e);
+                        }
+                        info!("WebSocket bus stopped");
+                        let _ = done.send(());
+                        return;
+                    }
+                    Some(command) => handle_command(&bus, &server, command).await,
+                    None => return,
+                },
+                _ = heartbeat.tick() => {
+                    if let Err(e) = send_heartbeat(&bus, &server).await {
+                        error!("Database error in WebSocket bus heartbeat: {:?}", e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn handle_command(bus: &WsBus, server: &WsServerActor, command: Command) {
+    match command {
+        Command::Publish(event) => bus.notify(event).await,
+        Command::PublishToUser { user_id, message } => {
+            let stored = sqlx::query_scalar::<_, i64>(
+                "INSERT INTO ws_user_events (user_id, message) VALUES ($1, $2) RETURNING id",
+            )
+            .bind(user_id)
+            .bind(sqlx::types::Json(&message))
+            .fetch_one(&bus.db)
+            .await;
+            match stored {
+                Ok(id) => bus.notify(ClusterEvent::User { user_id, id }).await,
+                Err(e) => error!("Database error storing WebSocket user event: {:?}", e),
+            }
+        }
+        Command::Connected { user_id, threads } => {
+            let recorded = sqlx::query(
+                "INSERT INTO ws_presence (instance_id, user_id) VALUES ($1, $2)
+                 ON CONFLICT DO NOTHING",
+            )
+            .bind(&bus.instance_id)
+            .bind(user_id)
+            .execute(&bus.db)
+            .await;
+            if let Err(e) = recorded {
+                error!("Database error recording WebSocket presence: {:?}", e);
+            }
+            announce_presence(bus, server, user_id, true, threads).await;
+        }
+        Command::Disconnected { user_id, threads } => {
+            let removed =
+                sqlx::query("DELETE FROM ws_presence WHERE instance_id = $1 AND user_id = $2")
+                    .bind(&bus.instance_id)
+                    .bind(user_id)
+                    .execute(&bus.db)
+                    .await;
+            if let Err(e) = removed {
+                error!("Database error removing WebSocket presence: {:?}", e);
+            }
+            announce_presence(bus, server, user_id, false, threads).await;
+        }
+        // Handled by the worker loop
+        Command::Shutdown(_) => {}
+    }
+}
+
+/// Tell every replica the user came online or went offline, unless another
+/// replica still (or already) has a session for them
+async fn announce_presence(
+    bus: &WsBus,
+    server: &WsServerActor,
+    user_id: i32,
+    is_online: bool,
+    threads: Vec<i32>,
+) {
+    match bus.online_elsewhere(user_id).await {
+        Ok(true) => return,
+        Ok(false) => {}
+        Err(e) => error!("Database error checking WebSocket presence: {:?}", e),
+    }
+
+    server.deliver_presence(user_id, is_online, &threads);
+    bus.notify(ClusterEvent::Presence {
+        user_id,
+        is_online,
+        threads,
+    })
+    .await;
+}
+
+/// Keep this replica's row fresh and forget replicas that stopped. If this
+/// replica was itself given up for dead, its presence rows are restored.
+async fn send_heartbeat(bus: &WsBus, server: &WsServerActor) -> Result<(), sqlx::Error> {
+    let inserted = sqlx::query_scalar::<_, bool>(
+        "INSERT INTO ws_instances (id) VALUES ($1)
+         ON CONFLICT (id) DO UPDATE SET last_seen_at = NOW()
+         RETURNING xmax = 0",
+    )
+    .bind(&bus.instance_id)
+    .fetch_one(&bus.db)
+    .await?;
+
+    if inserted {
+        sqlx::query(
+            "INSERT INTO ws_presence (instance_id, user_id)
+             SELECT $1, UNNEST($2::int[])
+             ON CONFLICT DO NOTHING",
+        )
+        .bind(&bus.instance_id)
+        .bind(server.local_users())
+        .execute(&bus.db)
+        .await?;
+    }
+
+    let stale = sqlx::query(
+        "DELETE FROM ws_instances WHERE last_seen_at < NOW() - make_interval(secs => $1)",
+    )
+    .bind(INSTANCE_TIMEOUT_SECS)
+    .execute(&bus.db)
+    .await?;
+    if stale.rows_affected() > 0 {
+        info!(
+            "Removed {} stale WebSocket instance(s)",
+            stale.rows_affected()
+        );
+    }
+    Ok(())
+}
+
+/// Deliver other replicas' events to the sessions on this one. Runs apart
+/// from the publisher because a `PgListener` must not be cancelled mid-read.
+async fn listen(bus: WsBus, server: WsServerActor) {
+    loop {
+        let mut listener = match PgListener::connect_with(&bus.db).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Failed to connect WebSocket bus listener: {:?}", e);
+                actix_web::rt::time::sleep(Duration::from_secs(RETRY_DELAY_SECS)).await;
+                continue;
+            }
+        };
+        if let Err(e) = listener.listen(CHANNEL).await {
+            error!("Failed to listen on {}: {:?}", CHANNEL, e);
+            actix_web::rt::time::sleep(Duration::from_secs(RETRY_DELAY_SECS)).await;
+            continue;
+        }
+
+        // `recv` reconnects by itself; events sent meanwhile are lost, which
+        // clients recover from with `resume`
+        loop {
+            match listener.recv().await {
+                Ok(notification) => deliver(&bus, &server, notification.payload()).await,
+                Err(e) => {
+                    error!("WebSocket bus listener failed: {:?}", e);
+                    break;
+                }
+            }
+        }
+        actix_web::rt::time::sleep(Duration::from_secs(RETRY_DELAY_SECS)).await;
+    }
+}
+
+async fn deliver(bus: &WsBus, server: &WsServerActor, payload: &str) {
+    let envelope = match serde_json::from_str::<Envelope>(payload) {
+        Ok(envelope) => envelope,
+        Err(e) => {
+            warn!("Ignoring malformed WebSocket bus event: {}", e);
+            return;
+        }
+    };
+    if envelope.origin == bus.instance_id {
+        return;
+    }
+    debug!(
+        "[ws] bus event from {}: {:?}",
+        envelope.origin, envelope.event
+    );
+
+    match envelope.event {
+        ClusterEvent::Thread {
+            thread_id,
+            seq,
+            message,
+        } => {
+            if let Some(message) = resolve(bus, seq, message).await {
+                server.fan_out_to_thread(thread_id, &message);
+            }
+        }
+        ClusterEvent::Post {
+            post_id,
+            seq,
+            message,
+        } => {
+            if let Some(message) = resolve(bus, seq, message).await {
+                server.fan_out_to_post(post_id, &message);
+            }
+        }
+        ClusterEvent::User { user_id, id } => {
+            if let Some(message) = load_user_event(bus, id).await {
+                server.deliver_to_user(user_id, &message);
+            }
+        }
+        ClusterEvent::Presence {
+            user_id,
+            is_online,
+            threads,
+        } => server.deliver_presence(user_id, is_online, &threads),
+    }
+}
+
+/// The message an event stands for, read from the event log when it was sent
+/// by sequence number
+async fn resolve(
+    bus: &WsBus,
+    seq: Option<i64>,
+    message: Option<ServerFrame>,
+) -> Option<ServerFrame> {
+    if let Some(message) = message {
+        return Some(message);
+    }
+    let seq = seq?;
+    let logged =
+        sqlx::query_scalar::<_, serde_json::Value>("SELECT message FROM ws_events WHERE id = $1")
+            .bind(seq)
+            .fetch_optional(&bus.db)
+            .await;
+
+    match logged {
+        Ok(Some(value)) => match serde_json::from_value::<ServerMessage>(value) {
+            Ok(message) => Some(ServerFrame {
+                seq: Some(seq),
+                message,
+            }),
+            Err(e) => {
+                error!("Failed to parse logged event {}: {}", seq, e);
+                None
+            }
+        },
+        Ok(None) => None,
+        Err(e) => {
+            error!("Database error loading event {}: {:?}", seq, e);
+            None
+        }
+    }
+}
+
+/// A frame stored for a user's sessions by another replica
+async fn load_user_event(bus: &WsBus, id: i64) -> Option<ServerFrame> {
+    let stored = sqlx::query_scalar::<_, sqlx::types::Json<ServerFrame>>(
+        "SELECT message FROM ws_user_events WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_optional(&bus.db)
+    .await;
+
+    match stored {
+        Ok(Some(message)) => Some(message.0),
+        Ok(None) => None,
+        Err(e) => {
+            error!("Database error loading WebSocket user event {}: {:?}", id, e);
+            None
+        }
+    }
+}
+
+/// Drop frames for users that the other replicas have had time to deliver
+pub(crate) async fn prune_user_events(db: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "DELETE FROM ws_user_events WHERE created_at < NOW() - make_interval(mins => $1)",
+    )
+    .bind(USER_EVENT_RETENTION_MINUTES)
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,600 @@
+//! The WebSocket wire protocol.
+//!
+//! Version 2 frames are the tagged [`ClientFrame`] and [`ServerFrame`] types;
+//! their JSON schema is generated from these types by [`json_schema`] and
+//! committed as `websocket_protocol.schema.json`. Clients pick the version
+//! when connecting, with `?protocol=2` or the `music-school.v2` subprotocol.
+//! Clients that don't ask get version 1, the original `msg_type` envelope,
+//! which is produced from the same messages so app builds already installed
+//! keep working.
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::chats::ChatAttachmentResponse;
+use crate::feeds::FeedCommentResponse;
+use crate::notifications::Notification;
+
+const SUBPROTOCOL_PREFIX: &str = "music-school.v";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProtocolVersion {
+    /// The original envelope: `msg_type`, `user_id`, `thread_id`, `post_id`, `data`
+    V1,
+    /// Tagged messages as described by the JSON schema
+    V2,
+}
+
+impl ProtocolVersion {
+    pub const LATEST: ProtocolVersion = ProtocolVersion::V2;
+
+    pub fn number(self) -> u32 {
+        match self {
+            ProtocolVersion::V1 => 1,
+            ProtocolVersion::V2 => 2,
+        }
+    }
+
+    fn from_number(number: u32) -> Option<Self> {
+        match number {
+            1 => Some(ProtocolVersion::V1),
+            2 => Some(ProtocolVersion::V2),
+            _ => None,
+        }
+    }
+
+    /// `Sec-WebSocket-Protocol` value for this version
+    pub fn subprotocol(self) -> String {
+        format!("{}{}", SUBPROTOCOL_PREFIX, self.number())
+    }
+}
+
+/// How the client asked for a version, which decides how the choice is confirmed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Negotiated {
+    /// Nothing requested; version 1
+    Default,
+    /// Through the `protocol` query parameter
+    Query(ProtocolVersion),
+    /// Through `Sec-WebSocket-Protocol`; the chosen value must be echoed back
+    Subprotocol(ProtocolVersion),
+}
+
+impl Negotiated {
+    pub fn version(self) -> ProtocolVersion {
+        match self {
+            Negotiated::Default => ProtocolVersion::V1,
+            Negotiated::Query(version) | Negotiated::Subprotocol(version) => version,
+        }
+    }
+}
+
+/// Pick the protocol version from the `protocol` query parameter or the
+/// offered subprotocols (comma separated). The highest supported version
+/// offered wins; offering only unsupported versions is an error.
+pub fn negotiate(query: Option<&str>, subprotocols: Option<&str>) -> Result<Negotiated, String> {
+    if let Some(query) = query {
+        return query
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .and_then(ProtocolVersion::from_number)
+            .map(Negotiated::Query)
+            .ok_or_else(|| unsupported_version(query));
+    }
+
+    let Some(subprotocols) = subprotocols else {
+        return Ok(Negotiated::Default);
+    };
+    let offered: Vec<&str> = subprotocols
+        .split(',')
+        .map(str::trim)
+        .filter(|protocol| protocol.starts_with(SUBPROTOCOL_PREFIX))
+        .collect();
+    if offered.is_empty() {
+        return Ok(Negotiated::Default);
+    }
+    offered
+        .iter()
+        .filter_map(|protocol| protocol[SUBPROTOCOL_PREFIX.len()..].parse::<u32>().ok())
+        .filter_map(ProtocolVersion::from_number)
+        .max()
+        .map(Negotiated::Subprotocol)
+        .ok_or_else(|| unsupported_version(subprotocols))
+}
+
+fn unsupported_version(requested: &str) -> String {
+    format!(
+        "Unsupported WebSocket protocol version {}; supported versions are 1 and {}",
+        requested,
+        ProtocolVersion::LATEST.number()
+    )
+}
+
+/// A thread or post to follow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Topic {
+    Thread { thread_id: i32 },
+    Post { post_id: i32 },
+}
+
+/// A message from the client. `request_id` is echoed in the `ack` or `error`
+/// answering it.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ClientFrame {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(flatten)]
+    pub message: ClientMessage,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// Receive the events of a chat thread or feed post
+    Subscribe {
+        #[serde(flatten)]
+        topic: Topic,
+    },
+    Unsubscribe {
+        #[serde(flatten)]
+        topic: Topic,
+    },
+    /// Tell the other participants of a thread whether the user is typing
+    Typing { thread_id: i32, is_typing: bool },
+    /// Replay the events after `last_seq` on the subscribed threads and
+    /// posts; without `last_seq` only the current position is reported
+    Resume {
+        #[serde(default)]
+        last_seq: Option<i64>,
+    },
+}
+
+impl ClientMessage {
+    /// The `type` tag, as echoed in `ack` and `error`
+    pub fn name(&self) -> &'static str {
+        match self {
+            ClientMessage::Subscribe { .. } => "subscribe",
+            ClientMessage::Unsubscribe { .. } => "unsubscribe",
+            ClientMessage::Typing { .. } => "typing",
+            ClientMessage::Resume { .. } => "resume",
+        }
+    }
+}
+
+const CLIENT_MESSAGE_TYPES: [&str; 4] = ["subscribe", "unsubscribe", "typing", "resume"];
+
+/// A message from the server. `seq` is set on chat and comment events, which
+/// can be replayed with `resume`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ServerFrame {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seq: Option<i64>,
+    #[serde(flatten)]
+    pub message: ServerMessage,
+}
+
+impl From<ServerMessage> for ServerFrame {
+    fn from(message: ServerMessage) -> Self {
+        ServerFrame { seq: None, message }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The frame is not valid JSON or doesn't match the schema
+    InvalidMessage,
+    /// A field is missing or out of range
+    InvalidRequest,
+    /// Unknown message type
+    Unsupported,
+    /// The user may not read the thread or post
+    Forbidden,
+    ServerError,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationChange {
+    Read,
+    Archived,
+    Snoozed,
+    Deleted,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ChatMessageEvent {
+    pub message_id: i32,
+    pub thread_id: i32,
+    pub sender_id: i32,
+    pub sender_name: String,
+    /// Quill JSON, as in the chat REST API
+    pub body: serde_json::Value,
+    pub attachments: Vec<ChatAttachmentResponse>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// First frame of a version 2 connection
+    Hello { protocol: u32, session_id: u64 },
+    /// A client request succeeded
+    Ack {
+        #[serde(default)]
+        request_id: Option<String>,
+        request: String,
+    },
+    /// A client request was refused or could not be read
+    Error {
+        #[serde(default)]
+        request_id: Option<String>,
+        request: String,
+        code: ErrorCode,
+        message: String,
+    },
+    ChatMessage(Box<ChatMessageEvent>),
+    ChatMessageUpdated(Box<ChatMessageEvent>),
+    ChatMessageDeleted {
+        thread_id: i32,
+        message_id: i32,
+        deleted_by: i32,
+    },
+    /// A participant received or read a message
+    Receipt {
+        thread_id: i32,
+        message_id: i32,
+        recipient_id: i32,
+        state: String,
+    },
+    /// A comment was added to or edited on a post
+    Comment {
+        /// Who added or edited it
+        user_id: i32,
+        comment: Box<FeedCommentResponse>,
+    },
+    Typing {
+        thread_id: i32,
+        user_id: i32,
+        is_typing: bool,
+    },
+    /// A participant of a subscribed thread came online or went offline
+    Presence { user_id: i32, is_online: bool },
+    Notification {
+        notification: Box<Notification>,
+        unread_count: i64,
+    },
+    /// Notifications were read, archived, snoozed or deleted on another device
+    NotificationsChanged {
+        user_id: i32,
+        change: NotificationChange,
+        notification_ids: Vec<i32>,
+        unread_count: i64,
+    },
+    /// Answer to `resume`: the position to resume from next time, and
+    /// whether every missed event could still be replayed
+    Resumed {
+        last_seq: i64,
+        replayed: i64,
+        complete: bool,
+    },
+}
+
+impl ServerMessage {
+    /// The `type` tag
+    pub fn name(&self) -> &'static str {
+        match self {
+            ServerMessage::Hello { .. } => "hello",
+            ServerMessage::Ack { .. } => "ack",
+            ServerMessage::Error { .. } => "error",
+            ServerMessage::ChatMessage(_) => "chat_message",
+            ServerMessage::ChatMessageUpdated(_) => "chat_message_updated",
+            ServerMessage::ChatMessageDeleted { .. } => "chat_message_deleted",
+            ServerMessage::Receipt { .. } => "receipt",
+            ServerMessage::Comment { .. } => "comment",
+            ServerMessage::Typing { .. } => "typing",
+            ServerMessage::Presence { .. } => "presence",
+            ServerMessage::Notification { .. } => "notification",
+            ServerMessage::NotificationsChanged { .. } => "notifications_changed",
+            ServerMessage::Resumed { .. } => "resumed",
+        }
+    }
+
+    pub fn error(request_id: Option<String>, request: &str, code: ErrorCode, message: &str) -> Self {
+        ServerMessage::Error {
+            request_id,
+            request: request.to_string(),
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Schema of both directions of protocol version 2
+pub fn json_schema() -> serde_json::Value {
+    let mut generator = schemars::gen::SchemaSettings::draft07().into_generator();
+    let client = generator.subschema_for::<ClientFrame>();
+    let server = generator.subschema_for::<ServerFrame>();
+    serde_json::json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Music school WebSocket protocol, version 2",
+        "description": "Frames are JSON text messages. The client sends ClientFrame and receives ServerFrame.",
+        "oneOf": [client, server],
+        "definitions": generator.definitions(),
+    })
+}
+
+/// Read a text frame from a client speaking `version`. On failure the error
+/// frame to send back is returned.
+pub fn decode(text: &str, version: ProtocolVersion) -> Result<ClientFrame, ServerMessage> {
+    match version {
+        ProtocolVersion::V1 => decode_v1(text),
+        ProtocolVersion::V2 => {
+            serde_json::from_str::<ClientFrame>(text).map_err(|err| {
+                let value = serde_json::from_str::<serde_json::Value>(text).ok();
+                let request_id = value
+                    .as_ref()
+                    .and_then(|value| value["request_id"].as_str())
+                    .map(str::to_string);
+                let request = value
+                    .as_ref()
+                    .and_then(|value| value["type"].as_str())
+                    .unwrap_or("unknown")
+                    .to_string();
+                if value.is_some() && !CLIENT_MESSAGE_TYPES.contains(&request.as_str()) {
+                    ServerMessage::error(
+                        request_id,
+                        &request,
+                        ErrorCode::Unsupported,
+                        "Unsupported message type",
+                    )
+                } else {
+                    ServerMessage::error(
+                        request_id,
+                        &request,
+                        ErrorCode::InvalidMessage,
+                        &err.to_string(),
+                    )
+                }
+            })
+        }
+    }
+}
+
+/// Serialize a frame for a client speaking `version`; `None` when the message
+/// doesn't exist in that version
+pub fn encode(frame: &ServerFrame, version: ProtocolVersion) -> Option<String> {
+    let encoded = match version {
+        ProtocolVersion::V1 => serde_json::to_string(&LegacyMessage::from_frame(frame)?),
+        ProtocolVersion::V2 => serde_json::to_string(frame),
+    };
+    encoded.ok()
+}
+
+/// The version 1 envelope
+#[derive(Debug, Serialize, Deserialize)]
+struct LegacyMessage {
+    msg_type: String,
+    #[serde(default)]
+    user_id: Option<i32>,
+    #[serde(default)]
+    thread_id: Option<i32>,
+    #[serde(default)]
+    post_id: Option<i32>,
+    #[serde(default)]
+    data: serde_json::Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seq: Option<i64>,
+}
+
+fn to_value<T: Serialize>(value: &T) -> serde_json::Value {
+    serde_json::to_value(value).unwrap_or_default()
+}
+
+fn decode_v1(text: &str) -> Result<ClientFrame, ServerMessage> {
+    let legacy = serde_json::from_str::<LegacyMessage>(text).map_err(|_| {
+        ServerMessage::error(None, "unknown", ErrorCode::InvalidMessage, "Malformed message")
+    })?;
+    let request = legacy.msg_type.as_str();
+    let missing = |field: &str| {
+        ServerMessage::error(
+            None,
+            request,
+            ErrorCode::InvalidRequest,
+            &format!("{} is required", field),
+        )
+    };
+
+    let message = match request {
+        "subscribe_thread" | "unsubscribe_thread" => {
+            let topic = Topic::Thread {
+                thread_id: legacy.thread_id.ok_or_else(|| missing("thread_id"))?,
+            };
+            if request == "subscribe_thread" {
+                ClientMessage::Subscribe { topic }
+            } else {
+                ClientMessage::Unsubscribe { topic }
+            }
+        }
+        "subscribe_post" | "unsubscribe_post" => {
+            let topic = Topic::Post {
+                post_id: legacy.post_id.ok_or_else(|| missing("post_id"))?,
+            };
+            if request == "subscribe_post" {
+                ClientMessage::Subscribe { topic }
+            } else {
+                ClientMessage::Unsubscribe { topic }
+            }
+        }
+        "typing" => ClientMessage::Typing {
+            thread_id: legacy.thread_id.ok_or_else(|| missing("thread_id"))?,
+            is_typing: legacy.data["is_typing"].as_bool().unwrap_or(false),
+        },
+        "resume" => {
+            let last_seq = match legacy.data.get("last_seq") {
+                None | Some(serde_json::Value::Null) => None,
+                Some(value) => Some(value.as_i64().ok_or_else(|| {
+                    ServerMessage::error(
+                        None,
+                        request,
+                        ErrorCode::InvalidRequest,
+                        "last_seq must be a non-negative integer",
+                    )
+                })?),
+            };
+            ClientMessage::Resume { last_seq }
+        }
+        _ => {
+            return Err(ServerMessage::error(
+                None,
+                request,
+                ErrorCode::Unsupported,
+                "Unsupported message type",
+            ))
+        }
+    };
+    Ok(ClientFrame {
+        request_id: None,
+        message,
+    })
+}
+
+impl LegacyMessage {
+    fn new(msg_type: &str, data: serde_json::Value) -> Self {
+        LegacyMessage {
+            msg_type: msg_type.to_string(),
+            user_id: None,
+            thread_id: None,
+            post_id: None,
+            data,
+            seq: None,
+        }
+    }
+
+    fn user(mut self, user_id: i32) -> Self {
+        self.user_id = Some(user_id);
+        self
+    }
+
+    fn thread(mut self, thread_id: i32) -> Self {
+        self.thread_id = Some(thread_id);
+        self
+    }
+
+    fn post(mut self, post_id: i32) -> Self {
+        self.post_id = Some(post_id);
+        self
+    }
+
+    /// The version 1 form of a frame. Successful requests were not answered
+    /// in version 1, and there was no greeting.
+    fn from_frame(frame: &ServerFrame) -> Option<Self> {
+        let legacy = match &frame.message {
+            ServerMessage::Hello { .. } | ServerMessage::Ack { .. } => return None,
+            ServerMessage::Error {
+                request,
+                code,
+                message,
+                ..
+            } => LegacyMessage::new(
+                "error",
+                serde_json::json!({ "request": request, "code": code, "error": message }),
+            ),
+            ServerMessage::ChatMessage(event) => LegacyMessage::new("chat_message", to_value(event))
+                .user(event.sender_id)
+                .thread(event.thread_id),
+            ServerMessage::ChatMessageUpdated(event) => {
+                LegacyMessage::new("chat_message_updated", to_value(event))
+                    .user(event.sender_id)
+                    .thread(event.thread_id)
+            }
+            ServerMessage::ChatMessageDeleted {
+                thread_id,
+                message_id,
+                deleted_by,
+            } => LegacyMessage::new(
+                "chat_message_deleted",
+                serde_json::json!({ "message_id": message_id }),
+            )
+            .user(*deleted_by)
+            .thread(*thread_id),
+            ServerMessage::Receipt {
+                thread_id,
+                message_id,
+                recipient_id,
+                state,
+            } => LegacyMessage::new(
+                "receipt",
+                serde_json::json!({
+                    "message_id": message_id,
+                    "recipient_id": recipient_id,
+                    "state": state,
+                }),
+            )
+            .user(*recipient_id)
+            .thread(*thread_id),
+            ServerMessage::Comment { user_id, comment } => {
+                LegacyMessage::new("comment", to_value(comment))
+                    .user(*user_id)
+                    .post(comment.post_id)
+            }
+            ServerMessage::Typing {
+                thread_id,
+                user_id,
+                is_typing,
+            } => LegacyMessage::new("typing", serde_json::json!({ "is_typing": is_typing }))
+                .user(*user_id)
+                .thread(*thread_id),
+            ServerMessage::Presence { user_id, is_online } => {
+                LegacyMessage::new("presence", serde_json::json!({ "is_online": is_online }))
+                    .user(*user_id)
+            }
+            ServerMessage::Notification {
+                notification,
+                unread_count,
+            } => LegacyMessage::new(
+                "notification",
+                serde_json::json!({ "notification": notification, "unread_count": unread_count }),
+            )
+            .user(notification.user_id),
+            ServerMessage::NotificationsChanged {
+                user_id,
+                change,
+                notification_ids,
+                unread_count,
+            } => LegacyMessage::new(
+                match change {
+                    NotificationChange::Read => "notification_read",
+                    NotificationChange::Archived => "notification_archived",
+                    NotificationChange::Snoozed => "notification_snoozed",
+                    NotificationChange::Deleted => "notification_deleted",
+                },
+                serde_json::json!({
+                    "notification_ids": notification_ids,
+                    "unread_count": unread_count,
+                }),
+            )
+            .user(*user_id),
+            ServerMessage::Resumed {
+                last_seq,
+                replayed,
+                complete,
+            } => LegacyMessage::new(
+                "resumed",
+                serde_json::json!({
+                    "last_seq": last_seq,
+                    "replayed": replayed,
+                    "complete": complete,
+                }),
+            ),
+        };
+        Some(LegacyMessage {
+            seq: frame.seq,
+            ..legacy
+        })
+    }
+}
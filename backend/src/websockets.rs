@@ -1,28 +1,147 @@
 use actix::{
-    Actor, ActorContext, AsyncContext, Handler, Message, Recipient, StreamHandler,
+    Actor, ActorContext, ActorFutureExt, AsyncContext, Handler, Message, Recipient, StreamHandler,
+    WrapFuture,
 };
-use log::debug;
+use log::{debug, error, info};
 use actix_web_actors::ws::{self, WebsocketContext};
-use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
 use std::collections::{HashMap, HashSet};
-use std::sync::Arc;
-use tokio::sync::RwLock;
-
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct WsMessage {
-    pub msg_type: String,      // "chat_message", "comment", "typing", "presence", etc.
-    pub user_id: Option<i32>,
-    pub thread_id: Option<i32>, // For chat
-    pub post_id: Option<i32>,   // For comments
-    pub data: serde_json::Value,
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+
+use crate::websocket_bus::{self, WsBus};
+use crate::websocket_protocol::{
+    self, ClientFrame, ClientMessage, ErrorCode, ProtocolVersion, ServerFrame, ServerMessage,
+    Topic,
+};
+use crate::{chats, feeds};
+
+/// How often the server pings each client
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// Clients that send nothing, not even a pong, for this long are dropped
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(45);
+/// Most events one `resume` replays; a client further behind reloads over REST
+const MAX_REPLAY: usize = 500;
+/// How long chat and comment events stay replayable
+const EVENT_RETENTION_HOURS: i64 = 24;
+const PRUNE_INTERVAL_SECS: u64 = 60 * 60;
+
+/// Identifies one connection; a user has one per open device or tab
+pub type SessionId = u64;
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+pub fn next_session_id() -> SessionId {
+    NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+struct Connection {
+    user_id: i32,
+    recipient: Recipient<WsNotification>,
+}
+
+/// Live sessions and their subscriptions. Everything is kept per session so a
+/// device disconnecting leaves the user's other sessions untouched.
+#[derive(Default)]
+struct Registry {
+    connections: HashMap<SessionId, Connection>,
+    user_sessions: HashMap<i32, HashSet<SessionId>>, // user_id -> live sessions
+    session_threads: HashMap<SessionId, HashSet<i32>>,
+    thread_watchers: HashMap<i32, HashSet<SessionId>>,
+    session_posts: HashMap<SessionId, HashSet<i32>>,
+    post_watchers: HashMap<i32, HashSet<SessionId>>,
+}
+
+impl Registry {
+    fn send(&self, sessions: impl IntoIterator<Item = SessionId>, message: &ServerFrame) {
+        for session_id in sessions {
+            if let Some(connection) = self.connections.get(&session_id) {
+                connection.recipient.do_send(WsNotification(message.clone()));
+            }
+        }
+    }
+
+    /// Remove the session from every thread and post it follows and return
+    /// the threads
+    fn remove_subscriptions(&mut self, session_id: SessionId) -> HashSet<i32> {
+        let threads = self.session_threads.remove(&session_id).unwrap_or_default();
+        for thread_id in &threads {
+            if let Some(watchers) = self.thread_watchers.get_mut(thread_id) {
+                watchers.remove(&session_id);
+                if watchers.is_empty() {
+                    self.thread_watchers.remove(thread_id);
+                }
+            }
+        }
+        for post_id in self.session_posts.remove(&session_id).unwrap_or_default() {
+            if let Some(watchers) = self.post_watchers.get_mut(&post_id) {
+                watchers.remove(&session_id);
+                if watchers.is_empty() {
+                    self.post_watchers.remove(&post_id);
+                }
+            }
+        }
+        threads
+    }
+
+    fn unwatch_thread(&mut self, session_id: SessionId, thread_id: i32) {
+        if let Some(threads) = self.session_threads.get_mut(&session_id) {
+            threads.remove(&thread_id);
+        }
+        if let Some(watchers) = self.thread_watchers.get_mut(&thread_id) {
+            watchers.remove(&session_id);
+            if watchers.is_empty() {
+                self.thread_watchers.remove(&thread_id);
+            }
+        }
+    }
+
+    fn unwatch_post(&mut self, session_id: SessionId, post_id: i32) {
+        if let Some(posts) = self.session_posts.get_mut(&session_id) {
+            posts.remove(&post_id);
+        }
+        if let Some(watchers) = self.post_watchers.get_mut(&post_id) {
+            watchers.remove(&session_id);
+            if watchers.is_empty() {
+                self.post_watchers.remove(&post_id);
+            }
+        }
+    }
+
+    /// Threads and posts the session follows
+    fn session_subscriptions(&self, session_id: SessionId) -> (Vec<i32>, Vec<i32>) {
+        let threads = self.session_threads.get(&session_id);
+        let posts = self.session_posts.get(&session_id);
+        (
+            threads.into_iter().flatten().copied().collect(),
+            posts.into_iter().flatten().copied().collect(),
+        )
+    }
+
+    fn user_session_ids(&self, user_id: i32) -> impl Iterator<Item = SessionId> + '_ {
+        self.user_sessions.get(&user_id).into_iter().flatten().copied()
+    }
+
+    /// Threads watched from any of the user's sessions
+    fn user_threads(&self, user_id: i32) -> HashSet<i32> {
+        self.user_session_ids(user_id)
+            .filter_map(|session_id| self.session_threads.get(&session_id))
+            .flatten()
+            .copied()
+            .collect()
+    }
 }
 
 #[derive(Clone)]
 pub struct WsServerActor {
-    pub connections: Arc<RwLock<HashMap<i32, Recipient<WsNotification>>>>,
-    pub user_threads: Arc<RwLock<HashMap<i32, HashSet<i32>>>>, // user_id -> set of thread_ids
-    pub thread_watchers: Arc<RwLock<HashMap<i32, HashSet<i32>>>>, // thread_id -> set of user_ids
-    pub post_watchers: Arc<RwLock<HashMap<i32, HashSet<i32>>>>, // post_id -> set of user_ids
+    registry: Arc<RwLock<Registry>>,
+    /// Where thread and post events are recorded for replay; without it
+    /// events are only delivered live
+    event_log: Option<PgPool>,
+    /// Relays events to the other backend replicas; without it everything
+    /// stays on this instance
+    bus: Option<WsBus>,
 }
 
 impl Default for WsServerActor {
@@ -34,165 +153,608 @@ impl Default for WsServerActor {
 impl WsServerActor {
     pub fn new() -> Self {
         WsServerActor {
-            connections: Arc::new(RwLock::new(HashMap::new())),
-            user_threads: Arc::new(RwLock::new(HashMap::new())),
-            thread_watchers: Arc::new(RwLock::new(HashMap::new())),
-            post_watchers: Arc::new(RwLock::new(HashMap::new())),
+            registry: Arc::new(RwLock::new(Registry::default())),
+            event_log: None,
+            bus: None,
+        }
+    }
+
+    /// A server that records thread and post events in `ws_events`
+    pub fn with_event_log(db: PgPool) -> Self {
+        WsServerActor {
+            event_log: Some(db),
+            ..Self::new()
         }
     }
 
-    pub async fn register_connection(
+    /// Share broadcasts and presence with the other replicas through `bus`
+    pub fn with_bus(self, bus: WsBus) -> Self {
+        WsServerActor {
+            bus: Some(bus),
+            ..self
+        }
+    }
+
+    // The lock is never held across an await, so a std lock does
+    fn read(&self) -> RwLockReadGuard<'_, Registry> {
+        self.registry.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Registry> {
+        self.registry.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Add a session. The user's first session announces them as online,
+    /// unless they already are on another replica.
+    /// Synchronous so a session is registered before it handles any message.
+    pub fn register_connection(
         &self,
+        session_id: SessionId,
         user_id: i32,
         recipient: Recipient<WsNotification>,
     ) {
-        let mut connections = self.connections.write().await;
-        connections.insert(user_id, recipient);
-        debug!("[ws] user {} connected", user_id);
-    }
-
-    pub async fn unregister_connection(&self, user_id: i32) {
-        let mut connections = self.connections.write().await;
-        connections.remove(&user_id);
-        debug!("[ws] user {} disconnected", user_id);
-
-        let mut user_threads = self.user_threads.write().await;
-        if let Some(threads) = user_threads.remove(&user_id) {
-            let mut thread_watchers = self.thread_watchers.write().await;
-            for thread_id in threads {
-                if let Some(watchers) = thread_watchers.get_mut(&thread_id) {
-                    watchers.remove(&user_id);
+        let came_online = {
+            let mut registry = self.write();
+            registry
+                .connections
+                .insert(session_id, Connection { user_id, recipient });
+            let sessions = registry.user_sessions.entry(user_id).or_default();
+            sessions.insert(session_id);
+            sessions.len() == 1
+        };
+        debug!("[ws] user {} connected (session {})", user_id, session_id);
+
+        if !came_online {
+            return;
+        }
+        let threads = self.read().user_threads(user_id).into_iter().collect();
+        match &self.bus {
+            Some(bus) => bus.user_connected(user_id, threads),
+            None => self.deliver_presence(user_id, true, &threads),
+        }
+    }
+
+    /// Drop a session and its subscriptions. The user's last session
+    /// announces them as offline to the threads it was watching, unless they
+    /// are still connected to another replica.
+    pub fn unregister_connection(&self, session_id: SessionId) {
+        let (user_id, threads) = {
+            let mut registry = self.write();
+            let Some(connection) = registry.connections.remove(&session_id) else {
+                return;
+            };
+            let user_id = connection.user_id;
+            debug!("[ws] user {} disconnected (session {})", user_id, session_id);
+
+            let threads = registry.remove_subscriptions(session_id);
+            let went_offline = match registry.user_sessions.get_mut(&user_id) {
+                Some(sessions) => {
+                    sessions.remove(&session_id);
+                    sessions.is_empty()
                 }
+                None => true,
+            };
+            if !went_offline {
+                return;
             }
+            registry.user_sessions.remove(&user_id);
+            (user_id, threads.into_iter().collect::<Vec<_>>())
+        };
+
+        match &self.bus {
+            Some(bus) => bus.user_disconnected(user_id, threads),
+            None => self.deliver_presence(user_id, false, &threads),
         }
     }
 
-    pub async fn subscribe_to_thread(&self, user_id: i32, thread_id: i32) {
-        // Add user to thread watchers
-        let mut thread_watchers = self.thread_watchers.write().await;
-        thread_watchers
+    pub async fn subscribe_to_thread(&self, session_id: SessionId, thread_id: i32) {
+        let mut registry = self.write();
+        if !registry.connections.contains_key(&session_id) {
+            return;
+        }
+        registry
+            .thread_watchers
             .entry(thread_id)
-            .or_insert_with(HashSet::new)
-            .insert(user_id);
-
-        // Track user's threads
-        let mut user_threads = self.user_threads.write().await;
-        user_threads
-            .entry(user_id)
-            .or_insert_with(HashSet::new)
+            .or_default()
+            .insert(session_id);
+        registry
+            .session_threads
+            .entry(session_id)
+            .or_default()
             .insert(thread_id);
-        debug!("[ws] user {} subscribed to thread {}", user_id, thread_id);
+        debug!("[ws] session {} subscribed to thread {}", session_id, thread_id);
     }
 
+    pub async fn unsubscribe_from_thread(&self, session_id: SessionId, thread_id: i32) {
+        self.write().unwatch_thread(session_id, thread_id);
+        debug!("[ws] session {} unsubscribed from thread {}", session_id, thread_id);
+    }
+
+    /// Whether any of the user's sessions follows the thread
     pub async fn is_user_watching_thread(&self, user_id: i32, thread_id: i32) -> bool {
-        let thread_watchers = self.thread_watchers.read().await;
-        thread_watchers
-            .get(&thread_id)
-            .map(|watchers| watchers.contains(&user_id))
-            .unwrap_or(false)
+        let registry = self.read();
+        registry.thread_watchers.get(&thread_id).is_some_and(|watchers| {
+            registry
+                .user_session_ids(user_id)
+                .any(|session_id| watchers.contains(&session_id))
+        })
     }
 
-    pub async fn subscribe_to_post(&self, user_id: i32, post_id: i32) {
-        let mut post_watchers = self.post_watchers.write().await;
-        post_watchers
+    pub async fn subscribe_to_post(&self, session_id: SessionId, post_id: i32) {
+        let mut registry = self.write();
+        if !registry.connections.contains_key(&session_id) {
+            return;
+        }
+        registry
+            .post_watchers
             .entry(post_id)
-            .or_insert_with(HashSet::new)
-            .insert(user_id);
-        debug!("[ws] user {} subscribed to post {}", user_id, post_id);
+            .or_default()
+            .insert(session_id);
+        registry
+            .session_posts
+            .entry(session_id)
+            .or_default()
+            .insert(post_id);
+        debug!("[ws] session {} subscribed to post {}", session_id, post_id);
     }
 
-    pub async fn broadcast_to_thread(&self, thread_id: i32, message: WsMessage) {
-        let thread_watchers = self.thread_watchers.read().await;
-        if let Some(watchers) = thread_watchers.get(&thread_id) {
+    pub async fn unsubscribe_from_post(&self, session_id: SessionId, post_id: i32) {
+        self.write().unwatch_post(session_id, post_id);
+        debug!("[ws] session {} unsubscribed from post {}", session_id, post_id);
+    }
+
+    /// Record the event for replay and send it to the thread's watchers
+    pub async fn broadcast_to_thread(&self, thread_id: i32, message: ServerMessage) {
+        let frame = ServerFrame {
+            seq: self.record_event(Some(thread_id), None, &message).await,
+            message,
+        };
+        self.fan_out_to_thread(thread_id, &frame);
+        if let Some(bus) = &self.bus {
+            bus.publish_thread(thread_id, &frame);
+        }
+    }
+
+    /// Send to the thread's watchers on this replica
+    pub(crate) fn fan_out_to_thread(&self, thread_id: i32, message: &ServerFrame) {
+        let registry = self.read();
+        if let Some(watchers) = registry.thread_watchers.get(&thread_id) {
             debug!(
-                "[ws] broadcast {} to thread {} ({} watchers)",
-                message.msg_type,
+                "[ws] broadcast {} to thread {} ({} sessions)",
+                message.message.name(),
                 thread_id,
                 watchers.len()
             );
-            let connections = self.connections.read().await;
-            for user_id in watchers {
-                if let Some(recipient) = connections.get(user_id) {
-                    let _ = recipient.do_send(WsNotification(message.clone()));
-                }
-            }
+            registry.send(watchers.iter().copied(), message);
         }
     }
 
-    pub async fn broadcast_to_post(&self, post_id: i32, message: WsMessage) {
-        let post_watchers = self.post_watchers.read().await;
-        if let Some(watchers) = post_watchers.get(&post_id) {
-            let connections = self.connections.read().await;
-            for user_id in watchers {
-                if let Some(recipient) = connections.get(user_id) {
-                    let _ = recipient.do_send(WsNotification(message.clone()));
-                }
+    /// Record the event for replay and send it to the post's watchers
+    pub async fn broadcast_to_post(&self, post_id: i32, message: ServerMessage) {
+        let frame = ServerFrame {
+            seq: self.record_event(None, Some(post_id), &message).await,
+            message,
+        };
+        self.fan_out_to_post(post_id, &frame);
+        if let Some(bus) = &self.bus {
+            bus.publish_post(post_id, &frame);
+        }
+    }
+
+    /// Send to the post's watchers on this replica
+    pub(crate) fn fan_out_to_post(&self, post_id: i32, message: &ServerFrame) {
+        let registry = self.read();
+        if let Some(watchers) = registry.post_watchers.get(&post_id) {
+            registry.send(watchers.iter().copied(), message);
+        }
+    }
+
+    /// Append an event to the log and return its sequence number
+    async fn record_event(
+        &self,
+        thread_id: Option<i32>,
+        post_id: Option<i32>,
+        message: &ServerMessage,
+    ) -> Option<i64> {
+        let db = self.event_log.as_ref()?;
+        let result = sqlx::query_scalar::<_, i64>(
+            "INSERT INTO ws_events (thread_id, post_id, message) VALUES ($1, $2, $3) RETURNING id",
+        )
+        .bind(thread_id)
+        .bind(post_id)
+        .bind(serde_json::to_value(message).unwrap_or_default())
+        .fetch_one(db)
+        .await;
+
+        match result {
+            Ok(seq) => Some(seq),
+            Err(e) => {
+                // Still deliver live; the event just can't be replayed
+                error!("[ws] Failed to record {} event: {:?}", message.name(), e);
+                None
             }
         }
     }
 
-    pub async fn broadcast_typing(&self, thread_id: i32, user_id: i32, is_typing: bool) {
-        let message = WsMessage {
-            msg_type: "typing".to_string(),
-            user_id: Some(user_id),
-            thread_id: Some(thread_id),
-            post_id: None,
-            data: serde_json::json!({ "is_typing": is_typing }),
-        };
-        self.broadcast_to_thread(thread_id, message).await;
+    fn send_to_session(&self, session_id: SessionId, message: &ServerFrame) {
+        self.read().send([session_id], message);
     }
 
-    pub async fn send_presence(&self, user_id: i32, is_online: bool) {
-        let message = WsMessage {
-            msg_type: "presence".to_string(),
-            user_id: Some(user_id),
-            thread_id: None,
-            post_id: None,
-            data: serde_json::json!({ "is_online": is_online }),
+    /// Whether the user has at least one open session on any replica
+    pub async fn is_user_online(&self, user_id: i32) -> bool {
+        !self.connected_users(&[user_id]).await.is_empty()
+    }
+
+    /// The subset of `user_ids` with an open session on any replica
+    pub async fn connected_users(&self, user_ids: &[i32]) -> Vec<i32> {
+        let mut connected: HashSet<i32> = {
+            let registry = self.read();
+            user_ids
+                .iter()
+                .copied()
+                .filter(|user_id| registry.user_sessions.contains_key(user_id))
+                .collect()
         };
 
-        // Send to all connections this user has subscribed threads
-        let user_threads = self.user_threads.read().await;
-        if let Some(threads) = user_threads.get(&user_id) {
-            for thread_id in threads {
-                self.broadcast_to_thread(*thread_id, message.clone()).await;
+        if let Some(bus) = &self.bus {
+            match bus.online_users(user_ids).await {
+                Ok(remote) => connected.extend(remote),
+                Err(e) => error!("[ws] Database error checking presence: {:?}", e),
             }
         }
+        user_ids
+            .iter()
+            .copied()
+            .filter(|user_id| connected.remove(user_id))
+            .collect()
+    }
+
+    /// Users with an open session on this replica
+    pub(crate) fn local_users(&self) -> Vec<i32> {
+        self.read().user_sessions.keys().copied().collect()
+    }
+
+    /// Send a message to every open session of a user, on every replica
+    pub async fn send_to_user(&self, user_id: i32, message: ServerMessage) {
+        let frame = ServerFrame::from(message);
+        self.deliver_to_user(user_id, &frame);
+        if let Some(bus) = &self.bus {
+            bus.publish_to_user(user_id, &frame);
+        }
+    }
+
+    /// Send to the user's sessions on this replica
+    pub(crate) fn deliver_to_user(&self, user_id: i32, message: &ServerFrame) {
+        let registry = self.read();
+        registry.send(registry.user_session_ids(user_id), message);
+    }
+
+    pub async fn broadcast_typing(&self, thread_id: i32, user_id: i32, is_typing: bool) {
+        let message = ServerFrame::from(ServerMessage::Typing {
+            thread_id,
+            user_id,
+            is_typing,
+        });
+        // Typing is only interesting live, so it is not recorded
+        self.fan_out_to_thread(thread_id, &message);
+        if let Some(bus) = &self.bus {
+            bus.publish_thread(thread_id, &message);
+        }
+    }
+
+    /// Tell the watchers of `threads` on this replica whether the user is online
+    pub(crate) fn deliver_presence(&self, user_id: i32, is_online: bool, threads: &[i32]) {
+        let message = ServerFrame::from(ServerMessage::Presence { user_id, is_online });
+        let registry = self.read();
+        for thread_id in threads {
+            if let Some(watchers) = registry.thread_watchers.get(thread_id) {
+                registry.send(watchers.iter().copied(), &message);
+            }
+        }
+    }
+}
+
+/// Drop events older than the replay window, and frames relayed to users'
+/// sessions on other replicas
+pub async fn prune_events(db: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "DELETE FROM ws_events WHERE created_at < NOW() - make_interval(hours => $1::int)",
+    )
+    .bind(EVENT_RETENTION_HOURS as i32)
+    .execute(db)
+    .await?;
+    let relayed = websocket_bus::prune_user_events(db).await?;
+    Ok(result.rows_affected() + relayed)
+}
+
+pub async fn run_event_pruner(db: PgPool) {
+    let mut interval = actix_web::rt::time::interval(Duration::from_secs(PRUNE_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        match prune_events(&db).await {
+            Ok(0) => {}
+            Ok(deleted) => info!("Pruned {} WebSocket events", deleted),
+            Err(e) => error!("Database error pruning WebSocket events: {:?}", e),
+        }
     }
 }
 
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct WsNotification(pub WsMessage);
+pub struct WsNotification(pub ServerFrame);
 
+#[derive(Clone)]
 pub struct WsSession {
+    pub session_id: SessionId,
     pub user_id: i32,
+    /// Admins may follow any feed post
+    pub is_admin: bool,
+    /// Opened with a read-only impersonation token, which may only listen
+    pub read_only: bool,
+    /// Wire format negotiated when the client connected
+    pub protocol: ProtocolVersion,
+    pub db: PgPool,
     pub server: WsServerActor,
+    last_heartbeat: Instant,
+}
+
+/// Why a client request was refused
+type Refusal = (ErrorCode, &'static str);
+
+impl WsSession {
+    pub fn new(
+        session_id: SessionId,
+        user_id: i32,
+        is_admin: bool,
+        protocol: ProtocolVersion,
+        db: PgPool,
+        server: WsServerActor,
+    ) -> Self {
+        WsSession {
+            session_id,
+            user_id,
+            is_admin,
+            read_only: false,
+            protocol,
+            db,
+            server,
+            last_heartbeat: Instant::now(),
+        }
+    }
+
+    /// Ping the client regularly and drop the connection once it goes quiet,
+    /// so dead mobile connections don't keep their subscriptions
+    fn start_heartbeat(&self, ctx: &mut WebsocketContext<Self>) {
+        ctx.run_interval(HEARTBEAT_INTERVAL, |session, ctx| {
+            if session.last_heartbeat.elapsed() > CLIENT_TIMEOUT {
+                debug!(
+                    "[ws] session {} of user {} timed out",
+                    session.session_id, session.user_id
+                );
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+    }
+
+    /// Send a frame in this session's protocol version; frames the version
+    /// doesn't have are dropped
+    fn send(&self, ctx: &mut WebsocketContext<Self>, frame: &ServerFrame) {
+        if let Some(text) = websocket_protocol::encode(frame, self.protocol) {
+            ctx.text(text);
+        }
+    }
+
+    /// Handle a request sent by this session's client and return the `ack`,
+    /// `error` or `resumed` answering it. Thread and post subscriptions and
+    /// typing indicators are only accepted where the REST API would let the
+    /// user read, and read-only sessions can't send typing indicators.
+    pub async fn handle_client_message(&self, frame: ClientFrame) -> ServerMessage {
+        let request = frame.message.name();
+        let result = match frame.message {
+            ClientMessage::Subscribe { topic } => self.subscribe(topic).await,
+            ClientMessage::Unsubscribe { topic } => {
+                match topic {
+                    Topic::Thread { thread_id } => {
+                        self.server
+                            .unsubscribe_from_thread(self.session_id, thread_id)
+                            .await
+                    }
+                    Topic::Post { post_id } => {
+                        self.server
+                            .unsubscribe_from_post(self.session_id, post_id)
+                            .await
+                    }
+                }
+                Ok(())
+            }
+            ClientMessage::Typing { .. } if self.read_only => {
+                Err((ErrorCode::Forbidden, "Impersonation session is read-only"))
+            }
+            ClientMessage::Typing {
+                thread_id,
+                is_typing,
+            } => match self.authorize_thread(request, thread_id).await {
+                Ok(()) => {
+                    self.server
+                        .broadcast_typing(thread_id, self.user_id, is_typing)
+                        .await;
+                    Ok(())
+                }
+                Err(refusal) => Err(refusal),
+            },
+            ClientMessage::Resume { last_seq } => match last_seq {
+                Some(last_seq) if last_seq < 0 => Err((
+                    ErrorCode::InvalidRequest,
+                    "last_seq must be a non-negative integer",
+                )),
+                _ => match self.replay_since(last_seq).await {
+                    Ok(resumed) => return resumed,
+                    Err(e) => {
+                        error!("[ws] Database error replaying events: {:?}", e);
+                        Err((ErrorCode::ServerError, "Failed to replay events"))
+                    }
+                },
+            },
+        };
+
+        match result {
+            Ok(()) => ServerMessage::Ack {
+                request_id: frame.request_id,
+                request: request.to_string(),
+            },
+            Err((code, message)) => ServerMessage::error(frame.request_id, request, code, message),
+        }
+    }
+
+    async fn subscribe(&self, topic: Topic) -> Result<(), Refusal> {
+        match topic {
+            Topic::Thread { thread_id } => {
+                // Only authorized users are ever added as watchers
+                self.authorize_thread("subscribe", thread_id).await?;
+                self.server
+                    .subscribe_to_thread(self.session_id, thread_id)
+                    .await;
+            }
+            Topic::Post { post_id } => {
+                match feeds::can_view_post(&self.db, post_id, self.user_id, self.is_admin).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        debug!(
+                            "[ws] user {} denied subscribe for post {}",
+                            self.user_id, post_id
+                        );
+                        return Err((ErrorCode::Forbidden, "Access denied"));
+                    }
+                    Err(e) => {
+                        error!("[ws] Database error checking post access: {:?}", e);
+                        return Err((ErrorCode::ServerError, "Failed to check access"));
+                    }
+                }
+                self.server.subscribe_to_post(self.session_id, post_id).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the user may follow the thread: the REST API would show it to
+    /// them. Checked on every request, as access may have been revoked since
+    /// another of their sessions subscribed.
+    async fn authorize_thread(&self, request: &str, thread_id: i32) -> Result<(), Refusal> {
+        match chats::can_view_thread(&self.db, self.user_id, thread_id).await {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                debug!(
+                    "[ws] user {} denied {} for thread {}",
+                    self.user_id, request, thread_id
+                );
+                Err((ErrorCode::Forbidden, "Access denied"))
+            }
+            Err(e) => {
+                error!("[ws] Database error checking thread access: {:?}", e);
+                Err((ErrorCode::ServerError, "Failed to check access"))
+            }
+        }
+    }
+
+    /// Send the events after `last_seq` on the threads and posts this
+    /// session follows, then return a `resumed` frame with the sequence
+    /// number to resume from next time. Clients subscribe first and drop
+    /// events whose `seq` they have already seen. Without `last_seq` nothing
+    /// is replayed and the frame only reports the current position.
+    async fn replay_since(&self, last_seq: Option<i64>) -> Result<ServerMessage, sqlx::Error> {
+        // The head and the events are read from one snapshot, and only
+        // committed events count: an ID taken by an insert that hasn't
+        // committed yet must not be reported as already sent
+        let mut tx = self.db.begin().await?;
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+            .execute(&mut *tx)
+            .await?;
+        let (head, oldest, allocated) = sqlx::query_as::<_, (Option<i64>, Option<i64>, i64)>(
+            "SELECT MAX(id), MIN(id),
+                    (SELECT CASE WHEN is_called THEN last_value ELSE 0 END FROM ws_events_id_seq)
+             FROM ws_events",
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let mut position = head.or(last_seq).unwrap_or(0);
+        let mut replayed = 0;
+        let mut complete = true;
+        if let Some(last_seq) = last_seq {
+            // Events older than the retention window are gone. With nothing
+            // left in the log, only the sequence tells whether any were
+            // logged after `last_seq`
+            complete = match oldest {
+                Some(oldest) => last_seq >= oldest - 1,
+                None => last_seq >= allocated,
+            };
+
+            let (threads, posts) = self.server.read().session_subscriptions(self.session_id);
+            let mut events = sqlx::query_as::<_, (i64, serde_json::Value)>(
+                "SELECT id, message FROM ws_events
+                 WHERE id > $1 AND (thread_id = ANY($2) OR post_id = ANY($3))
+                 ORDER BY id
+                 LIMIT $4",
+            )
+            .bind(last_seq)
+            .bind(&threads)
+            .bind(&posts)
+            .bind(MAX_REPLAY as i64 + 1)
+            .fetch_all(&mut *tx)
+            .await?;
+
+            if events.len() > MAX_REPLAY {
+                events.truncate(MAX_REPLAY);
+                complete = false;
+                position = events.last().map_or(last_seq, |(seq, _)| *seq);
+            } else {
+                position = position.max(last_seq);
+            }
+
+            for (seq, message) in events {
+                match serde_json::from_value::<ServerMessage>(message) {
+                    Ok(message) => {
+                        let frame = ServerFrame {
+                            seq: Some(seq),
+                            message,
+                        };
+                        self.server.send_to_session(self.session_id, &frame);
+                        replayed += 1;
+                    }
+                    Err(e) => {
+                        error!("[ws] Failed to parse logged event {}: {}", seq, e);
+                        complete = false;
+                    }
+                }
+            }
+        }
+
+        Ok(ServerMessage::Resumed {
+            last_seq: position,
+            replayed,
+            complete,
+        })
+    }
 }
 
 impl Actor for WsSession {
     type Context = WebsocketContext<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        let server = self.server.clone();
-        let user_id = self.user_id;
-        let recipient = ctx.address().recipient();
-
-        // Register connection asynchronously
-        actix::spawn(async move {
-            server.register_connection(user_id, recipient).await;
-            server.send_presence(user_id, true).await;
-        });
+        self.server
+            .register_connection(self.session_id, self.user_id, ctx.address().recipient());
+        self.start_heartbeat(ctx);
+        let hello = ServerMessage::Hello {
+            protocol: self.protocol.number(),
+            session_id: self.session_id,
+        };
+        self.send(ctx, &hello.into());
     }
 
     fn stopped(&mut self, _ctx: &mut Self::Context) {
-        let server = self.server.clone();
-        let user_id = self.user_id;
-
-        actix::spawn(async move {
-            server.send_presence(user_id, false).await;
-            server.unregister_connection(user_id).await;
-        });
+        self.server.unregister_connection(self.session_id);
     }
 }
 
@@ -200,65 +762,43 @@ impl Handler<WsNotification> for WsSession {
     type Result = ();
 
     fn handle(&mut self, msg: WsNotification, ctx: &mut Self::Context) {
-        let json = serde_json::to_string(&msg.0).unwrap_or_default();
-        ctx.text(json);
+        self.send(ctx, &msg.0);
     }
 }
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsSession {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        // Any frame shows the client is still there
+        if msg.is_ok() {
+            self.last_heartbeat = Instant::now();
+        }
         match msg {
             Ok(ws::Message::Ping(msg)) => {
                 ctx.pong(&msg);
             }
             Ok(ws::Message::Pong(_)) => {
-                // Pong received
+                // Answer to our heartbeat
             }
             Ok(ws::Message::Text(text)) => {
                 debug!("[ws] received text: {}", text);
-                let server = self.server.clone();
-                let user_id = self.user_id;
-
-                // Parse incoming message
-                match serde_json::from_str::<WsMessage>(&text) {
-                    Ok(ws_msg) => {
-                        debug!("[ws] parsed msg_type={}", ws_msg.msg_type);
-                        actix::spawn(async move {
-                            match ws_msg.msg_type.as_str() {
-                                "subscribe_thread" => {
-                                    if let Some(thread_id) = ws_msg.thread_id {
-                                        server.subscribe_to_thread(user_id, thread_id).await;
-                                    } else {
-                                        debug!("[ws] subscribe_thread missing thread_id");
-                                    }
-                                }
-                                "subscribe_post" => {
-                                    if let Some(post_id) = ws_msg.post_id {
-                                        server.subscribe_to_post(user_id, post_id).await;
-                                    } else {
-                                        debug!("[ws] subscribe_post missing post_id");
-                                    }
-                                }
-                                "typing" => {
-                                    if let Some(thread_id) = ws_msg.thread_id {
-                                        let is_typing = ws_msg.data["is_typing"]
-                                            .as_bool()
-                                            .unwrap_or(false);
-                                        server
-                                            .broadcast_typing(thread_id, user_id, is_typing)
-                                            .await;
-                                    } else {
-                                        debug!("[ws] typing missing thread_id");
-                                    }
-                                }
-                                _ => {
-                                    debug!("[ws] unhandled msg_type={}", ws_msg.msg_type);
-                                }
-                            }
-                        });
+                match websocket_protocol::decode(&text, self.protocol) {
+                    Ok(frame) => {
+                        debug!("[ws] parsed {}", frame.message.name());
+                        // Requests are handled one at a time in the order they
+                        // arrive, so a `resume` sees the subscriptions sent
+                        // before it. The reply is queued behind replayed events.
+                        let session = self.clone();
+                        ctx.wait(
+                            async move { session.handle_client_message(frame).await }
+                                .into_actor(self)
+                                .map(|reply, _session, ctx| {
+                                    ctx.address().do_send(WsNotification(reply.into()));
+                                }),
+                        );
                     }
-                    Err(err) => {
-                        debug!("[ws] failed to parse ws message: {}", err);
+                    Err(reply) => {
+                        debug!("[ws] rejected client message: {:?}", reply);
+                        self.send(ctx, &reply.into());
                     }
                 }
             }
@@ -0,0 +1,96 @@
+//! Erasing an account also removes the personal data kept in audit snapshots
+//! and in the replayable WebSocket events. Runs against a fresh database and
+//! is skipped when `DATABASE_URL` is not set.
+
+mod common;
+
+use common::{chat_message, create_user, TestDb};
+use music_school_app_backend::audit::user_snapshot;
+use music_school_app_backend::gdpr::anonymise_user;
+use music_school_app_backend::websocket_protocol::ServerMessage;
+use music_school_app_backend::websockets::WsServerActor;
+
+fn message_from(thread_id: i32, sender_id: i32, body: &str) -> ServerMessage {
+    let mut message = chat_message(thread_id, body);
+    if let ServerMessage::ChatMessage(event) = &mut message {
+        event.sender_id = sender_id;
+    }
+    message
+}
+
+#[actix_web::test]
+async fn erasure_redacts_audit_snapshots_and_logged_events() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let teacher_id = create_user(db, "teacher", "teacher").await;
+    let parent_id = create_user(db, "parent", "parent").await;
+    sqlx::query("UPDATE users SET email = 'teacher@example.com', phone = '0123' WHERE id = $1")
+        .bind(teacher_id)
+        .execute(db)
+        .await
+        .unwrap();
+
+    sqlx::query(
+        "INSERT INTO audit_log (action, entity_type, entity_id, after_data)
+         VALUES ('user.updated', 'user', $1, $2)",
+    )
+    .bind(teacher_id)
+    .bind(user_snapshot(db, teacher_id).await.unwrap())
+    .execute(db)
+    .await
+    .unwrap();
+
+    let thread_id = sqlx::query_scalar::<_, i32>(
+        "INSERT INTO chat_threads (participant_a_id, participant_b_id) VALUES ($1, $2) RETURNING id",
+    )
+    .bind(parent_id)
+    .bind(teacher_id)
+    .fetch_one(db)
+    .await
+    .unwrap();
+    let server = WsServerActor::with_event_log(db.clone());
+    server
+        .broadcast_to_thread(
+            thread_id,
+            message_from(thread_id, teacher_id, "from the teacher"),
+        )
+        .await;
+    server
+        .broadcast_to_thread(
+            thread_id,
+            message_from(thread_id, parent_id, "from the parent"),
+        )
+        .await;
+
+    let mut tx = db.begin().await.unwrap();
+    anonymise_user(&mut tx, teacher_id).await.unwrap();
+    tx.commit().await.unwrap();
+
+    let snapshot: serde_json::Value =
+        sqlx::query_scalar("SELECT after_data FROM audit_log WHERE entity_id = $1")
+            .bind(teacher_id)
+            .fetch_one(db)
+            .await
+            .unwrap();
+    for field in ["username", "full_name", "email", "phone"] {
+        assert!(
+            snapshot.get(field).is_none(),
+            "{} kept in {}",
+            field,
+            snapshot
+        );
+    }
+    assert_eq!(snapshot["id"], teacher_id);
+    assert_eq!(snapshot["roles"], serde_json::json!(["teacher"]));
+
+    let senders: Vec<String> =
+        sqlx::query_scalar("SELECT message->>'sender_id' FROM ws_events ORDER BY id")
+            .fetch_all(db)
+            .await
+            .unwrap();
+    assert_eq!(senders, vec![parent_id.to_string()]);
+
+    test_db.drop().await;
+}
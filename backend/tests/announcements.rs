@@ -0,0 +1,120 @@
+//! Announcements are reserved with a lease while they are sent. One left in
+//! `sending` by a scheduler that stopped is taken over once the lease has
+//! expired, without notifying anyone twice. Runs against a fresh database and
+//! is skipped when `DATABASE_URL` is not set.
+
+mod common;
+
+use common::{app_state, create_user, login_token, serve, TestDb};
+use music_school_app_backend::announcements::send_due_announcements;
+use music_school_app_backend::email::MemoryTransport;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+/// An announcement to all students, claimed by a scheduler `minutes_ago`
+async fn claimed_announcement(db: &PgPool, title: &str, minutes_ago: i32) -> i32 {
+    sqlx::query_scalar(
+        "INSERT INTO announcements (title, message, audience, status, claimed_at)
+         VALUES ($1, 'Text', '{\"roles\": [\"student\"]}', 'sending',
+                 NOW() - make_interval(mins => $2))
+         RETURNING id",
+    )
+    .bind(title)
+    .bind(minutes_ago)
+    .fetch_one(db)
+    .await
+    .unwrap()
+}
+
+async fn notifications_of(db: &PgPool, user_id: i32) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM notifications WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(db)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn abandoned_sends_are_resumed_after_the_lease() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let first = create_user(db, "first", "student").await;
+    let second = create_user(db, "second", "student").await;
+    let state = app_state(db, Arc::new(MemoryTransport::new()));
+
+    let running = claimed_announcement(db, "Still sending", 1).await;
+    let abandoned = claimed_announcement(db, "Abandoned", 30).await;
+    // The stopped scheduler got as far as the first student
+    sqlx::query(
+        "INSERT INTO announcement_recipients (announcement_id, user_id, in_app)
+         VALUES ($1, $2, TRUE)",
+    )
+    .bind(abandoned)
+    .bind(first)
+    .execute(db)
+    .await
+    .unwrap();
+
+    send_due_announcements(&state).await;
+
+    let status = |id: i32| async move {
+        sqlx::query_as::<_, (String, i32)>(
+            "SELECT status, recipient_count FROM announcements WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_one(db)
+        .await
+        .unwrap()
+    };
+    assert_eq!(status(abandoned).await, ("sent".to_string(), 2));
+    assert_eq!(status(running).await, ("sending".to_string(), 0));
+    assert_eq!(notifications_of(db, first).await, 0);
+    assert_eq!(notifications_of(db, second).await, 1);
+
+    // Nothing to take over on the next round
+    send_due_announcements(&state).await;
+    assert_eq!(notifications_of(db, second).await, 1);
+
+    test_db.drop().await;
+}
+
+#[actix_web::test]
+async fn announcements_sent_right_away_hold_a_lease() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    create_user(db, "principal", "admin").await;
+    let student = create_user(db, "student", "student").await;
+    let state = app_state(db, Arc::new(MemoryTransport::new()));
+    let (base_url, server_handle) = serve(state.clone());
+
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{}/api/announcements", base_url))
+        .bearer_auth(login_token("principal", &["admin"]))
+        .json(&serde_json::json!({
+            "title": "Concert",
+            "message": "Friday at 6",
+            "audience": { "roles": ["student"] },
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let (status, claimed): (String, bool) =
+        sqlx::query_as("SELECT status, claimed_at IS NOT NULL FROM announcements")
+            .fetch_one(db)
+            .await
+            .unwrap();
+    assert_eq!(status, "sent");
+    assert!(claimed);
+    assert_eq!(notifications_of(db, student).await, 1);
+
+    drop(client);
+    server_handle.stop(true).await;
+    test_db.drop().await;
+}
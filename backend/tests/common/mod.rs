@@ -0,0 +1,264 @@
+// Each test crate uses its own subset of these helpers
+#![allow(dead_code)]
+
+use actix::{Actor, Context, Handler};
+use actix_web::dev::ServerHandle;
+use actix_web::{web, HttpServer};
+use chrono::Utc;
+use music_school_app_backend::email::{EmailService, EmailTransport};
+use music_school_app_backend::feeds::FeedCommentResponse;
+use music_school_app_backend::notification_dispatcher::NotificationDispatcher;
+use music_school_app_backend::storage::LocalStorage;
+use music_school_app_backend::users::Claims;
+use music_school_app_backend::websocket_protocol::{ChatMessageEvent, ServerFrame, ServerMessage};
+use music_school_app_backend::websockets::{
+    next_session_id, SessionId, WsNotification, WsServerActor,
+};
+use music_school_app_backend::{create_app, AppState};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{ConnectOptions, PgPool};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// Stands in for a client connection and keeps every frame sent to it
+#[derive(Clone, Default)]
+pub struct Inbox(Arc<Mutex<Vec<ServerFrame>>>);
+
+impl Inbox {
+    pub fn frames(&self) -> Vec<ServerFrame> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// The `type` of each frame received
+    pub fn types(&self) -> Vec<&'static str> {
+        self.frames()
+            .iter()
+            .map(|frame| frame.message.name())
+            .collect()
+    }
+}
+
+struct Collector(Inbox);
+
+impl Actor for Collector {
+    type Context = Context<Self>;
+}
+
+impl Handler<WsNotification> for Collector {
+    type Result = ();
+
+    fn handle(&mut self, msg: WsNotification, _ctx: &mut Self::Context) {
+        self.0 .0.lock().unwrap().push(msg.0);
+    }
+}
+
+/// Register a new session for `user_id` whose frames end up in the returned inbox
+pub fn connect_client(server: &WsServerActor, user_id: i32) -> (SessionId, Inbox) {
+    let inbox = Inbox::default();
+    let session_id = next_session_id();
+    server.register_connection(
+        session_id,
+        user_id,
+        Collector(inbox.clone()).start().recipient(),
+    );
+    (session_id, inbox)
+}
+
+pub fn chat_message(thread_id: i32, body: &str) -> ServerMessage {
+    ServerMessage::ChatMessage(Box::new(ChatMessageEvent {
+        message_id: 1,
+        thread_id,
+        sender_id: 1,
+        sender_name: "sender".to_string(),
+        body: serde_json::json!(body),
+        attachments: Vec::new(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    }))
+}
+
+pub fn comment(post_id: i32) -> ServerMessage {
+    ServerMessage::Comment {
+        user_id: 1,
+        comment: Box::new(FeedCommentResponse {
+            id: 1,
+            post_id,
+            author_user_id: 1,
+            author_name: "author".to_string(),
+            author_profile_image: None,
+            parent_comment_id: None,
+            content: serde_json::json!({}),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            attachments: Vec::new(),
+        }),
+    }
+}
+
+pub fn presence_of(frame: &ServerFrame) -> Option<(i32, bool)> {
+    match frame.message {
+        ServerMessage::Presence { user_id, is_online } => Some((user_id, is_online)),
+        _ => None,
+    }
+}
+
+/// A fresh database on the server in `DATABASE_URL`, migrated and dropped
+/// again at the end of the test
+pub struct TestDb {
+    pub pool: PgPool,
+    name: String,
+    admin_url: String,
+}
+
+impl TestDb {
+    pub async fn create() -> Option<Self> {
+        dotenv::dotenv().ok();
+        let Ok(admin_url) = std::env::var("DATABASE_URL") else {
+            eprintln!("DATABASE_URL is not set; skipping");
+            return None;
+        };
+
+        let name = format!("ws_test_{}", uuid::Uuid::new_v4().simple());
+        let mut admin = PgConnectOptions::from_str(&admin_url)
+            .expect("invalid DATABASE_URL")
+            .connect()
+            .await
+            .expect("failed to connect to DATABASE_URL");
+        sqlx::query(&format!("CREATE DATABASE {}", name))
+            .execute(&mut admin)
+            .await
+            .expect("failed to create test database");
+
+        let options = PgConnectOptions::from_str(&admin_url)
+            .unwrap()
+            .database(&name);
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect_with(options)
+            .await
+            .expect("failed to connect to test database");
+        sqlx::migrate!("./migrations")
+            .run(&pool)
+            .await
+            .expect("failed to run migrations");
+
+        Some(TestDb {
+            pool,
+            name,
+            admin_url,
+        })
+    }
+
+    pub async fn drop(self) {
+        self.pool.close().await;
+        let mut admin = PgConnectOptions::from_str(&self.admin_url)
+            .unwrap()
+            .connect()
+            .await
+            .expect("failed to connect to DATABASE_URL");
+        sqlx::query(&format!(
+            "DROP DATABASE IF EXISTS {} WITH (FORCE)",
+            self.name
+        ))
+        .execute(&mut admin)
+        .await
+        .expect("failed to drop test database");
+    }
+}
+
+/// Application state for calling the HTTP API against `db`. Emails end up in
+/// `mail` and uploads in a fresh temporary directory.
+pub fn app_state(db: &PgPool, mail: Arc<dyn EmailTransport>) -> web::Data<AppState> {
+    let email_service = Arc::new(EmailService::with_transport(mail).unwrap());
+    let upload_dir =
+        std::env::temp_dir().join(format!("music_school_test_{}", uuid::Uuid::new_v4()));
+    let profile_images_dir = upload_dir.join("profile_images");
+    let media_dir = upload_dir.join("media");
+    std::fs::create_dir_all(&profile_images_dir).unwrap();
+    std::fs::create_dir_all(&media_dir).unwrap();
+    let ws_server = WsServerActor::new();
+
+    web::Data::new(AppState {
+        db: db.clone(),
+        jwt_secret: "test-secret".to_string(),
+        notifications: NotificationDispatcher::new(
+            db.clone(),
+            email_service.clone(),
+            ws_server.clone(),
+        ),
+        email_service,
+        storage: Arc::new(LocalStorage::new(
+            profile_images_dir.clone(),
+            "/uploads/profile_images".to_string(),
+        )),
+        profile_images_dir,
+        media_storage: Arc::new(LocalStorage::new(
+            media_dir.clone(),
+            "/uploads/media".to_string(),
+        )),
+        media_dir,
+        ws_server,
+    })
+}
+
+/// Serve the API on a free local port; returns its base URL
+pub fn serve(state: web::Data<AppState>) -> (String, ServerHandle) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let server = HttpServer::new(move || create_app(state.clone()))
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+    let handle = server.handle();
+    actix_web::rt::spawn(server);
+    (base_url, handle)
+}
+
+/// Session token for `username`, signed for [`app_state`]
+pub fn login_token(username: &str, roles: &[&str]) -> String {
+    let claims = Claims {
+        sub: username.to_string(),
+        exp: (Utc::now().timestamp() + 3600) as usize,
+        roles: roles.iter().map(|role| role.to_string()).collect(),
+        impersonation: None,
+    };
+    jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(b"test-secret"),
+    )
+    .unwrap()
+}
+
+pub async fn create_user(db: &PgPool, username: &str, role: &str) -> i32 {
+    let user_id = sqlx::query_scalar::<_, i32>(
+        "INSERT INTO users (username, full_name, password_hash) VALUES ($1, $1, 'x') RETURNING id",
+    )
+    .bind(username)
+    .fetch_one(db)
+    .await
+    .unwrap();
+
+    sqlx::query(
+        "INSERT INTO user_roles (user_id, role_id) SELECT $1, id FROM roles WHERE name = $2",
+    )
+    .bind(user_id)
+    .bind(role)
+    .execute(db)
+    .await
+    .unwrap();
+
+    let role_table = match role {
+        "student" => "INSERT INTO students (user_id, birthday) VALUES ($1, '2015-01-01')",
+        "parent" => "INSERT INTO parents (user_id) VALUES ($1)",
+        "teacher" => "INSERT INTO teachers (user_id) VALUES ($1)",
+        _ => return user_id,
+    };
+    sqlx::query(role_table)
+        .bind(user_id)
+        .execute(db)
+        .await
+        .unwrap();
+    user_id
+}
@@ -0,0 +1,87 @@
+//! Consent accepted on the registration form is recorded with the account,
+//! so new users aren't asked for it again on their first request. Runs
+//! against a fresh database and is skipped when `DATABASE_URL` is not set.
+
+mod common;
+
+use common::{app_state, create_user, login_token, serve, TestDb};
+use music_school_app_backend::email::MemoryTransport;
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+#[actix_web::test]
+async fn registration_records_the_accepted_consent() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let admin_id = create_user(db, "principal", "admin").await;
+    sqlx::query(
+        "INSERT INTO registration_tokens (token_hash, created_by_user_id, role, expires_at)
+         VALUES ($1, $2, 'teacher', NOW() + INTERVAL '1 day')",
+    )
+    .bind(format!("{:x}", Sha256::digest(b"invite")))
+    .bind(admin_id)
+    .execute(db)
+    .await
+    .unwrap();
+    let (base_url, server_handle) = serve(app_state(db, Arc::new(MemoryTransport::new())));
+    let client = reqwest::Client::new();
+    let register = |consent: serde_json::Value| {
+        let mut body = serde_json::json!({
+            "token": "invite",
+            "username": "new_teacher",
+            "password": "correct horse battery staple",
+            "full_name": "New Teacher",
+        });
+        body.as_object_mut()
+            .unwrap()
+            .extend(consent.as_object().unwrap().clone());
+        client
+            .post(format!("{}/api/register-with-token", base_url))
+            .json(&body)
+            .send()
+    };
+
+    // The form has to show and accept the version in force
+    for consent in [
+        serde_json::json!({}),
+        serde_json::json!({ "consent_version": 0 }),
+    ] {
+        let response = register(consent).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["code"], "consent_required");
+        assert_eq!(body["version"], 1);
+    }
+    let response = register(serde_json::json!({ "consent_version": 1, "consent_locale": "xx" }))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = register(serde_json::json!({ "consent_version": 1, "consent_locale": "ru" }))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let (version, locale): (i32, String) = sqlx::query_as(
+        "SELECT uc.version, uc.locale FROM user_consents uc
+         JOIN users u ON u.id = uc.user_id WHERE u.username = 'new_teacher'",
+    )
+    .fetch_one(db)
+    .await
+    .unwrap();
+    assert_eq!((version, locale.as_str()), (1, "ru"));
+
+    let response = client
+        .get(format!("{}/api/notifications", base_url))
+        .bearer_auth(login_token("new_teacher", &["teacher"]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    drop(client);
+    server_handle.stop(true).await;
+    test_db.drop().await;
+}
@@ -0,0 +1,96 @@
+//! The data export streams a zip archive of the user's data, including
+//! their uploads, and each user may only run a few exports. Runs against a
+//! fresh database and is skipped when `DATABASE_URL` is not set.
+
+mod common;
+
+use common::{app_state, create_user, login_token, serve, TestDb};
+use music_school_app_backend::email::MemoryTransport;
+use reqwest::StatusCode;
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+
+async fn export(client: &reqwest::Client, base_url: &str, token: &str) -> reqwest::Response {
+    client
+        .get(format!("{}/api/profile/export", base_url))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn export_streams_an_archive_and_is_limited_per_user() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let state = app_state(db, Arc::new(MemoryTransport::new()));
+    let image = vec![7u8; 256 * 1024];
+    std::fs::write(state.profile_images_dir.join("avatar.png"), &image).unwrap();
+    let (base_url, server_handle) = serve(state);
+
+    let user_id = create_user(db, "teacher", "teacher").await;
+    sqlx::query("UPDATE users SET profile_image = 'avatar.png' WHERE id = $1")
+        .bind(user_id)
+        .execute(db)
+        .await
+        .unwrap();
+    let token = login_token("teacher", &["teacher"]);
+    let client = reqwest::Client::new();
+
+    let response = export(&client, &base_url, &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["Content-Type"], "application/zip");
+    let body = response.bytes().await.unwrap();
+    let mut archive = zip::ZipArchive::new(Cursor::new(body)).unwrap();
+    let mut profile = String::new();
+    archive
+        .by_name("profile.json")
+        .unwrap()
+        .read_to_string(&mut profile)
+        .unwrap();
+    let profile: serde_json::Value = serde_json::from_str(&profile).unwrap();
+    assert_eq!(profile["username"], "teacher");
+    let mut exported_image = Vec::new();
+    archive
+        .by_name("profile_image/avatar.png")
+        .unwrap()
+        .read_to_end(&mut exported_image)
+        .unwrap();
+    assert_eq!(exported_image, image);
+    assert!(archive.by_name("manifest.json").is_ok());
+
+    // Another export can't start while one is running
+    sqlx::query("INSERT INTO data_exports (user_id) VALUES ($1)")
+        .bind(user_id)
+        .execute(db)
+        .await
+        .unwrap();
+    let response = export(&client, &base_url, &token).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // One that was abandoned long ago doesn't block new ones
+    sqlx::query(
+        "UPDATE data_exports SET started_at = NOW() - INTERVAL '2 hours' WHERE finished_at IS NULL",
+    )
+    .execute(db)
+    .await
+    .unwrap();
+    let response = export(&client, &base_url, &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Three exports started within the day
+    let response = export(&client, &base_url, &token).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let running: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM data_exports WHERE finished_at IS NULL")
+            .fetch_one(db)
+            .await
+            .unwrap();
+    assert_eq!(running, 0);
+
+    drop(client);
+    server_handle.stop(true).await;
+    test_db.drop().await;
+}
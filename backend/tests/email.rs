@@ -0,0 +1,52 @@
+//! Emails are rendered as multipart messages with a plain-text and an HTML
+//! part, and only a missing email configuration falls back to the maildir.
+
+use music_school_app_backend::email::{EmailService, MemoryTransport};
+use music_school_app_backend::i18n::Locale;
+use std::sync::Arc;
+
+#[actix_web::test]
+async fn password_reset_is_sent_as_multipart_alternative() {
+    let mail = Arc::new(MemoryTransport::new());
+    let service = EmailService::with_transport(mail.clone()).unwrap();
+    service
+        .send_password_reset_email(Locale::De, "anna@example.com", "anna", "tok123")
+        .await
+        .unwrap();
+
+    let sent = mail.sent_to("anna@example.com");
+    assert_eq!(sent.len(), 1);
+    let email = &sent[0];
+    assert!(email.text.contains("/reset-password/tok123"));
+    assert!(email.html.contains("/reset-password/tok123\""));
+    assert!(email.html.contains("anna"));
+
+    let formatted = String::from_utf8(email.to_message().unwrap().formatted()).unwrap();
+    assert!(formatted.contains("To: anna@example.com"));
+    assert!(formatted.contains("Content-Type: multipart/alternative"));
+    let text_part = formatted.find("Content-Type: text/plain").unwrap();
+    let html_part = formatted.find("Content-Type: text/html").unwrap();
+    // Clients show the last part they understand, so HTML comes last
+    assert!(text_part < html_part);
+}
+
+#[test]
+fn only_a_missing_configuration_counts_as_unconfigured() {
+    std::env::remove_var("EMAIL_TRANSPORT");
+    for (name, _) in std::env::vars().filter(|(name, _)| name.starts_with("SMTP_")) {
+        std::env::remove_var(name);
+    }
+    assert!(!EmailService::is_configured());
+
+    // SMTP settings without a sender address are an error, not development
+    std::env::set_var("SMTP_HOST", "smtp.example.com");
+    std::env::remove_var("FROM_EMAIL");
+    assert!(EmailService::is_configured());
+    assert!(EmailService::from_env().is_err());
+    std::env::remove_var("SMTP_HOST");
+
+    std::env::set_var("EMAIL_TRANSPORT", "carrier-pigeon");
+    assert!(EmailService::is_configured());
+    assert!(EmailService::from_env().is_err());
+    std::env::remove_var("EMAIL_TRANSPORT");
+}
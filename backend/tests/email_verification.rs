@@ -0,0 +1,96 @@
+//! Admins can send confirmation links to every unverified address at once,
+//! as needed for the addresses that existed before verification. Runs
+//! against a fresh database and is skipped when `DATABASE_URL` is not set.
+
+mod common;
+
+use common::{app_state, create_user, login_token, serve, TestDb};
+use music_school_app_backend::email::MemoryTransport;
+use music_school_app_backend::email_verification::cleanup_expired_tokens;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+
+async fn set_email(db: &PgPool, user_id: i32, email: &str, verified: bool) {
+    sqlx::query("UPDATE users SET email = $2 WHERE id = $1")
+        .bind(user_id)
+        .bind(email)
+        .execute(db)
+        .await
+        .unwrap();
+    if verified {
+        sqlx::query("UPDATE users SET email_verified_at = NOW() WHERE id = $1")
+            .bind(user_id)
+            .execute(db)
+            .await
+            .unwrap();
+    }
+}
+
+async fn send_all(client: &reqwest::Client, base_url: &str, token: &str) -> reqwest::Response {
+    client
+        .post(format!(
+            "{}/api/admin/email-verification/send-all",
+            base_url
+        ))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn send_all_reaches_each_unverified_address_once() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let mail = Arc::new(MemoryTransport::new());
+    let (base_url, server_handle) = serve(app_state(db, mail.clone()));
+
+    let admin = create_user(db, "principal", "admin").await;
+    set_email(db, admin, "principal@example.com", true).await;
+    let teacher = create_user(db, "teacher", "teacher").await;
+    set_email(db, teacher, "teacher@example.com", false).await;
+    let parent = create_user(db, "parent", "parent").await;
+    set_email(db, parent, "parent@example.com", false).await;
+    create_user(db, "student", "student").await;
+
+    let client = reqwest::Client::new();
+    let token = login_token("principal", &["admin"]);
+    let response = send_all(&client, &base_url, &token).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["sent"], 2);
+
+    // The emails are sent in the background
+    for _ in 0..50 {
+        if mail.sent().len() >= 2 {
+            break;
+        }
+        actix_web::rt::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(mail.sent_to("teacher@example.com").len(), 1);
+    assert_eq!(mail.sent_to("parent@example.com").len(), 1);
+    assert!(mail.sent_to("principal@example.com").is_empty());
+
+    // Pending links aren't sent again
+    let response = send_all(&client, &base_url, &token).await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["sent"], 0);
+
+    // Links are deleted a week after they expire
+    sqlx::query(
+        "UPDATE email_verification_tokens SET expires_at = NOW() - INTERVAL '8 days'
+         WHERE email = 'teacher@example.com'",
+    )
+    .execute(db)
+    .await
+    .unwrap();
+    assert_eq!(cleanup_expired_tokens(db).await.unwrap(), 1);
+
+    drop(client);
+    server_handle.stop(true).await;
+    test_db.drop().await;
+}
@@ -0,0 +1,101 @@
+//! Requests made while impersonating are logged without the credentials in
+//! their query string, and read-only sessions can't write over the WebSocket
+//! either. Runs against a fresh database and is skipped when `DATABASE_URL`
+//! is not set.
+
+mod common;
+
+use common::{app_state, create_user, login_token, serve, TestDb};
+use music_school_app_backend::email::MemoryTransport;
+use music_school_app_backend::websocket_protocol::{
+    ClientFrame, ClientMessage, ErrorCode, ProtocolVersion, ServerMessage,
+};
+use music_school_app_backend::websockets::{WsServerActor, WsSession};
+use std::sync::Arc;
+
+#[actix_web::test]
+async fn logged_queries_leave_out_the_token() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    create_user(db, "principal", "admin").await;
+    let teacher_id = create_user(db, "teacher", "teacher").await;
+    let (base_url, server_handle) = serve(app_state(db, Arc::new(MemoryTransport::new())));
+    let client = reqwest::Client::new();
+
+    let started: serde_json::Value = client
+        .post(format!(
+            "{}/api/admin/users/{}/impersonate",
+            base_url, teacher_id
+        ))
+        .bearer_auth(login_token("principal", &["admin"]))
+        .json(&serde_json::json!({ "reason": "support ticket" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let token = started["token"].as_str().unwrap();
+
+    // A WebSocket upgrade authenticates with the token in the query string
+    for query in [
+        vec![("protocol", "2"), ("token", token), ("x", "1")],
+        vec![("token", token)],
+    ] {
+        client
+            .get(format!("{}/ws", base_url))
+            .query(&query)
+            .send()
+            .await
+            .unwrap();
+    }
+
+    let logged: Vec<Option<String>> =
+        sqlx::query_scalar("SELECT query FROM impersonation_request_log ORDER BY id")
+            .fetch_all(db)
+            .await
+            .unwrap();
+    assert_eq!(logged, vec![Some("protocol=2&x=1".to_string()), None]);
+
+    drop(client);
+    server_handle.stop(true).await;
+    test_db.drop().await;
+}
+
+#[actix_web::test]
+async fn read_only_sessions_cannot_send_typing_indicators() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let teacher_id = create_user(db, "teacher", "teacher").await;
+    let server = WsServerActor::new();
+    let (session_id, _inbox) = common::connect_client(&server, teacher_id);
+
+    let mut session = WsSession::new(
+        session_id,
+        teacher_id,
+        false,
+        ProtocolVersion::LATEST,
+        db.clone(),
+        server.clone(),
+    );
+    session.read_only = true;
+    let reply = session
+        .handle_client_message(ClientFrame {
+            request_id: None,
+            message: ClientMessage::Typing {
+                thread_id: 1,
+                is_typing: true,
+            },
+        })
+        .await;
+    match reply {
+        ServerMessage::Error { code, .. } => assert_eq!(code, ErrorCode::Forbidden),
+        other => panic!("expected an error frame, got {:?}", other),
+    }
+
+    test_db.drop().await;
+}
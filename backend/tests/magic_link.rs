@@ -0,0 +1,63 @@
+//! Sign-in link requests are rate limited by the connecting address, not by
+//! headers the client sets, and old requests are cleaned up. Runs against a
+//! fresh database and is skipped when `DATABASE_URL` is not set.
+
+mod common;
+
+use common::{app_state, serve, TestDb};
+use music_school_app_backend::email::MemoryTransport;
+use music_school_app_backend::magic_link::cleanup_expired_links;
+use reqwest::StatusCode;
+use std::sync::Arc;
+
+#[actix_web::test]
+async fn rate_limit_ignores_forwarded_headers_from_clients() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    std::env::remove_var("TRUST_PROXY_HEADERS");
+    let (base_url, server_handle) = serve(app_state(db, Arc::new(MemoryTransport::new())));
+    let client = reqwest::Client::new();
+    let request = |n: usize, forwarded_for: String| {
+        client
+            .post(format!("{}/api/auth/magic-link", base_url))
+            .header("X-Forwarded-For", forwarded_for.clone())
+            .header("Forwarded", format!("for={}", forwarded_for))
+            .json(&serde_json::json!({ "identifier": format!("user{}", n) }))
+            .send()
+    };
+
+    for n in 0..10 {
+        let response = request(n, format!("198.51.100.{}", n)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    let response = request(10, "198.51.100.10".to_string()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // Behind a trusted proxy, the address it appended counts
+    std::env::set_var("TRUST_PROXY_HEADERS", "true");
+    let response = request(11, "198.51.100.1, 203.0.113.5".to_string())
+        .await
+        .unwrap();
+    std::env::remove_var("TRUST_PROXY_HEADERS");
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let addresses: Vec<String> = sqlx::query_scalar(
+        "SELECT DISTINCT ip_address FROM magic_link_requests ORDER BY ip_address",
+    )
+    .fetch_all(db)
+    .await
+    .unwrap();
+    assert_eq!(addresses, vec!["127.0.0.1", "203.0.113.5"]);
+
+    sqlx::query("UPDATE magic_link_requests SET requested_at = NOW() - INTERVAL '2 days'")
+        .execute(db)
+        .await
+        .unwrap();
+    assert_eq!(cleanup_expired_links(db).await.unwrap(), 11);
+
+    drop(client);
+    server_handle.stop(true).await;
+    test_db.drop().await;
+}
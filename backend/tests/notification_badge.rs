@@ -0,0 +1,72 @@
+//! The unread count sent with each new notification matches the badge count
+//! of the REST API, which leaves out snoozed notifications until they are due.
+//! Runs against a fresh database and is skipped when `DATABASE_URL` is not set.
+
+mod common;
+
+use common::{app_state, connect_client, create_user, TestDb};
+use music_school_app_backend::email::MemoryTransport;
+use music_school_app_backend::notifications::{
+    self, ContentBlock, NotificationBody, NotificationContent,
+};
+use music_school_app_backend::websocket_protocol::ServerMessage;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn body(title: &str) -> NotificationBody {
+    NotificationBody {
+        body_type: "feed_post".to_string(),
+        title: title.to_string(),
+        route: None,
+        content: NotificationContent {
+            blocks: vec![ContentBlock::Text {
+                text: "Hello".to_string(),
+                style: None,
+            }],
+            actions: None,
+        },
+        metadata: None,
+        collapse_key: None,
+    }
+}
+
+#[actix_web::test]
+async fn notification_frames_leave_out_snoozed_notifications() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let state = app_state(db, Arc::new(MemoryTransport::new()));
+    let user_id = create_user(db, "teacher", "teacher").await;
+    sqlx::query(
+        "INSERT INTO notifications (user_id, type, title, body, snoozed_until)
+         VALUES ($1, 'feed_post', 'Snoozed', '{}', NOW() + INTERVAL '1 day'),
+                ($1, 'feed_post', 'Due again', '{}', NOW() - INTERVAL '1 minute')",
+    )
+    .bind(user_id)
+    .execute(db)
+    .await
+    .unwrap();
+    let (_, inbox) = connect_client(&state.ws_server, user_id);
+
+    state
+        .notifications
+        .notify(user_id, |_| body("New post"), "normal")
+        .await
+        .unwrap();
+    actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+
+    let badge = notifications::unread_count(db, user_id).await.unwrap();
+    assert_eq!(badge, 2);
+    let counts: Vec<i64> = inbox
+        .frames()
+        .into_iter()
+        .filter_map(|frame| match frame.message {
+            ServerMessage::Notification { unread_count, .. } => Some(unread_count),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(counts, vec![badge]);
+
+    test_db.drop().await;
+}
@@ -0,0 +1,94 @@
+//! Notifications summarised in a digest are only marked as digested once the
+//! digest was actually sent. Runs against a fresh database and is skipped
+//! when `DATABASE_URL` is not set.
+
+mod common;
+
+use async_trait::async_trait;
+use common::{app_state, create_user, TestDb};
+use music_school_app_backend::email::{EmailError, EmailTransport, MemoryTransport, OutgoingEmail};
+use music_school_app_backend::notification_digests::send_due_digests;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+/// An SMTP server that is down
+struct FailingTransport;
+
+#[async_trait]
+impl EmailTransport for FailingTransport {
+    async fn send(&self, _email: &OutgoingEmail) -> Result<(), EmailError> {
+        Err(EmailError::SendError("connection refused".to_string()))
+    }
+}
+
+async fn pending_notifications(db: &PgPool) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM notifications WHERE digested_at IS NULL")
+        .fetch_one(db)
+        .await
+        .unwrap()
+}
+
+/// Make the daily digest due again
+async fn reset_digest(db: &PgPool) {
+    sqlx::query("UPDATE notification_digest_settings SET last_sent_at = NULL")
+        .execute(db)
+        .await
+        .unwrap();
+}
+
+#[actix_web::test]
+async fn notifications_stay_pending_until_the_digest_is_sent() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let user_id = create_user(db, "teacher", "teacher").await;
+    sqlx::query("UPDATE users SET email = 'teacher@example.com' WHERE id = $1")
+        .bind(user_id)
+        .execute(db)
+        .await
+        .unwrap();
+    sqlx::query(
+        "INSERT INTO notification_digest_settings (user_id, frequency, send_hour)
+         VALUES ($1, 'daily', 0)",
+    )
+    .bind(user_id)
+    .execute(db)
+    .await
+    .unwrap();
+    sqlx::query(
+        "INSERT INTO notifications (user_id, type, title, body)
+         VALUES ($1, 'feed_post', 'New post', '{}'), ($1, 'hometask_assigned', 'New task', '{}')",
+    )
+    .bind(user_id)
+    .execute(db)
+    .await
+    .unwrap();
+
+    // Not verified yet, so there is nowhere to send it
+    let mail = Arc::new(MemoryTransport::new());
+    send_due_digests(&app_state(db, mail.clone())).await;
+    assert!(mail.sent().is_empty());
+    assert_eq!(pending_notifications(db).await, 2);
+
+    sqlx::query("UPDATE users SET email_verified_at = NOW() WHERE id = $1")
+        .bind(user_id)
+        .execute(db)
+        .await
+        .unwrap();
+    reset_digest(db).await;
+    send_due_digests(&app_state(db, Arc::new(FailingTransport))).await;
+    assert_eq!(pending_notifications(db).await, 2);
+
+    reset_digest(db).await;
+    send_due_digests(&app_state(db, mail.clone())).await;
+    assert_eq!(mail.sent_to("teacher@example.com").len(), 1);
+    assert_eq!(pending_notifications(db).await, 0);
+
+    // Nothing left to summarise the next day
+    reset_digest(db).await;
+    send_due_digests(&app_state(db, mail.clone())).await;
+    assert_eq!(mail.sent().len(), 1);
+
+    test_db.drop().await;
+}
@@ -0,0 +1,71 @@
+//! Retention deletes read notifications after their window, but what users
+//! archived themselves only expires through the archive window. Runs against
+//! a fresh database and is skipped when `DATABASE_URL` is not set.
+
+mod common;
+
+use common::{create_user, TestDb};
+use music_school_app_backend::notification_retention::apply_retention;
+use sqlx::PgPool;
+
+/// A notification read `read_days_ago` and archived `archived_days_ago`, if at all
+async fn insert_notification(
+    db: &PgPool,
+    user_id: i32,
+    title: &str,
+    priority: &str,
+    read_days_ago: i32,
+    archived_days_ago: Option<i32>,
+) {
+    sqlx::query(
+        "INSERT INTO notifications (user_id, type, title, body, priority, read_at, archived_at)
+         VALUES ($1, 'feed_post', $2, '{}', $3,
+                 NOW() - make_interval(days => $4),
+                 NOW() - make_interval(days => $5))",
+    )
+    .bind(user_id)
+    .bind(title)
+    .bind(priority)
+    .bind(read_days_ago)
+    .bind(archived_days_ago)
+    .execute(db)
+    .await
+    .unwrap();
+}
+
+async fn remaining(db: &PgPool) -> Vec<(String, bool)> {
+    sqlx::query_as("SELECT title, archived_at IS NOT NULL FROM notifications ORDER BY title")
+        .fetch_all(db)
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn archived_notifications_expire_through_the_archive_window() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let user_id = create_user(db, "teacher", "teacher").await;
+
+    insert_notification(db, user_id, "archived", "normal", 5, Some(5)).await;
+    insert_notification(db, user_id, "archived long ago", "normal", 100, Some(100)).await;
+    insert_notification(db, user_id, "important archived", "high", 100, Some(100)).await;
+    insert_notification(db, user_id, "important read", "high", 10, None).await;
+    insert_notification(db, user_id, "read", "normal", 5, None).await;
+    insert_notification(db, user_id, "read today", "normal", 0, None).await;
+
+    let report = apply_retention(db).await.unwrap();
+    assert_eq!((report.deleted, report.archived), (2, 1));
+    assert_eq!(
+        remaining(db).await,
+        vec![
+            ("archived".to_string(), true),
+            ("important archived".to_string(), true),
+            ("important read".to_string(), true),
+            ("read today".to_string(), false),
+        ]
+    );
+
+    test_db.drop().await;
+}
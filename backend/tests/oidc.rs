@@ -0,0 +1,254 @@
+//! Single sign-on against a mock OpenID provider served from the test: the
+//! login must come back to the browser that started it, and identities are
+//! only linked to non-admin accounts with a verified email. Runs against a
+//! fresh database and is skipped when `DATABASE_URL` is not set.
+
+mod common;
+
+use actix_web::cookie::Cookie;
+use actix_web::{web, App, HttpResponse, HttpServer};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use common::{app_state, create_user, TestDb};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use music_school_app_backend::create_app;
+use music_school_app_backend::email::MemoryTransport;
+use p256::pkcs8::EncodePrivateKey;
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+const CLIENT_ID: &str = "music-school";
+
+/// What the mock provider answers with for the next login
+#[derive(Default)]
+struct MockLogin {
+    nonce: String,
+    code_challenge: String,
+    subject: String,
+    email: String,
+}
+
+struct MockProvider {
+    issuer: String,
+    key: EncodingKey,
+    jwk: serde_json::Value,
+    login: Mutex<MockLogin>,
+}
+
+async fn discovery(provider: web::Data<MockProvider>) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "issuer": provider.issuer,
+        "authorization_endpoint": format!("{}/authorize", provider.issuer),
+        "token_endpoint": format!("{}/token", provider.issuer),
+        "jwks_uri": format!("{}/jwks", provider.issuer),
+    }))
+}
+
+async fn jwks(provider: web::Data<MockProvider>) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "keys": [provider.jwk] }))
+}
+
+async fn token(
+    provider: web::Data<MockProvider>,
+    form: web::Form<HashMap<String, String>>,
+) -> HttpResponse {
+    let login = provider.login.lock().unwrap();
+    let verifier = form.get("code_verifier").cloned().unwrap_or_default();
+    if URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) != login.code_challenge {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "invalid_grant" }));
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let claims = serde_json::json!({
+        "iss": provider.issuer,
+        "aud": CLIENT_ID,
+        "sub": login.subject,
+        "email": login.email,
+        "email_verified": true,
+        "nonce": login.nonce,
+        "iat": now,
+        "exp": now + 300,
+    });
+    let mut header = Header::new(Algorithm::ES256);
+    header.kid = Some("test-key".to_string());
+    let id_token = encode(&header, &claims, &provider.key).unwrap();
+    HttpResponse::Ok().json(serde_json::json!({ "id_token": id_token, "token_type": "Bearer" }))
+}
+
+/// Serve the provider and configure it as `mock`
+async fn start_mock_provider() -> web::Data<MockProvider> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+    let secret = p256::SecretKey::random(&mut rand_core::OsRng);
+    let key = EncodingKey::from_ec_der(secret.to_pkcs8_der().unwrap().as_bytes());
+    let point = secret.public_key().to_sec1_bytes();
+    let jwk = serde_json::json!({
+        "kty": "EC",
+        "crv": "P-256",
+        "kid": "test-key",
+        "alg": "ES256",
+        "use": "sig",
+        "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+        "y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+    });
+
+    let provider = web::Data::new(MockProvider {
+        issuer: issuer.clone(),
+        key,
+        jwk,
+        login: Mutex::new(MockLogin::default()),
+    });
+    let data = provider.clone();
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(data.clone())
+            .route(
+                "/.well-known/openid-configuration",
+                web::get().to(discovery),
+            )
+            .route("/jwks", web::get().to(jwks))
+            .route("/token", web::post().to(token))
+    })
+    .workers(1)
+    .listen(listener)
+    .unwrap()
+    .run();
+    actix_web::rt::spawn(server);
+
+    std::env::set_var("OIDC_PROVIDERS", "mock");
+    std::env::set_var("OIDC_MOCK_ISSUER", &issuer);
+    std::env::set_var("OIDC_MOCK_CLIENT_ID", CLIENT_ID);
+    std::env::remove_var("OIDC_LOGIN_REDIRECT_URL");
+    provider
+}
+
+async fn create_user_with_email(db: &PgPool, username: &str, role: &str, verified: bool) {
+    let user_id = create_user(db, username, role).await;
+    sqlx::query("UPDATE users SET email = $2 WHERE id = $1")
+        .bind(user_id)
+        .bind(format!("{}@example.com", username))
+        .execute(db)
+        .await
+        .unwrap();
+    // Separately, as changing the address resets its verification
+    if verified {
+        sqlx::query("UPDATE users SET email_verified_at = NOW() WHERE id = $1")
+            .bind(user_id)
+            .execute(db)
+            .await
+            .unwrap();
+    }
+}
+
+struct Outcome {
+    status: StatusCode,
+    body: serde_json::Value,
+}
+
+/// Run a login through the API at `base_url`. With `same_browser` false the
+/// callback comes from a browser that didn't start the login, as when an
+/// attacker sends someone their callback URL.
+async fn log_in(
+    base_url: &str,
+    provider: &MockProvider,
+    subject: &str,
+    email: &str,
+    same_browser: bool,
+) -> Outcome {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let response = client
+        .get(format!("{}/api/auth/oidc/mock/authorize", base_url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FOUND);
+    let cookie = response
+        .headers()
+        .get_all("Set-Cookie")
+        .iter()
+        .filter_map(|value| Cookie::parse(value.to_str().ok()?.to_string()).ok())
+        .find(|cookie| cookie.name() == "oidc_state")
+        .expect("authorize sets the state cookie");
+    assert_eq!(cookie.http_only(), Some(true));
+
+    let location = response.headers()["Location"].to_str().unwrap();
+    let params: HashMap<_, _> = reqwest::Url::parse(location)
+        .unwrap()
+        .query_pairs()
+        .into_owned()
+        .collect();
+    *provider.login.lock().unwrap() = MockLogin {
+        nonce: params["nonce"].clone(),
+        code_challenge: params["code_challenge"].clone(),
+        subject: subject.to_string(),
+        email: email.to_string(),
+    };
+
+    let mut callback = client
+        .get(format!("{}/api/auth/oidc/mock/callback", base_url))
+        .query(&[("code", "abc"), ("state", params["state"].as_str())]);
+    if same_browser {
+        callback = callback.header("Cookie", format!("oidc_state={}", cookie.value()));
+    }
+    let response = callback.send().await.unwrap();
+    Outcome {
+        status: response.status(),
+        body: response.json().await.unwrap_or_default(),
+    }
+}
+
+#[actix_web::test]
+async fn login_links_verified_accounts_in_the_starting_browser() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let provider = start_mock_provider().await;
+    let state = app_state(db, Arc::new(MemoryTransport::new()));
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let server = HttpServer::new(move || create_app(state.clone()))
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+    let server_handle = server.handle();
+    actix_web::rt::spawn(server);
+
+    create_user_with_email(db, "teacher", "teacher", true).await;
+    create_user_with_email(db, "unverified", "teacher", false).await;
+    create_user_with_email(db, "principal", "admin", true).await;
+
+    let outcome = log_in(&base_url, &provider, "sub-1", "teacher@example.com", false).await;
+    assert_eq!(outcome.status, StatusCode::BAD_REQUEST);
+
+    let outcome = log_in(&base_url, &provider, "sub-1", "teacher@example.com", true).await;
+    assert_eq!(outcome.status, StatusCode::OK, "{}", outcome.body);
+    assert!(outcome.body["token"].is_string());
+    // Later logins use the linked identity
+    let outcome = log_in(&base_url, &provider, "sub-1", "changed@example.com", true).await;
+    assert_eq!(outcome.status, StatusCode::OK);
+
+    for (subject, email) in [
+        ("sub-2", "unverified@example.com"),
+        ("sub-3", "principal@example.com"),
+    ] {
+        let outcome = log_in(&base_url, &provider, subject, email, true).await;
+        assert_eq!(outcome.status, StatusCode::UNAUTHORIZED, "{}", email);
+    }
+    let linked: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_oidc_identities")
+        .fetch_one(db)
+        .await
+        .unwrap();
+    assert_eq!(linked, 1);
+
+    server_handle.stop(true).await;
+    test_db.drop().await;
+}
@@ -0,0 +1,332 @@
+//! The push outbox worker delivers queued pushes to every device through the
+//! provider of its platform, records the outcome per token, retries with
+//! exponential backoff until it gives up and deletes old pushes. Runs against
+//! a fresh database and is skipped when `DATABASE_URL` is not set.
+
+mod common;
+
+use chrono::{DateTime, Utc};
+use common::{create_user, TestDb};
+use music_school_app_backend::notifications::{
+    ContentBlock, NotificationBody, NotificationContent,
+};
+use music_school_app_backend::push::{
+    self, PushDelivery, PushOutcome, PushProviders, RecordingProvider,
+};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Attempts before a push is given up, as in the outbox worker
+const MAX_ATTEMPTS: i32 = 6;
+
+fn body(title: &str) -> NotificationBody {
+    NotificationBody {
+        body_type: "chat_message".to_string(),
+        title: title.to_string(),
+        route: None,
+        content: NotificationContent {
+            blocks: vec![ContentBlock::Text {
+                text: "Hello".to_string(),
+                style: None,
+            }],
+            actions: None,
+        },
+        metadata: None,
+        collapse_key: None,
+    }
+}
+
+async fn add_token(db: &PgPool, user_id: i32, token: &str, platform: &str) {
+    sqlx::query("INSERT INTO push_tokens (user_id, token, platform) VALUES ($1, $2, $3)")
+        .bind(user_id)
+        .bind(token)
+        .bind(platform)
+        .execute(db)
+        .await
+        .unwrap();
+}
+
+async fn queue(db: &PgPool, user_id: i32, title: &str) {
+    push::enqueue(
+        db,
+        vec![PushDelivery {
+            user_id,
+            body: body(title),
+            notification_id: None,
+        }],
+    )
+    .await;
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct Entry {
+    status: String,
+    attempts: i32,
+    next_attempt_at: DateTime<Utc>,
+    last_error: Option<String>,
+}
+
+async fn entry(db: &PgPool) -> Entry {
+    sqlx::query_as(
+        "SELECT status, attempts, next_attempt_at, last_error FROM push_outbox
+         ORDER BY id DESC LIMIT 1",
+    )
+    .fetch_one(db)
+    .await
+    .unwrap()
+}
+
+/// Status per token of the latest push
+async fn token_statuses(db: &PgPool) -> HashMap<String, String> {
+    sqlx::query_as::<_, (String, String)>(
+        "SELECT token, status FROM push_deliveries
+         WHERE outbox_id = (SELECT MAX(id) FROM push_outbox)",
+    )
+    .fetch_all(db)
+    .await
+    .unwrap()
+    .into_iter()
+    .collect()
+}
+
+/// Let a rescheduled push be retried right away
+async fn make_due(db: &PgPool) {
+    sqlx::query("UPDATE push_outbox SET next_attempt_at = NOW() WHERE status = 'pending'")
+        .execute(db)
+        .await
+        .unwrap();
+}
+
+#[actix_web::test]
+async fn pushes_reach_every_device_and_record_each_token() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let user_id = create_user(db, "student", "student").await;
+    let other_id = create_user(db, "parent", "parent").await;
+    add_token(db, user_id, "phone", "android").await;
+    add_token(db, user_id, "tablet", "ios").await;
+
+    let provider = Arc::new(RecordingProvider::new());
+    let providers = PushProviders::single(provider.clone());
+
+    queue(db, user_id, "First").await;
+    assert_eq!(push::process_due(db, &providers).await, 1);
+    assert_eq!(provider.sent_to("phone").len(), 1);
+    assert_eq!(provider.sent_to("tablet")[0].body.title, "First");
+    assert_eq!(entry(db).await.status, "delivered");
+    let statuses = token_statuses(db).await;
+    assert_eq!(statuses["phone"], "delivered");
+    assert_eq!(statuses["tablet"], "delivered");
+
+    // Nothing is due any more
+    assert_eq!(push::process_due(db, &providers).await, 0);
+
+    // A user without devices
+    queue(db, other_id, "Nobody").await;
+    push::process_due(db, &providers).await;
+    assert_eq!(entry(db).await.status, "skipped");
+
+    // An unregistered token is revoked and not retried
+    provider.clear();
+    provider.set_outcome(PushOutcome::Unregistered);
+    add_token(db, other_id, "old-phone", "android").await;
+    queue(db, other_id, "Stale").await;
+    push::process_due(db, &providers).await;
+    assert_eq!(token_statuses(db).await["old-phone"], "unregistered");
+    assert_eq!(entry(db).await.status, "failed");
+    let revoked: bool = sqlx::query_scalar(
+        "SELECT revoked_at IS NOT NULL FROM push_tokens WHERE token = 'old-phone'",
+    )
+    .fetch_one(db)
+    .await
+    .unwrap();
+    assert!(revoked);
+
+    test_db.drop().await;
+}
+
+#[actix_web::test]
+async fn failed_tokens_are_retried_with_backoff_until_the_push_fails() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let user_id = create_user(db, "student", "student").await;
+    add_token(db, user_id, "phone", "android").await;
+    add_token(db, user_id, "tablet", "ios").await;
+
+    let provider = Arc::new(RecordingProvider::new());
+    let providers = PushProviders::single(provider.clone());
+    provider.set_outcome(PushOutcome::Retry("503 Service Unavailable".to_string()));
+    queue(db, user_id, "Lesson moved").await;
+
+    let mut delays = Vec::new();
+    for attempt in 1..MAX_ATTEMPTS {
+        let before = Utc::now();
+        push::process_due(db, &providers).await;
+        let pushed = entry(db).await;
+        assert_eq!(pushed.status, "pending");
+        assert_eq!(pushed.attempts, attempt);
+        assert_eq!(
+            pushed.last_error.as_deref(),
+            Some("503 Service Unavailable")
+        );
+        delays.push((pushed.next_attempt_at - before).num_seconds());
+        assert_eq!(token_statuses(db).await["phone"], "failed");
+
+        // Not retried before its backoff has passed
+        assert_eq!(push::process_due(db, &providers).await, 0);
+        make_due(db).await;
+    }
+    // 30 seconds, doubled on every attempt
+    for (delay, expected) in delays.iter().zip([30, 60, 120, 240, 480]) {
+        assert!((expected - 1..=expected).contains(delay), "{:?}", delays);
+    }
+
+    push::process_due(db, &providers).await;
+    let pushed = entry(db).await;
+    assert_eq!(pushed.status, "failed");
+    assert_eq!(pushed.attempts, MAX_ATTEMPTS);
+    make_due(db).await;
+    assert_eq!(push::process_due(db, &providers).await, 0);
+    assert_eq!(provider.sent_to("phone").len(), MAX_ATTEMPTS as usize);
+
+    test_db.drop().await;
+}
+
+#[actix_web::test]
+async fn delivered_tokens_are_not_sent_to_again_on_retry() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let user_id = create_user(db, "student", "student").await;
+    add_token(db, user_id, "phone", "android").await;
+    add_token(db, user_id, "laptop", "apns").await;
+
+    let fcm = Arc::new(RecordingProvider::new());
+    let apns = Arc::new(RecordingProvider::new());
+    let providers = PushProviders::default()
+        .with_fcm(fcm.clone())
+        .with_apns(apns.clone());
+    apns.set_outcome(PushOutcome::Retry("timeout".to_string()));
+
+    queue(db, user_id, "Homework").await;
+    push::process_due(db, &providers).await;
+    let statuses = token_statuses(db).await;
+    assert_eq!(statuses["phone"], "delivered");
+    assert_eq!(statuses["laptop"], "failed");
+    assert_eq!(entry(db).await.status, "pending");
+
+    apns.set_outcome(PushOutcome::Delivered);
+    make_due(db).await;
+    push::process_due(db, &providers).await;
+    assert_eq!(entry(db).await.status, "delivered");
+    assert_eq!(token_statuses(db).await["laptop"], "delivered");
+    assert_eq!(fcm.sent_to("phone").len(), 1);
+    assert_eq!(apns.sent_to("laptop").len(), 2);
+
+    test_db.drop().await;
+}
+
+#[actix_web::test]
+async fn finished_pushes_are_deleted_after_30_days() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let user_id = create_user(db, "student", "student").await;
+    add_token(db, user_id, "phone", "android").await;
+    let providers = PushProviders::single(Arc::new(RecordingProvider::new()));
+
+    queue(db, user_id, "Old").await;
+    push::process_due(db, &providers).await;
+    sqlx::query("UPDATE push_outbox SET updated_at = NOW() - INTERVAL '31 days'")
+        .execute(db)
+        .await
+        .unwrap();
+    queue(db, user_id, "Recent").await;
+    push::process_due(db, &providers).await;
+    // Queued long ago but not finished yet
+    queue(db, user_id, "Waiting").await;
+    sqlx::query(
+        "UPDATE push_outbox SET updated_at = NOW() - INTERVAL '31 days',
+                next_attempt_at = NOW() + INTERVAL '1 hour'
+         WHERE status = 'pending'",
+    )
+    .execute(db)
+    .await
+    .unwrap();
+
+    assert_eq!(push::cleanup_finished(db).await.unwrap(), 1);
+    let titles: Vec<String> =
+        sqlx::query_scalar("SELECT body->>'title' FROM push_outbox ORDER BY id")
+            .fetch_all(db)
+            .await
+            .unwrap();
+    assert_eq!(titles, ["Recent", "Waiting"]);
+    let deliveries: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM push_deliveries")
+        .fetch_one(db)
+        .await
+        .unwrap();
+    assert_eq!(deliveries, 1);
+
+    test_db.drop().await;
+}
+
+#[actix_web::test]
+async fn tokens_are_routed_to_the_provider_of_their_platform() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let user_id = create_user(db, "student", "student").await;
+    add_token(db, user_id, "fcm-phone", "android").await;
+    add_token(db, user_id, "fcm-web", "web").await;
+    add_token(
+        db,
+        user_id,
+        "{\"endpoint\":\"https://push.example.net/1\"}",
+        "webpush",
+    )
+    .await;
+    add_token(db, user_id, "apns-device", "apns").await;
+
+    let fcm = Arc::new(RecordingProvider::new());
+    let web_push = Arc::new(RecordingProvider::new());
+    let apns = Arc::new(RecordingProvider::new());
+    let providers = PushProviders::default()
+        .with_fcm(fcm.clone())
+        .with_web_push(web_push.clone())
+        .with_apns(apns.clone());
+
+    queue(db, user_id, "Concert").await;
+    push::process_due(db, &providers).await;
+    let tokens = |provider: &RecordingProvider| {
+        let mut tokens: Vec<String> = provider.sent().into_iter().map(|push| push.token).collect();
+        tokens.sort();
+        tokens
+    };
+    assert_eq!(tokens(&fcm), ["fcm-phone", "fcm-web"]);
+    assert_eq!(
+        tokens(&web_push),
+        ["{\"endpoint\":\"https://push.example.net/1\"}"]
+    );
+    assert_eq!(tokens(&apns), ["apns-device"]);
+    assert_eq!(token_statuses(db).await.len(), 4);
+
+    // Devices of a service that isn't configured are left alone
+    let fcm_only = PushProviders::default().with_fcm(fcm.clone());
+    fcm.clear();
+    queue(db, user_id, "Rehearsal").await;
+    push::process_due(db, &fcm_only).await;
+    assert_eq!(tokens(&fcm), ["fcm-phone", "fcm-web"]);
+    assert_eq!(web_push.sent().len(), 1);
+    assert_eq!(apns.sent().len(), 1);
+    assert_eq!(entry(db).await.status, "delivered");
+
+    test_db.drop().await;
+}
@@ -0,0 +1,65 @@
+//! Web Push payload encryption against the example in RFC 8291, Appendix A.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use music_school_app_backend::push::{encrypt_payload, MessageKeys, Subscription};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::SecretKey;
+
+const PLAINTEXT: &str = "When I grow up, I want to be a watermelon";
+const AS_PRIVATE: &str = "yfWPiYE-n46HLnH0KqZOF1fJJU3MYrct3AELtAQ-oRw";
+const AS_PUBLIC: &str =
+    "BP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A8";
+const UA_PUBLIC: &str =
+    "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4";
+const AUTH_SECRET: &str = "BTBZMqHH6r4Tts7J_aSIgg";
+const SALT: &str = "DGv6ra1nlYgDCS1FRnbzlw";
+const BODY: &str = "DGv6ra1nlYgDCS1FRnbzlwAAEABBBP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A_yl95bQpu6cVPTpK4Mqgkf1CXztLVBSt2Ks3oZwbuwXPXLWyouBWLVWGNWQexSgSxsj_Qulcy4a-fN";
+
+fn decode(value: &str) -> Vec<u8> {
+    URL_SAFE_NO_PAD.decode(value).unwrap()
+}
+
+fn subscription() -> Subscription {
+    let token = serde_json::json!({
+        "endpoint": "https://push.example.net/push/JzLQ3raZJfFBR0aqvOMsLrt54w4rJUsV",
+        "keys": { "p256dh": UA_PUBLIC, "auth": AUTH_SECRET },
+    });
+    Subscription::parse(&token.to_string()).unwrap()
+}
+
+#[test]
+fn encrypts_the_rfc_8291_example() {
+    let secret = SecretKey::from_slice(&decode(AS_PRIVATE)).unwrap();
+    assert_eq!(
+        secret.public_key().to_encoded_point(false).as_bytes(),
+        decode(AS_PUBLIC)
+    );
+    let keys = MessageKeys {
+        secret,
+        salt: decode(SALT).try_into().unwrap(),
+    };
+
+    let body = encrypt_payload(&subscription(), PLAINTEXT.as_bytes(), &keys).unwrap();
+    assert_eq!(URL_SAFE_NO_PAD.encode(body), BODY);
+}
+
+#[test]
+fn every_message_gets_fresh_keys() {
+    let first = encrypt_payload(
+        &subscription(),
+        PLAINTEXT.as_bytes(),
+        &MessageKeys::random(),
+    );
+    let second = encrypt_payload(
+        &subscription(),
+        PLAINTEXT.as_bytes(),
+        &MessageKeys::random(),
+    );
+    let (first, second) = (first.unwrap(), second.unwrap());
+    // Salt and sender key differ, and so does the ciphertext
+    assert_ne!(first[..16], second[..16]);
+    assert_ne!(first[21..86], second[21..86]);
+    assert_ne!(first[86..], second[86..]);
+    assert_eq!(first.len(), decode(BODY).len());
+}
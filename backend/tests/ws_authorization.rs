@@ -0,0 +1,352 @@
+//! WebSocket subscriptions and typing indicators must follow the REST access
+//! rules. Each test runs against a fresh database created on the server in
+//! `DATABASE_URL` and is skipped when no database is configured.
+
+mod common;
+
+use common::{chat_message, comment, create_user, Inbox, TestDb};
+use music_school_app_backend::websocket_protocol::{
+    ClientFrame, ClientMessage, ErrorCode, ProtocolVersion, ServerMessage, Topic,
+};
+use music_school_app_backend::websockets::{WsServerActor, WsSession};
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// Two families with different teachers, and a chat between the first
+/// family's parent and teacher
+struct Families {
+    teacher_a: i32,
+    student_a: i32,
+    parent_a: i32,
+    teacher_b: i32,
+    parent_b: i32,
+    admin: i32,
+    thread_a: i32,
+}
+
+async fn seed(db: &PgPool) -> Families {
+    let teacher_a = create_user(db, "teacher_a", "teacher").await;
+    let student_a = create_user(db, "student_a", "student").await;
+    let parent_a = create_user(db, "parent_a", "parent").await;
+    let teacher_b = create_user(db, "teacher_b", "teacher").await;
+    let student_b = create_user(db, "student_b", "student").await;
+    let parent_b = create_user(db, "parent_b", "parent").await;
+    let admin = create_user(db, "admin_user", "admin").await;
+
+    for (teacher, student, parent) in [
+        (teacher_a, student_a, parent_a),
+        (teacher_b, student_b, parent_b),
+    ] {
+        sqlx::query(
+            "INSERT INTO teacher_student_relations (teacher_user_id, student_user_id) VALUES ($1, $2)",
+        )
+        .bind(teacher)
+        .bind(student)
+        .execute(db)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO parent_student_relations (parent_user_id, student_user_id) VALUES ($1, $2)",
+        )
+        .bind(parent)
+        .bind(student)
+        .execute(db)
+        .await
+        .unwrap();
+    }
+
+    let thread_a = sqlx::query_scalar::<_, i32>(
+        "INSERT INTO chat_threads (participant_a_id, participant_b_id) VALUES ($1, $2) RETURNING id",
+    )
+    .bind(parent_a)
+    .bind(teacher_a)
+    .fetch_one(db)
+    .await
+    .unwrap();
+
+    Families {
+        teacher_a,
+        student_a,
+        parent_a,
+        teacher_b,
+        parent_b,
+        admin,
+        thread_a,
+    }
+}
+
+async fn connect(
+    db: &PgPool,
+    server: &WsServerActor,
+    user_id: i32,
+    is_admin: bool,
+) -> (WsSession, Inbox) {
+    let (session_id, inbox) = common::connect_client(server, user_id);
+
+    let session = WsSession::new(
+        session_id,
+        user_id,
+        is_admin,
+        ProtocolVersion::LATEST,
+        db.clone(),
+        server.clone(),
+    );
+    (session, inbox)
+}
+
+fn request(message: ClientMessage) -> ClientFrame {
+    ClientFrame {
+        request_id: Some("r1".to_string()),
+        message,
+    }
+}
+
+fn subscribe_thread(thread_id: i32) -> ClientFrame {
+    request(ClientMessage::Subscribe {
+        topic: Topic::Thread { thread_id },
+    })
+}
+
+fn subscribe_post(post_id: i32) -> ClientFrame {
+    request(ClientMessage::Subscribe {
+        topic: Topic::Post { post_id },
+    })
+}
+
+fn typing(thread_id: i32) -> ClientFrame {
+    request(ClientMessage::Typing {
+        thread_id,
+        is_typing: true,
+    })
+}
+
+fn assert_ack(reply: ServerMessage) {
+    match reply {
+        ServerMessage::Ack { request_id, .. } => assert_eq!(request_id.as_deref(), Some("r1")),
+        other => panic!("expected an ack, got {:?}", other),
+    }
+}
+
+fn assert_error(reply: ServerMessage, expected: ErrorCode) {
+    match reply {
+        ServerMessage::Error {
+            request_id, code, ..
+        } => {
+            assert_eq!(code, expected);
+            assert_eq!(request_id.as_deref(), Some("r1"));
+        }
+        other => panic!("expected an error frame, got {:?}", other),
+    }
+}
+
+async fn settle() {
+    actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+}
+
+#[actix_web::test]
+async fn parent_cannot_subscribe_to_another_familys_chat() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let families = seed(db).await;
+    let server = WsServerActor::new();
+
+    let (parent_a, inbox_a) = connect(db, &server, families.parent_a, false).await;
+    let (parent_b, inbox_b) = connect(db, &server, families.parent_b, false).await;
+    let (teacher_b, inbox_teacher_b) = connect(db, &server, families.teacher_b, false).await;
+
+    let thread = families.thread_a;
+    assert_ack(parent_a.handle_client_message(subscribe_thread(thread)).await);
+    assert_error(
+        parent_b.handle_client_message(subscribe_thread(thread)).await,
+        ErrorCode::Forbidden,
+    );
+    assert_error(
+        teacher_b.handle_client_message(subscribe_thread(thread)).await,
+        ErrorCode::Forbidden,
+    );
+    assert!(
+        !server
+            .is_user_watching_thread(families.parent_b, families.thread_a)
+            .await
+    );
+    assert!(
+        !server
+            .is_user_watching_thread(families.teacher_b, families.thread_a)
+            .await
+    );
+
+    server
+        .broadcast_to_thread(thread, chat_message(thread, "hello"))
+        .await;
+    settle().await;
+
+    assert_eq!(inbox_a.frames().len(), 1);
+    assert!(inbox_b.frames().is_empty());
+    assert!(inbox_teacher_b.frames().is_empty());
+
+    // Unsubscribing stops the events
+    assert_ack(
+        parent_a
+            .handle_client_message(request(ClientMessage::Unsubscribe {
+                topic: Topic::Thread { thread_id: thread },
+            }))
+            .await,
+    );
+    server
+        .broadcast_to_thread(thread, chat_message(thread, "again"))
+        .await;
+    settle().await;
+    assert_eq!(inbox_a.frames().len(), 1);
+
+    test_db.drop().await;
+}
+
+#[actix_web::test]
+async fn typing_is_not_relayed_from_outside_the_chat() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let families = seed(db).await;
+    let server = WsServerActor::new();
+
+    let (parent_a, inbox_a) = connect(db, &server, families.parent_a, false).await;
+    let (parent_b, _) = connect(db, &server, families.parent_b, false).await;
+    let (teacher_a, _) = connect(db, &server, families.teacher_a, false).await;
+
+    let thread = families.thread_a;
+    assert_ack(parent_a.handle_client_message(subscribe_thread(thread)).await);
+
+    assert_error(
+        parent_b.handle_client_message(typing(thread)).await,
+        ErrorCode::Forbidden,
+    );
+    settle().await;
+    assert!(inbox_a.frames().is_empty());
+
+    assert_ack(teacher_a.handle_client_message(typing(thread)).await);
+    settle().await;
+    let frames = inbox_a.frames();
+    assert_eq!(frames.len(), 1);
+    match frames[0].message {
+        ServerMessage::Typing { user_id, .. } => assert_eq!(user_id, families.teacher_a),
+        ref other => panic!("expected typing, got {:?}", other),
+    }
+
+    test_db.drop().await;
+}
+
+#[actix_web::test]
+async fn access_is_checked_again_for_every_session() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let families = seed(db).await;
+    let server = WsServerActor::new();
+
+    let thread = families.thread_a;
+    let (first, _) = connect(db, &server, families.teacher_a, false).await;
+    assert_ack(first.handle_client_message(subscribe_thread(thread)).await);
+
+    // The chat is handed to another teacher while the first session still follows it
+    sqlx::query("UPDATE chat_threads SET participant_b_id = $2 WHERE id = $1")
+        .bind(thread)
+        .bind(families.teacher_b)
+        .execute(db)
+        .await
+        .unwrap();
+
+    let (second, inbox) = connect(db, &server, families.teacher_a, false).await;
+    assert_error(
+        second.handle_client_message(subscribe_thread(thread)).await,
+        ErrorCode::Forbidden,
+    );
+    assert_error(
+        second.handle_client_message(typing(thread)).await,
+        ErrorCode::Forbidden,
+    );
+    server
+        .broadcast_to_thread(thread, chat_message(thread, "hello"))
+        .await;
+    settle().await;
+    assert!(inbox.frames().is_empty());
+
+    test_db.drop().await;
+}
+
+#[actix_web::test]
+async fn post_subscription_requires_feed_access() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let families = seed(db).await;
+    let server = WsServerActor::new();
+
+    let feed_id = sqlx::query_scalar::<_, i32>(
+        "INSERT INTO feeds (owner_type, owner_user_id, title) VALUES ('teacher', $1, 'Teacher B') RETURNING id",
+    )
+    .bind(families.teacher_b)
+    .fetch_one(db)
+    .await
+    .unwrap();
+    let post_id = sqlx::query_scalar::<_, i32>(
+        "INSERT INTO feed_posts (feed_id, author_user_id, content) VALUES ($1, $2, '{}') RETURNING id",
+    )
+    .bind(feed_id)
+    .bind(families.teacher_b)
+    .fetch_one(db)
+    .await
+    .unwrap();
+
+    let (student_a, inbox_a) = connect(db, &server, families.student_a, false).await;
+    let (parent_b, inbox_b) = connect(db, &server, families.parent_b, false).await;
+    let (admin, _) = connect(db, &server, families.admin, true).await;
+
+    assert_error(
+        student_a.handle_client_message(subscribe_post(post_id)).await,
+        ErrorCode::Forbidden,
+    );
+    assert_ack(parent_b.handle_client_message(subscribe_post(post_id)).await);
+    assert_ack(admin.handle_client_message(subscribe_post(post_id)).await);
+    assert_error(
+        parent_b
+            .handle_client_message(subscribe_post(post_id + 1000))
+            .await,
+        ErrorCode::Forbidden,
+    );
+
+    server.broadcast_to_post(post_id, comment(post_id)).await;
+    settle().await;
+
+    assert!(inbox_a.frames().is_empty());
+    assert_eq!(inbox_b.frames().len(), 1);
+
+    test_db.drop().await;
+}
+
+#[actix_web::test]
+async fn invalid_resume_position_is_refused() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let families = seed(db).await;
+    let server = WsServerActor::new();
+
+    let (parent_a, _) = connect(db, &server, families.parent_a, false).await;
+
+    assert_error(
+        parent_a
+            .handle_client_message(request(ClientMessage::Resume {
+                last_seq: Some(-1),
+            }))
+            .await,
+        ErrorCode::InvalidRequest,
+    );
+
+    test_db.drop().await;
+}
@@ -0,0 +1,170 @@
+//! Two replicas sharing a database deliver each other's broadcasts and agree
+//! on presence. Runs against a fresh database like the authorization tests
+//! and is skipped when `DATABASE_URL` is not set.
+
+mod common;
+
+use common::{chat_message, connect_client, create_user, presence_of, TestDb};
+use music_school_app_backend::websocket_bus::{self, WsBus};
+use music_school_app_backend::websocket_protocol::{NotificationChange, ServerMessage};
+use music_school_app_backend::websockets::{self, WsServerActor};
+use sqlx::PgPool;
+use std::time::Duration;
+
+async fn start_replica(db: &PgPool) -> (WsServerActor, WsBus) {
+    let (bus, worker) = websocket_bus::new(db.clone());
+    let server = WsServerActor::with_event_log(db.clone()).with_bus(bus.clone());
+    actix_web::rt::spawn(worker.run(server.clone()));
+    (server, bus)
+}
+
+/// Stop the buses so their listeners are gone before the database is dropped
+async fn stop(buses: [WsBus; 2]) {
+    for bus in buses {
+        bus.shutdown().await;
+    }
+    settle().await;
+}
+
+async fn create_thread(db: &PgPool, a: i32, b: i32) -> i32 {
+    sqlx::query_scalar::<_, i32>(
+        "INSERT INTO chat_threads (participant_a_id, participant_b_id) VALUES ($1, $2) RETURNING id",
+    )
+    .bind(a)
+    .bind(b)
+    .fetch_one(db)
+    .await
+    .unwrap()
+}
+
+/// Give the buses time to listen and relay
+async fn settle() {
+    actix_web::rt::time::sleep(Duration::from_millis(300)).await;
+}
+
+#[actix_web::test]
+async fn broadcasts_reach_sessions_on_other_replicas() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let parent = create_user(db, "parent", "parent").await;
+    let teacher = create_user(db, "teacher", "teacher").await;
+    let thread = create_thread(db, parent, teacher).await;
+    let (replica_a, bus_a) = start_replica(db).await;
+    let (replica_b, bus_b) = start_replica(db).await;
+    settle().await;
+
+    let (session, inbox) = connect_client(&replica_b, parent);
+    replica_b.subscribe_to_thread(session, thread).await;
+
+    replica_a
+        .broadcast_to_thread(thread, chat_message(thread, "hello"))
+        .await;
+    replica_a.broadcast_typing(thread, teacher, true).await;
+    replica_a
+        .send_to_user(
+            parent,
+            ServerMessage::NotificationsChanged {
+                user_id: parent,
+                change: NotificationChange::Read,
+                notification_ids: vec![1],
+                unread_count: 0,
+            },
+        )
+        .await;
+    settle().await;
+
+    assert_eq!(
+        inbox.types(),
+        ["chat_message", "typing", "notifications_changed"]
+    );
+    assert!(inbox.frames()[0].seq.is_some());
+
+    stop([bus_a, bus_b]).await;
+    test_db.drop().await;
+}
+
+#[actix_web::test]
+async fn frames_for_a_user_of_any_size_reach_other_replicas() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let parent = create_user(db, "parent", "parent").await;
+    let (replica_a, bus_a) = start_replica(db).await;
+    let (replica_b, bus_b) = start_replica(db).await;
+    settle().await;
+
+    let (_, inbox) = connect_client(&replica_b, parent);
+    // Far more than fits in a NOTIFY payload
+    let notification_ids: Vec<i32> = (1..=2000).collect();
+    replica_a
+        .send_to_user(
+            parent,
+            ServerMessage::NotificationsChanged {
+                user_id: parent,
+                change: NotificationChange::Read,
+                notification_ids: notification_ids.clone(),
+                unread_count: 0,
+            },
+        )
+        .await;
+    settle().await;
+
+    let frames = inbox.frames();
+    assert_eq!(frames.len(), 1);
+    match &frames[0].message {
+        ServerMessage::NotificationsChanged {
+            notification_ids: received,
+            ..
+        } => assert_eq!(received, &notification_ids),
+        other => panic!("expected notifications_changed, got {:?}", other),
+    }
+
+    sqlx::query("UPDATE ws_user_events SET created_at = NOW() - INTERVAL '1 hour'")
+        .execute(db)
+        .await
+        .unwrap();
+    assert_eq!(websockets::prune_events(db).await.unwrap(), 1);
+
+    stop([bus_a, bus_b]).await;
+    test_db.drop().await;
+}
+
+#[actix_web::test]
+async fn user_stays_online_while_connected_to_any_replica() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let parent = create_user(db, "parent", "parent").await;
+    let teacher = create_user(db, "teacher", "teacher").await;
+    let thread = create_thread(db, parent, teacher).await;
+    let (replica_a, bus_a) = start_replica(db).await;
+    let (replica_b, bus_b) = start_replica(db).await;
+    settle().await;
+
+    let (watcher, watcher_inbox) = connect_client(&replica_a, teacher);
+    replica_a.subscribe_to_thread(watcher, thread).await;
+    let (phone, _) = connect_client(&replica_a, parent);
+    let (laptop, _) = connect_client(&replica_b, parent);
+    replica_a.subscribe_to_thread(phone, thread).await;
+    replica_b.subscribe_to_thread(laptop, thread).await;
+    settle().await;
+
+    replica_a.unregister_connection(phone);
+    settle().await;
+    assert!(replica_a.is_user_online(parent).await);
+    assert!(watcher_inbox.frames().is_empty());
+
+    replica_b.unregister_connection(laptop);
+    settle().await;
+    assert!(!replica_a.is_user_online(parent).await);
+    let frames = watcher_inbox.frames();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(presence_of(&frames[0]), Some((parent, false)));
+
+    stop([bus_a, bus_b]).await;
+    test_db.drop().await;
+}
@@ -0,0 +1,215 @@
+//! Both protocol versions decode the same client requests and encode the same
+//! server events, and the committed JSON schema matches the message types.
+//! Run with `UPDATE_SCHEMA=1` to rewrite the schema after changing them.
+
+mod common;
+
+use common::chat_message;
+use music_school_app_backend::websocket_protocol::{
+    self, ClientFrame, ClientMessage, ErrorCode, Negotiated, ProtocolVersion, ServerFrame,
+    ServerMessage, Topic,
+};
+use std::path::Path;
+
+const SCHEMA_FILE: &str = "websocket_protocol.schema.json";
+
+fn error_code(reply: ServerMessage) -> ErrorCode {
+    match reply {
+        ServerMessage::Error { code, .. } => code,
+        other => panic!("expected an error frame, got {:?}", other),
+    }
+}
+
+fn decode_v1(text: &str) -> Result<ClientMessage, ErrorCode> {
+    websocket_protocol::decode(text, ProtocolVersion::V1)
+        .map(|frame| frame.message)
+        .map_err(error_code)
+}
+
+fn encode_v1(message: ServerMessage, seq: Option<i64>) -> Option<serde_json::Value> {
+    let frame = ServerFrame { seq, message };
+    websocket_protocol::encode(&frame, ProtocolVersion::V1)
+        .map(|text| serde_json::from_str(&text).unwrap())
+}
+
+#[test]
+fn committed_schema_is_current() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(SCHEMA_FILE);
+    let schema = serde_json::to_string_pretty(&websocket_protocol::json_schema()).unwrap() + "\n";
+    if std::env::var_os("UPDATE_SCHEMA").is_some() {
+        std::fs::write(&path, schema).unwrap();
+        return;
+    }
+    let committed = std::fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        committed == schema,
+        "{} is out of date; run the tests with UPDATE_SCHEMA=1",
+        SCHEMA_FILE
+    );
+}
+
+#[test]
+fn negotiates_the_requested_version() {
+    let v2 = ProtocolVersion::V2;
+    assert_eq!(websocket_protocol::negotiate(None, None), Ok(Negotiated::Default));
+    assert_eq!(Negotiated::Default.version(), ProtocolVersion::V1);
+    assert_eq!(
+        websocket_protocol::negotiate(Some("2"), None),
+        Ok(Negotiated::Query(v2))
+    );
+    assert_eq!(
+        websocket_protocol::negotiate(None, Some("music-school.v1, music-school.v2, chat")),
+        Ok(Negotiated::Subprotocol(v2))
+    );
+    assert_eq!(
+        websocket_protocol::negotiate(None, Some("chat")),
+        Ok(Negotiated::Default)
+    );
+    assert!(websocket_protocol::negotiate(Some("3"), None).is_err());
+    assert!(websocket_protocol::negotiate(None, Some("music-school.v9")).is_err());
+}
+
+#[test]
+fn version_1_requests_keep_their_envelope() {
+    assert!(matches!(
+        decode_v1(r#"{"msg_type":"subscribe_thread","thread_id":4,"data":{}}"#),
+        Ok(ClientMessage::Subscribe {
+            topic: Topic::Thread { thread_id: 4 }
+        })
+    ));
+    assert!(matches!(
+        decode_v1(r#"{"msg_type":"unsubscribe_post","post_id":9}"#),
+        Ok(ClientMessage::Unsubscribe {
+            topic: Topic::Post { post_id: 9 }
+        })
+    ));
+    assert!(matches!(
+        decode_v1(r#"{"msg_type":"typing","thread_id":4,"data":{"is_typing":true}}"#),
+        Ok(ClientMessage::Typing {
+            thread_id: 4,
+            is_typing: true
+        })
+    ));
+    assert!(matches!(
+        decode_v1(r#"{"msg_type":"resume","data":{"last_seq":12}}"#),
+        Ok(ClientMessage::Resume { last_seq: Some(12) })
+    ));
+
+    assert_eq!(
+        decode_v1(r#"{"msg_type":"subscribe_thread","data":{}}"#).unwrap_err(),
+        ErrorCode::InvalidRequest
+    );
+    assert_eq!(
+        decode_v1(r#"{"msg_type":"subscribe_post"}"#).unwrap_err(),
+        ErrorCode::InvalidRequest
+    );
+    assert_eq!(
+        decode_v1(r#"{"msg_type":"listen_all"}"#).unwrap_err(),
+        ErrorCode::Unsupported
+    );
+    assert_eq!(decode_v1("not json").unwrap_err(), ErrorCode::InvalidMessage);
+}
+
+#[test]
+fn version_1_events_keep_their_envelope() {
+    let legacy = encode_v1(chat_message(4, "hi"), Some(7)).unwrap();
+    assert_eq!(legacy["msg_type"], "chat_message");
+    assert_eq!(legacy["user_id"], 1);
+    assert_eq!(legacy["thread_id"], 4);
+    assert_eq!(legacy["data"]["body"], "hi");
+    assert_eq!(legacy["data"]["sender_name"], "sender");
+    assert_eq!(legacy["seq"], 7);
+
+    let legacy = encode_v1(
+        ServerMessage::Presence {
+            user_id: 3,
+            is_online: false,
+        },
+        None,
+    )
+    .unwrap();
+    assert_eq!(legacy["msg_type"], "presence");
+    assert_eq!(legacy["user_id"], 3);
+    assert_eq!(legacy["data"]["is_online"], false);
+    assert!(legacy.get("seq").is_none());
+
+    let legacy = encode_v1(
+        ServerMessage::error(None, "subscribe", ErrorCode::Forbidden, "Access denied"),
+        None,
+    )
+    .unwrap();
+    assert_eq!(legacy["msg_type"], "error");
+    assert_eq!(legacy["data"]["code"], "forbidden");
+    assert_eq!(legacy["data"]["error"], "Access denied");
+
+    // Version 1 had no greeting and didn't answer successful requests
+    let ack = ServerMessage::Ack {
+        request_id: None,
+        request: "subscribe".to_string(),
+    };
+    assert!(encode_v1(ack, None).is_none());
+    let hello = ServerMessage::Hello {
+        protocol: 1,
+        session_id: 1,
+    };
+    assert!(encode_v1(hello, None).is_none());
+}
+
+#[test]
+fn version_2_frames_are_tagged() {
+    let frame = websocket_protocol::decode(
+        r#"{"type":"subscribe","request_id":"a","post_id":9}"#,
+        ProtocolVersion::V2,
+    )
+    .unwrap();
+    assert_eq!(frame.request_id.as_deref(), Some("a"));
+    assert!(matches!(
+        frame.message,
+        ClientMessage::Subscribe {
+            topic: Topic::Post { post_id: 9 }
+        }
+    ));
+    let request = ClientFrame {
+        request_id: None,
+        message: ClientMessage::Resume { last_seq: None },
+    };
+    assert_eq!(
+        serde_json::to_value(&request).unwrap(),
+        serde_json::json!({ "type": "resume", "last_seq": null })
+    );
+
+    let reply = websocket_protocol::decode(
+        r#"{"type":"listen_all","request_id":"b"}"#,
+        ProtocolVersion::V2,
+    )
+    .unwrap_err();
+    match reply {
+        ServerMessage::Error {
+            request_id,
+            request,
+            code,
+            ..
+        } => {
+            assert_eq!(request_id.as_deref(), Some("b"));
+            assert_eq!(request, "listen_all");
+            assert_eq!(code, ErrorCode::Unsupported);
+        }
+        other => panic!("expected an error frame, got {:?}", other),
+    }
+    let reply =
+        websocket_protocol::decode(r#"{"type":"typing","thread_id":4}"#, ProtocolVersion::V2)
+            .unwrap_err();
+    assert_eq!(error_code(reply), ErrorCode::InvalidMessage);
+
+    let frame = ServerFrame {
+        seq: Some(3),
+        message: chat_message(4, "hi"),
+    };
+    let text = websocket_protocol::encode(&frame, ProtocolVersion::V2).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(value["type"], "chat_message");
+    assert_eq!(value["seq"], 3);
+    assert_eq!(value["thread_id"], 4);
+    let decoded: ServerFrame = serde_json::from_str(&text).unwrap();
+    assert!(matches!(decoded.message, ServerMessage::ChatMessage(_)));
+}
@@ -0,0 +1,271 @@
+//! A reconnecting client can replay the chat and comment events it missed.
+//! Runs against a fresh database like the authorization tests and is skipped
+//! when `DATABASE_URL` is not set.
+
+mod common;
+
+use actix_http::ws::{Codec, Frame, Message};
+use actix_web::error::PayloadError;
+use actix_web_actors::ws::WebsocketContext;
+use bytes::{Bytes, BytesMut};
+use common::{chat_message, connect_client, create_user, Inbox, TestDb};
+use futures_util::{stream, StreamExt};
+use music_school_app_backend::websocket_protocol::{
+    ClientFrame, ClientMessage, ProtocolVersion, ServerFrame, ServerMessage,
+};
+use music_school_app_backend::websockets::{self, next_session_id, WsServerActor, WsSession};
+use sqlx::PgPool;
+use std::time::Duration;
+use tokio_util::codec::{Decoder, Encoder};
+
+async fn create_thread(db: &PgPool, a: i32, b: i32) -> i32 {
+    sqlx::query_scalar::<_, i32>(
+        "INSERT INTO chat_threads (participant_a_id, participant_b_id) VALUES ($1, $2) RETURNING id",
+    )
+    .bind(a)
+    .bind(b)
+    .fetch_one(db)
+    .await
+    .unwrap()
+}
+
+fn resume(last_seq: Option<i64>) -> ClientFrame {
+    ClientFrame {
+        request_id: None,
+        message: ClientMessage::Resume { last_seq },
+    }
+}
+
+fn body(frame: &ServerFrame) -> &serde_json::Value {
+    match &frame.message {
+        ServerMessage::ChatMessage(event) => &event.body,
+        other => panic!("expected a chat message, got {:?}", other),
+    }
+}
+
+/// `last_seq`, `replayed` and `complete` of a `resumed` frame
+fn resumed(reply: ServerMessage) -> (i64, i64, bool) {
+    match reply {
+        ServerMessage::Resumed {
+            last_seq,
+            replayed,
+            complete,
+        } => (last_seq, replayed, complete),
+        other => panic!("expected a resumed frame, got {:?}", other),
+    }
+}
+
+async fn connect(db: &PgPool, server: &WsServerActor, user_id: i32) -> (WsSession, Inbox) {
+    let (session_id, inbox) = connect_client(server, user_id);
+    let session = WsSession::new(
+        session_id,
+        user_id,
+        false,
+        ProtocolVersion::LATEST,
+        db.clone(),
+        server.clone(),
+    );
+    (session, inbox)
+}
+
+async fn settle() {
+    actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+}
+
+#[actix_web::test]
+async fn reconnecting_client_receives_missed_events() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let parent = create_user(db, "parent", "parent").await;
+    let teacher = create_user(db, "teacher", "teacher").await;
+    let other_parent = create_user(db, "other_parent", "parent").await;
+    let thread = create_thread(db, parent, teacher).await;
+    let other_thread = create_thread(db, other_parent, teacher).await;
+    let server = WsServerActor::with_event_log(db.clone());
+
+    let (session, inbox) = connect(db, &server, parent).await;
+    server.subscribe_to_thread(session.session_id, thread).await;
+    server
+        .broadcast_to_thread(thread, chat_message(thread, "seen"))
+        .await;
+    settle().await;
+    let last_seq = inbox.frames()[0].seq.expect("live events carry a seq");
+    server.unregister_connection(session.session_id);
+
+    server
+        .broadcast_to_thread(thread, chat_message(thread, "missed"))
+        .await;
+    server
+        .broadcast_to_thread(other_thread, chat_message(other_thread, "not mine"))
+        .await;
+    server.broadcast_typing(thread, teacher, true).await;
+
+    let (session, inbox) = connect(db, &server, parent).await;
+    server.subscribe_to_thread(session.session_id, thread).await;
+    let reply = session.handle_client_message(resume(Some(last_seq))).await;
+    settle().await;
+
+    let frames = inbox.frames();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(body(&frames[0]), "missed");
+    assert_eq!(frames[0].seq, Some(last_seq + 1));
+    assert_eq!(resumed(reply), (last_seq + 2, 1, true));
+
+    // Without a position nothing is replayed, only the current one reported
+    let reply = session.handle_client_message(resume(None)).await;
+    assert_eq!(resumed(reply), (last_seq + 2, 0, true));
+
+    test_db.drop().await;
+}
+
+#[actix_web::test]
+async fn resume_reports_pruned_events_as_incomplete() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let parent = create_user(db, "parent", "parent").await;
+    let teacher = create_user(db, "teacher", "teacher").await;
+    let thread = create_thread(db, parent, teacher).await;
+    let server = WsServerActor::with_event_log(db.clone());
+
+    for body in ["old", "older"] {
+        server
+            .broadcast_to_thread(thread, chat_message(thread, body))
+            .await;
+    }
+    sqlx::query("UPDATE ws_events SET created_at = NOW() - INTERVAL '2 days'")
+        .execute(db)
+        .await
+        .unwrap();
+    assert_eq!(websockets::prune_events(db).await.unwrap(), 2);
+
+    let (session, inbox) = connect(db, &server, parent).await;
+    server.subscribe_to_thread(session.session_id, thread).await;
+    let reply = session.handle_client_message(resume(Some(0))).await;
+    settle().await;
+
+    assert!(inbox.frames().is_empty());
+    assert_eq!(resumed(reply), (0, 0, false));
+
+    test_db.drop().await;
+}
+
+#[actix_web::test]
+async fn resume_position_leaves_out_uncommitted_events() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let parent = create_user(db, "parent", "parent").await;
+    let teacher = create_user(db, "teacher", "teacher").await;
+    let thread = create_thread(db, parent, teacher).await;
+    let server = WsServerActor::with_event_log(db.clone());
+    server
+        .broadcast_to_thread(thread, chat_message(thread, "sent"))
+        .await;
+
+    // An event still being logged has its ID but isn't visible yet
+    let mut pending = db.begin().await.unwrap();
+    sqlx::query("INSERT INTO ws_events (thread_id, message) VALUES ($1, $2)")
+        .bind(thread)
+        .bind(serde_json::to_value(chat_message(thread, "pending")).unwrap())
+        .execute(&mut *pending)
+        .await
+        .unwrap();
+
+    let (session, inbox) = connect(db, &server, parent).await;
+    server.subscribe_to_thread(session.session_id, thread).await;
+    let reply = session.handle_client_message(resume(None)).await;
+    assert_eq!(resumed(reply), (1, 0, true));
+
+    pending.commit().await.unwrap();
+    let reply = session.handle_client_message(resume(Some(1))).await;
+    settle().await;
+    assert_eq!(body(&inbox.frames()[0]), "pending");
+    assert_eq!(resumed(reply), (2, 1, true));
+
+    test_db.drop().await;
+}
+
+#[actix_web::test]
+async fn requests_sent_back_to_back_are_handled_in_order() {
+    let Some(test_db) = TestDb::create().await else {
+        return;
+    };
+    let db = &test_db.pool;
+    let parent = create_user(db, "parent", "parent").await;
+    let teacher = create_user(db, "teacher", "teacher").await;
+    let thread = create_thread(db, parent, teacher).await;
+    let server = WsServerActor::with_event_log(db.clone());
+    server
+        .broadcast_to_thread(thread, chat_message(thread, "missed"))
+        .await;
+
+    // Both requests arrive in one read, as from a client that doesn't wait
+    // for the ack before resuming
+    let mut client = Codec::new().client_mode();
+    let mut input = BytesMut::new();
+    for request in [
+        serde_json::json!({ "type": "subscribe", "thread_id": thread }),
+        serde_json::json!({ "type": "resume", "last_seq": 0 }),
+    ] {
+        client
+            .encode(Message::Text(request.to_string().into()), &mut input)
+            .unwrap();
+    }
+    let incoming = stream::once(async move { Ok::<Bytes, PayloadError>(input.freeze()) })
+        .chain(stream::pending());
+    let session = WsSession::new(
+        next_session_id(),
+        parent,
+        false,
+        ProtocolVersion::LATEST,
+        db.clone(),
+        server.clone(),
+    );
+    // The access check of the subscribe waits for the lock, while the
+    // events log stays readable
+    let mut lock = db.begin().await.unwrap();
+    sqlx::query("LOCK TABLE chat_threads IN ACCESS EXCLUSIVE MODE")
+        .execute(&mut *lock)
+        .await
+        .unwrap();
+    let mut outgoing = Box::pin(WebsocketContext::create(session, incoming));
+    let mut buffer = BytesMut::new();
+    let locked_until = tokio::time::Instant::now() + Duration::from_millis(200);
+    while let Ok(Some(chunk)) = tokio::time::timeout_at(locked_until, outgoing.next()).await {
+        buffer.extend_from_slice(&chunk.unwrap());
+    }
+    lock.commit().await.unwrap();
+
+    let mut received: Vec<serde_json::Value> = Vec::new();
+    loop {
+        while let Some(frame) = client.decode(&mut buffer).unwrap() {
+            if let Frame::Text(text) = frame {
+                received.push(serde_json::from_slice(&text).unwrap());
+            }
+        }
+        if received.iter().any(|frame| frame["type"] == "resumed") {
+            break;
+        }
+        let chunk = actix_web::rt::time::timeout(Duration::from_secs(5), outgoing.next())
+            .await
+            .expect("the session answers")
+            .unwrap()
+            .unwrap();
+        buffer.extend_from_slice(&chunk);
+    }
+
+    let types: Vec<&str> = received
+        .iter()
+        .map(|frame| frame["type"].as_str().unwrap())
+        .collect();
+    assert_eq!(types, ["hello", "ack", "chat_message", "resumed"]);
+    assert_eq!(received[2]["body"], "missed");
+    assert_eq!(received[3]["replayed"], 1);
+
+    test_db.drop().await;
+}
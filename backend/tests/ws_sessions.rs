@@ -0,0 +1,91 @@
+//! A user may be connected from several devices at once; each connection is
+//! its own session with its own subscriptions.
+
+mod common;
+
+use common::{chat_message, connect_client, presence_of};
+use music_school_app_backend::websocket_protocol::{NotificationChange, ServerMessage};
+use music_school_app_backend::websockets::WsServerActor;
+use std::time::Duration;
+
+const USER: i32 = 1;
+const OTHER_USER: i32 = 2;
+const THREAD: i32 = 7;
+
+fn notifications_read() -> ServerMessage {
+    ServerMessage::NotificationsChanged {
+        user_id: USER,
+        change: NotificationChange::Read,
+        notification_ids: vec![1],
+        unread_count: 0,
+    }
+}
+
+async fn settle() {
+    actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+}
+
+#[actix_web::test]
+async fn second_device_does_not_replace_the_first() {
+    let server = WsServerActor::new();
+    let (phone, phone_inbox) = connect_client(&server, USER);
+    let (laptop, laptop_inbox) = connect_client(&server, USER);
+    server.subscribe_to_thread(phone, THREAD).await;
+    server.subscribe_to_thread(laptop, THREAD).await;
+
+    server.send_to_user(USER, notifications_read()).await;
+    server
+        .broadcast_to_thread(THREAD, chat_message(THREAD, "hello"))
+        .await;
+    settle().await;
+
+    for inbox in [&phone_inbox, &laptop_inbox] {
+        assert_eq!(inbox.types(), ["notifications_changed", "chat_message"]);
+    }
+}
+
+#[actix_web::test]
+async fn closing_one_device_keeps_the_other_subscribed() {
+    let server = WsServerActor::new();
+    let (phone, phone_inbox) = connect_client(&server, USER);
+    let (laptop, laptop_inbox) = connect_client(&server, USER);
+    server.subscribe_to_thread(phone, THREAD).await;
+    server.subscribe_to_thread(laptop, THREAD).await;
+
+    server.unregister_connection(phone);
+    assert!(server.is_user_online(USER).await);
+    assert!(server.is_user_watching_thread(USER, THREAD).await);
+
+    server
+        .broadcast_to_thread(THREAD, chat_message(THREAD, "hello"))
+        .await;
+    settle().await;
+    assert!(phone_inbox.frames().is_empty());
+    assert_eq!(laptop_inbox.frames().len(), 1);
+
+    server.unregister_connection(laptop);
+    assert!(!server.is_user_online(USER).await);
+    assert!(!server.is_user_watching_thread(USER, THREAD).await);
+    assert!(server.connected_users(&[USER]).await.is_empty());
+}
+
+#[actix_web::test]
+async fn user_goes_offline_with_the_last_device() {
+    let server = WsServerActor::new();
+    let (watcher, watcher_inbox) = connect_client(&server, OTHER_USER);
+    let (phone, _) = connect_client(&server, USER);
+    let (laptop, _) = connect_client(&server, USER);
+    server.subscribe_to_thread(watcher, THREAD).await;
+    server.subscribe_to_thread(phone, THREAD).await;
+    server.subscribe_to_thread(laptop, THREAD).await;
+
+    server.unregister_connection(phone);
+    settle().await;
+    assert!(watcher_inbox.frames().is_empty());
+
+    server.unregister_connection(laptop);
+    settle().await;
+    let frames = watcher_inbox.frames();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(presence_of(&frames[0]), Some((USER, false)));
+}
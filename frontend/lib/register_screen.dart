@@ -37,6 +37,9 @@ class _RegisterScreenState extends State<RegisterScreen> {
   bool _consentAccepted = false;
   String _consentText = '';
   String? _consentLocaleCode;
+  // Version and locale of the text shown, when it came from the server
+  int? _consentVersion;
+  String? _consentDocumentLocale;
   String? _errorMessage;
   
   // Token info
@@ -86,6 +89,25 @@ class _RegisterScreenState extends State<RegisterScreen> {
   }
 
   Future<void> _loadConsentText(String localeCode) async {
+    try {
+      final response = await http.get(
+        Uri.parse('$_baseUrl/api/consent/current?locale=$localeCode'),
+      );
+      if (response.statusCode == 200) {
+        final document = jsonDecode(utf8.decode(response.bodyBytes));
+        if (mounted) {
+          setState(() {
+            _consentText = '${document['title']}\n\n${document['body']}'.trim();
+            _consentVersion = document['version'] as int?;
+            _consentDocumentLocale = document['locale'] as String?;
+          });
+        }
+        return;
+      }
+    } catch (_) {
+      // Fall back to the bundled text below
+    }
+
     try {
       final localizedPath = 'assets/consent_${localeCode}.txt';
       String text;
@@ -190,7 +212,7 @@ class _RegisterScreenState extends State<RegisterScreen> {
     });
 
     try {
-      final requestBody = {
+      final requestBody = <String, dynamic>{
         'token': widget.token,
         'username': _usernameController.text,
         'password': _passwordController.text,
@@ -199,6 +221,12 @@ class _RegisterScreenState extends State<RegisterScreen> {
         'full_name': _fullNameController.text,
       };
 
+      // The server records the accepted consent with the account
+      if (_consentVersion != null) {
+        requestBody['consent_version'] = _consentVersion;
+        requestBody['consent_locale'] = _consentDocumentLocale;
+      }
+
       // Add role-specific fields
       if (_role == 'student') {
         requestBody['birthday'] = _birthdayController.text;